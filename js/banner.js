@@ -0,0 +1,24 @@
+// The close button of the banner, the dismissed text stays hidden until the banner changes.
+// Loaded right after the banner, not every page has jQuery.
+
+(function() {
+    var scripts = document.getElementsByTagName('script');
+    var banner = scripts[scripts.length - 1].previousElementSibling;
+    var text = banner.getAttribute('data-banner');
+
+    try {
+        if (window.localStorage.getItem('dismissedBanner') === text) {
+            banner.hidden = true;
+        }
+    } catch (e) {
+        // Storage is disabled, the banner stays
+    }
+
+    banner.querySelector('.banner-close').addEventListener('click', function() {
+        banner.hidden = true;
+        try {
+            window.localStorage.setItem('dismissedBanner', text);
+        } catch (e) {
+        }
+    });
+})();
@@ -0,0 +1,28 @@
+// Fills the registration form with a saved draft, registrationDraft is set by the page
+
+$(function() {
+    if (typeof registrationDraft !== 'object' || registrationDraft === null) {
+        return;
+    }
+
+    $('#validate').find('input, select, textarea').each(function() {
+        // Lists like topics[] are stored without the brackets
+        var name = this.name.replace(/\[\]$/, '');
+
+        if (!name || this.type === 'submit' || this.type === 'hidden' || this.type === 'file') {
+            return;
+        }
+
+        var value = registrationDraft[name];
+        var values = $.isArray(value) ? value.map(String) : (value === undefined ? [] : [String(value)]);
+
+        if (this.type === 'checkbox' || this.type === 'radio') {
+            // Unchecked boxes are not submitted, so a missing value means unchecked
+            $(this).prop('checked', values.indexOf(this.value) >= 0);
+        } else if (value !== undefined) {
+            $(this).val(this.multiple ? values : values[0]);
+        }
+
+        $(this).trigger('change');
+    });
+});
@@ -0,0 +1,13 @@
+// The emergency contact is only needed for courses with a field trip
+
+$(function() {
+    function updateEmergencyContact() {
+        var fieldTrip = $('input[name=course_type]:checked').data('field-trip') === true;
+
+        $('#emergency_contact').prop('hidden', !fieldTrip);
+        $('#emergency_name, #emergency_phone').prop('required', fieldTrip);
+    }
+
+    $('input[name=course_type]').change(updateEmergencyContact);
+    updateEmergencyContact();
+});
@@ -0,0 +1,23 @@
+// Suggest institution names already used by other participants
+
+$(function() {
+    var lastQuery = "";
+
+    $('#institution').on('input', function() {
+        var query = $(this).val().trim();
+
+        if (query.length < 2 || query === lastQuery) {
+            return;
+        }
+
+        lastQuery = query;
+
+        $.getJSON('/api/institutions', { q: query }, function(names) {
+            var list = $('#institution_list').empty();
+
+            $.each(names, function(index, name) {
+                list.append($('<option>').attr('value', name));
+            });
+        });
+    });
+});
@@ -0,0 +1,24 @@
+// Updates the admin pages as soon as a new registration comes in
+
+$(function() {
+    if (!window.EventSource) {
+        return;
+    }
+
+    var source = new EventSource('/admin/events');
+
+    source.addEventListener('registration', function(event) {
+        var registration = JSON.parse(event.data);
+
+        $('#total').text(registration.total);
+
+        // New participants are shown at the top until the page is reloaded
+        var row = $('<tr>')
+            .append($('<td>').text(registration.name))
+            .append($('<td>').text(registration.pronouns))
+            .append($('<td>').text(registration.institution))
+            .append($('<td>').text('Neu'));
+
+        $('#participants tr').first().after(row);
+    });
+});
@@ -0,0 +1,13 @@
+// Title, authors and abstract are only asked for if the participant presents something
+
+$(function() {
+    function updatePresentationDetails() {
+        var presenting = $('#presentation').val() !== 'none';
+
+        $('#presentation_details').prop('hidden', !presenting);
+        $('#presentation_title, #presentation_authors, #abstract_text').prop('required', presenting);
+    }
+
+    $('#presentation').change(updatePresentationDetails);
+    updatePresentationDetails();
+});
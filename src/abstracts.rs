@@ -0,0 +1,434 @@
+use std::collections::BTreeMap;
+
+use iron::prelude::{Request, IronResult, Response, Set};
+use iron::modifiers::RedirectRaw;
+use iron::status;
+
+use handlebars_iron::{Template};
+use serde_json::Value as JsonValue;
+use params::Params;
+use plugin::Pluggable;
+use persistent::{Read, Write};
+
+use ::DBConnection;
+use admin::with_admin;
+use config::{Configuration, Session};
+use database::{lock_connection, load_abstracts, load_all_abstracts, assign_session, Abstract};
+use export::attachment_response;
+use handler::{HandleError, extract_string, EQUIPMENT_OPTIONS, router_param, not_found};
+use status::status_label;
+
+
+fn presentation_label(presentation: &str) -> &'static str {
+    match presentation {
+        "talk" => "Vortrag",
+        "poster" => "Poster",
+        _ => "-"
+    }
+}
+
+fn equipment_labels(equipment: &str) -> String {
+    let labels: Vec<&str> = EQUIPMENT_OPTIONS.iter().filter(|&&(key, _)| equipment.split(',').any(|option| option == key)).map(|&(_, label)| label).collect();
+    labels.join(", ")
+}
+
+pub fn handle_admin_abstracts(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "admin_abstracts", abstracts_page)
+}
+
+// For the topic filters of the presentation report and the poster gallery
+pub fn topic_options(topics: &[String], selected: &str) -> JsonValue {
+    json!(topics.iter().map(|topic| json!({
+        "name": topic,
+        "selected": topic == selected
+    })).collect::<Vec<JsonValue>>())
+}
+
+fn abstracts_page(req: &mut Request) -> Result<Response, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    let map = req.get::<Params>()?;
+    let filter = extract_string(&map, "topic").unwrap_or_default();
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let abstracts: Vec<JsonValue> = load_all_abstracts(&db_connection)?.iter()
+        .filter(|abs| filter.is_empty() || abs.topics.contains(&filter)).map(|abs| {
+        let sessions: Vec<JsonValue> = config.sessions.iter().map(|session| json!({
+            "key": session.key,
+            "name": session.name,
+            "selected": session.key == abs.session
+        })).collect();
+
+        json!({
+            "id": abs.id,
+            "presenter": abs.presenter(),
+            "status": status_label(&abs.status),
+            "presentation": presentation_label(&abs.presentation),
+            "title": abs.title,
+            "authors": abs.authors.join(", "),
+            "topics": abs.topics.join(", "),
+            "sessions": sessions,
+            "position": abs.position,
+            "file_name": abs.file_name,
+            "talk_duration": if abs.talk_duration > 0 { format!("{} min", abs.talk_duration) } else { String::new() },
+            "talk_equipment": equipment_labels(&abs.talk_equipment),
+            "unavailable_days": abs.unavailable_days
+        })
+    }).collect();
+
+    let sessions: Vec<JsonValue> = config.sessions.iter().map(|session| json!({
+        "key": session.key,
+        "name": session.name
+    })).collect();
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("abstracts".to_string(), json!(abstracts));
+    data.insert("sessions".to_string(), json!(sessions));
+    data.insert("topics".to_string(), topic_options(&config.topics, &filter));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("admin_abstracts", data)).set_mut(status::Ok);
+    Ok(resp)
+}
+
+pub fn handle_admin_abstract_session(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "admin_abstract_session", assign)
+}
+
+fn assign(req: &mut Request) -> Result<Response, HandleError> {
+    let id = match router_param(req, "id").parse::<i64>() {
+        Ok(id) => id,
+        Err(_) => return Ok(not_found())
+    };
+
+    let config = req.get::<Read<Configuration>>()?;
+    let map = req.get::<Params>()?;
+
+    // An empty session takes the presentation out of the program again
+    let session = extract_string(&map, "session").unwrap_or_default();
+    if !session.is_empty() && !config.sessions.iter().any(|s| s.key == session) {
+        return Err(HandleError::Validation("session".to_string()));
+    }
+
+    let position = extract_string(&map, "position").ok().and_then(|position| position.parse::<i64>().ok()).unwrap_or(0);
+
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    if !assign_session(&db_connection, id, &session, position)? {
+        return Ok(not_found());
+    }
+
+    info!("Abstract {}: assigned to session '{}' at position {}", id, session, position);
+
+    Ok(Response::with((status::Found, RedirectRaw("/admin/abstracts".to_string()))))
+}
+
+const DEFAULT_SESSION_SIZE: usize = 6;
+
+#[derive(Debug, PartialEq)]
+struct SessionSuggestion {
+    presentation: String,
+    topic: String,
+    ids: Vec<i64>
+}
+
+// Greedy first cut for the program committee: every abstract without a session goes to its most common topic,
+// then the abstracts of each presentation type and topic are split into evenly sized sessions of at most max_size
+fn suggest_sessions(topics: &[String], abstracts: &[Abstract], max_size: usize) -> Vec<SessionSuggestion> {
+    let open: Vec<&Abstract> = abstracts.iter().filter(|abs| abs.session.is_empty()).collect();
+    let frequency = |topic: &String| open.iter().filter(|abs| abs.topics.contains(topic)).count();
+
+    // Most common topics first, ties in the configured order
+    let mut ranked: Vec<&String> = topics.iter().collect();
+    ranked.sort_by_key(|topic| ::std::cmp::Reverse(frequency(topic)));
+
+    let mut result = Vec::new();
+
+    for presentation in &["talk", "poster"] {
+        let mut clusters: Vec<(String, Vec<i64>)> = ranked.iter().map(|topic| (topic.to_string(), Vec::new())).collect();
+        let mut unclassified = Vec::new();
+
+        for abs in open.iter().filter(|abs| abs.presentation == *presentation) {
+            match clusters.iter_mut().find(|&&mut (ref topic, _)| abs.topics.contains(topic)) {
+                Some(&mut (_, ref mut ids)) => ids.push(abs.id),
+                None => unclassified.push(abs.id)
+            }
+        }
+
+        clusters.push(("Ohne Thema".to_string(), unclassified));
+
+        for (topic, ids) in clusters {
+            if ids.is_empty() {
+                continue;
+            }
+
+            let sessions = ids.len().div_ceil(max_size);
+            let size = ids.len().div_ceil(sessions);
+
+            for chunk in ids.chunks(size) {
+                result.push(SessionSuggestion {
+                    presentation: presentation.to_string(),
+                    topic: topic.clone(),
+                    ids: chunk.to_vec()
+                });
+            }
+        }
+    }
+
+    result
+}
+
+pub fn handle_session_suggestions(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "session_suggestions", session_suggestions_page)
+}
+
+fn session_suggestions_page(req: &mut Request) -> Result<Response, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    let map = req.get::<Params>()?;
+    let max_size = match extract_string(&map, "size").ok().and_then(|size| size.parse::<usize>().ok()) {
+        Some(size) if size > 0 => size,
+        _ => DEFAULT_SESSION_SIZE
+    };
+
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let abstracts = load_abstracts(&db_connection)?;
+
+    let suggestions: Vec<JsonValue> = suggest_sessions(&config.topics, &abstracts, max_size).iter().map(|suggestion| {
+        let entries: Vec<JsonValue> = suggestion.ids.iter()
+            .filter_map(|id| abstracts.iter().find(|abs| abs.id == *id))
+            .map(|abs| json!({
+                "presenter": abs.presenter(),
+                "title": abs.title,
+                "topics": abs.topics.join(", "),
+                "unavailable_days": abs.unavailable_days
+            })).collect();
+
+        json!({
+            "presentation": presentation_label(&suggestion.presentation),
+            "topic": suggestion.topic,
+            "abstracts": entries
+        })
+    }).collect();
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("suggestions".to_string(), json!(suggestions));
+    data.insert("size".to_string(), json!(max_size));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("session_suggestions", data)).set_mut(status::Ok);
+    Ok(resp)
+}
+
+pub fn handle_export_abstracts(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "export_abstracts", export_abstracts)
+}
+
+fn export_abstracts(req: &mut Request) -> Result<Response, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let body = book_of_abstracts(&config.sessions, &load_abstracts(&db_connection)?);
+
+    info!("Book of abstracts exported");
+
+    Ok(attachment_response("abstracts.tex", "application/x-tex; charset=utf-8", body.into_bytes()))
+}
+
+pub fn latex_escape(text: &str) -> String {
+    let mut result = String::new();
+
+    for c in text.chars() {
+        match c {
+            '\\' => result.push_str("\\textbackslash{}"),
+            '~' => result.push_str("\\textasciitilde{}"),
+            '^' => result.push_str("\\textasciicircum{}"),
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                result.push('\\');
+                result.push(c);
+            }
+            _ => result.push(c)
+        }
+    }
+
+    result
+}
+
+// "Alice Brown" is sorted as "Brown, Alice"
+fn index_name(author: &str) -> String {
+    match author.rfind(' ') {
+        Some(pos) => format!("{}, {}", &author[pos + 1..], &author[..pos]),
+        None => author.to_string()
+    }
+}
+
+fn latex_abstract(abs: &Abstract) -> String {
+    format!("\\subsection*{{{}}}\n\\label{{abstract:{}}}\n\\textit{{{}}} ({})\n\n{}\n\n",
+        latex_escape(&abs.title), abs.id, latex_escape(&abs.authors.join(", ")),
+        presentation_label(&abs.presentation), latex_escape(&abs.text))
+}
+
+// One section per session in program order, abstracts without a session at the end,
+// followed by an index of all authors referring to the pages of their abstracts
+fn book_of_abstracts(sessions: &[Session], abstracts: &[Abstract]) -> String {
+    let mut result = "\\documentclass{article}\n\\usepackage[utf8]{inputenc}\n\\usepackage[T1]{fontenc}\n\n\\begin{document}\n\n".to_string();
+
+    for session in sessions {
+        result.push_str(&format!("\\section*{{{}}}\n{} {} - {} {}\n\n", latex_escape(&session.name),
+            session.day, session.start, session.end, latex_escape(&session.room)));
+
+        for abs in abstracts.iter().filter(|abs| abs.session == session.key) {
+            result.push_str(&latex_abstract(abs));
+        }
+    }
+
+    let unassigned: Vec<&Abstract> = abstracts.iter().filter(|abs| !sessions.iter().any(|session| session.key == abs.session)).collect();
+
+    if !unassigned.is_empty() {
+        result.push_str("\\section*{Weitere Beiträge}\n\n");
+
+        for abs in unassigned {
+            result.push_str(&latex_abstract(abs));
+        }
+    }
+
+    let mut index: BTreeMap<String, Vec<i64>> = BTreeMap::new();
+
+    for abs in abstracts {
+        for author in &abs.authors {
+            index.entry(index_name(author)).or_default().push(abs.id);
+        }
+    }
+
+    result.push_str("\\section*{Autorenverzeichnis}\n\n\\begin{description}\n");
+
+    for (author, ids) in &index {
+        let pages: Vec<String> = ids.iter().map(|id| format!("\\pageref{{abstract:{}}}", id)).collect();
+        result.push_str(&format!("\\item[{}] {}\n", latex_escape(author), pages.join(", ")));
+    }
+
+    result.push_str("\\end{description}\n\n\\end{document}\n");
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{latex_escape, index_name, book_of_abstracts, equipment_labels, suggest_sessions};
+    use config::Session;
+    use database::Abstract;
+
+    #[test]
+    fn test_latex_escape() {
+        assert_eq!(latex_escape("Plate tectonics"), "Plate tectonics".to_string());
+        assert_eq!(latex_escape("50% of H_2O & CO_2"), "50\\% of H\\_2O \\& CO\\_2".to_string());
+        assert_eq!(latex_escape("\\{x^2~y}"), "\\textbackslash{}\\{x\\textasciicircum{}2\\textasciitilde{}y\\}".to_string());
+    }
+
+    #[test]
+    fn test_suggest_sessions() {
+        let topics = vec!["Geodynamics".to_string(), "Seismology".to_string()];
+
+        let create_abstract = |id: i64, presentation: &str, session: &str, topics: &[&str]| Abstract {
+            id,
+            first_name: "Alice".to_string(),
+            last_name: "Brown".to_string(),
+            presentation: presentation.to_string(),
+            title: format!("Title {}", id),
+            authors: vec!["Alice Brown".to_string()],
+            text: "Text".to_string(),
+            session: session.to_string(),
+            position: 0,
+            file: "".to_string(),
+            file_name: "".to_string(),
+            talk_duration: 0,
+            talk_equipment: "".to_string(),
+            unavailable_days: "".to_string(),
+            status: "confirmed".to_string(),
+
+            topics: topics.iter().map(|topic| topic.to_string()).collect()
+        };
+
+        let abstracts = vec![
+            create_abstract(1, "talk", "", &["Seismology"]),
+            create_abstract(2, "talk", "", &["Geodynamics", "Seismology"]),
+            create_abstract(3, "talk", "", &["Seismology"]),
+            create_abstract(4, "talk", "geo1", &["Geodynamics"]),
+            create_abstract(5, "poster", "", &["Geodynamics"]),
+            create_abstract(6, "talk", "", &[]),
+            create_abstract(7, "talk", "", &["Seismology"]),
+            create_abstract(8, "talk", "", &["Seismology"])
+        ];
+
+        let suggestions = suggest_sessions(&topics, &abstracts, 3);
+
+        // Seismology is the most common topic, so abstract 2 goes there too, 6 abstracts give two sessions of 3
+        assert_eq!(suggestions.len(), 4);
+        assert_eq!((suggestions[0].topic.as_str(), suggestions[0].ids.clone()), ("Seismology", vec![1, 2, 3]));
+        assert_eq!((suggestions[1].topic.as_str(), suggestions[1].ids.clone()), ("Seismology", vec![7, 8]));
+        assert_eq!((suggestions[2].topic.as_str(), suggestions[2].ids.clone()), ("Ohne Thema", vec![6]));
+        assert_eq!(suggestions[3].presentation, "poster".to_string());
+        assert_eq!(suggestions[3].ids, vec![5]);
+    }
+
+    #[test]
+    fn test_equipment_labels() {
+        assert_eq!(equipment_labels("audio,whiteboard"), "Tonwiedergabe, Tafel / Whiteboard".to_string());
+        assert_eq!(equipment_labels(""), "".to_string());
+    }
+
+    #[test]
+    fn test_index_name() {
+        assert_eq!(index_name("Alice Brown"), "Brown, Alice".to_string());
+        assert_eq!(index_name("Anna Maria Miller"), "Miller, Anna Maria".to_string());
+        assert_eq!(index_name("Plato"), "Plato".to_string());
+    }
+
+    #[test]
+    fn test_book_of_abstracts() {
+        let sessions = vec![Session {
+            key: "geo1".to_string(),
+            name: "Geodynamics".to_string(),
+            day: "2017-03-01".to_string(),
+            start: "09:00".to_string(),
+            end: "10:30".to_string(),
+            room: "Lecture hall 1".to_string()
+        }];
+
+        let create_abstract = |id: i64, session: &str, authors: &[&str]| Abstract {
+            id,
+            first_name: "Test".to_string(),
+            last_name: "Smith".to_string(),
+            presentation: "talk".to_string(),
+            title: format!("Title {}", id),
+            authors: authors.iter().map(|author| author.to_string()).collect(),
+            text: "Text".to_string(),
+            session: session.to_string(),
+            position: 0,
+            file: "".to_string(),
+            file_name: "".to_string(),
+            talk_duration: 0,
+            talk_equipment: "".to_string(),
+            unavailable_days: "".to_string(),
+            status: "confirmed".to_string(),
+
+            topics: Vec::new()
+        };
+
+        let abstracts = vec![create_abstract(1, "", &["Bob Smith"]), create_abstract(2, "geo1", &["Alice Brown", "Bob Smith"])];
+        let book = book_of_abstracts(&sessions, &abstracts);
+
+        let session = book.find("\\section*{Geodynamics}").unwrap();
+        let title2 = book.find("\\subsection*{Title 2}").unwrap();
+        let unassigned = book.find("\\section*{Weitere Beiträge}").unwrap();
+        let title1 = book.find("\\subsection*{Title 1}").unwrap();
+        let index = book.find("\\section*{Autorenverzeichnis}").unwrap();
+
+        assert!(session < title2 && title2 < unassigned && unassigned < title1 && title1 < index);
+        assert!(book.contains("\\item[Brown, Alice] \\pageref{abstract:2}\n\\item[Smith, Bob] \\pageref{abstract:1}, \\pageref{abstract:2}\n"));
+        assert!(book.ends_with("\\end{document}\n"));
+    }
+}
@@ -0,0 +1,129 @@
+use iron::prelude::{Request, IronResult, Response};
+
+use plugin::Pluggable;
+use persistent::{Read, Write};
+use chrono::NaiveDateTime;
+
+use ::DBConnection;
+use admin::with_admin;
+use config::Configuration;
+use database::{lock_connection, load_payments, Payment};
+use export::{export_response, Table};
+use handler::HandleError;
+
+
+// The fees include the VAT, rounded to the nearest cent
+fn vat_amount(gross: i64, vat_rate: i64) -> i64 {
+    (gross * vat_rate * 2 + 100 + vat_rate) / (2 * (100 + vat_rate))
+}
+
+fn format_decimal(cents: i64, decimal_separator: &str) -> String {
+    format!("{}{}{:02}", cents / 100, decimal_separator, cents % 100)
+}
+
+fn format_date(timestamp: &str, date_format: &str) -> String {
+    match NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S") {
+        Ok(time) => time.format(date_format).to_string(),
+        Err(_) => timestamp.to_string()
+    }
+}
+
+// Also the registration code the participants see, e.g. on the thank-you page of the conference website
+pub fn invoice_number(config: &Configuration, id: i64) -> String {
+    format!("{}{:05}", config.invoice_prefix, id)
+}
+
+fn column_value(column: &str, payment: &Payment, config: &Configuration) -> String {
+    let vat = vat_amount(payment.fee, config.vat_rate);
+
+    match column {
+        "invoice" => invoice_number(config, payment.id),
+        "date" => format_date(&payment.paid_at, &config.accounting_date_format),
+        "name" => format!("{} {}", payment.first_name, payment.last_name),
+        "institution" => payment.institution.clone(),
+        "amount" => format_decimal(payment.fee, &config.accounting_decimal_separator),
+        "net" => format_decimal(payment.fee - vat, &config.accounting_decimal_separator),
+        "vat" => format_decimal(vat, &config.accounting_decimal_separator),
+        "vat_rate" => config.vat_rate.to_string(),
+        "method" => payment.payment_method.clone(),
+        _ => String::new()
+    }
+}
+
+// Columns, headers and number formats come from [Accounting], so that the file can be imported without re-keying
+fn payments_table(payments: &[Payment], config: &Configuration) -> Table {
+    let headers: Vec<&str> = config.accounting_columns.iter().map(|(_, header)| header.as_str()).collect();
+    let mut table = Table::new("payments", &headers);
+    table.separator = config.accounting_separator.chars().next().unwrap_or(',');
+
+    for payment in payments {
+        table.rows.push(config.accounting_columns.iter().map(|(column, _)| column_value(column, payment, config)).collect());
+    }
+
+    table
+}
+
+pub fn handle_export_payments(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "export_payments", export_payments)
+}
+
+fn export_payments(req: &mut Request) -> Result<Response, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let table = payments_table(&load_payments(&db_connection)?, &config);
+    export_response(req, &table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{vat_amount, format_decimal, format_date, payments_table};
+    use config::example_configuration;
+    use database::Payment;
+
+    #[test]
+    fn test_vat_amount() {
+        assert_eq!(vat_amount(11900, 19), 1900);
+        assert_eq!(vat_amount(12050, 19), 1924);
+        assert_eq!(vat_amount(10700, 7), 700);
+        assert_eq!(vat_amount(5000, 0), 0);
+    }
+
+    #[test]
+    fn test_formats() {
+        assert_eq!(format_decimal(12050, ","), "120,50".to_string());
+        assert_eq!(format_decimal(5, "."), "0.05".to_string());
+        assert_eq!(format_date("2017-02-24 10:00:00", "%d.%m.%Y"), "24.02.2017".to_string());
+        assert_eq!(format_date("", "%d.%m.%Y"), "".to_string());
+    }
+
+    #[test]
+    fn test_payments_csv() {
+        let payment = Payment {
+            id: 42,
+            first_name: "Bob".to_string(),
+            last_name: "Smith".to_string(),
+            institution: "Uni; Tübingen".to_string(),
+            fee: 11900,
+            paid_at: "2017-02-24 10:00:00".to_string(),
+            payment_method: "transfer".to_string()
+        };
+
+        let mut config = example_configuration();
+        assert_eq!(payments_table(&[payment.clone()], &config).to_csv(),
+            "invoice,date,name,institution,amount,net,vat,vat_rate,method\r\n\
+             00042,2017-02-24,Bob Smith,Uni; Tübingen,119.00,119.00,0.00,0,transfer\r\n".to_string());
+
+        config.accounting_columns = vec![("invoice".to_string(), "Belegnummer".to_string()), ("institution".to_string(), "Institut".to_string()),
+            ("date".to_string(), "Datum".to_string()), ("net".to_string(), "Netto".to_string()), ("vat".to_string(), "MwSt".to_string())];
+        config.accounting_separator = ";".to_string();
+        config.accounting_decimal_separator = ",".to_string();
+        config.accounting_date_format = "%d.%m.%Y".to_string();
+        config.invoice_prefix = "TAG2017-".to_string();
+        config.vat_rate = 19;
+
+        assert_eq!(payments_table(&[payment], &config).to_csv(),
+            "Belegnummer;Institut;Datum;Netto;MwSt\r\nTAG2017-00042;\"Uni; Tübingen\";24.02.2017;100,00;19,00\r\n".to_string());
+    }
+}
@@ -0,0 +1,839 @@
+use std::collections::BTreeMap;
+
+use iron::prelude::{Request, IronResult, Response, Set};
+use iron::headers::{Authorization, Basic};
+use iron::method::Method;
+use iron::status;
+
+use handlebars_iron::{Template};
+use plugin::Pluggable;
+use persistent::{Read, Write};
+use serde_json::Value as JsonValue;
+use chrono::{NaiveDate, NaiveDateTime, Timelike};
+use rusqlite::Connection;
+
+use ::{DBConnection, AdminCache};
+use cache::cached;
+use config::Configuration;
+use countries::find_country;
+use database::{lock_connection, count_registrations, count_by_status, count_by_country, count_by_day, count_by_tshirt_size, count_social_bookings,
+    count_banquet_guests, load_participants, load_emergency_contacts,
+    load_arrivals, Arrival, load_child_care_requests, ChildCareRequest,
+    load_accessibility_needs, AccessibilityNeed, load_media_refusals,
+    load_room_requests, RoomRequest};
+use export::{export_response, Table};
+#[cfg(feature = "pdf")]
+use export::attachment_response;
+use fee::format_amount;
+use institutions::load_institution_counts;
+use filter::RegistrationFilter;
+use config::SocialEvent;
+use handler::{HandleError, ACCESSIBILITY_OPTIONS};
+use remember::{remembered_admin, set_token_cookie, login_redirect, Remembered, REMEMBER_SECONDS};
+use status::{RegistrationStatus, ALL_STATUSES, status_label};
+use api::json_response;
+#[cfg(feature = "pdf")]
+use pdf::Document;
+
+
+// Compares in constant time so that the password can't be guessed character by character
+pub fn secure_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+pub fn is_admin(req: &Request, config: &Configuration) -> bool {
+    if config.admin_password.is_empty() {
+        return false;
+    }
+
+    match req.headers.get::<Authorization<Basic>>() {
+        Some(&Authorization(Basic { ref username, password: Some(ref password) })) =>
+            secure_eq(username, &config.admin_username) & secure_eq(password, &config.admin_password),
+        _ => false
+    }
+}
+
+pub fn unauthorized() -> Response {
+    let mut resp = Response::with((status::Unauthorized, "Unauthorized"));
+    resp.headers.set_raw("WWW-Authenticate", vec![b"Basic realm=\"Registration admin\"".to_vec()]);
+    resp
+}
+
+// Runs the given admin page only if the request carries the configured credentials
+// or the token of a device the admin stays logged in on, see remember.rs
+pub fn with_admin<F>(req: &mut Request, page: &str, f: F) -> IronResult<Response>
+    where F: FnOnce(&mut Request) -> Result<Response, HandleError> {
+
+    let config = match req.get::<Read<Configuration>>() {
+        Ok(config) => config,
+        Err(_) => return Ok(unauthorized())
+    };
+
+    let by_password = is_admin(req, &config);
+    let remembered = if by_password || config.admin_password.is_empty() {
+        Remembered::No
+    } else {
+        remembered_admin(req).unwrap_or_else(|e| {
+            error!("Could not check the admin token: {:?}", e);
+            Remembered::No
+        })
+    };
+
+    if !by_password && remembered == Remembered::No {
+        info!("Unauthorized access to admin page '{}'", page);
+        // A browser gets the login form and comes back to the page, everything else the basic auth challenge
+        if req.method == Method::Get && !config.admin_password.is_empty() && req.headers.get::<Authorization<Basic>>().is_none() {
+            return Ok(login_redirect(req));
+        }
+        return Ok(unauthorized());
+    }
+
+    match f(req) {
+        Ok(mut resp) => {
+            if let Remembered::Rotated(token) = remembered {
+                set_token_cookie(&mut resp, &token, REMEMBER_SECONDS, &config);
+            }
+            Ok(resp)
+        }
+        Err(e) => {
+            error!("Error in admin page '{}': {:?}", page, e);
+            Ok(Response::with((status::InternalServerError, "Internal server error")))
+        }
+    }
+}
+
+pub fn handle_stats(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "stats", stats_page)
+}
+
+fn stats_page(req: &mut Request) -> Result<Response, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    let cache = req.get::<Write<AdminCache>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let data = cached(&cache, &db_connection, "stats", |db_connection| stats_data(db_connection, &config))?;
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("stats", data)).set_mut(status::Ok);
+    Ok(resp)
+}
+
+fn stats_data(db_connection: &Connection, config: &Configuration) -> Result<JsonValue, HandleError> {
+    let countries: Vec<JsonValue> = count_by_country(db_connection)?.into_iter().map(|(code, count)| {
+        let name = match find_country(&code) {
+            Some(country) => country.name(config.language),
+            None => "-"
+        };
+
+        json!({ "code": code, "name": name, "count": count })
+    }).collect();
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("total".to_string(), json!(count_registrations(db_connection)?));
+    data.insert("statuses".to_string(), json!(status_report(&count_by_status(db_connection)?)));
+    data.insert("countries".to_string(), json!(countries));
+    data.insert("institutions".to_string(), json!(load_institution_counts(db_connection)?.into_iter()
+        .map(|count| json!({ "name": count.name, "curated": count.curated, "count": count.count })).collect::<Vec<JsonValue>>()));
+
+    let series = daily_registrations(&count_by_day(db_connection)?);
+    if series.len() > 1 {
+        data.insert("chart".to_string(), json!({
+            "points": chart_points(&series, CHART_WIDTH, CHART_HEIGHT),
+            "width": CHART_WIDTH,
+            "height": CHART_HEIGHT,
+            "first_day": series[0].day,
+            "last_day": series[series.len() - 1].day
+        }));
+    }
+
+    if !config.tshirt_sizes.is_empty() {
+        let counts = count_by_tshirt_size(db_connection)?;
+        data.insert("tshirt_sizes".to_string(), json!(tshirt_size_report(&config.tshirt_sizes, &counts)));
+    }
+
+    if !config.social_events.is_empty() {
+        let counts = count_social_bookings(db_connection)?;
+        data.insert("social_events".to_string(), json!(social_event_report(&config.social_events, &counts)));
+    }
+
+    // Head count for the catering
+    if config.banquet_max_guests > 0 {
+        let participants = count_social_bookings(db_connection)?.into_iter()
+            .find(|(key, _)| *key == config.banquet_event).map_or(0, |(_, count)| count);
+        let guests = count_banquet_guests(db_connection)?;
+
+        data.insert("banquet".to_string(), json!({
+            "participants": participants,
+            "guests": guests,
+            "total": participants + guests,
+            "revenue": format_amount(guests * config.banquet_guest_price)
+        }));
+    }
+
+    Ok(json!(data))
+}
+
+const CHART_WIDTH: i64 = 600;
+const CHART_HEIGHT: i64 = 200;
+
+#[derive(Debug, PartialEq)]
+struct DailyRegistrations {
+    day: String,
+    count: i64,
+    // Including this day
+    total: i64
+}
+
+// Days without a registration are filled in, so that the curve can be compared day by day with earlier years
+fn daily_registrations(counts: &[(String, i64)]) -> Vec<DailyRegistrations> {
+    let mut result = Vec::new();
+    let mut total = 0;
+    let mut next_day: Option<NaiveDate> = None;
+
+    for &(ref day, count) in counts {
+        let date = match NaiveDate::parse_from_str(day, "%Y-%m-%d") {
+            Ok(date) => date,
+            Err(_) => continue
+        };
+
+        if let Some(mut missing) = next_day {
+            while missing < date {
+                result.push(DailyRegistrations { day: missing.format("%Y-%m-%d").to_string(), count: 0, total });
+                missing = missing.succ();
+            }
+        }
+
+        total += count;
+        result.push(DailyRegistrations { day: day.clone(), count, total });
+        next_day = Some(date.succ());
+    }
+
+    result
+}
+
+// Polyline of the running total, scaled to the given size with the origin at the bottom left
+fn chart_points(series: &[DailyRegistrations], width: i64, height: i64) -> String {
+    let max_total = series.last().map_or(0, |last| last.total).max(1);
+    let steps = (series.len() as i64 - 1).max(1);
+
+    let points: Vec<String> = series.iter().enumerate().map(|(i, entry)| {
+        format!("{},{}", i as i64 * width / steps, height - entry.total * height / max_total)
+    }).collect();
+
+    points.join(" ")
+}
+
+pub fn handle_daily_registrations(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "daily_registrations", daily_registrations_json)
+}
+
+fn daily_registrations_json(req: &mut Request) -> Result<Response, HandleError> {
+    let cache = req.get::<Write<AdminCache>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let series = cached(&cache, &db_connection, "daily", |db_connection| {
+        let series: Vec<JsonValue> = daily_registrations(&count_by_day(db_connection)?).iter()
+            .map(|entry| json!({ "day": entry.day, "count": entry.count, "total": entry.total }))
+            .collect();
+        Ok(json!(series))
+    })?;
+
+    Ok(json_response(series))
+}
+
+// In the order of the configured sizes, including the ones nobody picked, for the T-shirt order
+fn tshirt_size_report(sizes: &[String], counts: &[(String, i64)]) -> Vec<JsonValue> {
+    sizes.iter().map(|size| {
+        let count = counts.iter().find(|&(s, _)| s == size).map_or(0, |&(_, count)| count);
+        json!({ "size": size, "count": count })
+    }).collect()
+}
+
+fn social_event_report(events: &[SocialEvent], counts: &[(String, i64)]) -> Vec<JsonValue> {
+    events.iter().map(|event| {
+        let count = counts.iter().find(|&(key, _)| *key == event.key).map_or(0, |&(_, count)| count);
+        json!({
+            "name": event.name,
+            "count": count,
+            "capacity": if event.capacity > 0 { event.capacity.to_string() } else { "-".to_string() },
+            "revenue": format_amount(count * event.price)
+        })
+    }).collect()
+}
+
+pub fn handle_participants(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "participants", participants_page)
+}
+
+// All statuses with their number, those without a registration too
+fn status_report(counts: &[(String, i64)]) -> Vec<JsonValue> {
+    ALL_STATUSES.iter().map(|status| {
+        let count = counts.iter().find(|&(name, _)| name == status.name()).map_or(0, |&(_, count)| count);
+        json!({ "name": status.name(), "label": status.label(), "count": count })
+    }).collect()
+}
+
+fn participants_page(req: &mut Request) -> Result<Response, HandleError> {
+    let filter = RegistrationFilter::from_request(req)?;
+    let cache = req.get::<Write<AdminCache>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let key = format!("participants_{}", filter.query_string());
+    let participants = cached(&cache, &db_connection, &key, |db_connection| {
+        let participants: Vec<JsonValue> = load_participants(db_connection, &filter)?.iter().map(|participant| json!({
+            "id": participant.id,
+            "name": participant.display_name(),
+            "pronouns": participant.pronouns,
+            "institution": participant.institution,
+            "email_undeliverable": participant.email_undeliverable,
+            "status": status_label(&participant.status),
+            "transitions": RegistrationStatus::from_name(&participant.status).map_or(Vec::new(), |status| status.transitions())
+                .iter().map(|next| json!({ "id": participant.id, "name": next.name(), "label": next.label() })).collect::<Vec<JsonValue>>()
+        })).collect();
+        Ok(json!(participants))
+    })?;
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("participants".to_string(), json!(participants));
+    data.insert("filter".to_string(), filter.form_json());
+    data.insert("pdf".to_string(), json!(cfg!(feature = "pdf")));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("participants", data)).set_mut(status::Ok);
+    Ok(resp)
+}
+
+// The same rows for the CSV export and the printed list
+fn participants_table(db_connection: &Connection, filter: &RegistrationFilter) -> Result<Table, HandleError> {
+    let mut table = Table::new("participants", &["name", "pronouns", "institution", "status"]);
+
+    for participant in load_participants(db_connection, filter)? {
+        table.push(&[&participant.display_name(), &participant.pronouns, &participant.institution, &participant.status]);
+    }
+
+    Ok(table)
+}
+
+pub fn handle_export_participants(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "export_participants", export_participants)
+}
+
+fn export_participants(req: &mut Request) -> Result<Response, HandleError> {
+    let filter = RegistrationFilter::from_request(req)?;
+    let table = {
+        let mutex = req.get::<Write<DBConnection>>()?;
+        let db_connection = lock_connection(&mutex)?;
+        participants_table(&db_connection, &filter)?
+    };
+
+    export_response(req, &table)
+}
+
+// Institutions in alphabetical order, participants without one at the end. The participants keep the order
+// of the query, by last name.
+#[cfg(feature = "pdf")]
+fn institution_groups(table: &Table) -> Vec<(String, Vec<String>)> {
+    let mut groups: Vec<(String, Vec<String>)> = Vec::new();
+
+    for row in &table.rows {
+        let institution = row[2].trim();
+        let name = if row[1].is_empty() { row[0].clone() } else { format!("{} ({})", row[0], row[1]) };
+
+        match groups.iter().position(|(existing, _)| existing.to_lowercase() == institution.to_lowercase()) {
+            Some(index) => groups[index].1.push(name),
+            None => groups.push((institution.to_string(), vec![name]))
+        }
+    }
+
+    groups.sort_by_key(|(institution, _)| (institution.is_empty(), institution.to_lowercase()));
+    groups
+}
+
+// For the printed conference booklet, only the participants that take a place, from those the filter selects
+#[cfg(feature = "pdf")]
+pub fn handle_export_participants_pdf(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "export_participants_pdf", export_participants_pdf)
+}
+
+#[cfg(feature = "pdf")]
+fn export_participants_pdf(req: &mut Request) -> Result<Response, HandleError> {
+    let filter = RegistrationFilter::from_request(req)?;
+    let config = req.get::<Read<Configuration>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let mut table = participants_table(&db_connection, &filter)?;
+    table.rows.retain(|row| RegistrationStatus::from_name(&row[3]).is_some_and(|status| status.takes_place()));
+    let mut document = Document::new("Teilnehmerliste", &config.date_format.today());
+
+    for (institution, names) in institution_groups(&table) {
+        document.heading(if institution.is_empty() { "Ohne Institution" } else { &institution });
+        for name in names {
+            document.line(&name);
+        }
+    }
+
+    Ok(attachment_response("participants.pdf", "application/pdf", document.to_pdf()))
+}
+
+pub fn handle_arrivals(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "arrivals", arrivals_page)
+}
+
+fn arrivals_page(req: &mut Request) -> Result<Response, HandleError> {
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("windows".to_string(), json!(arrival_windows(&load_arrivals(&db_connection)?)));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("arrivals", data)).set_mut(status::Ok);
+    Ok(resp)
+}
+
+// One shuttle per window, so everybody landing between 08:00 and 10:00 ends up in the same group
+const ARRIVAL_WINDOW_HOURS: u32 = 2;
+
+fn arrival_window(time: &str) -> String {
+    match NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M") {
+        Ok(time) => {
+            let start = time.hour() - time.hour() % ARRIVAL_WINDOW_HOURS;
+            format!("{} {:02}:00 - {:02}:00", time.date().format("%Y-%m-%d"), start, start + ARRIVAL_WINDOW_HOURS)
+        }
+        Err(_) => time.to_string()
+    }
+}
+
+// The arrivals are already sorted by time, so the groups are consecutive
+fn arrival_windows(arrivals: &[Arrival]) -> Vec<JsonValue> {
+    let mut result: Vec<(String, Vec<JsonValue>, usize)> = Vec::new();
+
+    for arrival in arrivals {
+        let window = arrival_window(&arrival.arrival_time);
+        let person = json!({
+            "name": format!("{} {}", arrival.first_name, arrival.last_name),
+            "mobile": arrival.mobile,
+            "mode": arrival.arrival_mode,
+            "number": arrival.arrival_number,
+            "time": arrival.arrival_time,
+            "share_ride": arrival.share_ride
+        });
+
+        let new_window = result.last().is_none_or(|(w, _, _)| *w != window);
+        if new_window {
+            result.push((window, Vec::new(), 0));
+        }
+
+        let last = result.last_mut().unwrap();
+        last.1.push(person);
+        if arrival.share_ride {
+            last.2 += 1;
+        }
+    }
+
+    result.into_iter().map(|(window, people, share_ride)| json!({
+        "window": window,
+        "people": people,
+        "count": people.len(),
+        "share_ride": share_ride
+    })).collect()
+}
+
+pub fn handle_child_care(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "child_care", child_care_page)
+}
+
+fn child_care_page(req: &mut Request) -> Result<Response, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let requests = load_child_care_requests(&db_connection)?;
+
+    let families: Vec<JsonValue> = requests.iter().map(|request| json!({
+        "name": format!("{} {}", request.first_name, request.last_name),
+        "email": request.email_to,
+        "children": request.children,
+        "ages": request.children_ages,
+        "days": request.days.join(", ")
+    })).collect();
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("days".to_string(), json!(child_care_report(&config.child_care_days, &requests)));
+    data.insert("families".to_string(), json!(families));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("child_care", data)).set_mut(status::Ok);
+    Ok(resp)
+}
+
+// How many children have to be looked after on each of the configured days
+fn child_care_report(days: &[String], requests: &[ChildCareRequest]) -> Vec<JsonValue> {
+    days.iter().map(|day| {
+        let booked: Vec<&ChildCareRequest> = requests.iter().filter(|request| request.days.contains(day)).collect();
+        json!({
+            "day": day,
+            "families": booked.len(),
+            "children": booked.iter().map(|request| request.children).sum::<i64>()
+        })
+    }).collect()
+}
+
+pub fn handle_accessibility(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "accessibility", accessibility_page)
+}
+
+fn accessibility_page(req: &mut Request) -> Result<Response, HandleError> {
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let needs = load_accessibility_needs(&db_connection)?;
+
+    let people: Vec<JsonValue> = needs.iter().map(|need| json!({
+        "name": format!("{} {}", need.first_name, need.last_name),
+        "email": need.email_to,
+        "mobile": need.mobile,
+        "options": need.options.iter().map(|option| accessibility_label(option)).collect::<Vec<&str>>(),
+        "needs": need.needs
+    })).collect();
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("options".to_string(), json!(accessibility_report(&needs)));
+    data.insert("people".to_string(), json!(people));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("accessibility", data)).set_mut(status::Ok);
+    Ok(resp)
+}
+
+fn accessibility_label(option: &str) -> &str {
+    ACCESSIBILITY_OPTIONS.iter().find(|&&(key, _)| key == option).map_or(option, |&(_, label)| label)
+}
+
+// Per checkbox, so that interpreters and ramps can be booked in time
+fn accessibility_report(needs: &[AccessibilityNeed]) -> Vec<JsonValue> {
+    ACCESSIBILITY_OPTIONS.iter().map(|&(key, label)| json!({
+        "label": label,
+        "count": needs.iter().filter(|need| need.options.iter().any(|option| option == key)).count()
+    })).collect()
+}
+
+pub fn handle_rooms(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "rooms", rooms_page)
+}
+
+fn rooms_page(req: &mut Request) -> Result<Response, HandleError> {
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let requests = load_room_requests(&db_connection)?;
+    let (pairs, unmatched) = suggest_room_pairs(&requests);
+    let person = |index: usize| {
+        let request = &requests[index];
+        json!({
+            "name": format!("{} {}", request.first_name, request.last_name),
+            "email": request.email_normalized,
+            "roommate_email": request.roommate_email
+        })
+    };
+
+    let pairs: Vec<JsonValue> = pairs.into_iter().map(|(first, second, reason)| json!({
+        "first": person(first),
+        "second": person(second),
+        "reason": reason
+    })).collect();
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("pairs".to_string(), json!(pairs));
+    data.insert("unmatched".to_string(), json!(unmatched.into_iter().map(person).collect::<Vec<JsonValue>>()));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("rooms", data)).set_mut(status::Ok);
+    Ok(resp)
+}
+
+// Mutual wishes first, then one-sided wishes where the other person has no wish of their own,
+// everybody left is paired in order of registration. Only a suggestion, the organizers have the final word.
+fn suggest_room_pairs(requests: &[RoomRequest]) -> (Vec<(usize, usize, &'static str)>, Vec<usize>) {
+    let mut paired = vec![false; requests.len()];
+    let mut pairs = Vec::new();
+    let wished = |index: usize| -> Option<usize> {
+        let email = &requests[index].roommate_email;
+        if email.is_empty() {
+            None
+        } else {
+            requests.iter().position(|request| request.email_normalized == *email).filter(|&other| other != index)
+        }
+    };
+
+    for index in 0..requests.len() {
+        if let Some(other) = wished(index) {
+            if !paired[index] && !paired[other] && wished(other) == Some(index) {
+                paired[index] = true;
+                paired[other] = true;
+                pairs.push((index, other, "Gegenseitiger Wunsch"));
+            }
+        }
+    }
+
+    for index in 0..requests.len() {
+        if let Some(other) = wished(index) {
+            if !paired[index] && !paired[other] && requests[other].roommate_email.is_empty() {
+                paired[index] = true;
+                paired[other] = true;
+                pairs.push((index, other, "Einseitiger Wunsch"));
+            }
+        }
+    }
+
+    let mut rest: Vec<usize> = (0..requests.len()).filter(|&index| !paired[index]).collect();
+    let unmatched = if rest.len() % 2 == 1 { rest.pop() } else { None };
+
+    for chunk in rest.chunks(2) {
+        pairs.push((chunk[0], chunk[1], "Vorschlag"));
+    }
+
+    (pairs, unmatched.into_iter().collect())
+}
+
+// For the trip leaders only, this is the only export containing the emergency contacts
+pub fn handle_export_emergency(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "export_emergency", export_emergency)
+}
+
+fn export_emergency(req: &mut Request) -> Result<Response, HandleError> {
+    let filter = RegistrationFilter::from_request(req)?;
+    let config = req.get::<Read<Configuration>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let mut table = Table::new("emergency_contacts", &["course", "first_name", "last_name", "mobile", "emergency_name", "emergency_phone"]);
+
+    for contact in load_emergency_contacts(&db_connection, &filter)? {
+        let course = if contact.course_type == "course1" { &config.course1 } else { &config.course2 };
+        table.push(&[course, &contact.first_name, &contact.last_name, &contact.mobile,
+            &contact.emergency_name, &contact.emergency_phone]);
+    }
+
+    info!("Emergency contacts exported");
+
+    export_response(req, &table)
+}
+
+// For the photographer and the AV team: whom not to photograph or record
+pub fn handle_export_media_consent(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "export_media_consent", export_media_consent)
+}
+
+fn export_media_consent(req: &mut Request) -> Result<Response, HandleError> {
+    let filter = RegistrationFilter::from_request(req)?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let mut table = Table::new("media_consent", &["name", "institution", "photos", "recording"]);
+
+    for refusal in load_media_refusals(&db_connection, &filter)? {
+        table.push(&[&refusal.participant.display_name(), &refusal.participant.institution,
+            yes_no(refusal.photo_consent), yes_no(refusal.recording_consent)]);
+    }
+
+    export_response(req, &table)
+}
+
+pub fn yes_no(value: bool) -> &'static str {
+    if value { "yes" } else { "no" }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{secure_eq, daily_registrations, chart_points, DailyRegistrations, tshirt_size_report, social_event_report, arrival_window, arrival_windows, child_care_report, accessibility_report,
+        suggest_room_pairs, status_report};
+    #[cfg(feature = "pdf")]
+    use super::institution_groups;
+    #[cfg(feature = "pdf")]
+    use export::Table;
+    use database::{Arrival, ChildCareRequest, AccessibilityNeed, RoomRequest};
+    use config::SocialEvent;
+
+    #[test]
+    fn test_secure_eq() {
+        assert!(secure_eq("secret", "secret"));
+        assert!(!secure_eq("secret", "Secret"));
+        assert!(!secure_eq("secret", "secret2"));
+        assert!(!secure_eq("", "secret"));
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_institution_groups() {
+        let mut table = Table::new("participants", &["name", "pronouns", "institution"]);
+        table.push(&["Alice Brown", "", "University of Potsdam"]);
+        table.push(&["Eve Jones", "", ""]);
+        table.push(&["Carol Miller", "sie/ihr", "GFZ Potsdam"]);
+        table.push(&["Bob Smith", "he/him", "university of potsdam"]);
+
+        assert_eq!(institution_groups(&table), vec![
+            ("GFZ Potsdam".to_string(), vec!["Carol Miller (sie/ihr)".to_string()]),
+            ("University of Potsdam".to_string(), vec!["Alice Brown".to_string(), "Bob Smith (he/him)".to_string()]),
+            ("".to_string(), vec!["Eve Jones".to_string()])
+        ]);
+    }
+
+    #[test]
+    fn test_daily_registrations() {
+        let counts = vec![("2017-01-30".to_string(), 2), ("2017-02-02".to_string(), 3), ("2017-02-03".to_string(), 1)];
+        let series = daily_registrations(&counts);
+
+        assert_eq!(series.len(), 5);
+        assert_eq!(series[1], DailyRegistrations { day: "2017-01-31".to_string(), count: 0, total: 2 });
+        assert_eq!(series[2], DailyRegistrations { day: "2017-02-01".to_string(), count: 0, total: 2 });
+        assert_eq!(series[4], DailyRegistrations { day: "2017-02-03".to_string(), count: 1, total: 6 });
+
+        assert_eq!(chart_points(&series, 400, 60), "0,40 100,40 200,40 300,10 400,0".to_string());
+        assert!(daily_registrations(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_tshirt_size_report() {
+        let sizes = vec!["S".to_string(), "M".to_string(), "L".to_string()];
+        let counts = vec![("L".to_string(), 4), ("S".to_string(), 2)];
+
+        assert_eq!(tshirt_size_report(&sizes, &counts), vec![
+            json!({ "size": "S", "count": 2 }),
+            json!({ "size": "M", "count": 0 }),
+            json!({ "size": "L", "count": 4 })
+        ]);
+    }
+
+    #[test]
+    fn test_status_report() {
+        let counts = vec![("cancelled".to_string(), 1), ("confirmed".to_string(), 5)];
+
+        assert_eq!(status_report(&counts), vec![
+            json!({ "name": "pending", "label": "Offen", "count": 0 }),
+            json!({ "name": "confirmed", "label": "Bestätigt", "count": 5 }),
+            json!({ "name": "waitlisted", "label": "Warteliste", "count": 0 }),
+            json!({ "name": "cancelled", "label": "Storniert", "count": 1 }),
+            json!({ "name": "rejected", "label": "Abgelehnt", "count": 0 })
+        ]);
+    }
+
+    #[test]
+    fn test_social_event_report() {
+        let events = vec![
+            SocialEvent { key: "hike".to_string(), name: "Hike".to_string(), capacity: 20, price: 0 },
+            SocialEvent { key: "banquet".to_string(), name: "Banquet".to_string(), capacity: 0, price: 4500 }
+        ];
+        let counts = vec![("banquet".to_string(), 3)];
+
+        assert_eq!(social_event_report(&events, &counts), vec![
+            json!({ "name": "Hike", "count": 0, "capacity": "20", "revenue": "0,00 €" }),
+            json!({ "name": "Banquet", "count": 3, "capacity": "-", "revenue": "135,00 €" })
+        ]);
+    }
+
+    fn arrival(last_name: &str, time: &str, share_ride: bool) -> Arrival {
+        Arrival {
+            first_name: "Test".to_string(),
+            last_name: last_name.to_string(),
+            mobile: "".to_string(),
+            arrival_mode: "plane".to_string(),
+            arrival_number: "".to_string(),
+            arrival_time: time.to_string(),
+            share_ride
+        }
+    }
+
+    #[test]
+    fn test_arrival_window() {
+        assert_eq!(arrival_window("2017-03-28 08:00"), "2017-03-28 08:00 - 10:00");
+        assert_eq!(arrival_window("2017-03-28 09:59"), "2017-03-28 08:00 - 10:00");
+        assert_eq!(arrival_window("2017-03-28 23:15"), "2017-03-28 22:00 - 24:00");
+    }
+
+    #[test]
+    fn test_arrival_windows() {
+        let arrivals = vec![
+            arrival("A", "2017-03-28 08:10", true),
+            arrival("B", "2017-03-28 09:45", false),
+            arrival("C", "2017-03-28 10:05", true)
+        ];
+
+        let windows = arrival_windows(&arrivals);
+
+        assert_eq!(windows.len(), 2);
+        assert_eq!(windows[0]["window"], "2017-03-28 08:00 - 10:00");
+        assert_eq!(windows[0]["count"], 2);
+        assert_eq!(windows[0]["share_ride"], 1);
+        assert_eq!(windows[1]["window"], "2017-03-28 10:00 - 12:00");
+        assert_eq!(windows[1]["people"][0]["name"], "Test C");
+    }
+
+    #[test]
+    fn test_child_care_report() {
+        let days = vec!["Monday".to_string(), "Tuesday".to_string(), "Wednesday".to_string()];
+        let request = |children, days: &[&str]| ChildCareRequest {
+            first_name: "Test".to_string(),
+            last_name: "Test".to_string(),
+            email_to: "test@example.org".to_string(),
+            children,
+            children_ages: "".to_string(),
+            days: days.iter().map(|day| day.to_string()).collect()
+        };
+        let requests = vec![request(2, &["Monday", "Tuesday"]), request(1, &["Tuesday"])];
+
+        assert_eq!(child_care_report(&days, &requests), vec![
+            json!({ "day": "Monday", "families": 1, "children": 2 }),
+            json!({ "day": "Tuesday", "families": 2, "children": 3 }),
+            json!({ "day": "Wednesday", "families": 0, "children": 0 })
+        ]);
+    }
+
+    #[test]
+    fn test_accessibility_report() {
+        let need = |options: &[&str]| AccessibilityNeed {
+            first_name: "Test".to_string(),
+            last_name: "Test".to_string(),
+            email_to: "test@example.org".to_string(),
+            mobile: "".to_string(),
+            options: options.iter().map(|option| option.to_string()).collect(),
+            needs: "".to_string()
+        };
+        let needs = vec![need(&["step_free", "sign_language"]), need(&["step_free"]), need(&[])];
+
+        let report = accessibility_report(&needs);
+
+        assert_eq!(report.len(), 3);
+        assert_eq!(report[0]["count"], 2);
+        assert_eq!(report[1]["count"], 1);
+        assert_eq!(report[2]["count"], 0);
+    }
+
+    #[test]
+    fn test_suggest_room_pairs() {
+        let request = |name: &str, roommate: &str| RoomRequest {
+            first_name: "Test".to_string(),
+            last_name: name.to_string(),
+            email_normalized: format!("{}@example.org", name),
+            roommate_email: if roommate.is_empty() { "".to_string() } else { format!("{}@example.org", roommate) }
+        };
+        let requests = vec![
+            request("a", "c"),
+            request("b", ""),
+            request("c", "a"),
+            request("d", "b"),
+            request("e", "c"),
+            request("f", ""),
+            request("g", "")
+        ];
+
+        let (pairs, unmatched) = suggest_room_pairs(&requests);
+
+        assert_eq!(pairs, vec![(0, 2, "Gegenseitiger Wunsch"), (3, 1, "Einseitiger Wunsch"), (4, 5, "Vorschlag")]);
+        assert_eq!(unmatched, vec![6]);
+    }
+}
@@ -0,0 +1,172 @@
+use std::cell::RefCell;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use iron::prelude::{Request, IronResult, Response};
+use iron::middleware::{AroundMiddleware, Handler};
+
+use log::{Log, LogLevel, LogLevelFilter, LogMetadata, LogRecord};
+use simplelog::{SharedLogger, Config};
+use chrono::Local;
+
+use config::Configuration;
+use handler::send_alert;
+
+
+static REQUEST_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    // The request the current worker thread is handling, None in the background tasks
+    static CURRENT_REQUEST: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Numbers the requests, so that an alert mail names the one that failed. The query is left out,
+/// it may contain the token of a personal link.
+pub struct RequestIds;
+
+struct RequestIdHandler {
+    handler: Box<dyn Handler>
+}
+
+impl Handler for RequestIdHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let id = REQUEST_COUNTER.fetch_add(1, Ordering::SeqCst) + 1;
+        let label = format!("#{} {} /{}", id, req.method, req.url.path().join("/"));
+
+        CURRENT_REQUEST.with(|current| *current.borrow_mut() = Some(label));
+        let result = self.handler.handle(req);
+        CURRENT_REQUEST.with(|current| *current.borrow_mut() = None);
+
+        result
+    }
+}
+
+impl AroundMiddleware for RequestIds {
+    fn around(self, handler: Box<dyn Handler>) -> Box<dyn Handler> {
+        Box::new(RequestIdHandler { handler })
+    }
+}
+
+/// Like "#12 POST /submit", None outside of a request
+pub fn current_request() -> Option<String> {
+    CURRENT_REQUEST.with(|current| current.borrow().clone())
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct AlertState {
+    last_sent: i64,
+    // Errors since the last mail that were only logged
+    suppressed: u32
+}
+
+impl AlertState {
+    // Some(number of suppressed errors) if a mail is due
+    fn due(&mut self, now: i64, interval: i64) -> Option<u32> {
+        if self.last_sent > 0 && now - self.last_sent < interval {
+            self.suppressed += 1;
+            return None;
+        }
+
+        let suppressed = self.suppressed;
+        self.last_sent = now;
+        self.suppressed = 0;
+        Some(suppressed)
+    }
+}
+
+fn alert_body(message: &str, location: &str, request: Option<String>, suppressed: u32, time: &str) -> String {
+    let mut body = format!("Zeit: {}\nAnfrage: {}\nOrt: {}\n\n{}\n", time, request.unwrap_or_else(|| "-".to_string()), location, message);
+
+    if suppressed > 0 {
+        body.push_str(&format!("\nSeit der letzten Warnung wurden {} weitere Fehler nur ins Log geschrieben.\n", suppressed));
+    }
+
+    body
+}
+
+/// Mails every error of the log to alerts in [Admin], at most once per alert_interval minutes.
+/// A broken SMTP password only shows up in the log otherwise, the alert is then tried on the same server
+/// but without the copy to the organizers.
+pub struct AlertLogger {
+    config: Configuration,
+    state: Mutex<AlertState>
+}
+
+impl AlertLogger {
+    pub fn new(config: &Configuration) -> Box<AlertLogger> {
+        Box::new(AlertLogger { config: config.clone(), state: Mutex::new(AlertState::default()) })
+    }
+}
+
+impl Log for AlertLogger {
+    fn enabled(&self, metadata: &LogMetadata) -> bool {
+        metadata.level() <= LogLevel::Error
+    }
+
+    fn log(&self, record: &LogRecord) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let suppressed = match self.state.lock() {
+            Ok(mut state) => state.due(Local::now().timestamp(), self.config.alert_interval * 60),
+            Err(_) => None
+        };
+
+        if let Some(suppressed) = suppressed {
+            let location = format!("{} ({}:{})", record.target(), record.location().file(), record.location().line());
+            let body = alert_body(&record.args().to_string(), &location, current_request(),
+                suppressed, &self.config.date_format.now());
+            let config = self.config.clone();
+
+            // Not logged with error!, that would only lead to the next alert
+            thread::spawn(move || {
+                if let Err(e) = send_alert(&config.alert_emails, "Fehler im Anmeldeserver", &body, &config) {
+                    eprintln!("Could not send the alert mail: {:?}", e);
+                }
+            });
+        }
+    }
+}
+
+impl SharedLogger for AlertLogger {
+    fn level(&self) -> LogLevelFilter {
+        LogLevelFilter::Error
+    }
+
+    fn config(&self) -> Option<&Config> {
+        None
+    }
+
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        Box::new(*self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AlertState, alert_body};
+
+    #[test]
+    fn test_alert_due() {
+        let mut state = AlertState::default();
+
+        assert_eq!(state.due(1000, 3600), Some(0));
+        assert_eq!(state.due(1010, 3600), None);
+        assert_eq!(state.due(2000, 3600), None);
+        assert_eq!(state.due(4600, 3600), Some(2));
+        assert_eq!(state.suppressed, 0);
+    }
+
+    #[test]
+    fn test_alert_body() {
+        let body = alert_body("Could not send mail: Io", "conference_registration::handler (src/handler.rs:10)",
+            Some("#12 POST /submit".to_string()), 3, "01.03.2017 10:00:00");
+
+        assert!(body.starts_with("Zeit: 01.03.2017 10:00:00\nAnfrage: #12 POST /submit\n"));
+        assert!(body.contains("\n\nCould not send mail: Io\n"));
+        assert!(body.contains("3 weitere Fehler"));
+        assert!(alert_body("Error", "-", None, 0, "").contains("Anfrage: -\n"));
+    }
+}
@@ -0,0 +1,316 @@
+use iron::prelude::{Request, IronResult, Response, Set};
+use iron::mime::Mime;
+use iron::status;
+
+use params::Params;
+use plugin::Pluggable;
+use persistent::{Read, Write};
+use serde_json::Value;
+use chrono::Local;
+
+use ::{DBConnection, RegistrationCount};
+use admin::with_admin;
+use config::{Configuration, Session};
+use countries::find_country;
+use database::{lock_connection, find_institutions, load_abstracts, load_participants, count_registrations, count_by_country,
+    count_by_presentation, Abstract};
+use filter::RegistrationFilter;
+use handler::{HandleError, extract_string};
+
+
+const MAX_SUGGESTIONS: u32 = 20;
+
+// In seconds, for our cache as well as for the browsers and proxies
+const COUNT_MAX_AGE: i64 = 60;
+
+pub fn json_response(json: Value) -> Response {
+    let content_type = "application/json; charset=utf-8".parse::<Mime>().unwrap();
+    let mut resp = Response::with((status::Ok, json.to_string()));
+
+    resp.set_mut(content_type);
+    resp
+}
+
+pub fn handle_institutions(req: &mut Request) -> IronResult<Response> {
+    match institution_suggestions(req) {
+        Ok(names) => Ok(json_response(json!(names))),
+        Err(e) => {
+            error!("Error while looking up institutions: {:?}", e);
+            Ok(Response::with(status::InternalServerError))
+        }
+    }
+}
+
+fn institution_suggestions(req: &mut Request) -> Result<Vec<String>, HandleError> {
+    let map = req.get::<Params>()?;
+    let query = extract_string(&map, "q").unwrap_or_default();
+
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let config = req.get::<Read<Configuration>>()?;
+
+    let from_db = {
+        let mutex = req.get::<Write<DBConnection>>()?;
+        let db_connection = lock_connection(&mutex)?;
+        find_institutions(&db_connection, &query, MAX_SUGGESTIONS)?
+    };
+
+    Ok(merge_suggestions(&query, &config.institution_seeds, from_db))
+}
+
+// Seed entries come first, names already in the database are added if they differ only by case or not at all
+fn merge_suggestions(query: &str, seeds: &[String], from_db: Vec<String>) -> Vec<String> {
+    let query = query.to_lowercase();
+    let mut result: Vec<String> = seeds.iter().filter(|name| name.to_lowercase().contains(&query)).cloned().collect();
+
+    for name in from_db {
+        if !result.iter().any(|existing| existing.to_lowercase() == name.to_lowercase()) {
+            result.push(name);
+        }
+    }
+
+    result.truncate(MAX_SUGGESTIONS as usize);
+    result
+}
+
+// Only numbers, for showing "187 of 250 places taken" on the conference website
+pub fn handle_count(req: &mut Request) -> IronResult<Response> {
+    match registration_count(req) {
+        Ok(json) => {
+            let mut resp = json_response(json);
+            resp.headers.set_raw("Cache-Control", vec![format!("public, max-age={}", COUNT_MAX_AGE).into_bytes()]);
+            Ok(resp)
+        }
+        Err(e) => {
+            error!("Error while counting registrations: {:?}", e);
+            Ok(Response::with(status::InternalServerError))
+        }
+    }
+}
+
+fn registration_count(req: &mut Request) -> Result<Value, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    let now = Local::now().timestamp();
+
+    let cache = req.get::<Write<RegistrationCount>>()?;
+    let mut cached = cache.lock()?;
+
+    let count = match *cached {
+        Some((counted_at, count)) if now - counted_at < COUNT_MAX_AGE => count,
+        _ => {
+            let mutex = req.get::<Write<DBConnection>>()?;
+            let db_connection = lock_connection(&mutex)?;
+            let count = count_registrations(&db_connection)?;
+
+            *cached = Some((now, count));
+            count
+        }
+    };
+
+    Ok(count_json(count, config.capacity))
+}
+
+fn count_json(count: i64, capacity: i64) -> Value {
+    if capacity > 0 {
+        json!({ "registered": count, "capacity": capacity, "remaining": (capacity - count).max(0) })
+    } else {
+        json!({ "registered": count, "capacity": null, "remaining": null })
+    }
+}
+
+// For embedding on the conference website: only totals, nothing about single participants.
+// Countries are listed without their numbers, a country with one participant would point to that person.
+pub fn handle_public_stats(req: &mut Request) -> IronResult<Response> {
+    match public_stats(req) {
+        Ok(json) => {
+            let mut resp = json_response(json);
+            resp.headers.set_raw("Cache-Control", vec![format!("public, max-age={}", COUNT_MAX_AGE).into_bytes()]);
+            Ok(resp)
+        }
+        Err(e) => {
+            error!("Error while collecting public statistics: {:?}", e);
+            Ok(Response::with(status::InternalServerError))
+        }
+    }
+}
+
+fn public_stats(req: &mut Request) -> Result<Value, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let registered = count_registrations(&db_connection)?;
+    let presentations = count_by_presentation(&db_connection)?;
+    let countries: Vec<String> = count_by_country(&db_connection)?.into_iter().map(|(code, _)| code).collect();
+
+    Ok(public_stats_json(registered, presentations, &countries, &config))
+}
+
+fn public_stats_json(registered: i64, (talks, posters): (i64, i64), countries: &[String], config: &Configuration) -> Value {
+    // Unknown or empty codes would only show up as "-"
+    let mut names: Vec<&str> = countries.iter().filter_map(|code| find_country(code)).map(|country| country.name(config.language)).collect();
+    names.sort();
+
+    json!({
+        "registered": registered,
+        "talks": talks,
+        "posters": posters,
+        "country_count": names.len(),
+        "countries": names
+    })
+}
+
+// For the organizers' scripts, the selection of /admin/participants with the same query parameters
+pub fn handle_participants_json(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "participants_json", participants_json)
+}
+
+fn participants_json(req: &mut Request) -> Result<Response, HandleError> {
+    let filter = RegistrationFilter::from_request(req)?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let participants: Vec<Value> = load_participants(&db_connection, &filter)?.iter().map(|participant| json!({
+        "id": participant.id,
+        "name": participant.display_name(),
+        "pronouns": participant.pronouns,
+        "institution": participant.institution,
+        "status": participant.status
+    })).collect();
+
+    Ok(json_response(json!(participants)))
+}
+
+pub fn handle_program_json(req: &mut Request) -> IronResult<Response> {
+    match program(req) {
+        Ok(json) => Ok(json_response(json)),
+        Err(e) => {
+            error!("Error while loading program: {:?}", e);
+            Ok(Response::with(status::InternalServerError))
+        }
+    }
+}
+
+fn program(req: &mut Request) -> Result<Value, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    Ok(program_json(&config.sessions, &load_abstracts(&db_connection)?))
+}
+
+// Flat list of sessions in chronological order, meant for the signage screens and other clients.
+// The abstract texts are not included, only their ids.
+fn program_json(sessions: &[Session], abstracts: &[Abstract]) -> Value {
+    let sessions: Vec<Value> = sessions.iter().map(|session| {
+        let contributions: Vec<Value> = abstracts.iter().filter(|abs| abs.session == session.key).map(|abs| json!({
+            "abstract_id": abs.id,
+            "type": abs.presentation,
+            "title": abs.title,
+            "authors": abs.authors,
+            "presenter": abs.presenter()
+        })).collect();
+
+        json!({
+            "key": session.key,
+            "name": session.name,
+            "day": session.day,
+            "start": session.start,
+            "end": session.end,
+            "room": session.room,
+            "contributions": contributions
+        })
+    }).collect();
+
+    json!({ "sessions": sessions })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge_suggestions, count_json, program_json, public_stats_json};
+    use config::{Session, example_configuration};
+    use database::Abstract;
+
+    #[test]
+    fn test_merge_suggestions() {
+        let seeds = vec!["University of Tübingen".to_string(), "GFZ Potsdam".to_string()];
+
+        assert_eq!(merge_suggestions("Potsdam", &seeds, vec!["University of Potsdam".to_string()]),
+            vec!["GFZ Potsdam".to_string(), "University of Potsdam".to_string()]);
+        assert_eq!(merge_suggestions("tübingen", &seeds, vec!["university of tübingen".to_string()]),
+            vec!["University of Tübingen".to_string()]);
+        assert!(merge_suggestions("MIT", &seeds, Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn test_count_json() {
+        assert_eq!(count_json(187, 250), json!({ "registered": 187, "capacity": 250, "remaining": 63 }));
+        assert_eq!(count_json(260, 250), json!({ "registered": 260, "capacity": 250, "remaining": 0 }));
+        assert_eq!(count_json(12, 0), json!({ "registered": 12, "capacity": null, "remaining": null }));
+    }
+
+    #[test]
+    fn test_public_stats_json() {
+        let config = example_configuration();
+        let countries = vec!["DE".to_string(), "".to_string(), "AT".to_string()];
+        let json = public_stats_json(42, (10, 15), &countries, &config);
+
+        assert_eq!(json["registered"], json!(42));
+        assert_eq!(json["talks"], json!(10));
+        assert_eq!(json["posters"], json!(15));
+        assert_eq!(json["country_count"], json!(2));
+        assert_eq!(json["countries"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_program_json() {
+        let sessions = vec![Session {
+            key: "geo1".to_string(),
+            name: "Geodynamics".to_string(),
+            day: "2017-03-01".to_string(),
+            start: "09:00".to_string(),
+            end: "10:30".to_string(),
+            room: "Lecture hall 1".to_string()
+        }];
+
+        let abstracts = vec![Abstract {
+            id: 7,
+            first_name: "Alice".to_string(),
+            last_name: "Brown".to_string(),
+            presentation: "talk".to_string(),
+            title: "Plate tectonics".to_string(),
+            authors: vec!["Alice Brown".to_string()],
+            text: "Text".to_string(),
+            session: "geo1".to_string(),
+            position: 1,
+            file: "".to_string(),
+            file_name: "".to_string(),
+            talk_duration: 0,
+            talk_equipment: "".to_string(),
+            unavailable_days: "".to_string(),
+            status: "confirmed".to_string(),
+
+            topics: Vec::new()
+        }];
+
+        assert_eq!(program_json(&sessions, &abstracts), json!({
+            "sessions": [{
+                "key": "geo1",
+                "name": "Geodynamics",
+                "day": "2017-03-01",
+                "start": "09:00",
+                "end": "10:30",
+                "room": "Lecture hall 1",
+                "contributions": [{
+                    "abstract_id": 7,
+                    "type": "talk",
+                    "title": "Plate tectonics",
+                    "authors": ["Alice Brown"],
+                    "presenter": "Alice Brown"
+                }]
+            }]
+        }));
+    }
+}
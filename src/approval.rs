@@ -0,0 +1,54 @@
+use std::collections::BTreeMap;
+
+use iron::prelude::{Request, IronResult, Response, Set};
+use iron::status;
+
+use handlebars_iron::{Template};
+use serde_json::Value as JsonValue;
+use plugin::Pluggable;
+use persistent::{Read, Write};
+
+use ::DBConnection;
+use admin::with_admin;
+use config::Configuration;
+use countries::find_country;
+use database::{lock_connection, load_pending_registrations};
+use handler::HandleError;
+
+
+// With approval in [Basic] every new registration waits here. The buttons change the status like
+// /admin/participants does, confirmed sends status_confirmed and rejected status_rejected.
+pub fn handle_approvals(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "approvals", approvals_page)
+}
+
+fn approvals_page(req: &mut Request) -> Result<Response, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let pending: Vec<JsonValue> = load_pending_registrations(&db_connection)?.iter().map(|registration| json!({
+        "id": registration.id,
+        "name": format!("{} {}", registration.first_name, registration.last_name),
+        "institution": registration.institution,
+        "email": registration.email_to,
+        "country": find_country(&registration.country).map_or(registration.country.clone(), |country| country.name(config.language).to_string()),
+        "course": if registration.course_type == "course1" { config.course1.clone() } else { config.course2.clone() },
+        "presentation": match registration.presentation.as_str() {
+            "talk" => "Vortrag",
+            "poster" => "Poster",
+            _ => ""
+        },
+        "presentation_title": registration.presentation_title,
+        "more_info": registration.more_info,
+        "registered_at": config.date_format.timestamp(&registration.registered_at)
+    })).collect();
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("approval".to_string(), json!(config.approval));
+    data.insert("pending".to_string(), json!(pending));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("approvals", data)).set_mut(status::Ok);
+    Ok(resp)
+}
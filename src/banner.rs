@@ -0,0 +1,117 @@
+use chrono::{Local, NaiveDateTime};
+use handlebars_iron::handlebars::{Handlebars, Helper, HelperDef, RenderContext, RenderError, html_escape};
+use serde_json::Value as JsonValue;
+
+use config::Configuration;
+use dates::CONFIGURED_TIME;
+use settings::RuntimeConfiguration;
+
+
+// Empty for a banner without an end
+pub fn valid_banner_until(value: &str) -> bool {
+    value.is_empty() || NaiveDateTime::parse_from_str(value, CONFIGURED_TIME).is_ok()
+}
+
+// The banner if there is one and it hasn't expired at now
+fn visible_banner(config: &Configuration, now: NaiveDateTime) -> Option<&str> {
+    if config.banner.is_empty() {
+        return None;
+    }
+
+    match NaiveDateTime::parse_from_str(&config.banner_until, CONFIGURED_TIME) {
+        Ok(until) if now >= until => None,
+        _ => Some(&config.banner)
+    }
+}
+
+fn banner_html(text: &str, dismissible: bool) -> String {
+    let text = html_escape(text);
+
+    if dismissible {
+        // banner.js remembers the dismissed text in the browser, a new text is shown again
+        format!("<div class=\"banner\" data-banner=\"{}\"><button type=\"button\" class=\"banner-close\" title=\"Ausblenden\">×</button>{}</div>\n\
+            <script src=\"/js/banner.js\"></script>", text, text)
+    } else {
+        format!("<p class=\"banner\">{}</p>", text)
+    }
+}
+
+/// {{banner dismissible=true}} shows the banner of /admin/settings until banner_until, nothing without one.
+/// The helper reads the current settings on every page, a changed banner needs no restart.
+/// Handlebars only calls a helper with at least one argument, a bare {{banner}} stays empty.
+pub struct BannerHelper {
+    runtime: RuntimeConfiguration
+}
+
+impl BannerHelper {
+    pub fn new(runtime: &RuntimeConfiguration) -> BannerHelper {
+        BannerHelper { runtime: runtime.clone() }
+    }
+}
+
+impl HelperDef for BannerHelper {
+    fn call(&self, h: &Helper, _: &Handlebars, rc: &mut RenderContext) -> Result<(), RenderError> {
+        let config = self.runtime.current();
+        let dismissible = h.hash_get("dismissible").is_some_and(|value| *value.value() == JsonValue::Bool(true));
+
+        if let Some(text) = visible_banner(&config, Local::now().naive_local()) {
+            rc.writer.write_all(banner_html(text, dismissible).as_bytes())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{valid_banner_until, visible_banner, banner_html, BannerHelper};
+    use config::example_configuration;
+    use database::init_database;
+    use settings::RuntimeConfiguration;
+    use chrono::NaiveDateTime;
+    use handlebars_iron::handlebars::Handlebars;
+    use rusqlite::Connection;
+
+    #[test]
+    fn test_visible_banner() {
+        let now = NaiveDateTime::parse_from_str("2017-03-01 12:00", "%Y-%m-%d %H:%M").unwrap();
+        let mut config = example_configuration();
+        assert_eq!(visible_banner(&config, now), None);
+
+        config.banner = "Abstract deadline extended to Friday!".to_string();
+        assert_eq!(visible_banner(&config, now), Some("Abstract deadline extended to Friday!"));
+
+        config.banner_until = "2017-03-01 12:01".to_string();
+        assert!(visible_banner(&config, now).is_some());
+        config.banner_until = "2017-03-01 12:00".to_string();
+        assert_eq!(visible_banner(&config, now), None);
+    }
+
+    #[test]
+    fn test_valid_banner_until() {
+        assert!(valid_banner_until(""));
+        assert!(valid_banner_until("2017-03-03 18:00"));
+        assert!(!valid_banner_until("2017-03-03"));
+        assert!(!valid_banner_until("Freitag"));
+    }
+
+    #[test]
+    fn test_banner_helper() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+        let mut config = example_configuration();
+        config.banner = "Neu".to_string();
+
+        let mut handlebars = Handlebars::new();
+        handlebars.register_helper("banner", Box::new(BannerHelper::new(&RuntimeConfiguration::new(config, &conn).unwrap())));
+
+        assert_eq!(handlebars.template_render("{{banner dismissible=false}}", &()).unwrap(), "<p class=\"banner\">Neu</p>".to_string());
+        assert!(handlebars.template_render("{{banner dismissible=true}}", &()).unwrap().contains("banner.js"));
+    }
+
+    #[test]
+    fn test_banner_html() {
+        assert_eq!(banner_html("<b>Neu</b>", false), "<p class=\"banner\">&lt;b&gt;Neu&lt;/b&gt;</p>".to_string());
+        assert!(banner_html("Neu & wichtig", true).contains("data-banner=\"Neu &amp; wichtig\""));
+    }
+}
@@ -0,0 +1,75 @@
+use iron::prelude::{Request, IronResult, Response};
+use iron::middleware::{AroundMiddleware, Handler};
+use iron::headers::{ContentLength, TransferEncoding};
+use iron::status;
+
+use config::Configuration;
+
+
+// Rejects oversized requests before Params reads the body into memory or the upload folder.
+// The body reader stops at the announced Content-Length, so checking the header is enough.
+pub struct BodyLimit {
+    max_size: u64
+}
+
+impl BodyLimit {
+    pub fn new(config: &Configuration) -> BodyLimit {
+        BodyLimit { max_size: config.max_request_size }
+    }
+
+    fn check(&self, content_length: Option<u64>, chunked: bool) -> Option<status::Status> {
+        match content_length {
+            Some(length) if length > self.max_size => Some(status::PayloadTooLarge),
+            Some(_) => None,
+            // A chunked body could be of any size, browsers always send the length of a form
+            None if chunked => Some(status::LengthRequired),
+            None => None
+        }
+    }
+}
+
+struct BodyLimitHandler {
+    limit: BodyLimit,
+    handler: Box<dyn Handler>
+}
+
+impl Handler for BodyLimitHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let content_length = req.headers.get::<ContentLength>().map(|length| length.0);
+        let chunked = req.headers.has::<TransferEncoding>();
+
+        match self.limit.check(content_length, chunked) {
+            Some(status) => {
+                info!("Request to '{}' rejected: {:?}, {:?} bytes", req.url, status, content_length);
+                Ok(Response::with((status, "Die Anfrage ist zu groß.")))
+            }
+            None => self.handler.handle(req)
+        }
+    }
+}
+
+impl AroundMiddleware for BodyLimit {
+    fn around(self, handler: Box<dyn Handler>) -> Box<dyn Handler> {
+        Box::new(BodyLimitHandler { limit: self, handler })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BodyLimit;
+    use config::example_configuration;
+    use iron::status;
+
+    #[test]
+    fn test_body_limit() {
+        let mut config = example_configuration();
+        config.max_request_size = 1000;
+        let limit = BodyLimit::new(&config);
+
+        assert_eq!(limit.check(None, false), None);
+        assert_eq!(limit.check(Some(1000), false), None);
+        assert_eq!(limit.check(Some(1001), false), Some(status::PayloadTooLarge));
+        assert_eq!(limit.check(None, true), Some(status::LengthRequired));
+        assert_eq!(limit.check(Some(10), true), None);
+    }
+}
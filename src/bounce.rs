@@ -0,0 +1,279 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+use openssl::error::ErrorStack;
+use openssl::ssl::{SslConnector, SslMethod, SslStream, HandshakeError};
+use rusqlite::Connection;
+use rusqlite;
+
+use config::Configuration;
+use database::mark_undeliverable;
+use handler::normalize_email;
+use dates::timestamp;
+
+
+#[derive(Debug)]
+pub enum BounceError {
+    IO,
+    Tls,
+    Sql,
+    // The server answered NO or BAD
+    Imap
+}
+
+impl From<io::Error> for BounceError {
+    fn from(_: io::Error) -> BounceError {
+        BounceError::IO
+    }
+}
+
+impl From<ErrorStack> for BounceError {
+    fn from(_: ErrorStack) -> BounceError {
+        BounceError::Tls
+    }
+}
+
+impl From<HandshakeError<TcpStream>> for BounceError {
+    fn from(_: HandshakeError<TcpStream>) -> BounceError {
+        BounceError::Tls
+    }
+}
+
+impl From<rusqlite::Error> for BounceError {
+    fn from(_: rusqlite::Error) -> BounceError {
+        BounceError::Sql
+    }
+}
+
+// Just enough IMAP (RFC 3501) to read and flag the messages of one mailbox
+struct ImapClient {
+    stream: BufReader<SslStream<TcpStream>>,
+    tag: u32
+}
+
+impl ImapClient {
+    fn connect(server: &str, port: u16) -> Result<ImapClient, BounceError> {
+        let connector = SslConnector::builder(SslMethod::tls())?.build();
+        let tcp = TcpStream::connect((server, port))?;
+        tcp.set_read_timeout(Some(Duration::from_secs(60)))?;
+
+        let mut client = ImapClient {
+            stream: BufReader::new(connector.connect(server, tcp)?),
+            tag: 0
+        };
+
+        // Greeting
+        client.read_item()?;
+
+        Ok(client)
+    }
+
+    // One response line, with the data of a literal ("{123}") appended
+    fn read_item(&mut self) -> Result<Vec<u8>, BounceError> {
+        let mut item = Vec::new();
+
+        loop {
+            let mut line = Vec::new();
+            if self.stream.read_until(b'\n', &mut line)? == 0 {
+                return Err(BounceError::IO);
+            }
+            item.extend_from_slice(&line);
+
+            match literal_size(&line) {
+                Some(size) => {
+                    let mut literal = vec![0u8; size];
+                    self.stream.read_exact(&mut literal)?;
+                    item.extend_from_slice(&literal);
+                }
+                None => return Ok(item)
+            }
+        }
+    }
+
+    // Returns the untagged responses
+    fn command(&mut self, command: &str) -> Result<Vec<Vec<u8>>, BounceError> {
+        self.tag += 1;
+        let tag = format!("A{} ", self.tag);
+
+        self.stream.get_mut().write_all(format!("{}{}\r\n", tag, command).as_bytes())?;
+
+        let mut responses = Vec::new();
+
+        loop {
+            let item = self.read_item()?;
+
+            if item.starts_with(tag.as_bytes()) {
+                let status = String::from_utf8_lossy(&item[tag.len()..]).trim().to_string();
+
+                if status.starts_with("OK") {
+                    return Ok(responses);
+                }
+
+                error!("IMAP server rejected command: {}", status);
+                return Err(BounceError::Imap);
+            }
+
+            responses.push(item);
+        }
+    }
+}
+
+fn literal_size(line: &[u8]) -> Option<usize> {
+    let line = String::from_utf8_lossy(line);
+    let line = line.trim_end();
+
+    if !line.ends_with('}') {
+        return None;
+    }
+
+    let start = line.rfind('{')?;
+    line[start + 1..line.len() - 1].parse::<usize>().ok()
+}
+
+fn extract_literal(item: &[u8]) -> Option<Vec<u8>> {
+    let start = item.iter().position(|byte| *byte == b'{')?;
+    let end = start + item[start..].iter().position(|byte| *byte == b'}')?;
+    let size = String::from_utf8_lossy(&item[start + 1..end]).parse::<usize>().ok()?;
+    let data = end + 3;
+
+    if item.len() < data + size {
+        return None;
+    }
+
+    Some(item[data..data + size].to_vec())
+}
+
+fn parse_search(responses: &[Vec<u8>]) -> Vec<u32> {
+    responses.iter()
+        .map(|response| String::from_utf8_lossy(response).to_string())
+        .filter(|response| response.starts_with("* SEARCH"))
+        .flat_map(|response| response["* SEARCH".len()..].split_whitespace().filter_map(|uid| uid.parse::<u32>().ok()).collect::<Vec<u32>>())
+        .collect()
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Delivery status notifications (RFC 3464) and the older style reports of some mail servers
+fn is_bounce(message: &str) -> bool {
+    let head = message.split("\r\n\r\n").next().unwrap_or("").to_lowercase();
+
+    head.contains("report-type=delivery-status") || head.contains("x-failed-recipients:") ||
+        head.lines().any(|line| line.starts_with("from:") && (line.contains("mailer-daemon") || line.contains("postmaster")))
+}
+
+fn bounced_recipients(message: &str) -> Vec<String> {
+    let mut result: Vec<String> = Vec::new();
+
+    for line in message.lines() {
+        let lower = line.to_lowercase();
+
+        let addresses: Vec<&str> = if lower.starts_with("final-recipient:") || lower.starts_with("original-recipient:") {
+            // "Final-Recipient: rfc822; bob@example.org"
+            line.split_once(';').map(|(_, rest)| rest).into_iter().collect()
+        } else if lower.starts_with("x-failed-recipients:") {
+            line.split_once(':').map(|(_, rest)| rest).map_or(Vec::new(), |addresses| addresses.split(',').collect())
+        } else {
+            Vec::new()
+        };
+
+        for address in addresses {
+            let address = normalize_email(address.trim().trim_matches(|c| c == '<' || c == '>'));
+            if address.contains('@') && !result.contains(&address) {
+                result.push(address);
+            }
+        }
+    }
+
+    result
+}
+
+// Bounces are flagged as seen, all other messages stay unread for the organizers
+fn poll_bounces(config: &Configuration) -> Result<usize, BounceError> {
+    let mut client = ImapClient::connect(&config.bounce_server, config.bounce_port)?;
+
+    client.command(&format!("LOGIN {} {}", quote(&config.bounce_username), quote(&config.bounce_password)))?;
+    client.command("SELECT INBOX")?;
+
+    let uids = parse_search(&client.command("UID SEARCH UNSEEN")?);
+
+    // Separate from the web server's connection, rusqlite waits up to 5 seconds if the database is locked
+    let db_connection = Connection::open(&config.db_filename)?;
+
+    let mut flagged = 0;
+
+    for uid in uids {
+        let responses = client.command(&format!("UID FETCH {} BODY.PEEK[]", uid))?;
+        let message = match responses.iter().filter_map(|response| extract_literal(response)).next() {
+            Some(message) => String::from_utf8_lossy(&message).to_string(),
+            None => continue
+        };
+
+        if !is_bounce(&message) {
+            continue;
+        }
+
+        for recipient in bounced_recipients(&message) {
+            let changed = mark_undeliverable(&db_connection, &recipient, &timestamp())?;
+            if changed > 0 {
+                info!("Email to '{}' bounced", recipient);
+            }
+            flagged += changed;
+        }
+
+        client.command(&format!("UID STORE {} +FLAGS (\\Seen)", uid))?;
+    }
+
+    client.command("LOGOUT")?;
+
+    Ok(flagged)
+}
+
+// Runs for the lifetime of the server if a [Bounces] mailbox is configured
+pub fn start_bounce_polling(config: Configuration) {
+    thread::spawn(move || loop {
+        match poll_bounces(&config) {
+            Ok(flagged) => info!("Bounce mailbox checked, {} registrations flagged", flagged),
+            Err(e) => error!("Could not check bounce mailbox: {:?}", e)
+        }
+
+        thread::sleep(Duration::from_secs(config.bounce_interval * 60));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{literal_size, extract_literal, parse_search, quote, is_bounce, bounced_recipients};
+
+    #[test]
+    fn test_imap_parsing() {
+        assert_eq!(literal_size(b"* 1 FETCH (UID 5 BODY[] {12}\r\n"), Some(12));
+        assert_eq!(literal_size(b"* OK ready\r\n"), None);
+        assert_eq!(extract_literal(b"* 1 FETCH (UID 5 BODY[] {5}\r\nHello)\r\n"), Some(b"Hello".to_vec()));
+        assert_eq!(extract_literal(b"* 1 FETCH (UID 5 BODY[] {9}\r\nHello)\r\n"), None);
+        assert_eq!(parse_search(&[b"* SEARCH 3 5 12\r\n".to_vec(), b"* OK\r\n".to_vec()]), vec![3, 5, 12]);
+        assert_eq!(parse_search(&[b"* SEARCH\r\n".to_vec()]), Vec::<u32>::new());
+        assert_eq!(quote("pa\"ss\\"), "\"pa\\\"ss\\\\\"".to_string());
+    }
+
+    #[test]
+    fn test_bounces() {
+        let bounce = "From: Mail Delivery System <MAILER-DAEMON@example.org>\r\n\
+            Content-Type: multipart/report; report-type=delivery-status; boundary=\"x\"\r\n\r\n\
+            --x\r\nContent-Type: message/delivery-status\r\n\r\n\
+            Original-Recipient: rfc822;Bob@Example.org\r\n\
+            Final-Recipient: rfc822; bob@example.org\r\nAction: failed\r\n";
+
+        assert!(is_bounce(bounce));
+        assert_eq!(bounced_recipients(bounce), vec!["bob@example.org".to_string()]);
+
+        let exim = "From: Mail Delivery System <Mailer-Daemon@example.org>\r\nX-Failed-Recipients: alice@example.org, <carol@example.org>\r\n\r\nText\r\n";
+        assert!(is_bounce(exim));
+        assert_eq!(bounced_recipients(exim), vec!["alice@example.org".to_string(), "carol@example.org".to_string()]);
+
+        assert!(!is_bounce("From: Alice <alice@example.org>\r\nSubject: Question\r\n\r\nmailer-daemon\r\n"));
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use rusqlite::Connection;
+use serde_json::Value as JsonValue;
+
+use database::data_version;
+use handler::HandleError;
+
+
+// The data of the admin pages with the expensive aggregate queries. Everything is thrown away
+// as soon as the database changed, so a refreshed dashboard only costs a lookup.
+pub struct QueryCache {
+    version: (i64, i64),
+    entries: HashMap<String, JsonValue>
+}
+
+impl QueryCache {
+    pub fn new() -> QueryCache {
+        QueryCache { version: (-1, -1), entries: HashMap::new() }
+    }
+
+    fn get(&mut self, version: (i64, i64), key: &str) -> Option<JsonValue> {
+        if version != self.version {
+            self.entries.clear();
+            self.version = version;
+        }
+
+        self.entries.get(key).cloned()
+    }
+
+    fn insert(&mut self, version: (i64, i64), key: &str, value: JsonValue) {
+        // Something was written while the value was computed
+        if version == self.version {
+            self.entries.insert(key.to_string(), value);
+        }
+    }
+}
+
+// The caller holds the database lock, the cache lock is only taken after it
+pub fn cached<F>(cache: &Mutex<QueryCache>, db_connection: &Connection, key: &str, f: F) -> Result<JsonValue, HandleError>
+    where F: FnOnce(&Connection) -> Result<JsonValue, HandleError> {
+
+    let version = data_version(db_connection)?;
+
+    if let Some(value) = cache.lock()?.get(version, key) {
+        return Ok(value);
+    }
+
+    let value = f(db_connection)?;
+    cache.lock()?.insert(version, key, value.clone());
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{QueryCache, cached};
+    use std::sync::Mutex;
+    use rusqlite::Connection;
+    use serde_json::Value as JsonValue;
+    use database::{init_database, count_registrations};
+    use handler::HandleError;
+
+    #[test]
+    fn test_query_cache() {
+        let mut cache = QueryCache::new();
+        assert_eq!(cache.get((0, 1), "stats"), None);
+
+        cache.insert((0, 1), "stats", json!(1));
+        assert_eq!(cache.get((0, 1), "stats"), Some(json!(1)));
+
+        // A new version drops everything, values computed for the old version are not stored anymore
+        assert_eq!(cache.get((1, 1), "stats"), None);
+        cache.insert((0, 1), "stats", json!(1));
+        assert_eq!(cache.get((1, 1), "stats"), None);
+    }
+
+    #[test]
+    fn test_cached() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+        let cache = Mutex::new(QueryCache::new());
+
+        let count = |conn: &Connection| -> Result<JsonValue, HandleError> { Ok(json!(count_registrations(conn)?)) };
+
+        assert_eq!(cached(&cache, &conn, "total", count).unwrap(), json!(0));
+        assert_eq!(cached(&cache, &conn, "total", |_| Ok(json!("not called"))).unwrap(), json!(0));
+
+        conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                      city, phone, email_to, more_info, price_category, course_type)
+                      VALUES ('sir', 'Smith', 'Bob', 'Uni', 'Street', '1', '12345', 'City', '123', 'bob@smith.com', '', 'student', 'course1')",
+                      &[]).unwrap();
+
+        assert_eq!(cached(&cache, &conn, "total", count).unwrap(), json!(1));
+    }
+}
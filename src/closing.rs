@@ -0,0 +1,145 @@
+use std::collections::BTreeMap;
+
+use iron::prelude::{Request, Response, Set};
+use iron::status;
+
+use handlebars_iron::{Template};
+use serde_json::Value as JsonValue;
+use plugin::Pluggable;
+use params::Params;
+use persistent::{Read, Write};
+use chrono::{Local, NaiveDateTime};
+
+use ::DBConnection;
+use admin::secure_eq;
+use config::Configuration;
+use database::{lock_connection, count_registrations};
+use dates::CONFIGURED_TIME;
+use handler::{HandleError, extract_string};
+
+
+fn registration_closed(config: &Configuration, now: NaiveDateTime) -> bool {
+    match NaiveDateTime::parse_from_str(&config.registration_close, CONFIGURED_TIME) {
+        Ok(close) => now >= close,
+        Err(_) => false
+    }
+}
+
+fn registration_not_open(config: &Configuration, now: NaiveDateTime) -> bool {
+    match NaiveDateTime::parse_from_str(&config.registration_open, CONFIGURED_TIME) {
+        Ok(open) => now < open,
+        Err(_) => false
+    }
+}
+
+/// The preview_code of [Basic] if the request carries it, as ?preview= on / and as a hidden field of the form
+pub fn preview_code(req: &mut Request, config: &Configuration) -> Option<String> {
+    if config.preview_code.is_empty() {
+        return None;
+    }
+
+    let map = req.get_ref::<Params>().ok()?;
+    extract_string(map, "preview").ok().filter(|code| secure_eq(code, &config.preview_code))
+}
+
+fn participant_count(req: &mut Request) -> Result<i64, HandleError> {
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+    Ok(count_registrations(&db_connection)?)
+}
+
+/// After registration_close the form, the drafts and the login links are replaced by the closing page.
+/// None while the registration is open. page_status is 200 for the form and 403 for a refused submission.
+pub fn closed_page(req: &mut Request, page_status: status::Status) -> Option<Response> {
+    let config = match req.get::<Read<Configuration>>() {
+        Ok(config) => config,
+        Err(_) => return None
+    };
+
+    if !registration_closed(&config, Local::now().naive_local()) {
+        return None;
+    }
+
+    if page_status != status::Ok {
+        info!("Request to '{}' after registration_close refused", req.url.path().join("/"));
+    }
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("message".to_string(), json!(config.closed_message));
+    data.insert("program_link".to_string(), json!(config.closed_program_link));
+
+    if config.closed_show_count {
+        match participant_count(req) {
+            Ok(count) => { data.insert("count".to_string(), json!(count)); }
+            Err(e) => error!("Could not count the participants for the closing page: {:?}", e)
+        }
+    }
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("closed", data)).set_mut(page_status);
+    Some(resp)
+}
+
+/// Before registration_open the form and the drafts show the closing page with the opening time, except with
+/// the preview code. The login links stay open, the committee's test registrations can use them.
+pub fn unopened_page(req: &mut Request, page_status: status::Status) -> Option<Response> {
+    let config = match req.get::<Read<Configuration>>() {
+        Ok(config) => config,
+        Err(_) => return None
+    };
+
+    if !registration_not_open(&config, Local::now().naive_local()) {
+        return None;
+    }
+
+    if preview_code(req, &config).is_some() {
+        if page_status != status::Ok {
+            info!("Request to '{}' before registration_open with the preview code", req.url.path().join("/"));
+        }
+        return None;
+    }
+
+    if page_status != status::Ok {
+        info!("Request to '{}' before registration_open refused", req.url.path().join("/"));
+    }
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("message".to_string(), json!(format!("Die Anmeldung öffnet am {}.", config.date_format.timestamp(&config.registration_open))));
+    data.insert("opens".to_string(), json!(true));
+    data.insert("program_link".to_string(), json!(config.closed_program_link));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("closed", data)).set_mut(page_status);
+    Some(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{registration_closed, registration_not_open};
+    use config::example_configuration;
+    use chrono::NaiveDateTime;
+
+    #[test]
+    fn test_registration_closed() {
+        let now = NaiveDateTime::parse_from_str("2017-03-01 23:59", "%Y-%m-%d %H:%M").unwrap();
+        let mut config = example_configuration();
+        assert!(!registration_closed(&config, now));
+
+        config.registration_close = "2017-03-02 00:00".to_string();
+        assert!(!registration_closed(&config, now));
+        config.registration_close = "2017-03-01 23:59".to_string();
+        assert!(registration_closed(&config, now));
+    }
+
+    #[test]
+    fn test_registration_not_open() {
+        let now = NaiveDateTime::parse_from_str("2017-01-15 08:59", "%Y-%m-%d %H:%M").unwrap();
+        let mut config = example_configuration();
+        assert!(!registration_not_open(&config, now));
+
+        config.registration_open = "2017-01-15 09:00".to_string();
+        assert!(registration_not_open(&config, now));
+        config.registration_open = "2017-01-15 08:59".to_string();
+        assert!(!registration_not_open(&config, now));
+    }
+}
@@ -0,0 +1,115 @@
+use handlebars_iron::handlebars::{Handlebars, Helper, HelperDef, RenderContext, RenderError, html_escape};
+use serde_json::Value as JsonValue;
+
+
+// The keys of [Conference], also the placeholders of the mail templates and the argument of {{conference "..."}}
+pub const CONFERENCE_KEYS: [&str; 6] = ["conference", "dates", "venue", "organizer", "contact_email", "website"];
+
+/// What [Conference] says about the event. The pages get it with {{conference "venue"}}, the mails with {venue}.
+/// The defaults are the texts the mails had before the section existed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conference {
+    pub name: String,
+    // As it should be read, e.g. "28. bis 31. Maerz 2017"
+    pub dates: String,
+    pub venue: String,
+    // Signs the mails after "Mit freundlichen Gruessen,"
+    pub organizer: String,
+    pub contact_email: String,
+    pub website: String
+}
+
+impl Default for Conference {
+    fn default() -> Conference {
+        Conference {
+            name: "TGAG Fortbildung".to_string(),
+            dates: String::new(),
+            venue: String::new(),
+            organizer: "die Fortbildungsorganisation".to_string(),
+            contact_email: String::new(),
+            website: String::new()
+        }
+    }
+}
+
+impl Conference {
+    pub fn value(&self, key: &str) -> Option<&str> {
+        match key {
+            "conference" => Some(&self.name),
+            "dates" => Some(&self.dates),
+            "venue" => Some(&self.venue),
+            "organizer" => Some(&self.organizer),
+            "contact_email" => Some(&self.contact_email),
+            "website" => Some(&self.website),
+            _ => None
+        }
+    }
+
+    // Filled into every mail template in addition to its own placeholders
+    pub fn mail_values(&self) -> Vec<(&'static str, String)> {
+        CONFERENCE_KEYS.iter().map(|&key| (key, self.value(key).unwrap_or("").to_string())).collect()
+    }
+}
+
+/// {{conference "name"}} in any page, the value is escaped like {{...}}. A key that doesn't exist fails the page,
+/// a typo in a customized template shows up at once.
+pub struct ConferenceHelper {
+    conference: Conference
+}
+
+impl ConferenceHelper {
+    pub fn new(conference: &Conference) -> ConferenceHelper {
+        ConferenceHelper { conference: conference.clone() }
+    }
+}
+
+impl HelperDef for ConferenceHelper {
+    fn call(&self, h: &Helper, _: &Handlebars, rc: &mut RenderContext) -> Result<(), RenderError> {
+        let key = match h.param(0).map(|param| param.value()) {
+            Some(JsonValue::String(key)) => key.clone(),
+            _ => return Err(RenderError::new("conference needs the key in quotes, e.g. {{conference \"venue\"}}"))
+        };
+
+        // "name" reads better in a page than "conference"
+        let key = if key == "name" { "conference".to_string() } else { key };
+
+        match self.conference.value(&key) {
+            Some(value) => {
+                rc.writer.write_all(html_escape(value).as_bytes())?;
+                Ok(())
+            }
+            None => Err(RenderError::new(format!("Unknown key '{}' for conference", key)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Conference, ConferenceHelper};
+    use handlebars_iron::handlebars::Handlebars;
+
+    #[test]
+    fn test_mail_values() {
+        let mut conference = Conference::default();
+        conference.venue = "Tuebingen".to_string();
+
+        let values = conference.mail_values();
+        assert_eq!(values[0], ("conference", "TGAG Fortbildung".to_string()));
+        assert!(values.contains(&("venue", "Tuebingen".to_string())));
+        assert_eq!(conference.value("unknown"), None);
+    }
+
+    #[test]
+    fn test_conference_helper() {
+        let mut conference = Conference::default();
+        conference.venue = "Hotel <Sonne> & Mond".to_string();
+
+        let mut handlebars = Handlebars::new();
+        handlebars.register_helper("conference", Box::new(ConferenceHelper::new(&conference)));
+
+        assert_eq!(handlebars.template_render("{{conference \"name\"}} in {{conference \"venue\"}}", &()).unwrap(),
+            "TGAG Fortbildung in Hotel &lt;Sonne&gt; &amp; Mond".to_string());
+        assert!(handlebars.template_render("{{conference \"place\"}}", &()).is_err());
+        assert!(handlebars.template_render("{{conference venue}}", &()).is_err());
+    }
+}
@@ -0,0 +1,149 @@
+use iron::prelude::{Request, IronResult, Response};
+use iron::middleware::{AroundMiddleware, Handler};
+use iron::method::Method;
+use iron::status;
+
+use config::Configuration;
+
+
+// Cross-origin access to the JSON API (/api/...) for the conference website, the other pages are left alone
+pub struct Cors {
+    origins: Vec<String>,
+    methods: Vec<String>,
+    headers: Vec<String>,
+    max_age: u64
+}
+
+impl Cors {
+    pub fn new(config: &Configuration) -> Cors {
+        Cors {
+            origins: config.cors_origins.clone(),
+            methods: config.cors_methods.clone(),
+            headers: config.cors_headers.clone(),
+            max_age: config.cors_max_age
+        }
+    }
+
+    // The value of Access-Control-Allow-Origin, None if the origin is not allowed
+    fn allowed_origin(&self, origin: &str) -> Option<String> {
+        if self.origins.iter().any(|allowed| allowed == "*") {
+            Some("*".to_string())
+        } else if self.origins.iter().any(|allowed| allowed == origin) {
+            Some(origin.to_string())
+        } else {
+            None
+        }
+    }
+
+    fn preflight_headers(&self, origin: &str) -> Vec<(&'static str, String)> {
+        let mut headers = vec![
+            ("Access-Control-Allow-Origin", origin.to_string()),
+            ("Access-Control-Allow-Methods", self.methods.join(", ")),
+            ("Access-Control-Max-Age", self.max_age.to_string())
+        ];
+
+        if !self.headers.is_empty() {
+            headers.push(("Access-Control-Allow-Headers", self.headers.join(", ")));
+        }
+
+        headers
+    }
+}
+
+fn is_api(req: &Request) -> bool {
+    req.url.path().first().is_some_and(|segment| *segment == "api")
+}
+
+fn request_origin(req: &Request) -> Option<String> {
+    req.headers.get_raw("Origin")
+        .and_then(|values| values.first())
+        .map(|value| String::from_utf8_lossy(value).trim().to_string())
+}
+
+fn set_header(resp: &mut Response, name: &'static str, value: String) {
+    resp.headers.set_raw(name, vec![value.into_bytes()]);
+}
+
+struct CorsHandler {
+    cors: Cors,
+    handler: Box<dyn Handler>
+}
+
+impl Handler for CorsHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        if !is_api(req) {
+            return self.handler.handle(req);
+        }
+
+        let allowed = request_origin(req).and_then(|origin| self.cors.allowed_origin(&origin));
+
+        // Preflight, the router only knows GET and POST
+        if req.method == Method::Options {
+            let mut resp = Response::with(status::NoContent);
+
+            if let Some(origin) = allowed {
+                for (name, value) in self.cors.preflight_headers(&origin) {
+                    set_header(&mut resp, name, value);
+                }
+            }
+
+            return Ok(resp);
+        }
+
+        let mut result = self.handler.handle(req);
+
+        if let Some(origin) = allowed {
+            let resp = match result {
+                Ok(ref mut resp) => resp,
+                Err(ref mut e) => &mut e.response
+            };
+
+            set_header(resp, "Access-Control-Allow-Origin", origin);
+            // The answer differs by origin, caches must not hand it to another website
+            set_header(resp, "Vary", "Origin".to_string());
+        }
+
+        result
+    }
+}
+
+impl AroundMiddleware for Cors {
+    fn around(self, handler: Box<dyn Handler>) -> Box<dyn Handler> {
+        Box::new(CorsHandler { cors: self, handler })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cors;
+    use config::example_configuration;
+
+    #[test]
+    fn test_allowed_origin() {
+        let mut config = example_configuration();
+        config.cors_origins = vec!["https://conference.example.org".to_string()];
+
+        let cors = Cors::new(&config);
+        assert_eq!(cors.allowed_origin("https://conference.example.org"), Some("https://conference.example.org".to_string()));
+        assert_eq!(cors.allowed_origin("https://evil.example.com"), None);
+
+        config.cors_origins = vec!["*".to_string()];
+        assert_eq!(Cors::new(&config).allowed_origin("https://evil.example.com"), Some("*".to_string()));
+    }
+
+    #[test]
+    fn test_preflight_headers() {
+        let mut config = example_configuration();
+        config.cors_origins = vec!["https://conference.example.org".to_string()];
+
+        assert_eq!(Cors::new(&config).preflight_headers("https://conference.example.org"), vec![
+            ("Access-Control-Allow-Origin", "https://conference.example.org".to_string()),
+            ("Access-Control-Allow-Methods", "GET".to_string()),
+            ("Access-Control-Max-Age", "86400".to_string())
+        ]);
+
+        config.cors_methods = vec!["GET".to_string(), "POST".to_string()];
+        config.cors_headers = vec!["Content-Type".to_string()];
+        assert_eq!(Cors::new(&config).preflight_headers("*")[3], ("Access-Control-Allow-Headers", "Content-Type".to_string()));
+    }
+}
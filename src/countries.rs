@@ -0,0 +1,324 @@
+// ISO 3166-1 alpha-2 country codes with their English and German names
+
+use config::Language;
+
+
+pub struct Country {
+    pub code: &'static str,
+    pub name_en: &'static str,
+    pub name_de: &'static str
+}
+
+impl Country {
+    pub fn name(&self, language: Language) -> &'static str {
+        match language {
+            Language::English => self.name_en,
+            Language::German => self.name_de
+        }
+    }
+}
+
+pub fn find_country(code: &str) -> Option<&'static Country> {
+    COUNTRIES.iter().find(|country| country.code.eq_ignore_ascii_case(code))
+}
+
+// Sorted by the localized name, for the selector in the registration form
+pub fn sorted_countries(language: Language) -> Vec<&'static Country> {
+    let mut result: Vec<&'static Country> = COUNTRIES.iter().collect();
+
+    result.sort_by_key(|country| sort_key(country.name(language)));
+    result
+}
+
+// Umlauts and accents must not push names like "Ägypten" or "Österreich" to the end of the list
+fn sort_key(name: &str) -> String {
+    name.to_lowercase().chars().map(|c| match c {
+        'ä' | 'å' | 'á' | 'à' | 'â' => 'a',
+        'é' | 'è' | 'ê' => 'e',
+        'í' | 'î' => 'i',
+        'ö' | 'ó' | 'ô' => 'o',
+        'ü' | 'ú' | 'û' => 'u',
+        'ç' => 'c',
+        _ => c
+    }).collect()
+}
+
+const COUNTRIES: [Country; 249] = [
+    Country { code: "AD", name_en: "Andorra", name_de: "Andorra" },
+    Country { code: "AE", name_en: "United Arab Emirates", name_de: "Vereinigte Arabische Emirate" },
+    Country { code: "AF", name_en: "Afghanistan", name_de: "Afghanistan" },
+    Country { code: "AG", name_en: "Antigua and Barbuda", name_de: "Antigua und Barbuda" },
+    Country { code: "AI", name_en: "Anguilla", name_de: "Anguilla" },
+    Country { code: "AL", name_en: "Albania", name_de: "Albanien" },
+    Country { code: "AM", name_en: "Armenia", name_de: "Armenien" },
+    Country { code: "AO", name_en: "Angola", name_de: "Angola" },
+    Country { code: "AQ", name_en: "Antarctica", name_de: "Antarktis" },
+    Country { code: "AR", name_en: "Argentina", name_de: "Argentinien" },
+    Country { code: "AS", name_en: "American Samoa", name_de: "Amerikanisch-Samoa" },
+    Country { code: "AT", name_en: "Austria", name_de: "Österreich" },
+    Country { code: "AU", name_en: "Australia", name_de: "Australien" },
+    Country { code: "AW", name_en: "Aruba", name_de: "Aruba" },
+    Country { code: "AX", name_en: "Åland Islands", name_de: "Ålandinseln" },
+    Country { code: "AZ", name_en: "Azerbaijan", name_de: "Aserbaidschan" },
+    Country { code: "BA", name_en: "Bosnia and Herzegovina", name_de: "Bosnien und Herzegowina" },
+    Country { code: "BB", name_en: "Barbados", name_de: "Barbados" },
+    Country { code: "BD", name_en: "Bangladesh", name_de: "Bangladesch" },
+    Country { code: "BE", name_en: "Belgium", name_de: "Belgien" },
+    Country { code: "BF", name_en: "Burkina Faso", name_de: "Burkina Faso" },
+    Country { code: "BG", name_en: "Bulgaria", name_de: "Bulgarien" },
+    Country { code: "BH", name_en: "Bahrain", name_de: "Bahrain" },
+    Country { code: "BI", name_en: "Burundi", name_de: "Burundi" },
+    Country { code: "BJ", name_en: "Benin", name_de: "Benin" },
+    Country { code: "BL", name_en: "Saint Barthélemy", name_de: "St. Barthélemy" },
+    Country { code: "BM", name_en: "Bermuda", name_de: "Bermuda" },
+    Country { code: "BN", name_en: "Brunei Darussalam", name_de: "Brunei Darussalam" },
+    Country { code: "BO", name_en: "Bolivia", name_de: "Bolivien" },
+    Country { code: "BQ", name_en: "Bonaire, Sint Eustatius and Saba", name_de: "Bonaire, Sint Eustatius und Saba" },
+    Country { code: "BR", name_en: "Brazil", name_de: "Brasilien" },
+    Country { code: "BS", name_en: "Bahamas", name_de: "Bahamas" },
+    Country { code: "BT", name_en: "Bhutan", name_de: "Bhutan" },
+    Country { code: "BV", name_en: "Bouvet Island", name_de: "Bouvetinsel" },
+    Country { code: "BW", name_en: "Botswana", name_de: "Botswana" },
+    Country { code: "BY", name_en: "Belarus", name_de: "Belarus" },
+    Country { code: "BZ", name_en: "Belize", name_de: "Belize" },
+    Country { code: "CA", name_en: "Canada", name_de: "Kanada" },
+    Country { code: "CC", name_en: "Cocos (Keeling) Islands", name_de: "Kokosinseln" },
+    Country { code: "CD", name_en: "Congo, Democratic Republic of the", name_de: "Kongo, Demokratische Republik" },
+    Country { code: "CF", name_en: "Central African Republic", name_de: "Zentralafrikanische Republik" },
+    Country { code: "CG", name_en: "Congo", name_de: "Kongo" },
+    Country { code: "CH", name_en: "Switzerland", name_de: "Schweiz" },
+    Country { code: "CI", name_en: "Côte d'Ivoire", name_de: "Côte d'Ivoire" },
+    Country { code: "CK", name_en: "Cook Islands", name_de: "Cookinseln" },
+    Country { code: "CL", name_en: "Chile", name_de: "Chile" },
+    Country { code: "CM", name_en: "Cameroon", name_de: "Kamerun" },
+    Country { code: "CN", name_en: "China", name_de: "China" },
+    Country { code: "CO", name_en: "Colombia", name_de: "Kolumbien" },
+    Country { code: "CR", name_en: "Costa Rica", name_de: "Costa Rica" },
+    Country { code: "CU", name_en: "Cuba", name_de: "Kuba" },
+    Country { code: "CV", name_en: "Cabo Verde", name_de: "Cabo Verde" },
+    Country { code: "CW", name_en: "Curaçao", name_de: "Curaçao" },
+    Country { code: "CX", name_en: "Christmas Island", name_de: "Weihnachtsinsel" },
+    Country { code: "CY", name_en: "Cyprus", name_de: "Zypern" },
+    Country { code: "CZ", name_en: "Czechia", name_de: "Tschechien" },
+    Country { code: "DE", name_en: "Germany", name_de: "Deutschland" },
+    Country { code: "DJ", name_en: "Djibouti", name_de: "Dschibuti" },
+    Country { code: "DK", name_en: "Denmark", name_de: "Dänemark" },
+    Country { code: "DM", name_en: "Dominica", name_de: "Dominica" },
+    Country { code: "DO", name_en: "Dominican Republic", name_de: "Dominikanische Republik" },
+    Country { code: "DZ", name_en: "Algeria", name_de: "Algerien" },
+    Country { code: "EC", name_en: "Ecuador", name_de: "Ecuador" },
+    Country { code: "EE", name_en: "Estonia", name_de: "Estland" },
+    Country { code: "EG", name_en: "Egypt", name_de: "Ägypten" },
+    Country { code: "EH", name_en: "Western Sahara", name_de: "Westsahara" },
+    Country { code: "ER", name_en: "Eritrea", name_de: "Eritrea" },
+    Country { code: "ES", name_en: "Spain", name_de: "Spanien" },
+    Country { code: "ET", name_en: "Ethiopia", name_de: "Äthiopien" },
+    Country { code: "FI", name_en: "Finland", name_de: "Finnland" },
+    Country { code: "FJ", name_en: "Fiji", name_de: "Fidschi" },
+    Country { code: "FK", name_en: "Falkland Islands (Malvinas)", name_de: "Falklandinseln" },
+    Country { code: "FM", name_en: "Micronesia", name_de: "Mikronesien" },
+    Country { code: "FO", name_en: "Faroe Islands", name_de: "Färöer" },
+    Country { code: "FR", name_en: "France", name_de: "Frankreich" },
+    Country { code: "GA", name_en: "Gabon", name_de: "Gabun" },
+    Country { code: "GB", name_en: "United Kingdom", name_de: "Vereinigtes Königreich" },
+    Country { code: "GD", name_en: "Grenada", name_de: "Grenada" },
+    Country { code: "GE", name_en: "Georgia", name_de: "Georgien" },
+    Country { code: "GF", name_en: "French Guiana", name_de: "Französisch-Guayana" },
+    Country { code: "GG", name_en: "Guernsey", name_de: "Guernsey" },
+    Country { code: "GH", name_en: "Ghana", name_de: "Ghana" },
+    Country { code: "GI", name_en: "Gibraltar", name_de: "Gibraltar" },
+    Country { code: "GL", name_en: "Greenland", name_de: "Grönland" },
+    Country { code: "GM", name_en: "Gambia", name_de: "Gambia" },
+    Country { code: "GN", name_en: "Guinea", name_de: "Guinea" },
+    Country { code: "GP", name_en: "Guadeloupe", name_de: "Guadeloupe" },
+    Country { code: "GQ", name_en: "Equatorial Guinea", name_de: "Äquatorialguinea" },
+    Country { code: "GR", name_en: "Greece", name_de: "Griechenland" },
+    Country { code: "GS", name_en: "South Georgia and the South Sandwich Islands", name_de: "Südgeorgien und die Südlichen Sandwichinseln" },
+    Country { code: "GT", name_en: "Guatemala", name_de: "Guatemala" },
+    Country { code: "GU", name_en: "Guam", name_de: "Guam" },
+    Country { code: "GW", name_en: "Guinea-Bissau", name_de: "Guinea-Bissau" },
+    Country { code: "GY", name_en: "Guyana", name_de: "Guyana" },
+    Country { code: "HK", name_en: "Hong Kong", name_de: "Hongkong" },
+    Country { code: "HM", name_en: "Heard Island and McDonald Islands", name_de: "Heard und McDonaldinseln" },
+    Country { code: "HN", name_en: "Honduras", name_de: "Honduras" },
+    Country { code: "HR", name_en: "Croatia", name_de: "Kroatien" },
+    Country { code: "HT", name_en: "Haiti", name_de: "Haiti" },
+    Country { code: "HU", name_en: "Hungary", name_de: "Ungarn" },
+    Country { code: "ID", name_en: "Indonesia", name_de: "Indonesien" },
+    Country { code: "IE", name_en: "Ireland", name_de: "Irland" },
+    Country { code: "IL", name_en: "Israel", name_de: "Israel" },
+    Country { code: "IM", name_en: "Isle of Man", name_de: "Isle of Man" },
+    Country { code: "IN", name_en: "India", name_de: "Indien" },
+    Country { code: "IO", name_en: "British Indian Ocean Territory", name_de: "Britisches Territorium im Indischen Ozean" },
+    Country { code: "IQ", name_en: "Iraq", name_de: "Irak" },
+    Country { code: "IR", name_en: "Iran", name_de: "Iran" },
+    Country { code: "IS", name_en: "Iceland", name_de: "Island" },
+    Country { code: "IT", name_en: "Italy", name_de: "Italien" },
+    Country { code: "JE", name_en: "Jersey", name_de: "Jersey" },
+    Country { code: "JM", name_en: "Jamaica", name_de: "Jamaika" },
+    Country { code: "JO", name_en: "Jordan", name_de: "Jordanien" },
+    Country { code: "JP", name_en: "Japan", name_de: "Japan" },
+    Country { code: "KE", name_en: "Kenya", name_de: "Kenia" },
+    Country { code: "KG", name_en: "Kyrgyzstan", name_de: "Kirgisistan" },
+    Country { code: "KH", name_en: "Cambodia", name_de: "Kambodscha" },
+    Country { code: "KI", name_en: "Kiribati", name_de: "Kiribati" },
+    Country { code: "KM", name_en: "Comoros", name_de: "Komoren" },
+    Country { code: "KN", name_en: "Saint Kitts and Nevis", name_de: "St. Kitts und Nevis" },
+    Country { code: "KP", name_en: "Korea, Democratic People's Republic of", name_de: "Korea, Demokratische Volksrepublik" },
+    Country { code: "KR", name_en: "Korea, Republic of", name_de: "Korea, Republik" },
+    Country { code: "KW", name_en: "Kuwait", name_de: "Kuwait" },
+    Country { code: "KY", name_en: "Cayman Islands", name_de: "Kaimaninseln" },
+    Country { code: "KZ", name_en: "Kazakhstan", name_de: "Kasachstan" },
+    Country { code: "LA", name_en: "Lao People's Democratic Republic", name_de: "Laos" },
+    Country { code: "LB", name_en: "Lebanon", name_de: "Libanon" },
+    Country { code: "LC", name_en: "Saint Lucia", name_de: "St. Lucia" },
+    Country { code: "LI", name_en: "Liechtenstein", name_de: "Liechtenstein" },
+    Country { code: "LK", name_en: "Sri Lanka", name_de: "Sri Lanka" },
+    Country { code: "LR", name_en: "Liberia", name_de: "Liberia" },
+    Country { code: "LS", name_en: "Lesotho", name_de: "Lesotho" },
+    Country { code: "LT", name_en: "Lithuania", name_de: "Litauen" },
+    Country { code: "LU", name_en: "Luxembourg", name_de: "Luxemburg" },
+    Country { code: "LV", name_en: "Latvia", name_de: "Lettland" },
+    Country { code: "LY", name_en: "Libya", name_de: "Libyen" },
+    Country { code: "MA", name_en: "Morocco", name_de: "Marokko" },
+    Country { code: "MC", name_en: "Monaco", name_de: "Monaco" },
+    Country { code: "MD", name_en: "Moldova", name_de: "Moldau" },
+    Country { code: "ME", name_en: "Montenegro", name_de: "Montenegro" },
+    Country { code: "MF", name_en: "Saint Martin (French part)", name_de: "St. Martin (französischer Teil)" },
+    Country { code: "MG", name_en: "Madagascar", name_de: "Madagaskar" },
+    Country { code: "MH", name_en: "Marshall Islands", name_de: "Marshallinseln" },
+    Country { code: "MK", name_en: "North Macedonia", name_de: "Nordmazedonien" },
+    Country { code: "ML", name_en: "Mali", name_de: "Mali" },
+    Country { code: "MM", name_en: "Myanmar", name_de: "Myanmar" },
+    Country { code: "MN", name_en: "Mongolia", name_de: "Mongolei" },
+    Country { code: "MO", name_en: "Macao", name_de: "Macau" },
+    Country { code: "MP", name_en: "Northern Mariana Islands", name_de: "Nördliche Marianen" },
+    Country { code: "MQ", name_en: "Martinique", name_de: "Martinique" },
+    Country { code: "MR", name_en: "Mauritania", name_de: "Mauretanien" },
+    Country { code: "MS", name_en: "Montserrat", name_de: "Montserrat" },
+    Country { code: "MT", name_en: "Malta", name_de: "Malta" },
+    Country { code: "MU", name_en: "Mauritius", name_de: "Mauritius" },
+    Country { code: "MV", name_en: "Maldives", name_de: "Malediven" },
+    Country { code: "MW", name_en: "Malawi", name_de: "Malawi" },
+    Country { code: "MX", name_en: "Mexico", name_de: "Mexiko" },
+    Country { code: "MY", name_en: "Malaysia", name_de: "Malaysia" },
+    Country { code: "MZ", name_en: "Mozambique", name_de: "Mosambik" },
+    Country { code: "NA", name_en: "Namibia", name_de: "Namibia" },
+    Country { code: "NC", name_en: "New Caledonia", name_de: "Neukaledonien" },
+    Country { code: "NE", name_en: "Niger", name_de: "Niger" },
+    Country { code: "NF", name_en: "Norfolk Island", name_de: "Norfolkinsel" },
+    Country { code: "NG", name_en: "Nigeria", name_de: "Nigeria" },
+    Country { code: "NI", name_en: "Nicaragua", name_de: "Nicaragua" },
+    Country { code: "NL", name_en: "Netherlands", name_de: "Niederlande" },
+    Country { code: "NO", name_en: "Norway", name_de: "Norwegen" },
+    Country { code: "NP", name_en: "Nepal", name_de: "Nepal" },
+    Country { code: "NR", name_en: "Nauru", name_de: "Nauru" },
+    Country { code: "NU", name_en: "Niue", name_de: "Niue" },
+    Country { code: "NZ", name_en: "New Zealand", name_de: "Neuseeland" },
+    Country { code: "OM", name_en: "Oman", name_de: "Oman" },
+    Country { code: "PA", name_en: "Panama", name_de: "Panama" },
+    Country { code: "PE", name_en: "Peru", name_de: "Peru" },
+    Country { code: "PF", name_en: "French Polynesia", name_de: "Französisch-Polynesien" },
+    Country { code: "PG", name_en: "Papua New Guinea", name_de: "Papua-Neuguinea" },
+    Country { code: "PH", name_en: "Philippines", name_de: "Philippinen" },
+    Country { code: "PK", name_en: "Pakistan", name_de: "Pakistan" },
+    Country { code: "PL", name_en: "Poland", name_de: "Polen" },
+    Country { code: "PM", name_en: "Saint Pierre and Miquelon", name_de: "St. Pierre und Miquelon" },
+    Country { code: "PN", name_en: "Pitcairn", name_de: "Pitcairninseln" },
+    Country { code: "PR", name_en: "Puerto Rico", name_de: "Puerto Rico" },
+    Country { code: "PS", name_en: "Palestine, State of", name_de: "Palästina" },
+    Country { code: "PT", name_en: "Portugal", name_de: "Portugal" },
+    Country { code: "PW", name_en: "Palau", name_de: "Palau" },
+    Country { code: "PY", name_en: "Paraguay", name_de: "Paraguay" },
+    Country { code: "QA", name_en: "Qatar", name_de: "Katar" },
+    Country { code: "RE", name_en: "Réunion", name_de: "Réunion" },
+    Country { code: "RO", name_en: "Romania", name_de: "Rumänien" },
+    Country { code: "RS", name_en: "Serbia", name_de: "Serbien" },
+    Country { code: "RU", name_en: "Russian Federation", name_de: "Russische Föderation" },
+    Country { code: "RW", name_en: "Rwanda", name_de: "Ruanda" },
+    Country { code: "SA", name_en: "Saudi Arabia", name_de: "Saudi-Arabien" },
+    Country { code: "SB", name_en: "Solomon Islands", name_de: "Salomonen" },
+    Country { code: "SC", name_en: "Seychelles", name_de: "Seychellen" },
+    Country { code: "SD", name_en: "Sudan", name_de: "Sudan" },
+    Country { code: "SE", name_en: "Sweden", name_de: "Schweden" },
+    Country { code: "SG", name_en: "Singapore", name_de: "Singapur" },
+    Country { code: "SH", name_en: "Saint Helena, Ascension and Tristan da Cunha", name_de: "St. Helena, Ascension und Tristan da Cunha" },
+    Country { code: "SI", name_en: "Slovenia", name_de: "Slowenien" },
+    Country { code: "SJ", name_en: "Svalbard and Jan Mayen", name_de: "Svalbard und Jan Mayen" },
+    Country { code: "SK", name_en: "Slovakia", name_de: "Slowakei" },
+    Country { code: "SL", name_en: "Sierra Leone", name_de: "Sierra Leone" },
+    Country { code: "SM", name_en: "San Marino", name_de: "San Marino" },
+    Country { code: "SN", name_en: "Senegal", name_de: "Senegal" },
+    Country { code: "SO", name_en: "Somalia", name_de: "Somalia" },
+    Country { code: "SR", name_en: "Suriname", name_de: "Suriname" },
+    Country { code: "SS", name_en: "South Sudan", name_de: "Südsudan" },
+    Country { code: "ST", name_en: "Sao Tome and Principe", name_de: "São Tomé und Príncipe" },
+    Country { code: "SV", name_en: "El Salvador", name_de: "El Salvador" },
+    Country { code: "SX", name_en: "Sint Maarten (Dutch part)", name_de: "Sint Maarten (niederländischer Teil)" },
+    Country { code: "SY", name_en: "Syrian Arab Republic", name_de: "Syrien" },
+    Country { code: "SZ", name_en: "Eswatini", name_de: "Eswatini" },
+    Country { code: "TC", name_en: "Turks and Caicos Islands", name_de: "Turks- und Caicosinseln" },
+    Country { code: "TD", name_en: "Chad", name_de: "Tschad" },
+    Country { code: "TF", name_en: "French Southern Territories", name_de: "Französische Süd- und Antarktisgebiete" },
+    Country { code: "TG", name_en: "Togo", name_de: "Togo" },
+    Country { code: "TH", name_en: "Thailand", name_de: "Thailand" },
+    Country { code: "TJ", name_en: "Tajikistan", name_de: "Tadschikistan" },
+    Country { code: "TK", name_en: "Tokelau", name_de: "Tokelau" },
+    Country { code: "TL", name_en: "Timor-Leste", name_de: "Timor-Leste" },
+    Country { code: "TM", name_en: "Turkmenistan", name_de: "Turkmenistan" },
+    Country { code: "TN", name_en: "Tunisia", name_de: "Tunesien" },
+    Country { code: "TO", name_en: "Tonga", name_de: "Tonga" },
+    Country { code: "TR", name_en: "Türkiye", name_de: "Türkei" },
+    Country { code: "TT", name_en: "Trinidad and Tobago", name_de: "Trinidad und Tobago" },
+    Country { code: "TV", name_en: "Tuvalu", name_de: "Tuvalu" },
+    Country { code: "TW", name_en: "Taiwan", name_de: "Taiwan" },
+    Country { code: "TZ", name_en: "Tanzania", name_de: "Tansania" },
+    Country { code: "UA", name_en: "Ukraine", name_de: "Ukraine" },
+    Country { code: "UG", name_en: "Uganda", name_de: "Uganda" },
+    Country { code: "UM", name_en: "United States Minor Outlying Islands", name_de: "Kleinere Amerikanische Überseeinseln" },
+    Country { code: "US", name_en: "United States of America", name_de: "Vereinigte Staaten von Amerika" },
+    Country { code: "UY", name_en: "Uruguay", name_de: "Uruguay" },
+    Country { code: "UZ", name_en: "Uzbekistan", name_de: "Usbekistan" },
+    Country { code: "VA", name_en: "Holy See", name_de: "Vatikanstadt" },
+    Country { code: "VC", name_en: "Saint Vincent and the Grenadines", name_de: "St. Vincent und die Grenadinen" },
+    Country { code: "VE", name_en: "Venezuela", name_de: "Venezuela" },
+    Country { code: "VG", name_en: "Virgin Islands (British)", name_de: "Britische Jungferninseln" },
+    Country { code: "VI", name_en: "Virgin Islands (U.S.)", name_de: "Amerikanische Jungferninseln" },
+    Country { code: "VN", name_en: "Viet Nam", name_de: "Vietnam" },
+    Country { code: "VU", name_en: "Vanuatu", name_de: "Vanuatu" },
+    Country { code: "WF", name_en: "Wallis and Futuna", name_de: "Wallis und Futuna" },
+    Country { code: "WS", name_en: "Samoa", name_de: "Samoa" },
+    Country { code: "YE", name_en: "Yemen", name_de: "Jemen" },
+    Country { code: "YT", name_en: "Mayotte", name_de: "Mayotte" },
+    Country { code: "ZA", name_en: "South Africa", name_de: "Südafrika" },
+    Country { code: "ZM", name_en: "Zambia", name_de: "Sambia" },
+    Country { code: "ZW", name_en: "Zimbabwe", name_de: "Simbabwe" },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::{find_country, sorted_countries};
+    use config::Language;
+
+    #[test]
+    fn test_find_country() {
+        assert_eq!(find_country("DE").unwrap().name_de, "Deutschland");
+        assert_eq!(find_country("at").unwrap().name_en, "Austria");
+        assert!(find_country("XX").is_none());
+        assert!(find_country("").is_none());
+    }
+
+    #[test]
+    fn test_sorted_countries() {
+        let countries = sorted_countries(Language::German);
+        let egypt = countries.iter().position(|country| country.code == "EG").unwrap();
+        let austria = countries.iter().position(|country| country.code == "AT").unwrap();
+        let panama = countries.iter().position(|country| country.code == "PA").unwrap();
+        let albania = countries.iter().position(|country| country.code == "AL").unwrap();
+
+        assert_eq!(countries[0].code, "AF");
+        assert!(egypt < albania);
+        assert!(austria < panama);
+        assert_eq!(sorted_countries(Language::English)[0].code, "AF");
+    }
+}
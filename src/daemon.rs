@@ -0,0 +1,193 @@
+// Running the server in the background: --daemon on Unix, --service under the Windows service manager.
+
+#[cfg(unix)]
+pub use self::unix::daemonize;
+
+#[cfg(windows)]
+pub use self::windows::run_as_service;
+
+#[cfg(unix)]
+mod unix {
+    use std::fs::File;
+    use std::io::{self, Write};
+    use std::process;
+
+    use libc;
+
+    fn fork() -> io::Result<()> {
+        match unsafe { libc::fork() } {
+            -1 => Err(io::Error::last_os_error()),
+            // The child goes on
+            0 => Ok(()),
+            _ => process::exit(0)
+        }
+    }
+
+    // Detaches from the terminal and writes the pid of the remaining process to pid_file.
+    // Must be called before any thread is started. The working directory is kept,
+    // the paths in the configuration are relative to it.
+    pub fn daemonize(pid_file: &str) -> io::Result<()> {
+        fork()?;
+
+        if unsafe { libc::setsid() } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // The session leader exits, so the daemon can never get a terminal again
+        fork()?;
+
+        unsafe { libc::umask(0o027) };
+
+        // Still visible on the terminal if this fails
+        writeln!(File::create(pid_file)?, "{}", process::id())?;
+
+        let null = unsafe { libc::open(b"/dev/null\0".as_ptr() as *const libc::c_char, libc::O_RDWR) };
+        if null == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        for fd in 0..3 {
+            unsafe { libc::dup2(null, fd) };
+        }
+
+        if null > 2 {
+            unsafe { libc::close(null) };
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+#[allow(non_snake_case, non_camel_case_types)]
+mod windows {
+    use std::env;
+    use std::io;
+    use std::os::raw::c_void;
+    use std::process;
+    use std::ptr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    type DWORD = u32;
+    type SERVICE_STATUS_HANDLE = *mut c_void;
+
+    #[repr(C)]
+    struct SERVICE_STATUS {
+        dwServiceType: DWORD,
+        dwCurrentState: DWORD,
+        dwControlsAccepted: DWORD,
+        dwWin32ExitCode: DWORD,
+        dwServiceSpecificExitCode: DWORD,
+        dwCheckPoint: DWORD,
+        dwWaitHint: DWORD
+    }
+
+    #[repr(C)]
+    struct SERVICE_TABLE_ENTRYW {
+        lpServiceName: *const u16,
+        lpServiceProc: Option<extern "system" fn(DWORD, *mut *mut u16)>
+    }
+
+    type HandlerEx = extern "system" fn(DWORD, DWORD, *mut c_void, *mut c_void) -> DWORD;
+
+    #[link(name = "advapi32")]
+    extern "system" {
+        fn StartServiceCtrlDispatcherW(service_table: *const SERVICE_TABLE_ENTRYW) -> i32;
+        fn RegisterServiceCtrlHandlerExW(service_name: *const u16, handler: HandlerEx, context: *mut c_void) -> SERVICE_STATUS_HANDLE;
+        fn SetServiceStatus(handle: SERVICE_STATUS_HANDLE, status: *mut SERVICE_STATUS) -> i32;
+    }
+
+    const SERVICE_WIN32_OWN_PROCESS: DWORD = 0x10;
+    const SERVICE_STOPPED: DWORD = 1;
+    const SERVICE_START_PENDING: DWORD = 2;
+    const SERVICE_RUNNING: DWORD = 4;
+    const SERVICE_ACCEPT_STOP: DWORD = 1;
+    const SERVICE_ACCEPT_SHUTDOWN: DWORD = 4;
+    const SERVICE_CONTROL_STOP: DWORD = 1;
+    const SERVICE_CONTROL_INTERROGATE: DWORD = 4;
+    const SERVICE_CONTROL_SHUTDOWN: DWORD = 5;
+    const NO_ERROR: DWORD = 0;
+    const ERROR_CALL_NOT_IMPLEMENTED: DWORD = 120;
+
+    // Ignored for a service that runs in its own process, the name from "sc create" counts
+    const SERVICE_NAME: &'static str = "conference_registration";
+
+    // The service manager calls back without any context, so these are global
+    static STATUS_HANDLE: AtomicUsize = AtomicUsize::new(0);
+    static SERVE: AtomicUsize = AtomicUsize::new(0);
+
+    fn wide(value: &str) -> Vec<u16> {
+        value.encode_utf16().chain(Some(0)).collect()
+    }
+
+    fn set_state(state: DWORD) {
+        let mut status = SERVICE_STATUS {
+            dwServiceType: SERVICE_WIN32_OWN_PROCESS,
+            dwCurrentState: state,
+            dwControlsAccepted: if state == SERVICE_RUNNING { SERVICE_ACCEPT_STOP | SERVICE_ACCEPT_SHUTDOWN } else { 0 },
+            dwWin32ExitCode: NO_ERROR,
+            dwServiceSpecificExitCode: 0,
+            dwCheckPoint: 0,
+            dwWaitHint: 0
+        };
+
+        unsafe { SetServiceStatus(STATUS_HANDLE.load(Ordering::SeqCst) as SERVICE_STATUS_HANDLE, &mut status) };
+    }
+
+    extern "system" fn control_handler(control: DWORD, _event_type: DWORD, _event_data: *mut c_void, _context: *mut c_void) -> DWORD {
+        match control {
+            // Nothing is written outside of SQLite's transactions, so the process can just end
+            SERVICE_CONTROL_STOP | SERVICE_CONTROL_SHUTDOWN => {
+                set_state(SERVICE_STOPPED);
+                process::exit(0);
+            }
+            SERVICE_CONTROL_INTERROGATE => NO_ERROR,
+            _ => ERROR_CALL_NOT_IMPLEMENTED
+        }
+    }
+
+    extern "system" fn service_main(_argc: DWORD, _argv: *mut *mut u16) {
+        let name = wide(SERVICE_NAME);
+        let handle = unsafe { RegisterServiceCtrlHandlerExW(name.as_ptr(), control_handler, ptr::null_mut()) };
+        if handle.is_null() {
+            return;
+        }
+        STATUS_HANDLE.store(handle as usize, Ordering::SeqCst);
+
+        set_state(SERVICE_START_PENDING);
+
+        let serve: fn() = unsafe { ::std::mem::transmute(SERVE.load(Ordering::SeqCst)) };
+        let server = thread::spawn(serve);
+
+        set_state(SERVICE_RUNNING);
+
+        // Only returns if the server couldn't be started
+        let _ = server.join();
+        set_state(SERVICE_STOPPED);
+    }
+
+    // Hands the process over to the service manager, serve runs until the service is stopped.
+    // Services start in the system folder, so the working directory is moved next to the executable,
+    // where registration_config.ini and the templates are expected.
+    pub fn run_as_service(serve: fn()) -> io::Result<()> {
+        if let Some(folder) = env::current_exe()?.parent() {
+            env::set_current_dir(folder)?;
+        }
+
+        SERVE.store(serve as usize, Ordering::SeqCst);
+
+        let name = wide(SERVICE_NAME);
+        let service_table = [
+            SERVICE_TABLE_ENTRYW { lpServiceName: name.as_ptr(), lpServiceProc: Some(service_main) },
+            SERVICE_TABLE_ENTRYW { lpServiceName: ptr::null(), lpServiceProc: None }
+        ];
+
+        // Blocks until the service has stopped
+        if unsafe { StartServiceCtrlDispatcherW(service_table.as_ptr()) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+}
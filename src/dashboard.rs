@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+
+use iron::prelude::{Request, IronResult, Response, Set};
+use iron::status;
+
+use handlebars_iron::{Template};
+use serde_json::Value as JsonValue;
+use params::{Params, Map};
+use plugin::Pluggable;
+use persistent::{Read, Write};
+
+use ::DBConnection;
+use admin::with_admin;
+use config::Configuration;
+use database::{lock_connection, count_all_registrations, load_registration_page};
+use filter::{RegistrationFilter, payment_label};
+use handler::{HandleError, extract_string};
+use status::status_label;
+
+
+const PAGE_SIZE: i64 = 50;
+
+// The key in the query, the ORDER BY of the column and its heading
+const SORT_COLUMNS: [(&str, &str, &str); 8] = [
+    ("id", "id", "Nr."),
+    ("registered", "registered_at", "Angemeldet"),
+    ("name", "last_name COLLATE NOCASE, first_name COLLATE NOCASE", "Name"),
+    ("institution", "institution COLLATE NOCASE", "Institution"),
+    ("email", "email_to COLLATE NOCASE", "E-Mail"),
+    ("course", "course_type", "Kurs"),
+    ("status", "status", "Status"),
+    ("payment", "payment_status", "Zahlung")
+];
+
+/// Filter, sorting and page of /admin, from ?status=pending&sort=name&order=desc&page=2.
+/// Unknown values give the newest registrations first.
+#[derive(Debug, PartialEq)]
+struct Listing {
+    filter: RegistrationFilter,
+    // Index into SORT_COLUMNS
+    sort: usize,
+    descending: bool,
+    // Starts at 1
+    page: i64
+}
+
+impl Listing {
+    fn from_map(map: &Map) -> Listing {
+        let value = |key| extract_string(map, key).unwrap_or_default();
+        let page = value("page").parse::<i64>().ok().filter(|&page| page > 0).unwrap_or(1);
+        let filter = RegistrationFilter::from_map(map);
+
+        match SORT_COLUMNS.iter().position(|&(key, _, _)| key == value("sort")) {
+            Some(sort) => Listing { filter, sort, descending: value("order") == "desc", page },
+            None => Listing { filter, sort: 1, descending: true, page }
+        }
+    }
+
+    // Keeps the filter
+    fn url(&self, sort: usize, descending: bool, page: i64) -> String {
+        let query = self.filter.query_string();
+        format!("/admin?{}{}sort={}&order={}&page={}", query, if query.is_empty() { "" } else { "&" },
+            SORT_COLUMNS[sort].0, if descending { "desc" } else { "asc" }, page)
+    }
+
+    // A heading sorts by its column, the current one changes the direction. Both start on the first page again.
+    fn columns(&self) -> Vec<JsonValue> {
+        SORT_COLUMNS.iter().enumerate().map(|(index, &(_, _, label))| {
+            let active = index == self.sort;
+            json!({
+                "label": label,
+                "url": self.url(index, active && !self.descending, 1),
+                "marker": if !active { "" } else if self.descending { "▼" } else { "▲" }
+            })
+        }).collect()
+    }
+}
+
+fn page_count(total: i64) -> i64 {
+    ((total + PAGE_SIZE - 1) / PAGE_SIZE).max(1)
+}
+
+/// All registrations, whatever their status, for the front desk, with the filter of /admin/participants.
+/// /admin/participants has the exports.
+pub fn handle_dashboard(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "dashboard", dashboard_page)
+}
+
+fn dashboard_page(req: &mut Request) -> Result<Response, HandleError> {
+    let mut listing = Listing::from_map(req.get_ref::<Params>()?);
+    let config = req.get::<Read<Configuration>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let total = count_all_registrations(&db_connection, &listing.filter)?;
+    let pages = page_count(total);
+    listing.page = listing.page.min(pages);
+
+    let rows: Vec<JsonValue> = load_registration_page(&db_connection, &listing.filter, SORT_COLUMNS[listing.sort].1, listing.descending,
+        PAGE_SIZE, (listing.page - 1) * PAGE_SIZE)?.iter().map(|row| json!({
+        "id": row.id,
+        "registered_at": config.date_format.timestamp(&row.registered_at),
+        "name": format!("{} {}", row.first_name, row.last_name),
+        "institution": row.institution,
+        "email": row.email_to,
+        "course": if row.course_type == "course1" { config.course1.clone() } else { config.course2.clone() },
+        "status": status_label(&row.status),
+        "payment": payment_label(&row.payment_status)
+    })).collect();
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("columns".to_string(), json!(listing.columns()));
+    data.insert("filter".to_string(), listing.filter.form_json());
+    data.insert("sort".to_string(), json!(SORT_COLUMNS[listing.sort].0));
+    data.insert("order".to_string(), json!(if listing.descending { "desc" } else { "asc" }));
+    data.insert("registrations".to_string(), json!(rows));
+    data.insert("total".to_string(), json!(total));
+    data.insert("page".to_string(), json!(listing.page));
+    data.insert("pages".to_string(), json!(pages));
+    if listing.page > 1 {
+        data.insert("previous".to_string(), json!(listing.url(listing.sort, listing.descending, listing.page - 1)));
+    }
+    if listing.page < pages {
+        data.insert("next".to_string(), json!(listing.url(listing.sort, listing.descending, listing.page + 1)));
+    }
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("admin", data)).set_mut(status::Ok);
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Listing, page_count};
+    use filter::RegistrationFilter;
+    use status::RegistrationStatus;
+    use params::{Map, Value};
+
+    #[test]
+    fn test_listing() {
+        assert_eq!(Listing::from_map(&Map::new()), Listing { filter: RegistrationFilter::default(), sort: 1, descending: true, page: 1 });
+
+        let mut map = Map::new();
+        map.assign("sort", Value::String("name".into())).unwrap();
+        map.assign("page", Value::String("3".into())).unwrap();
+        let listing = Listing::from_map(&map);
+        assert_eq!(listing, Listing { filter: RegistrationFilter::default(), sort: 2, descending: false, page: 3 });
+
+        let columns = listing.columns();
+        assert_eq!(columns[2]["url"], json!("/admin?sort=name&order=desc&page=1"));
+        assert_eq!(columns[2]["marker"], json!("▲"));
+        assert_eq!(columns[3]["url"], json!("/admin?sort=institution&order=asc&page=1"));
+
+        map.assign("sort", Value::String("email_to; DROP TABLE registration".into())).unwrap();
+        map.assign("page", Value::String("-1".into())).unwrap();
+        assert_eq!(Listing::from_map(&map), Listing { filter: RegistrationFilter::default(), sort: 1, descending: true, page: 1 });
+
+        // The links keep the filter
+        map.assign("status", Value::String("waitlisted".into())).unwrap();
+        let listing = Listing::from_map(&map);
+        assert_eq!(listing.filter.status, Some(RegistrationStatus::Waitlisted));
+        assert_eq!(listing.url(0, false, 2), "/admin?status=waitlisted&sort=id&order=asc&page=2".to_string());
+    }
+
+    #[test]
+    fn test_page_count() {
+        assert_eq!(page_count(0), 1);
+        assert_eq!(page_count(50), 1);
+        assert_eq!(page_count(51), 2);
+    }
+}
@@ -0,0 +1,2980 @@
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
+
+use rusqlite::Connection;
+use rusqlite::types::{Value, ToSql, ToSqlOutput};
+use rusqlite;
+
+use token::generate_token;
+use dates::timestamp;
+use validation::normalize_institution;
+use handler::HandleError;
+use filter::{RegistrationFilter, FILTER_SQL};
+
+
+/// The database with all registrations
+pub struct Repository {
+    connection: Connection
+}
+
+impl Repository {
+    /// Opens or creates the SQLite database and adds missing tables and columns
+    pub fn open(filename: &str) -> Result<Repository, HandleError> {
+        let connection = Connection::open(filename)?;
+        init_database(&connection)?;
+
+        Ok(Repository { connection })
+    }
+
+    /// For queries the engine doesn't offer
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    pub fn count_registrations(&self) -> Result<i64, HandleError> {
+        Ok(count_registrations(&self.connection)?)
+    }
+
+    /// The connection is handed to the request handlers
+    pub fn into_connection(self) -> Connection {
+        self.connection
+    }
+}
+
+// The name shown to other participants, the legal name stays in the registration for invoicing
+#[derive(Debug, PartialEq)]
+pub struct Participant {
+    pub id: i64,
+    pub status: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub preferred_name: String,
+    pub pronouns: String,
+    pub institution: String,
+    // When a bounce for the confirmation email came back, empty if none did
+    pub email_undeliverable: String
+}
+
+impl Participant {
+    pub fn display_name(&self) -> String {
+        if self.preferred_name.is_empty() {
+            format!("{} {}", self.first_name, self.last_name)
+        } else {
+            self.preferred_name.clone()
+        }
+    }
+}
+
+/// Locks the connection the request handlers share. A handler that panics while holding the lock poisons the mutex,
+/// every later request would fail until a restart. The connection itself survives the panic, a transaction the
+/// handler may have left open is rolled back before the next request uses it.
+pub fn lock_connection<'a>(mutex: &'a Mutex<Connection>) -> Result<MutexGuard<'a, Connection>, HandleError> {
+    match mutex.lock() {
+        Ok(guard) => Ok(guard),
+        Err(poisoned) => {
+            error!("A request panicked while holding the database connection, the connection is used again");
+            let guard = poisoned.into_inner();
+            // Fails if no transaction was open, which is the usual case
+            let _ = guard.execute_batch("ROLLBACK");
+            mutex.clear_poison();
+            Ok(guard)
+        }
+    }
+}
+
+pub fn init_database(db_connection: &Connection) -> Result<(), HandleError> {
+    db_connection.execute("CREATE TABLE IF NOT EXISTS registration (
+              id              INTEGER PRIMARY KEY,
+              title           TEXT NOT NULL,
+              last_name       TEXT NOT NULL,
+              first_name      TEXT NOT NULL,
+              institution     TEXT NOT NULL,
+              street          TEXT NOT NULL,
+              street_no       TEXT NOT NULL,
+              zip_code        TEXT NOT NULL,
+              city            TEXT NOT NULL,
+              phone           TEXT NOT NULL,
+              email_to        TEXT NOT NULL,
+              more_info       TEXT NOT NULL,
+              price_category  TEXT NOT NULL,
+              course_type     TEXT NOT NULL
+              )", &[])?;
+
+    // Columns added after the first release, appended so that existing databases can be upgraded in place
+    if add_column(db_connection, "registration", "email_normalized", "TEXT NOT NULL DEFAULT ''")? {
+        db_connection.execute("UPDATE registration SET email_normalized = lower(trim(email_to))", &[])?;
+    }
+    add_column(db_connection, "registration", "orcid", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(db_connection, "registration", "country", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(db_connection, "registration", "mobile", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(db_connection, "registration", "preferred_name", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(db_connection, "registration", "pronouns", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(db_connection, "registration", "tshirt_size", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(db_connection, "registration", "emergency_name", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(db_connection, "registration", "emergency_phone", "TEXT NOT NULL DEFAULT ''")?;
+
+    // Secret used in the personal links sent to the participant
+    if add_column(db_connection, "registration", "token", "TEXT NOT NULL DEFAULT ''")? {
+        let ids = {
+            let mut stmt = db_connection.prepare("SELECT id FROM registration")?;
+            let rows = stmt.query_map(&[], |row| row.get::<i32, i64>(0))?;
+            rows.collect::<Result<Vec<i64>, rusqlite::Error>>()?
+        };
+
+        for id in ids {
+            db_connection.execute("UPDATE registration SET token = $1 WHERE id = $2", &[&generate_token()?, &id])?;
+        }
+    }
+
+    add_column(db_connection, "registration", "arrival_mode", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(db_connection, "registration", "arrival_number", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(db_connection, "registration", "arrival_time", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(db_connection, "registration", "share_ride", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column(db_connection, "registration", "children", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column(db_connection, "registration", "children_ages", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(db_connection, "registration", "child_care_days", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(db_connection, "registration", "accessibility_options", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(db_connection, "registration", "accessibility_needs", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(db_connection, "registration", "code_of_conduct_version", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(db_connection, "registration", "code_of_conduct_accepted", "TEXT NOT NULL DEFAULT ''")?;
+    // Registrations from before the question was asked count as not consented
+    add_column(db_connection, "registration", "photo_consent", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column(db_connection, "registration", "recording_consent", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column(db_connection, "registration", "fee", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column(db_connection, "registration", "banquet_guests", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column(db_connection, "registration", "room_sharing", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(db_connection, "registration", "roommate_email", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(db_connection, "registration", "fee_waiver_reason", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(db_connection, "registration", "fee_waiver_status", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(db_connection, "registration", "presentation", "TEXT NOT NULL DEFAULT 'none'")?;
+    add_column(db_connection, "registration", "presentation_title", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(db_connection, "registration", "presentation_authors", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(db_connection, "registration", "abstract_text", "TEXT NOT NULL DEFAULT ''")?;
+    // Key of the configured session and the order within it, set by the program committee
+    add_column(db_connection, "registration", "session", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(db_connection, "registration", "session_position", "INTEGER NOT NULL DEFAULT 0")?;
+    // Slides of a talk, uploaded by the speaker before the meeting
+    add_column(db_connection, "registration", "presentation_file", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(db_connection, "registration", "presentation_file_name", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(db_connection, "registration", "talk_duration", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column(db_connection, "registration", "talk_equipment", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(db_connection, "registration", "unavailable_days", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(db_connection, "registration", "email_undeliverable", "TEXT NOT NULL DEFAULT ''")?;
+    // Registrations from before the column existed count from the upgrade
+    if add_column(db_connection, "registration", "registered_at", "TEXT NOT NULL DEFAULT ''")? {
+        db_connection.execute("UPDATE registration SET registered_at = $1", &[&timestamp()])?;
+    }
+    // "unpaid", "paid" or "waived"
+    add_column(db_connection, "registration", "payment_status", "TEXT NOT NULL DEFAULT 'unpaid'")?;
+    add_column(db_connection, "registration", "payment_reminders", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column(db_connection, "registration", "last_payment_reminder", "TEXT NOT NULL DEFAULT ''")?;
+    // When the payment was booked or waived, and how the participant paid ("transfer", "cash" or "card")
+    add_column(db_connection, "registration", "paid_at", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(db_connection, "registration", "payment_method", "TEXT NOT NULL DEFAULT ''")?;
+    // The form as it was submitted (JSON), so that the registration can be derived again if the parsing was wrong
+    add_column(db_connection, "registration", "form_data", "TEXT NOT NULL DEFAULT ''")?;
+    // One-time token of the form, a second submission of the same form doesn't register again
+    add_column(db_connection, "registration", "form_token", "TEXT NOT NULL DEFAULT ''")?;
+    db_connection.execute("CREATE UNIQUE INDEX IF NOT EXISTS registration_form_token ON registration (form_token) WHERE form_token != ''", &[])?;
+    // A second recipient of the confirmation, as entered
+    add_column(db_connection, "registration", "confirmation_cc", "TEXT NOT NULL DEFAULT ''")?;
+    // "pending", "confirmed", "cancelled", "waitlisted" or "rejected", see status.rs. Everybody registered before counts as confirmed.
+    add_column(db_connection, "registration", "status", "TEXT NOT NULL DEFAULT 'confirmed'")?;
+    // The entry of the institution table the typed institution is an alias of, 0 if none is
+    add_column(db_connection, "registration", "institution_id", "INTEGER NOT NULL DEFAULT 0")?;
+
+    db_connection.execute("CREATE TABLE IF NOT EXISTS reimbursement (
+              id              INTEGER PRIMARY KEY,
+              registration_id INTEGER NOT NULL REFERENCES registration(id),
+              account_holder  TEXT NOT NULL,
+              iban            TEXT NOT NULL,
+              bic             TEXT NOT NULL,
+              amount          INTEGER NOT NULL,
+              description     TEXT NOT NULL,
+              receipt_file    TEXT NOT NULL,
+              receipt_name    TEXT NOT NULL,
+              status          TEXT NOT NULL,
+              submitted_at    TEXT NOT NULL,
+              updated_at      TEXT NOT NULL
+              )", &[])?;
+
+    db_connection.execute("CREATE TABLE IF NOT EXISTS social_booking (
+              id              INTEGER PRIMARY KEY,
+              registration_id INTEGER NOT NULL REFERENCES registration(id),
+              event           TEXT NOT NULL
+              )", &[])?;
+
+    db_connection.execute("CREATE TABLE IF NOT EXISTS presentation_topic (
+              registration_id INTEGER NOT NULL REFERENCES registration(id),
+              topic           TEXT NOT NULL,
+              PRIMARY KEY (registration_id, topic)
+              )", &[])?;
+
+    // score is NULL until the application has been reviewed
+    db_connection.execute("CREATE TABLE IF NOT EXISTS travel_grant (
+              id              INTEGER PRIMARY KEY,
+              registration_id INTEGER NOT NULL UNIQUE REFERENCES registration(id),
+              amount          INTEGER NOT NULL,
+              motivation      TEXT NOT NULL,
+              cv_file         TEXT NOT NULL,
+              cv_name         TEXT NOT NULL,
+              score           INTEGER,
+              award           INTEGER NOT NULL DEFAULT 0,
+              submitted_at    TEXT NOT NULL,
+              notified_at     TEXT NOT NULL DEFAULT ''
+              )", &[])?;
+
+    // Kept apart from the registrations, so that the addresses can be reused for the next event
+    // and deleting the event data does not lose the opt-ins
+    db_connection.execute("CREATE TABLE IF NOT EXISTS newsletter (
+              id              INTEGER PRIMARY KEY,
+              email           TEXT NOT NULL UNIQUE,
+              name            TEXT NOT NULL,
+              token           TEXT NOT NULL UNIQUE,
+              subscribed_at   TEXT NOT NULL
+              )", &[])?;
+
+    // Addresses that asked for no more bulk mail, outlives the registrations just like the newsletter
+    db_connection.execute("CREATE TABLE IF NOT EXISTS suppression (
+              email           TEXT PRIMARY KEY,
+              suppressed_at   TEXT NOT NULL
+              )", &[])?;
+
+    // Partially filled registration forms, form_data as in registration.form_data
+    db_connection.execute("CREATE TABLE IF NOT EXISTS draft (
+              token           TEXT PRIMARY KEY,
+              email           TEXT NOT NULL,
+              form_data       TEXT NOT NULL,
+              saved_at        TEXT NOT NULL,
+              expires_at      TEXT NOT NULL
+              )", &[])?;
+
+    // Settings changed at /admin/settings, they replace the values of the configuration file
+    db_connection.execute("CREATE TABLE IF NOT EXISTS setting (
+              key             TEXT PRIMARY KEY,
+              value           TEXT NOT NULL,
+              updated_at      TEXT NOT NULL
+              )", &[])?;
+
+    // Mail texts changed at /admin/mail_templates, they replace the built-in texts and the files of the configuration
+    db_connection.execute("CREATE TABLE IF NOT EXISTS mail_template (
+              name            TEXT PRIMARY KEY,
+              subject         TEXT NOT NULL,
+              body            TEXT NOT NULL,
+              updated_at      TEXT NOT NULL
+              )", &[])?;
+
+    // Every mail sent because of a change by an admin, sent_at or error is set once the mail server answered
+    db_connection.execute("CREATE TABLE IF NOT EXISTS outbox (
+              id              INTEGER PRIMARY KEY,
+              registration_id INTEGER NOT NULL REFERENCES registration(id),
+              template        TEXT NOT NULL,
+              email_to        TEXT NOT NULL,
+              subject         TEXT NOT NULL,
+              body            TEXT NOT NULL,
+              created_at      TEXT NOT NULL,
+              sent_at         TEXT NOT NULL DEFAULT '',
+              error           TEXT NOT NULL DEFAULT ''
+              )", &[])?;
+
+    // Devices an admin chose to stay logged in on, only the SHA-256 of the cookie is stored.
+    // Times are Unix timestamps, replaced_at is 0 until the token was rotated.
+    db_connection.execute("CREATE TABLE IF NOT EXISTS admin_token (
+              token_hash      TEXT PRIMARY KEY,
+              created_at      INTEGER NOT NULL,
+              expires_at      INTEGER NOT NULL,
+              replaced_at     INTEGER NOT NULL DEFAULT 0
+              )", &[])?;
+
+    // Links participants ask for at /login, the same way only the SHA-256 of the token is stored
+    db_connection.execute("CREATE TABLE IF NOT EXISTS login_link (
+              token_hash      TEXT PRIMARY KEY,
+              registration_id INTEGER NOT NULL REFERENCES registration(id),
+              expires_at      INTEGER NOT NULL
+              )", &[])?;
+
+    // Status changes and edits of the details, for the weekly lists at /admin/roster_changes.
+    // kind is "status" or "details", the statuses are only set for "status".
+    db_connection.execute("CREATE TABLE IF NOT EXISTS roster_change (
+              id              INTEGER PRIMARY KEY,
+              registration_id INTEGER NOT NULL REFERENCES registration(id),
+              kind            TEXT NOT NULL,
+              old_status      TEXT NOT NULL DEFAULT '',
+              new_status      TEXT NOT NULL DEFAULT '',
+              changed_at      TEXT NOT NULL
+              )", &[])?;
+
+    // Canonical institution names, curated at /admin/institutions. key is normalize_institution of an alias,
+    // the canonical name is an alias of itself.
+    db_connection.execute("CREATE TABLE IF NOT EXISTS institution (
+              id              INTEGER PRIMARY KEY,
+              name            TEXT NOT NULL UNIQUE
+              )", &[])?;
+
+    db_connection.execute("CREATE TABLE IF NOT EXISTS institution_alias (
+              key             TEXT PRIMARY KEY,
+              alias           TEXT NOT NULL,
+              institution_id  INTEGER NOT NULL REFERENCES institution(id)
+              )", &[])?;
+
+    Ok(())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Institution {
+    pub id: i64,
+    pub name: String,
+    // As they were entered, without the name itself
+    pub aliases: Vec<String>
+}
+
+// By name, the aliases in alphabetical order
+pub fn load_institutions(db_connection: &Connection) -> Result<Vec<Institution>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("
+        SELECT i.id, i.name, a.alias FROM institution i LEFT JOIN institution_alias a ON a.institution_id = i.id AND a.alias != i.name
+        ORDER BY i.name COLLATE NOCASE, i.id, a.alias COLLATE NOCASE")?;
+    let mut rows = stmt.query(&[])?;
+    let mut result: Vec<Institution> = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        let id: i64 = row.get(0);
+        let alias: Option<String> = row.get(2);
+
+        if result.last().is_none_or(|institution| institution.id != id) {
+            result.push(Institution { id, name: row.get(1), aliases: Vec::new() });
+        }
+
+        if let (Some(alias), Some(institution)) = (alias, result.last_mut()) {
+            institution.aliases.push(alias);
+        }
+    }
+
+    Ok(result)
+}
+
+// None if the name or one of its spellings is already taken
+pub fn add_institution(db_connection: &Connection, name: &str) -> Result<Option<i64>, rusqlite::Error> {
+    let key = normalize_institution(name);
+    let taken: i64 = db_connection.query_row("SELECT COUNT(*) FROM institution_alias WHERE key = $1", &[&key], |row| row.get(0))?;
+
+    if taken > 0 || db_connection.execute("INSERT OR IGNORE INTO institution (name) VALUES ($1)", &[&name])? == 0 {
+        return Ok(None);
+    }
+
+    let id = db_connection.last_insert_rowid();
+    db_connection.execute("INSERT INTO institution_alias (key, alias, institution_id) VALUES ($1, $2, $3)", &[&key, &name, &id])?;
+    Ok(Some(id))
+}
+
+// An alias that belonged to another institution moves over, false if the institution doesn't exist
+pub fn add_institution_alias(db_connection: &Connection, institution_id: i64, alias: &str) -> Result<bool, rusqlite::Error> {
+    let changed = db_connection.execute("
+        INSERT OR REPLACE INTO institution_alias (key, alias, institution_id) SELECT $1, $2, id FROM institution WHERE id = $3",
+        &[&normalize_institution(alias), &alias, &institution_id])?;
+    Ok(changed > 0)
+}
+
+// The canonical name stays an alias of its institution
+pub fn remove_institution_alias(db_connection: &Connection, alias: &str) -> Result<bool, rusqlite::Error> {
+    let removed = db_connection.execute("
+        DELETE FROM institution_alias WHERE key = $1 AND alias NOT IN (SELECT name FROM institution WHERE id = institution_id)",
+        &[&normalize_institution(alias)])?;
+    Ok(removed > 0)
+}
+
+fn institution_keys(db_connection: &Connection) -> Result<BTreeMap<String, i64>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("SELECT key, institution_id FROM institution_alias")?;
+    let rows = stmt.query_map(&[], |row| (row.get(0), row.get(1)))?;
+    rows.collect()
+}
+
+// Sets institution_id of every registration after the aliases changed, returns how many changed
+pub fn link_institutions(db_connection: &Connection) -> Result<usize, rusqlite::Error> {
+    let keys = institution_keys(db_connection)?;
+    let registrations = {
+        let mut stmt = db_connection.prepare("SELECT id, institution, institution_id FROM registration")?;
+        let rows = stmt.query_map(&[], |row| (row.get::<i32, i64>(0), row.get::<i32, String>(1), row.get::<i32, i64>(2)))?;
+        rows.collect::<Result<Vec<(i64, String, i64)>, rusqlite::Error>>()?
+    };
+
+    let mut changed = 0;
+
+    for (id, institution, linked) in registrations {
+        let institution_id = keys.get(&normalize_institution(&institution)).cloned().unwrap_or(0);
+
+        if institution_id != linked {
+            db_connection.execute("UPDATE registration SET institution_id = $1 WHERE id = $2", &[&institution_id, &id])?;
+            changed += 1;
+        }
+    }
+
+    Ok(changed)
+}
+
+// The same for one registration, after it was stored or its institution corrected
+pub fn link_institution(db_connection: &Connection, id: i64) -> Result<(), rusqlite::Error> {
+    let institution: String = db_connection.query_row("SELECT institution FROM registration WHERE id = $1", &[&id], |row| row.get(0))?;
+    let institution_id: i64 = {
+        let mut stmt = db_connection.prepare("SELECT institution_id FROM institution_alias WHERE key = $1")?;
+        let mut rows = stmt.query(&[&normalize_institution(&institution)])?;
+
+        match rows.next() {
+            Some(row) => row?.get(0),
+            None => 0
+        }
+    };
+
+    db_connection.execute("UPDATE registration SET institution_id = $1 WHERE id = $2", &[&institution_id, &id])?;
+    Ok(())
+}
+
+// The linked institution and the typed name of everybody who takes part (pending or confirmed)
+pub fn load_institution_links(db_connection: &Connection) -> Result<Vec<(i64, String)>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("
+        SELECT institution_id, institution FROM registration WHERE status IN ('pending', 'confirmed') ORDER BY id")?;
+    let rows = stmt.query_map(&[], |row| (row.get(0), row.get(1)))?;
+    rows.collect()
+}
+
+// Case-insensitive substring search over the institution names already registered.
+// Called on every keystroke of the autocompletion, so the statement stays prepared on the connection,
+// the same for the statements of each new registration below.
+pub fn find_institutions(db_connection: &Connection, query: &str, limit: u32) -> Result<Vec<String>, rusqlite::Error> {
+    let pattern = format!("%{}%", query.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+    let mut stmt = db_connection.prepare_cached("
+        SELECT DISTINCT institution FROM registration
+        WHERE institution LIKE $1 ESCAPE '\\'
+        ORDER BY institution COLLATE NOCASE
+        LIMIT $2")?;
+    let mut rows = stmt.query(&[&pattern, &limit])?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        result.push(row?.get(0));
+    }
+
+    Ok(result)
+}
+
+pub fn find_by_form_token(db_connection: &Connection, form_token: &str) -> Result<Option<i64>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare_cached("SELECT id FROM registration WHERE form_token = $1 AND form_token != ''")?;
+    let mut rows = stmt.query(&[&form_token])?;
+
+    match rows.next() {
+        Some(row) => Ok(Some(row?.get(0))),
+        None => Ok(None)
+    }
+}
+
+// Only the registrations that take a place, cancelled and waitlisted ones don't count here or below
+pub fn count_registrations(db_connection: &Connection) -> Result<i64, rusqlite::Error> {
+    db_connection.prepare_cached("SELECT COUNT(*) FROM registration WHERE status IN ('pending', 'confirmed')")?.query_row(&[], |row| row.get(0))
+}
+
+pub fn count_by_status(db_connection: &Connection) -> Result<Vec<(String, i64)>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("SELECT status, COUNT(*) FROM registration GROUP BY status")?;
+    let mut rows = stmt.query(&[])?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        result.push((row.get(0), row.get(1)));
+    }
+
+    Ok(result)
+}
+
+// Changes whenever anything is written to the database: total_changes() counts the writes over this
+// connection, data_version the ones over the connections of the background jobs
+pub fn data_version(db_connection: &Connection) -> Result<(i64, i64), rusqlite::Error> {
+    let changes = db_connection.prepare_cached("SELECT total_changes()")?.query_row(&[], |row| row.get(0))?;
+    let version = db_connection.prepare_cached("PRAGMA data_version")?.query_row(&[], |row| row.get(0))?;
+    Ok((changes, version))
+}
+
+// Number of registrations per day (YYYY-MM-DD), oldest first. Registrations from before
+// the registration time was recorded all count for the day of the upgrade.
+pub fn count_by_day(db_connection: &Connection) -> Result<Vec<(String, i64)>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("
+        SELECT substr(registered_at, 1, 10) AS day, COUNT(*) FROM registration
+        WHERE registered_at != ''
+        GROUP BY day
+        ORDER BY day")?;
+    let mut rows = stmt.query(&[])?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        result.push((row.get(0), row.get(1)));
+    }
+
+    Ok(result)
+}
+
+// Number of registrations per ISO country code, largest group first
+pub fn count_by_country(db_connection: &Connection) -> Result<Vec<(String, i64)>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("
+        SELECT country, COUNT(*) AS participants FROM registration
+        WHERE status IN ('pending', 'confirmed')
+        GROUP BY country
+        ORDER BY participants DESC, country")?;
+    let mut rows = stmt.query(&[])?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        result.push((row.get(0), row.get(1)));
+    }
+
+    Ok(result)
+}
+
+// Number of talks and of posters
+pub fn count_by_presentation(db_connection: &Connection) -> Result<(i64, i64), rusqlite::Error> {
+    db_connection.query_row("
+        SELECT COALESCE(SUM(presentation = 'talk'), 0), COALESCE(SUM(presentation = 'poster'), 0) FROM registration
+        WHERE status IN ('pending', 'confirmed')",
+        &[], |row| (row.get(0), row.get(1)))
+}
+
+pub fn count_by_tshirt_size(db_connection: &Connection) -> Result<Vec<(String, i64)>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("
+        SELECT tshirt_size, COUNT(*) FROM registration
+        WHERE tshirt_size != '' AND status IN ('pending', 'confirmed')
+        GROUP BY tshirt_size")?;
+    let mut rows = stmt.query(&[])?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        result.push((row.get(0), row.get(1)));
+    }
+
+    Ok(result)
+}
+
+// Only the first bounce is recorded
+pub fn mark_undeliverable(db_connection: &Connection, email_normalized: &str, timestamp: &str) -> Result<usize, rusqlite::Error> {
+    let changed = db_connection.execute("
+        UPDATE registration SET email_undeliverable = $1 WHERE email_normalized = $2 AND email_undeliverable = ''",
+        &[&timestamp, &email_normalized])?;
+
+    Ok(changed as usize)
+}
+
+// Everybody, whatever the status, unless only one status is asked for
+pub fn load_participants(db_connection: &Connection, filter: &RegistrationFilter) -> Result<Vec<Participant>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare(&format!("
+        SELECT id, status, first_name, last_name, preferred_name, pronouns, institution, email_undeliverable FROM registration
+        WHERE {}
+        ORDER BY last_name COLLATE NOCASE, first_name COLLATE NOCASE", FILTER_SQL))?;
+    let values = filter.sql_values();
+    let mut rows = stmt.query(&filter_params(&values))?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        result.push(Participant {
+            id: row.get(0),
+            status: row.get(1),
+            first_name: row.get(2),
+            last_name: row.get(3),
+            preferred_name: row.get(4),
+            pronouns: row.get(5),
+            institution: row.get(6),
+            email_undeliverable: row.get(7)
+        });
+    }
+
+    Ok(result)
+}
+
+// The values of RegistrationFilter::sql_values as query parameters
+fn filter_params(values: &[String]) -> Vec<&dyn ToSql> {
+    values.iter().map(|value| value as &dyn ToSql).collect()
+}
+
+#[derive(Debug, PartialEq)]
+pub struct PendingRegistration {
+    pub id: i64,
+    pub first_name: String,
+    pub last_name: String,
+    pub institution: String,
+    pub email_to: String,
+    pub country: String,
+    pub course_type: String,
+    pub presentation: String,
+    pub presentation_title: String,
+    pub more_info: String,
+    pub registered_at: String
+}
+
+// The queue of /admin/approvals, the longest waiting first
+pub fn load_pending_registrations(db_connection: &Connection) -> Result<Vec<PendingRegistration>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("
+        SELECT id, first_name, last_name, institution, email_to, country, course_type, presentation, presentation_title,
+               more_info, registered_at FROM registration
+        WHERE status = 'pending'
+        ORDER BY registered_at, id")?;
+    let mut rows = stmt.query(&[])?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        result.push(PendingRegistration {
+            id: row.get(0),
+            first_name: row.get(1),
+            last_name: row.get(2),
+            institution: row.get(3),
+            email_to: row.get(4),
+            country: row.get(5),
+            course_type: row.get(6),
+            presentation: row.get(7),
+            presentation_title: row.get(8),
+            more_info: row.get(9),
+            registered_at: row.get(10)
+        });
+    }
+
+    Ok(result)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RegistrationRow {
+    pub id: i64,
+    pub registered_at: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub institution: String,
+    pub email_to: String,
+    pub course_type: String,
+    pub status: String,
+    pub payment_status: String
+}
+
+pub fn count_all_registrations(db_connection: &Connection, filter: &RegistrationFilter) -> Result<i64, rusqlite::Error> {
+    let values = filter.sql_values();
+    db_connection.query_row(&format!("SELECT COUNT(*) FROM registration WHERE {}", FILTER_SQL), &filter_params(&values), |row| row.get(0))
+}
+
+// One page of /admin. order_by comes from the columns the page offers, never from the request.
+// The id keeps the order of equal values the same from page to page.
+pub fn load_registration_page(db_connection: &Connection, filter: &RegistrationFilter, order_by: &'static str, descending: bool,
+    limit: i64, offset: i64) -> Result<Vec<RegistrationRow>, rusqlite::Error> {
+
+    let direction = if descending { "DESC" } else { "ASC" };
+    let mut stmt = db_connection.prepare(&format!("
+        SELECT id, registered_at, first_name, last_name, institution, email_to, course_type, status, payment_status FROM registration
+        WHERE {}
+        ORDER BY {} {}, id {}
+        LIMIT $6 OFFSET $7", FILTER_SQL, order_by, direction, direction))?;
+    let values = filter.sql_values();
+    let mut params = filter_params(&values);
+    params.push(&limit);
+    params.push(&offset);
+    let mut rows = stmt.query(&params)?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        result.push(RegistrationRow {
+            id: row.get(0),
+            registered_at: row.get(1),
+            first_name: row.get(2),
+            last_name: row.get(3),
+            institution: row.get(4),
+            email_to: row.get(5),
+            course_type: row.get(6),
+            status: row.get(7),
+            payment_status: row.get(8)
+        });
+    }
+
+    Ok(result)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct EmergencyContact {
+    pub first_name: String,
+    pub last_name: String,
+    pub course_type: String,
+    pub mobile: String,
+    pub emergency_name: String,
+    pub emergency_phone: String
+}
+
+// Everybody of the filter's selection who gave an emergency contact, i.e. registered for a field trip
+pub fn load_emergency_contacts(db_connection: &Connection, filter: &RegistrationFilter) -> Result<Vec<EmergencyContact>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare(&format!("
+        SELECT first_name, last_name, course_type, mobile, emergency_name, emergency_phone FROM registration
+        WHERE {} AND emergency_name != '' AND status IN ('pending', 'confirmed')
+        ORDER BY course_type, last_name COLLATE NOCASE, first_name COLLATE NOCASE", FILTER_SQL))?;
+    let values = filter.sql_values();
+    let mut rows = stmt.query(&filter_params(&values))?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        result.push(EmergencyContact {
+            first_name: row.get(0),
+            last_name: row.get(1),
+            course_type: row.get(2),
+            mobile: row.get(3),
+            emergency_name: row.get(4),
+            emergency_phone: row.get(5)
+        });
+    }
+
+    Ok(result)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct MediaConsent {
+    pub participant: Participant,
+    pub photo_consent: bool,
+    pub recording_consent: bool
+}
+
+// Everybody of the filter's selection who must not be photographed or recorded
+pub fn load_media_refusals(db_connection: &Connection, filter: &RegistrationFilter) -> Result<Vec<MediaConsent>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare(&format!("
+        SELECT first_name, last_name, preferred_name, pronouns, institution, photo_consent, recording_consent, email_undeliverable, id, status FROM registration
+        WHERE {} AND (photo_consent = 0 OR recording_consent = 0) AND status IN ('pending', 'confirmed')
+        ORDER BY last_name COLLATE NOCASE, first_name COLLATE NOCASE", FILTER_SQL))?;
+    let values = filter.sql_values();
+    let mut rows = stmt.query(&filter_params(&values))?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        result.push(MediaConsent {
+            participant: Participant {
+                id: row.get(8),
+                status: row.get(9),
+                first_name: row.get(0),
+                last_name: row.get(1),
+                preferred_name: row.get(2),
+                pronouns: row.get(3),
+                institution: row.get(4),
+                email_undeliverable: row.get(7)
+            },
+            photo_consent: row.get(5),
+            recording_consent: row.get(6)
+        });
+    }
+
+    Ok(result)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Arrival {
+    pub first_name: String,
+    pub last_name: String,
+    pub mobile: String,
+    pub arrival_mode: String,
+    pub arrival_number: String,
+    pub arrival_time: String,
+    pub share_ride: bool
+}
+
+// Everybody who told us when they arrive, in order of arrival
+pub fn load_arrivals(db_connection: &Connection) -> Result<Vec<Arrival>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("
+        SELECT first_name, last_name, mobile, arrival_mode, arrival_number, arrival_time, share_ride FROM registration
+        WHERE arrival_time != '' AND status IN ('pending', 'confirmed')
+        ORDER BY arrival_time, last_name COLLATE NOCASE")?;
+    let mut rows = stmt.query(&[])?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        result.push(Arrival {
+            first_name: row.get(0),
+            last_name: row.get(1),
+            mobile: row.get(2),
+            arrival_mode: row.get(3),
+            arrival_number: row.get(4),
+            arrival_time: row.get(5),
+            share_ride: row.get(6)
+        });
+    }
+
+    Ok(result)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ChildCareRequest {
+    pub first_name: String,
+    pub last_name: String,
+    pub email_to: String,
+    pub children: i64,
+    pub children_ages: String,
+    pub days: Vec<String>
+}
+
+pub fn load_child_care_requests(db_connection: &Connection) -> Result<Vec<ChildCareRequest>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("
+        SELECT first_name, last_name, email_to, children, children_ages, child_care_days FROM registration
+        WHERE children > 0 AND status IN ('pending', 'confirmed')
+        ORDER BY last_name COLLATE NOCASE, first_name COLLATE NOCASE")?;
+    let mut rows = stmt.query(&[])?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        let days: String = row.get(5);
+        result.push(ChildCareRequest {
+            first_name: row.get(0),
+            last_name: row.get(1),
+            email_to: row.get(2),
+            children: row.get(3),
+            children_ages: row.get(4),
+            days: days.split(", ").filter(|day| !day.is_empty()).map(|day| day.to_string()).collect()
+        });
+    }
+
+    Ok(result)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct AccessibilityNeed {
+    pub first_name: String,
+    pub last_name: String,
+    pub email_to: String,
+    pub mobile: String,
+    pub options: Vec<String>,
+    pub needs: String
+}
+
+// Everybody who asked for something, checkboxes or free text
+pub fn load_accessibility_needs(db_connection: &Connection) -> Result<Vec<AccessibilityNeed>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("
+        SELECT first_name, last_name, email_to, mobile, accessibility_options, accessibility_needs FROM registration
+        WHERE (accessibility_options != '' OR accessibility_needs != '') AND status IN ('pending', 'confirmed')
+        ORDER BY last_name COLLATE NOCASE, first_name COLLATE NOCASE")?;
+    let mut rows = stmt.query(&[])?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        let options: String = row.get(4);
+        result.push(AccessibilityNeed {
+            first_name: row.get(0),
+            last_name: row.get(1),
+            email_to: row.get(2),
+            mobile: row.get(3),
+            options: options.split(',').filter(|option| !option.is_empty()).map(|option| option.to_string()).collect(),
+            needs: row.get(5)
+        });
+    }
+
+    Ok(result)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RoomRequest {
+    pub first_name: String,
+    pub last_name: String,
+    pub email_normalized: String,
+    pub roommate_email: String
+}
+
+// Everybody who wants to share a hotel room, in order of registration
+pub fn load_room_requests(db_connection: &Connection) -> Result<Vec<RoomRequest>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("
+        SELECT first_name, last_name, email_normalized, roommate_email FROM registration
+        WHERE room_sharing = 'shared' AND status IN ('pending', 'confirmed')
+        ORDER BY id")?;
+    let mut rows = stmt.query(&[])?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        result.push(RoomRequest {
+            first_name: row.get(0),
+            last_name: row.get(1),
+            email_normalized: row.get(2),
+            roommate_email: row.get(3)
+        });
+    }
+
+    Ok(result)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FeeWaiver {
+    pub id: i64,
+    pub title: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub email_to: String,
+    pub fee: i64,
+    pub reason: String,
+    pub status: String
+}
+
+// Open requests first, then the decided ones
+pub fn load_fee_waivers(db_connection: &Connection) -> Result<Vec<FeeWaiver>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("
+        SELECT id, title, first_name, last_name, email_to, fee, fee_waiver_reason, fee_waiver_status FROM registration
+        WHERE fee_waiver_status != ''
+        ORDER BY fee_waiver_status != 'requested', id")?;
+    let mut rows = stmt.query(&[])?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        result.push(FeeWaiver {
+            id: row.get(0),
+            title: row.get(1),
+            first_name: row.get(2),
+            last_name: row.get(3),
+            email_to: row.get(4),
+            fee: row.get(5),
+            reason: row.get(6),
+            status: row.get(7)
+        });
+    }
+
+    Ok(result)
+}
+
+// Only open requests can be decided, an approved waiver sets the fee to zero.
+// Returns false if the request was already decided.
+pub fn decide_fee_waiver(db_connection: &Connection, id: i64, approved: bool) -> Result<bool, rusqlite::Error> {
+    let status = if approved { "approved" } else { "denied" };
+    let changed = db_connection.execute("
+        UPDATE registration SET fee_waiver_status = $1, fee = CASE WHEN $2 THEN 0 ELSE fee END
+        WHERE id = $3 AND fee_waiver_status = 'requested'", &[&status, &approved, &id])?;
+
+    Ok(changed > 0)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Abstract {
+    pub id: i64,
+    pub first_name: String,
+    pub last_name: String,
+    pub presentation: String,
+    pub title: String,
+    pub authors: Vec<String>,
+    pub text: String,
+    pub session: String,
+    pub position: i64,
+    // Empty until the presenter uploaded the file
+    pub file: String,
+    pub file_name: String,
+    // Scheduling wishes of the speaker
+    pub talk_duration: i64,
+    pub talk_equipment: String,
+    pub unavailable_days: String,
+    pub status: String,
+    pub topics: Vec<String>
+}
+
+impl Abstract {
+    pub fn presenter(&self) -> String {
+        format!("{} {}", self.first_name, self.last_name)
+    }
+}
+
+// The talks and posters of everybody who takes part (pending or confirmed), in the order of their position
+// within a session. Cancelled, rejected and waitlisted presenters don't show up in the program or the gallery.
+pub fn load_abstracts(db_connection: &Connection) -> Result<Vec<Abstract>, rusqlite::Error> {
+    query_abstracts(db_connection, "AND status IN ('pending', 'confirmed')")
+}
+
+// Every talk and poster whatever the status of the registration, for the admin
+pub fn load_all_abstracts(db_connection: &Connection) -> Result<Vec<Abstract>, rusqlite::Error> {
+    query_abstracts(db_connection, "")
+}
+
+fn query_abstracts(db_connection: &Connection, condition: &str) -> Result<Vec<Abstract>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare(&format!("
+        SELECT id, first_name, last_name, presentation, presentation_title, presentation_authors, abstract_text,
+          session, session_position, presentation_file, presentation_file_name,
+          talk_duration, talk_equipment, unavailable_days, status FROM registration
+        WHERE presentation != 'none' {}
+        ORDER BY session_position, id", condition))?;
+    let mut rows = stmt.query(&[])?;
+    let mut result = Vec::new();
+    let mut topics = load_presentation_topics(db_connection)?;
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        let id: i64 = row.get(0);
+        let authors: String = row.get(5);
+        result.push(Abstract {
+            id,
+            first_name: row.get(1),
+            last_name: row.get(2),
+            presentation: row.get(3),
+            title: row.get(4),
+            authors: authors.lines().map(|author| author.trim()).filter(|author| !author.is_empty()).map(|author| author.to_string()).collect(),
+            text: row.get(6),
+            session: row.get(7),
+            position: row.get(8),
+            file: row.get(9),
+            file_name: row.get(10),
+            talk_duration: row.get(11),
+            talk_equipment: row.get(12),
+            unavailable_days: row.get(13),
+            status: row.get(14),
+            topics: topics.remove(&id).unwrap_or_default()
+        });
+    }
+
+    Ok(result)
+}
+
+pub fn add_presentation_topic(db_connection: &Connection, registration_id: i64, topic: &str) -> Result<(), rusqlite::Error> {
+    db_connection.prepare_cached("INSERT OR IGNORE INTO presentation_topic (registration_id, topic) VALUES ($1, $2)")?
+        .execute(&[&registration_id, &topic])?;
+    Ok(())
+}
+
+fn load_presentation_topics(db_connection: &Connection) -> Result<BTreeMap<i64, Vec<String>>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("SELECT registration_id, topic FROM presentation_topic ORDER BY rowid")?;
+    let mut rows = stmt.query(&[])?;
+    let mut result: BTreeMap<i64, Vec<String>> = BTreeMap::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        result.entry(row.get(0)).or_default().push(row.get(1));
+    }
+
+    Ok(result)
+}
+
+pub fn set_presentation_file(db_connection: &Connection, id: i64, file: &str, file_name: &str) -> Result<(), rusqlite::Error> {
+    db_connection.execute("
+        UPDATE registration SET presentation_file = $1, presentation_file_name = $2 WHERE id = $3",
+        &[&file, &file_name, &id])?;
+
+    Ok(())
+}
+
+// An empty session removes the presentation from the program
+pub fn assign_session(db_connection: &Connection, id: i64, session: &str, position: i64) -> Result<bool, rusqlite::Error> {
+    let changed = db_connection.execute("
+        UPDATE registration SET session = $1, session_position = $2 WHERE id = $3 AND presentation != 'none'",
+        &[&session, &position, &id])?;
+
+    Ok(changed > 0)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct TokenOwner {
+    pub id: i64,
+    pub first_name: String,
+    pub last_name: String,
+    pub email_normalized: String
+}
+
+pub fn find_by_token(db_connection: &Connection, token: &str) -> Result<Option<TokenOwner>, rusqlite::Error> {
+    // An empty token would match all old rows that never got one
+    if token.is_empty() {
+        return Ok(None);
+    }
+
+    let mut stmt = db_connection.prepare("
+        SELECT id, first_name, last_name, email_normalized FROM registration WHERE token = $1")?;
+    let mut rows = stmt.query(&[&token])?;
+
+    match rows.next() {
+        Some(row) => {
+            let row = row?;
+            Ok(Some(TokenOwner {
+                id: row.get(0),
+                first_name: row.get(1),
+                last_name: row.get(2),
+                email_normalized: row.get(3)
+            }))
+        }
+        None => Ok(None)
+    }
+}
+
+pub fn book_social_event(db_connection: &Connection, registration_id: i64, event: &str) -> Result<(), rusqlite::Error> {
+    db_connection.prepare_cached("INSERT INTO social_booking (registration_id, event) VALUES ($1, $2)")?.execute(&[&registration_id, &event])?;
+    Ok(())
+}
+
+pub fn count_social_bookings(db_connection: &Connection) -> Result<Vec<(String, i64)>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare_cached("
+        SELECT event, count(*) FROM social_booking
+        WHERE registration_id NOT IN (SELECT id FROM registration WHERE status IN ('cancelled', 'waitlisted', 'rejected'))
+        GROUP BY event ORDER BY event")?;
+    let mut rows = stmt.query(&[])?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        result.push((row.get(0), row.get(1)));
+    }
+
+    Ok(result)
+}
+
+pub fn count_banquet_guests(db_connection: &Connection) -> Result<i64, rusqlite::Error> {
+    db_connection.prepare_cached("SELECT coalesce(sum(banquet_guests), 0) FROM registration WHERE status IN ('pending', 'confirmed')")?.query_row(&[], |row| row.get(0))
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Subscriber {
+    pub email: String,
+    pub name: String,
+    pub token: String
+}
+
+// Subscribing twice keeps the first subscription and its unsubscribe token
+pub fn subscribe_newsletter(db_connection: &Connection, email: &str, name: &str, token: &str) -> Result<(), rusqlite::Error> {
+    db_connection.prepare_cached("INSERT OR IGNORE INTO newsletter (email, name, token, subscribed_at) VALUES ($1, $2, $3, $4)")?
+        .execute(&[&email, &name, &token, &timestamp()])?;
+    Ok(())
+}
+
+pub fn load_newsletter_subscribers(db_connection: &Connection) -> Result<Vec<Subscriber>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("
+        SELECT email, name, token FROM newsletter
+        WHERE email NOT IN (SELECT email FROM suppression)
+        ORDER BY email")?;
+    let mut rows = stmt.query(&[])?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        result.push(Subscriber {
+            email: row.get(0),
+            name: row.get(1),
+            token: row.get(2)
+        });
+    }
+
+    Ok(result)
+}
+
+// The token is either a newsletter token or the token of a registration.
+// Returns false if the token is unknown.
+pub fn suppress_by_token(db_connection: &Connection, token: &str, timestamp: &str) -> Result<bool, rusqlite::Error> {
+    let email = match find_by_token(db_connection, token)? {
+        Some(owner) => owner.email_normalized,
+        None => {
+            let mut stmt = db_connection.prepare("SELECT email FROM newsletter WHERE token = $1")?;
+            let mut rows = stmt.query(&[&token])?;
+
+            match rows.next() {
+                Some(row) => row?.get(0),
+                None => return Ok(false)
+            }
+        }
+    };
+
+    db_connection.execute("INSERT OR IGNORE INTO suppression (email, suppressed_at) VALUES ($1, $2)", &[&email, &timestamp])?;
+    db_connection.execute("DELETE FROM newsletter WHERE email = $1", &[&email])?;
+
+    Ok(true)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct BulkRecipient {
+    pub email_to: String,
+    pub name: String,
+    pub token: String
+}
+
+// One entry per address, without bounced and suppressed addresses
+pub fn load_bulk_recipients(db_connection: &Connection, filter: &RegistrationFilter) -> Result<Vec<BulkRecipient>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare(&format!("
+        SELECT email_to, first_name || ' ' || last_name, token FROM registration
+        WHERE {} AND email_undeliverable = '' AND token != '' AND email_normalized NOT IN (SELECT email FROM suppression)
+        GROUP BY email_normalized
+        ORDER BY email_normalized", FILTER_SQL))?;
+    let values = filter.sql_values();
+    let mut rows = stmt.query(&filter_params(&values))?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        result.push(BulkRecipient {
+            email_to: row.get(0),
+            name: row.get(1),
+            token: row.get(2)
+        });
+    }
+
+    Ok(result)
+}
+
+#[cfg(feature = "payments")]
+#[derive(Debug, PartialEq)]
+pub struct UnpaidRegistration {
+    pub id: i64,
+    pub title: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub email_to: String,
+    // In cents
+    pub fee: i64,
+    pub registered_at: String,
+    pub payment_reminders: i64,
+    pub last_payment_reminder: String,
+    pub email_undeliverable: String
+}
+
+// Open fee waiver requests are left alone until they are decided
+#[cfg(feature = "payments")]
+pub fn load_unpaid_registrations(db_connection: &Connection) -> Result<Vec<UnpaidRegistration>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("
+        SELECT id, title, first_name, last_name, email_to, fee, registered_at, payment_reminders, last_payment_reminder,
+               email_undeliverable
+        FROM registration
+        WHERE payment_status = 'unpaid' AND fee > 0 AND fee_waiver_status != 'requested'
+        ORDER BY registered_at, id")?;
+    let mut rows = stmt.query(&[])?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        result.push(UnpaidRegistration {
+            id: row.get(0),
+            title: row.get(1),
+            first_name: row.get(2),
+            last_name: row.get(3),
+            email_to: row.get(4),
+            fee: row.get(5),
+            registered_at: row.get(6),
+            payment_reminders: row.get(7),
+            last_payment_reminder: row.get(8),
+            email_undeliverable: row.get(9)
+        });
+    }
+
+    Ok(result)
+}
+
+#[cfg(feature = "payments")]
+pub fn record_payment_reminder(db_connection: &Connection, id: i64, timestamp: &str) -> Result<(), rusqlite::Error> {
+    db_connection.execute("
+        UPDATE registration SET payment_reminders = payment_reminders + 1, last_payment_reminder = $1 WHERE id = $2",
+        &[&timestamp, &id])?;
+    Ok(())
+}
+
+pub fn load_registration_status(db_connection: &Connection, id: i64) -> Result<Option<String>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("SELECT status FROM registration WHERE id = $1")?;
+    let mut rows = stmt.query(&[&id])?;
+
+    match rows.next() {
+        Some(row) => Ok(Some(row?.get(0))),
+        None => Ok(None)
+    }
+}
+
+// Only changes the status if it is still the one the change was decided on
+pub fn update_registration_status(db_connection: &Connection, id: i64, current: &str, next: &str) -> Result<bool, rusqlite::Error> {
+    let changed = db_connection.execute("UPDATE registration SET status = $1 WHERE id = $2 AND status = $3", &[&next, &id, &current])?;
+
+    if changed == 1 {
+        record_roster_change(db_connection, id, "status", current, next)?;
+    }
+
+    Ok(changed == 1)
+}
+
+// What participants change themselves at /edit/:token, false if the registration doesn't exist
+pub fn update_own_details(db_connection: &Connection, id: i64, presentation_title: &str, presentation_authors: &str,
+    abstract_text: &str, more_info: &str) -> Result<bool, rusqlite::Error> {
+
+    let changed = db_connection.execute("
+        UPDATE registration SET presentation_title = $1, presentation_authors = $2, abstract_text = $3, more_info = $4
+        WHERE id = $5", &[&presentation_title, &presentation_authors, &abstract_text, &more_info, &id])?;
+
+    if changed == 1 {
+        record_roster_change(db_connection, id, "details", "", "")?;
+    }
+
+    Ok(changed == 1)
+}
+
+pub fn record_roster_change(db_connection: &Connection, id: i64, kind: &str, old_status: &str, new_status: &str) -> Result<(), rusqlite::Error> {
+    db_connection.execute("
+        INSERT INTO roster_change (registration_id, kind, old_status, new_status, changed_at) VALUES ($1, $2, $3, $4, $5)",
+        &[&id, &kind, &old_status, &new_status, &timestamp()])?;
+    Ok(())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RosterEntry {
+    pub id: i64,
+    pub first_name: String,
+    pub last_name: String,
+    pub institution: String,
+    pub email_to: String,
+    pub course_type: String,
+    pub more_info: String,
+    pub status: String,
+    pub registered_at: String
+}
+
+// Everybody registered before until, whatever the status is now
+pub fn load_roster(db_connection: &Connection, until: &str) -> Result<Vec<RosterEntry>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("
+        SELECT id, first_name, last_name, institution, email_to, course_type, more_info, status, registered_at FROM registration
+        WHERE registered_at < $1
+        ORDER BY last_name COLLATE NOCASE, first_name COLLATE NOCASE")?;
+    let mut rows = stmt.query(&[&until])?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        result.push(RosterEntry {
+            id: row.get(0),
+            first_name: row.get(1),
+            last_name: row.get(2),
+            institution: row.get(3),
+            email_to: row.get(4),
+            course_type: row.get(5),
+            more_info: row.get(6),
+            status: row.get(7),
+            registered_at: row.get(8)
+        });
+    }
+
+    Ok(result)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RosterChange {
+    pub registration_id: i64,
+    pub kind: String,
+    pub old_status: String,
+    pub new_status: String,
+    pub changed_at: String
+}
+
+// All changes in the order they happened, the later ones tell the status a registration had before them
+pub fn load_roster_changes(db_connection: &Connection) -> Result<Vec<RosterChange>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("
+        SELECT registration_id, kind, old_status, new_status, changed_at FROM roster_change ORDER BY changed_at, id")?;
+    let mut rows = stmt.query(&[])?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        result.push(RosterChange {
+            registration_id: row.get(0),
+            kind: row.get(1),
+            old_status: row.get(2),
+            new_status: row.get(3),
+            changed_at: row.get(4)
+        });
+    }
+
+    Ok(result)
+}
+
+// Only unpaid registrations can be marked "paid" or "waived"
+#[cfg(feature = "payments")]
+pub fn set_payment_status(db_connection: &Connection, id: i64, status: &str, method: &str, timestamp: &str) -> Result<bool, rusqlite::Error> {
+    let changed = db_connection.execute("
+        UPDATE registration SET payment_status = $1, payment_method = $2, paid_at = $3
+        WHERE id = $4 AND payment_status = 'unpaid'", &[&status, &method, &timestamp, &id])?;
+
+    Ok(changed > 0)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Payment {
+    pub id: i64,
+    pub first_name: String,
+    pub last_name: String,
+    pub institution: String,
+    // In cents
+    pub fee: i64,
+    pub paid_at: String,
+    pub payment_method: String
+}
+
+pub fn load_payments(db_connection: &Connection) -> Result<Vec<Payment>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("
+        SELECT id, first_name, last_name, institution, fee, paid_at, payment_method FROM registration
+        WHERE payment_status = 'paid'
+        ORDER BY paid_at, id")?;
+    let mut rows = stmt.query(&[])?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        result.push(Payment {
+            id: row.get(0),
+            first_name: row.get(1),
+            last_name: row.get(2),
+            institution: row.get(3),
+            fee: row.get(4),
+            paid_at: row.get(5),
+            payment_method: row.get(6)
+        });
+    }
+
+    Ok(result)
+}
+
+// Amounts are stored in cents
+#[derive(Debug, PartialEq)]
+pub struct Reimbursement {
+    pub id: i64,
+    pub registration_id: i64,
+    pub first_name: String,
+    pub last_name: String,
+    pub account_holder: String,
+    pub iban: String,
+    pub bic: String,
+    pub amount: i64,
+    pub description: String,
+    pub receipt_file: String,
+    pub receipt_name: String,
+    pub status: String,
+    pub submitted_at: String,
+    pub updated_at: String
+}
+
+pub fn insert_reimbursement(db_connection: &Connection, reimbursement: &Reimbursement) -> Result<i64, rusqlite::Error> {
+    db_connection.execute("
+        INSERT INTO reimbursement (registration_id, account_holder, iban, bic, amount, description,
+          receipt_file, receipt_name, status, submitted_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)", &[
+            &reimbursement.registration_id,
+            &reimbursement.account_holder,
+            &reimbursement.iban,
+            &reimbursement.bic,
+            &reimbursement.amount,
+            &reimbursement.description,
+            &reimbursement.receipt_file,
+            &reimbursement.receipt_name,
+            &reimbursement.status,
+            &reimbursement.submitted_at,
+            &reimbursement.updated_at
+        ])?;
+
+    Ok(db_connection.last_insert_rowid())
+}
+
+// All requests if no registration is given, newest first
+pub fn load_reimbursements(db_connection: &Connection, registration_id: Option<i64>) -> Result<Vec<Reimbursement>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("
+        SELECT r.id, r.registration_id, p.first_name, p.last_name, r.account_holder, r.iban, r.bic, r.amount,
+          r.description, r.receipt_file, r.receipt_name, r.status, r.submitted_at, r.updated_at
+        FROM reimbursement r JOIN registration p ON p.id = r.registration_id
+        WHERE $1 IS NULL OR r.registration_id = $1
+        ORDER BY r.submitted_at DESC, r.id DESC")?;
+    let mut rows = stmt.query(&[&registration_id])?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        result.push(Reimbursement {
+            id: row.get(0),
+            registration_id: row.get(1),
+            first_name: row.get(2),
+            last_name: row.get(3),
+            account_holder: row.get(4),
+            iban: row.get(5),
+            bic: row.get(6),
+            amount: row.get(7),
+            description: row.get(8),
+            receipt_file: row.get(9),
+            receipt_name: row.get(10),
+            status: row.get(11),
+            submitted_at: row.get(12),
+            updated_at: row.get(13)
+        });
+    }
+
+    Ok(result)
+}
+
+pub fn update_reimbursement_status(db_connection: &Connection, id: i64, status: &str, updated_at: &str) -> Result<(), rusqlite::Error> {
+    db_connection.execute("UPDATE reimbursement SET status = $1, updated_at = $2 WHERE id = $3", &[&status, &updated_at, &id])?;
+    Ok(())
+}
+
+// Amounts are stored in cents
+#[derive(Debug, PartialEq)]
+pub struct TravelGrant {
+    pub id: i64,
+    pub registration_id: i64,
+    pub title: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub email_to: String,
+    pub amount: i64,
+    pub motivation: String,
+    pub cv_file: String,
+    pub cv_name: String,
+    pub score: Option<i64>,
+    pub award: i64,
+    pub submitted_at: String,
+    pub notified_at: String
+}
+
+pub fn insert_travel_grant(db_connection: &Connection, grant: &TravelGrant) -> Result<i64, rusqlite::Error> {
+    db_connection.execute("
+        INSERT INTO travel_grant (registration_id, amount, motivation, cv_file, cv_name, submitted_at)
+        VALUES ($1, $2, $3, $4, $5, $6)", &[
+            &grant.registration_id,
+            &grant.amount,
+            &grant.motivation,
+            &grant.cv_file,
+            &grant.cv_name,
+            &grant.submitted_at
+        ])?;
+
+    Ok(db_connection.last_insert_rowid())
+}
+
+// All applications if no registration is given, the best reviewed ones first
+pub fn load_travel_grants(db_connection: &Connection, registration_id: Option<i64>) -> Result<Vec<TravelGrant>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("
+        SELECT g.id, g.registration_id, p.title, p.first_name, p.last_name, p.email_to, g.amount, g.motivation,
+          g.cv_file, g.cv_name, g.score, g.award, g.submitted_at, g.notified_at
+        FROM travel_grant g JOIN registration p ON p.id = g.registration_id
+        WHERE $1 IS NULL OR g.registration_id = $1
+        ORDER BY g.score IS NULL, g.score DESC, g.id")?;
+    let mut rows = stmt.query(&[&registration_id])?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        result.push(TravelGrant {
+            id: row.get(0),
+            registration_id: row.get(1),
+            title: row.get(2),
+            first_name: row.get(3),
+            last_name: row.get(4),
+            email_to: row.get(5),
+            amount: row.get(6),
+            motivation: row.get(7),
+            cv_file: row.get(8),
+            cv_name: row.get(9),
+            score: row.get(10),
+            award: row.get(11),
+            submitted_at: row.get(12),
+            notified_at: row.get(13)
+        });
+    }
+
+    Ok(result)
+}
+
+// Returns false if the applicant has already been notified, the decision is final then
+pub fn review_travel_grant(db_connection: &Connection, id: i64, score: i64, award: i64) -> Result<bool, rusqlite::Error> {
+    let changed = db_connection.execute("UPDATE travel_grant SET score = $1, award = $2 WHERE id = $3 AND notified_at = ''",
+        &[&score, &award, &id])?;
+
+    Ok(changed > 0)
+}
+
+pub fn mark_travel_grant_notified(db_connection: &Connection, id: i64, notified_at: &str) -> Result<(), rusqlite::Error> {
+    db_connection.execute("UPDATE travel_grant SET notified_at = $1 WHERE id = $2", &[&notified_at, &id])?;
+    Ok(())
+}
+
+fn has_column(db_connection: &Connection, table: &str, column: &str) -> Result<bool, rusqlite::Error> {
+    let mut stmt = db_connection.prepare(&format!("PRAGMA table_info({})", table))?;
+    let mut rows = stmt.query(&[])?;
+
+    while let Some(row) = rows.next() {
+        let name: String = row?.get(1);
+        if name == column {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+// Returns true if the column was missing and has been added
+fn add_column(db_connection: &Connection, table: &str, column: &str, definition: &str) -> Result<bool, rusqlite::Error> {
+    if has_column(db_connection, table, column)? {
+        return Ok(false);
+    }
+
+    info!("Adding column '{}' to table '{}'", column, table);
+    db_connection.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), &[])?;
+
+    Ok(true)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Draft {
+    pub email: String,
+    pub form_data: String,
+    pub expires_at: String
+}
+
+// Saving again under the same token replaces the draft and extends its expiry
+pub fn save_draft(db_connection: &Connection, token: &str, email: &str, form_data: &str, timestamp: &str, expires_at: &str) -> Result<(), rusqlite::Error> {
+    db_connection.execute("INSERT OR REPLACE INTO draft (token, email, form_data, saved_at, expires_at) VALUES ($1, $2, $3, $4, $5)",
+        &[&token, &email, &form_data, &timestamp, &expires_at])?;
+    Ok(())
+}
+
+pub fn load_draft(db_connection: &Connection, token: &str, timestamp: &str) -> Result<Option<Draft>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("SELECT email, form_data, expires_at FROM draft WHERE token = $1 AND expires_at > $2")?;
+    let mut rows = stmt.query(&[&token, &timestamp])?;
+
+    match rows.next() {
+        Some(row) => {
+            let row = row?;
+            Ok(Some(Draft { email: row.get(0), form_data: row.get(1), expires_at: row.get(2) }))
+        }
+        None => Ok(None)
+    }
+}
+
+pub fn delete_draft(db_connection: &Connection, token: &str) -> Result<(), rusqlite::Error> {
+    db_connection.execute("DELETE FROM draft WHERE token = $1", &[&token])?;
+    Ok(())
+}
+
+pub fn delete_expired_drafts(db_connection: &Connection, timestamp: &str) -> Result<i32, rusqlite::Error> {
+    db_connection.execute("DELETE FROM draft WHERE expires_at <= $1", &[&timestamp])
+}
+
+pub fn load_settings(db_connection: &Connection) -> Result<Vec<(String, String)>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("SELECT key, value FROM setting ORDER BY key")?;
+    let mut rows = stmt.query(&[])?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        result.push((row.get(0), row.get(1)));
+    }
+
+    Ok(result)
+}
+
+pub fn save_setting(db_connection: &Connection, key: &str, value: &str, timestamp: &str) -> Result<(), rusqlite::Error> {
+    db_connection.execute("INSERT OR REPLACE INTO setting (key, value, updated_at) VALUES ($1, $2, $3)", &[&key, &value, &timestamp])?;
+    Ok(())
+}
+
+pub fn delete_setting(db_connection: &Connection, key: &str) -> Result<(), rusqlite::Error> {
+    db_connection.execute("DELETE FROM setting WHERE key = $1", &[&key])?;
+    Ok(())
+}
+
+// Subject and body, None if the template was never changed
+pub fn load_mail_template(db_connection: &Connection, name: &str) -> Result<Option<(String, String)>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare_cached("SELECT subject, body FROM mail_template WHERE name = $1")?;
+    let mut rows = stmt.query(&[&name])?;
+
+    match rows.next() {
+        Some(row) => {
+            let row = row?;
+            Ok(Some((row.get(0), row.get(1))))
+        }
+        None => Ok(None)
+    }
+}
+
+pub fn save_mail_template(db_connection: &Connection, name: &str, subject: &str, body: &str, timestamp: &str) -> Result<(), rusqlite::Error> {
+    db_connection.execute("INSERT OR REPLACE INTO mail_template (name, subject, body, updated_at) VALUES ($1, $2, $3, $4)",
+        &[&name, &subject, &body, &timestamp])?;
+    Ok(())
+}
+
+// Id and name of every registration, for choosing one in the email preview
+pub fn load_registration_names(db_connection: &Connection) -> Result<Vec<(i64, String)>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("
+        SELECT id, first_name, last_name FROM registration ORDER BY last_name COLLATE NOCASE, first_name COLLATE NOCASE")?;
+    let mut rows = stmt.query(&[])?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        result.push((row.get(0), format!("{} {}", row.get::<i32, String>(1), row.get::<i32, String>(2))));
+    }
+
+    Ok(result)
+}
+
+pub fn delete_mail_template(db_connection: &Connection, name: &str) -> Result<(), rusqlite::Error> {
+    db_connection.execute("DELETE FROM mail_template WHERE name = $1", &[&name])?;
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+pub struct AdminToken {
+    pub created_at: i64,
+    pub expires_at: i64,
+    pub replaced_at: i64
+}
+
+pub fn insert_admin_token(db_connection: &Connection, token_hash: &str, created_at: i64, expires_at: i64) -> Result<(), rusqlite::Error> {
+    db_connection.execute("INSERT INTO admin_token (token_hash, created_at, expires_at) VALUES ($1, $2, $3)",
+        &[&token_hash, &created_at, &expires_at])?;
+    Ok(())
+}
+
+pub fn load_admin_token(db_connection: &Connection, token_hash: &str) -> Result<Option<AdminToken>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare_cached("SELECT created_at, expires_at, replaced_at FROM admin_token WHERE token_hash = $1")?;
+    let mut rows = stmt.query(&[&token_hash])?;
+
+    match rows.next() {
+        Some(row) => {
+            let row = row?;
+            Ok(Some(AdminToken { created_at: row.get(0), expires_at: row.get(1), replaced_at: row.get(2) }))
+        }
+        None => Ok(None)
+    }
+}
+
+// Only the first of two requests with the same token rotates it
+pub fn mark_admin_token_replaced(db_connection: &Connection, token_hash: &str, replaced_at: i64) -> Result<bool, rusqlite::Error> {
+    let changed = db_connection.execute("UPDATE admin_token SET replaced_at = $1 WHERE token_hash = $2 AND replaced_at = 0",
+        &[&replaced_at, &token_hash])?;
+    Ok(changed == 1)
+}
+
+pub fn delete_admin_token(db_connection: &Connection, token_hash: &str) -> Result<(), rusqlite::Error> {
+    db_connection.execute("DELETE FROM admin_token WHERE token_hash = $1", &[&token_hash])?;
+    Ok(())
+}
+
+// Expired tokens and the ones replaced before the given time
+pub fn delete_stale_admin_tokens(db_connection: &Connection, now: i64, replaced_before: i64) -> Result<usize, rusqlite::Error> {
+    let deleted = db_connection.execute("
+        DELETE FROM admin_token WHERE expires_at <= $1 OR (replaced_at != 0 AND replaced_at < $2)", &[&now, &replaced_before])?;
+    Ok(deleted as usize)
+}
+
+// The newest registration of the address, one that still takes a place if there is any
+pub fn find_registration_by_email(db_connection: &Connection, email_normalized: &str) -> Result<Option<i64>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("
+        SELECT id FROM registration WHERE email_normalized = $1
+        ORDER BY status IN ('pending', 'confirmed') DESC, id DESC LIMIT 1")?;
+    let mut rows = stmt.query(&[&email_normalized])?;
+
+    match rows.next() {
+        Some(row) => Ok(Some(row?.get(0))),
+        None => Ok(None)
+    }
+}
+
+pub fn insert_login_link(db_connection: &Connection, token_hash: &str, registration_id: i64, expires_at: i64) -> Result<(), rusqlite::Error> {
+    db_connection.execute("INSERT INTO login_link (token_hash, registration_id, expires_at) VALUES ($1, $2, $3)",
+        &[&token_hash, &registration_id, &expires_at])?;
+    Ok(())
+}
+
+// The registration the link belongs to, as long as it hasn't expired
+pub fn find_login_link(db_connection: &Connection, token_hash: &str, now: i64) -> Result<Option<i64>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("SELECT registration_id FROM login_link WHERE token_hash = $1 AND expires_at > $2")?;
+    let mut rows = stmt.query(&[&token_hash, &now])?;
+
+    match rows.next() {
+        Some(row) => Ok(Some(row?.get(0))),
+        None => Ok(None)
+    }
+}
+
+pub fn delete_expired_login_links(db_connection: &Connection, now: i64) -> Result<usize, rusqlite::Error> {
+    let deleted = db_connection.execute("DELETE FROM login_link WHERE expires_at <= $1", &[&now])?;
+    Ok(deleted as usize)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct OutboxMail {
+    pub id: i64,
+    pub registration_id: i64,
+    pub template: String,
+    pub email_to: String,
+    pub subject: String,
+    pub body: String,
+    pub created_at: String,
+    pub sent_at: String,
+    pub error: String
+}
+
+// Recorded before sending, so that a mail is listed even if the server stops while sending it
+pub fn insert_outbox(db_connection: &Connection, mail: &OutboxMail) -> Result<i64, rusqlite::Error> {
+    db_connection.execute("
+        INSERT INTO outbox (registration_id, template, email_to, subject, body, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6)",
+        &[&mail.registration_id, &mail.template, &mail.email_to, &mail.subject, &mail.body, &mail.created_at])?;
+    Ok(db_connection.last_insert_rowid())
+}
+
+// Either the time it was sent or why it wasn't
+pub fn mark_outbox_result(db_connection: &Connection, id: i64, sent_at: &str, error: &str) -> Result<(), rusqlite::Error> {
+    db_connection.execute("UPDATE outbox SET sent_at = $1, error = $2 WHERE id = $3", &[&sent_at, &error, &id])?;
+    Ok(())
+}
+
+// Newest first
+pub fn load_outbox(db_connection: &Connection, limit: u32) -> Result<Vec<OutboxMail>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("
+        SELECT id, registration_id, template, email_to, subject, body, created_at, sent_at, error FROM outbox
+        ORDER BY id DESC
+        LIMIT $1")?;
+    let mut rows = stmt.query(&[&limit])?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        result.push(OutboxMail {
+            id: row.get(0),
+            registration_id: row.get(1),
+            template: row.get(2),
+            email_to: row.get(3),
+            subject: row.get(4),
+            body: row.get(5),
+            created_at: row.get(6),
+            sent_at: row.get(7),
+            error: row.get(8)
+        });
+    }
+
+    Ok(result)
+}
+
+// What the duplicate search compares
+#[derive(Debug, PartialEq)]
+pub struct DuplicateCandidate {
+    pub id: i64,
+    pub first_name: String,
+    pub last_name: String,
+    pub institution: String,
+    pub email_normalized: String,
+    pub registered_at: String
+}
+
+pub fn load_duplicate_candidates(db_connection: &Connection) -> Result<Vec<DuplicateCandidate>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("
+        SELECT id, first_name, last_name, institution, email_normalized, registered_at FROM registration
+        ORDER BY id")?;
+    let mut rows = stmt.query(&[])?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        result.push(DuplicateCandidate {
+            id: row.get(0),
+            first_name: row.get(1),
+            last_name: row.get(2),
+            institution: row.get(3),
+            email_normalized: row.get(4),
+            registered_at: row.get(5)
+        });
+    }
+
+    Ok(result)
+}
+
+fn registration_row(db_connection: &Connection, id: i64) -> Result<Option<Vec<(String, Value)>>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("SELECT * FROM registration WHERE id = $1")?;
+    let columns: Vec<String> = stmt.column_names().iter().map(|name| name.to_string()).collect();
+    let mut rows = stmt.query(&[&id])?;
+
+    match rows.next() {
+        Some(row) => {
+            let row = row?;
+            let mut result = Vec::new();
+            for (index, column) in columns.into_iter().enumerate() {
+                if column != "id" {
+                    result.push((column, row.get_checked(index as i32)?));
+                }
+            }
+            Ok(Some(result))
+        }
+        None => Ok(None)
+    }
+}
+
+// Every column of a registration except the id, as text for comparing two registrations side by side
+pub fn load_registration_values(db_connection: &Connection, id: i64) -> Result<Option<Vec<(String, String)>>, rusqlite::Error> {
+    Ok(registration_row(db_connection, id)?.map(|row| row.into_iter().map(|(column, value)| {
+        let text = match value {
+            Value::Null => String::new(),
+            Value::Integer(number) => number.to_string(),
+            Value::Real(number) => number.to_string(),
+            Value::Text(text) => text,
+            Value::Blob(bytes) => format!("({} Bytes)", bytes.len())
+        };
+        (column, text)
+    }).collect()))
+}
+
+// Tables whose rows belong to a registration, and whether rows the kept registration already has
+// may be dropped when merging (a topic is only listed once) instead of refusing the merge
+pub const LINKED_TABLES: [(&str, bool); 7] = [
+    ("reimbursement", false),
+    ("social_booking", false),
+    ("presentation_topic", true),
+    ("travel_grant", false),
+    ("outbox", false),
+    ("login_link", true),
+    ("roster_change", true)
+];
+
+// Folds the registration remove into keep: the columns in take get the values of remove, everything
+// pointing to remove is moved to keep, then remove is deleted. All or nothing.
+pub fn merge_registrations(db_connection: &Connection, keep: i64, remove: i64, take: &[String]) -> Result<(), HandleError> {
+    if keep == remove {
+        return Err(HandleError::Validation("Eine Anmeldung kann nicht mit sich selbst zusammengeführt werden".to_string()));
+    }
+
+    let kept = registration_row(db_connection, keep)?.ok_or(HandleError::NotFound)?;
+    let removed = registration_row(db_connection, remove)?.ok_or(HandleError::NotFound)?;
+
+    if let Some(column) = take.iter().find(|column| !kept.iter().any(|(name, _)| name == *column)) {
+        return Err(HandleError::Validation(format!("Unbekannte Spalte '{}'", column)));
+    }
+
+    db_connection.execute_batch("BEGIN")?;
+
+    match merge_rows(db_connection, keep, remove, removed, take) {
+        Ok(()) => {
+            db_connection.execute_batch("COMMIT")?;
+            Ok(())
+        }
+        Err(e) => {
+            db_connection.execute_batch("ROLLBACK")?;
+            Err(e)
+        }
+    }
+}
+
+fn merge_rows(db_connection: &Connection, keep: i64, remove: i64, removed: Vec<(String, Value)>, take: &[String]) -> Result<(), HandleError> {
+    for &(table, drop_duplicates) in LINKED_TABLES.iter() {
+        db_connection.execute(&format!("UPDATE OR IGNORE {} SET registration_id = $1 WHERE registration_id = $2", table), &[&keep, &remove])?;
+
+        let left: i64 = db_connection.query_row(&format!("SELECT COUNT(*) FROM {} WHERE registration_id = $1", table), &[&remove], |row| row.get(0))?;
+        if left > 0 {
+            if !drop_duplicates {
+                return Err(HandleError::Validation(format!("Beide Anmeldungen haben Einträge in '{}', bitte zuerst bereinigen", table)));
+            }
+            db_connection.execute(&format!("DELETE FROM {} WHERE registration_id = $1", table), &[&remove])?;
+        }
+    }
+
+    // Deleted first, unique values like the form token can then move over
+    db_connection.execute("DELETE FROM registration WHERE id = $1", &[&remove])?;
+
+    for (column, value) in removed {
+        if take.contains(&column) {
+            db_connection.execute(&format!("UPDATE registration SET \"{}\" = $1 WHERE id = $2", column), &[&ToSqlOutput::Owned(value), &keep])?;
+        }
+    }
+
+    // The institution may have been taken over without its institution_id
+    link_institution(db_connection, keep)?;
+
+    Ok(())
+}
+
+// How many rows of each of LINKED_TABLES belong to the registration
+pub fn count_linked_rows(db_connection: &Connection, id: i64) -> Result<Vec<(&'static str, i64)>, rusqlite::Error> {
+    LINKED_TABLES.iter().map(|&(table, _)| {
+        db_connection.query_row(&format!("SELECT COUNT(*) FROM {} WHERE registration_id = $1", table), &[&id], |row| row.get(0))
+            .map(|count| (table, count))
+    }).collect()
+}
+
+/// Removes a registration with its rows in LINKED_TABLES, all or nothing. For test entries and requests to erase
+/// the data, a cancellation keeps the row with the status cancelled. Uploaded files stay in the upload directory.
+pub fn delete_registration(db_connection: &Connection, id: i64) -> Result<(), HandleError> {
+    db_connection.execute_batch("BEGIN")?;
+
+    match delete_rows(db_connection, id) {
+        Ok(()) => {
+            db_connection.execute_batch("COMMIT")?;
+            Ok(())
+        }
+        Err(e) => {
+            db_connection.execute_batch("ROLLBACK")?;
+            Err(e)
+        }
+    }
+}
+
+fn delete_rows(db_connection: &Connection, id: i64) -> Result<(), HandleError> {
+    for &(table, _) in LINKED_TABLES.iter() {
+        db_connection.execute(&format!("DELETE FROM {} WHERE registration_id = $1", table), &[&id])?;
+    }
+
+    if db_connection.execute("DELETE FROM registration WHERE id = $1", &[&id])? == 0 {
+        return Err(HandleError::NotFound);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{init_database, has_column, find_institutions, count_registrations, count_by_country, count_by_presentation, count_by_day, load_participants, load_pending_registrations, load_arrivals,
+        count_by_status, load_registration_status, update_registration_status, update_own_details, load_roster, load_roster_changes,
+        load_child_care_requests, load_accessibility_needs, load_media_refusals,
+        subscribe_newsletter, load_newsletter_subscribers, count_banquet_guests,
+        suppress_by_token, load_bulk_recipients, save_draft, load_draft, delete_draft, delete_expired_drafts, Draft,
+        load_mail_template, save_mail_template, delete_mail_template, insert_outbox, mark_outbox_result, load_outbox, OutboxMail,
+        insert_admin_token, load_admin_token, mark_admin_token_replaced, delete_admin_token, delete_stale_admin_tokens,
+        find_registration_by_email, insert_login_link, find_login_link, delete_expired_login_links, load_settings, save_setting, delete_setting,
+        load_room_requests, load_fee_waivers, decide_fee_waiver,
+        load_abstracts, load_all_abstracts, assign_session, set_presentation_file, add_presentation_topic, mark_undeliverable,
+        insert_travel_grant, load_travel_grants, review_travel_grant, mark_travel_grant_notified, TravelGrant,
+        find_by_token, insert_reimbursement, load_reimbursements, update_reimbursement_status, Reimbursement, Repository,
+        load_duplicate_candidates, load_registration_values, merge_registrations, count_linked_rows, delete_registration, book_social_event, LINKED_TABLES, lock_connection,
+        count_all_registrations, load_registration_page};
+    use status::RegistrationStatus;
+    use filter::RegistrationFilter;
+    use handler::Presentation;
+    #[cfg(feature = "payments")]
+    use super::{load_unpaid_registrations, record_payment_reminder, set_payment_status, load_payments};
+
+    use rusqlite::Connection;
+
+    #[test]
+    fn test_lock_connection() {
+        use std::sync::{Arc, Mutex};
+        use std::thread;
+
+        let db_connection = Connection::open_in_memory().unwrap();
+        init_database(&db_connection).unwrap();
+        let mutex = Arc::new(Mutex::new(db_connection));
+
+        let shared = mutex.clone();
+        let result = thread::spawn(move || {
+            let db_connection = shared.lock().unwrap();
+            db_connection.execute_batch("BEGIN; INSERT INTO newsletter (email, name, token, subscribed_at) VALUES ('a@example.com', 'A', 'token', '2017-03-01 10:00:00');").unwrap();
+            panic!("handler failed");
+        }).join();
+        assert!(result.is_err());
+        assert!(mutex.is_poisoned());
+
+        let db_connection = lock_connection(&mutex).unwrap();
+        assert_eq!(load_newsletter_subscribers(&db_connection).unwrap().len(), 0);
+        db_connection.execute_batch("BEGIN; COMMIT;").unwrap();
+        drop(db_connection);
+        assert!(!mutex.is_poisoned());
+    }
+
+    #[test]
+    fn test_repository() {
+        let repository = Repository::open(":memory:").unwrap();
+        assert!(has_column(repository.connection(), "registration", "form_token").unwrap());
+        assert_eq!(repository.count_registrations().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_init_database1() {
+        let conn = Connection::open_in_memory().unwrap();
+
+        assert!(init_database(&conn).is_ok());
+        assert!(has_column(&conn, "registration", "email_to").unwrap());
+        assert!(has_column(&conn, "registration", "email_normalized").unwrap());
+        assert!(!has_column(&conn, "registration", "no_such_column").unwrap());
+
+        // Running it a second time must not fail
+        assert!(init_database(&conn).is_ok());
+    }
+
+    #[test]
+    fn test_init_database2() {
+        let conn = Connection::open_in_memory().unwrap();
+
+        conn.execute("CREATE TABLE registration (
+                  id              INTEGER PRIMARY KEY,
+                  title           TEXT NOT NULL,
+                  last_name       TEXT NOT NULL,
+                  first_name      TEXT NOT NULL,
+                  institution     TEXT NOT NULL,
+                  street          TEXT NOT NULL,
+                  street_no       TEXT NOT NULL,
+                  zip_code        TEXT NOT NULL,
+                  city            TEXT NOT NULL,
+                  phone           TEXT NOT NULL,
+                  email_to        TEXT NOT NULL,
+                  more_info       TEXT NOT NULL,
+                  price_category  TEXT NOT NULL,
+                  course_type     TEXT NOT NULL
+                  )", &[]).unwrap();
+        conn.execute("INSERT INTO registration VALUES (1, 'sir', 'Smith', 'Bob', 'Uni', 'Street', '1', '12345',
+                  'City', '123', ' Bob@Smith.com', '', 'student', 'course1')", &[]).unwrap();
+
+        assert!(init_database(&conn).is_ok());
+
+        let email: String = conn.query_row("SELECT email_normalized FROM registration WHERE id = 1", &[], |row| row.get(0)).unwrap();
+        assert_eq!(email, "bob@smith.com".to_string());
+    }
+
+    #[test]
+    fn test_find_institutions() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        for institution in &["University of Tübingen", "University of Tübingen", "GFZ Potsdam", "100% University", "Uni_Bonn"] {
+            conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                      city, phone, email_to, more_info, price_category, course_type)
+                      VALUES ('sir', 'Smith', 'Bob', $1, 'Street', '1', '12345', 'City', '123', 'bob@smith.com', '', 'student', 'course1')",
+                      &[institution]).unwrap();
+        }
+
+        assert_eq!(find_institutions(&conn, "tüb", 10).unwrap(), vec!["University of Tübingen".to_string()]);
+        assert_eq!(find_institutions(&conn, "uni", 10).unwrap().len(), 3);
+        assert_eq!(find_institutions(&conn, "uni", 1).unwrap().len(), 1);
+        assert_eq!(find_institutions(&conn, "%", 10).unwrap(), vec!["100% University".to_string()]);
+        assert_eq!(find_institutions(&conn, "_", 10).unwrap(), vec!["Uni_Bonn".to_string()]);
+        assert!(find_institutions(&conn, "MIT", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_count_by_country() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        for country in &["DE", "AT", "DE", "CH", "DE", "AT"] {
+            conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                      city, phone, email_to, more_info, price_category, course_type, country)
+                      VALUES ('sir', 'Smith', 'Bob', 'Uni', 'Street', '1', '12345', 'City', '123', 'bob@smith.com', '', 'student', 'course1', $1)",
+                      &[country]).unwrap();
+        }
+
+        assert_eq!(count_registrations(&conn).unwrap(), 6);
+        assert_eq!(count_by_country(&conn).unwrap(), vec![("DE".to_string(), 3), ("AT".to_string(), 2), ("CH".to_string(), 1)]);
+
+        assert_eq!(count_by_presentation(&conn).unwrap(), (0, 0));
+        conn.execute("UPDATE registration SET presentation = 'talk' WHERE id < 3", &[]).unwrap();
+        conn.execute("UPDATE registration SET presentation = 'poster' WHERE id = 3", &[]).unwrap();
+        assert_eq!(count_by_presentation(&conn).unwrap(), (2, 1));
+    }
+
+    #[test]
+    fn test_load_participants() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        for &(first_name, last_name, preferred_name) in &[("Robert", "Smith", "Bob"), ("Alice", "Brown", "")] {
+            conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                      city, phone, email_to, more_info, price_category, course_type, preferred_name, pronouns)
+                      VALUES ('sir', $1, $2, 'Uni', 'Street', '1', '12345', 'City', '123', 'bob@smith.com', '', 'student', 'course1', $3, '')",
+                      &[&last_name, &first_name, &preferred_name]).unwrap();
+        }
+
+        let participants = load_participants(&conn, &RegistrationFilter::default()).unwrap();
+
+        assert_eq!(participants.len(), 2);
+        assert_eq!(participants[0].display_name(), "Alice Brown".to_string());
+        assert_eq!(participants[0].status, "confirmed".to_string());
+        assert_eq!(participants[1].display_name(), "Bob".to_string());
+        assert_eq!(participants[1].last_name, "Smith".to_string());
+    }
+
+    #[test]
+    fn test_load_registration_page() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        for last_name in &["Smith", "brown", "Miller"] {
+            conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                      city, phone, email_to, more_info, price_category, course_type)
+                      VALUES ('sir', $1, 'Test', 'Uni', 'Street', '1', '12345', 'City', '123', 'bob@smith.com', '', 'student', 'course1')",
+                      &[last_name]).unwrap();
+        }
+
+        let all = RegistrationFilter::default();
+        assert_eq!(count_all_registrations(&conn, &all).unwrap(), 3);
+
+        let names = |filter, order_by, descending, limit, offset| load_registration_page(&conn, filter, order_by, descending, limit, offset).unwrap()
+            .into_iter().map(|row| row.last_name).collect::<Vec<String>>();
+        assert_eq!(names(&all, "last_name COLLATE NOCASE", false, 2, 0), vec!["brown".to_string(), "Miller".to_string()]);
+        assert_eq!(names(&all, "last_name COLLATE NOCASE", false, 2, 2), vec!["Smith".to_string()]);
+        assert_eq!(names(&all, "id", true, 10, 0), vec!["Miller".to_string(), "brown".to_string(), "Smith".to_string()]);
+
+        let row = &load_registration_page(&conn, &all, "id", false, 1, 0).unwrap()[0];
+        assert_eq!((row.id, row.status.as_str(), row.payment_status.as_str()), (1, "confirmed", "unpaid"));
+
+        conn.execute("UPDATE registration SET status = 'cancelled' WHERE last_name = 'Miller'", &[]).unwrap();
+        let cancelled = RegistrationFilter { status: Some(RegistrationStatus::Cancelled), ..RegistrationFilter::default() };
+        assert_eq!(count_all_registrations(&conn, &cancelled).unwrap(), 1);
+        assert_eq!(names(&cancelled, "id", false, 10, 0), vec!["Miller".to_string()]);
+    }
+
+    #[test]
+    fn test_registration_status() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        for last_name in &["Smith", "Brown", "Miller"] {
+            conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                      city, phone, email_to, more_info, price_category, course_type, country)
+                      VALUES ('sir', $1, 'Test', 'Uni', 'Street', '1', '12345', 'City', '123', 'bob@smith.com', '', 'student', 'course1', 'DE')",
+                      &[last_name]).unwrap();
+        }
+
+        assert_eq!(load_registration_status(&conn, 1).unwrap(), Some("confirmed".to_string()));
+        assert_eq!(load_registration_status(&conn, 4).unwrap(), None);
+
+        assert!(update_registration_status(&conn, 1, "confirmed", "cancelled").unwrap());
+        // Already changed by somebody else
+        assert!(!update_registration_status(&conn, 1, "confirmed", "cancelled").unwrap());
+        conn.execute("UPDATE registration SET status = 'waitlisted' WHERE id = 2", &[]).unwrap();
+
+        assert_eq!(count_registrations(&conn).unwrap(), 1);
+        assert_eq!(count_by_country(&conn).unwrap(), vec![("DE".to_string(), 1)]);
+        let mut counts = count_by_status(&conn).unwrap();
+        counts.sort();
+        assert_eq!(counts, vec![("cancelled".to_string(), 1), ("confirmed".to_string(), 1), ("waitlisted".to_string(), 1)]);
+
+        assert!(load_pending_registrations(&conn).unwrap().is_empty());
+        conn.execute("UPDATE registration SET status = 'pending' WHERE id = 3", &[]).unwrap();
+        assert_eq!(load_pending_registrations(&conn).unwrap()[0].last_name, "Miller".to_string());
+        conn.execute("UPDATE registration SET status = 'confirmed' WHERE id = 3", &[]).unwrap();
+
+        let cancelled = load_participants(&conn, &RegistrationFilter { status: Some(RegistrationStatus::Cancelled), ..RegistrationFilter::default() }).unwrap();
+        assert_eq!(cancelled.len(), 1);
+        assert_eq!(cancelled[0].last_name, "Smith".to_string());
+        assert_eq!(load_participants(&conn, &RegistrationFilter::default()).unwrap().len(), 3);
+
+        conn.execute("UPDATE registration SET presentation = 'talk', payment_status = 'paid', registered_at = '2017-01-31 23:00:00' WHERE id = 2", &[]).unwrap();
+        let filter = RegistrationFilter { presentation: Some(Presentation::Talk), payment: Some("paid"), ..RegistrationFilter::default() };
+        assert_eq!(load_participants(&conn, &filter).unwrap().len(), 1);
+        // Both days are included
+        let filter = RegistrationFilter { from: Some("2017-01-31".to_string()), until: Some("2017-01-31".to_string()), ..RegistrationFilter::default() };
+        assert_eq!(load_participants(&conn, &filter).unwrap()[0].last_name, "Brown".to_string());
+    }
+
+    #[test]
+    fn test_count_by_day() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        for registered_at in &["2017-01-02 10:00:00", "2017-01-01 09:00:00", "2017-01-02 18:30:00", ""] {
+            conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                      city, phone, email_to, more_info, price_category, course_type, registered_at)
+                      VALUES ('sir', 'Smith', 'Bob', 'Uni', 'Street', '1', '12345', 'City', '123', 'bob@smith.com', '', 'student', 'course1', $1)",
+                      &[registered_at]).unwrap();
+        }
+
+        assert_eq!(count_by_day(&conn).unwrap(), vec![("2017-01-01".to_string(), 1), ("2017-01-02".to_string(), 2)]);
+    }
+
+    #[test]
+    fn test_mark_undeliverable() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        for &(last_name, email) in &[("Smith", "bob@smith.com"), ("Brown", "alice@brown.com")] {
+            conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                      city, phone, email_to, email_normalized, more_info, price_category, course_type)
+                      VALUES ('sir', $1, 'Test', 'Uni', 'Street', '1', '12345', 'City', '123', $2, $2, '', 'student', 'course1')",
+                      &[&last_name, &email]).unwrap();
+        }
+
+        assert_eq!(mark_undeliverable(&conn, "bob@smith.com", "2017-03-01 10:00:00").unwrap(), 1);
+        // The first bounce is kept
+        assert_eq!(mark_undeliverable(&conn, "bob@smith.com", "2017-03-02 10:00:00").unwrap(), 0);
+        assert_eq!(mark_undeliverable(&conn, "nobody@example.org", "2017-03-02 10:00:00").unwrap(), 0);
+
+        let participants = load_participants(&conn, &RegistrationFilter::default()).unwrap();
+
+        assert_eq!(participants[0].email_undeliverable, "".to_string());
+        assert_eq!(participants[1].email_undeliverable, "2017-03-01 10:00:00".to_string());
+    }
+
+    #[test]
+    fn test_load_arrivals() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        for &(last_name, arrival_time) in &[("Smith", "2017-03-28 14:10"), ("Brown", ""), ("Miller", "2017-03-28 09:45")] {
+            conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                      city, phone, email_to, more_info, price_category, course_type, arrival_mode, arrival_time, share_ride)
+                      VALUES ('sir', $1, 'Test', 'Uni', 'Street', '1', '12345', 'City', '123', 'bob@smith.com', '', 'student', 'course1', 'plane', $2, 1)",
+                      &[&last_name, &arrival_time]).unwrap();
+        }
+
+        let arrivals = load_arrivals(&conn).unwrap();
+
+        assert_eq!(arrivals.len(), 2);
+        assert_eq!(arrivals[0].last_name, "Miller".to_string());
+        assert_eq!(arrivals[1].last_name, "Smith".to_string());
+        assert!(arrivals[1].share_ride);
+    }
+
+    #[test]
+    fn test_load_child_care_requests() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        for &(last_name, children, days) in &[("Smith", 2, "Monday, Tuesday"), ("Brown", 0, "")] {
+            conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                      city, phone, email_to, more_info, price_category, course_type, children, children_ages, child_care_days)
+                      VALUES ('sir', $1, 'Test', 'Uni', 'Street', '1', '12345', 'City', '123', 'bob@smith.com', '', 'student', 'course1', $2, '3, 5', $3)",
+                      &[&last_name, &children, &days]).unwrap();
+        }
+
+        let requests = load_child_care_requests(&conn).unwrap();
+
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].last_name, "Smith".to_string());
+        assert_eq!(requests[0].children, 2);
+        assert_eq!(requests[0].days, vec!["Monday".to_string(), "Tuesday".to_string()]);
+    }
+
+    #[test]
+    fn test_load_accessibility_needs() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        for &(last_name, options, needs) in &[("Smith", "step_free,sign_language", ""), ("Brown", "", ""), ("Miller", "", "Hearing loop")] {
+            conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                      city, phone, email_to, more_info, price_category, course_type, accessibility_options, accessibility_needs)
+                      VALUES ('sir', $1, 'Test', 'Uni', 'Street', '1', '12345', 'City', '123', 'bob@smith.com', '', 'student', 'course1', $2, $3)",
+                      &[&last_name, &options, &needs]).unwrap();
+        }
+
+        let needs = load_accessibility_needs(&conn).unwrap();
+
+        assert_eq!(needs.len(), 2);
+        assert_eq!(needs[0].last_name, "Miller".to_string());
+        assert_eq!(needs[0].options, Vec::<String>::new());
+        assert_eq!(needs[0].needs, "Hearing loop".to_string());
+        assert_eq!(needs[1].options, vec!["step_free".to_string(), "sign_language".to_string()]);
+    }
+
+    #[test]
+    fn test_load_media_refusals() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        for &(last_name, photo, recording) in &[("Smith", true, true), ("Brown", false, true), ("Miller", true, false)] {
+            conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                      city, phone, email_to, more_info, price_category, course_type, photo_consent, recording_consent)
+                      VALUES ('sir', $1, 'Test', 'Uni', 'Street', '1', '12345', 'City', '123', 'bob@smith.com', '', 'student', 'course1', $2, $3)",
+                      &[&last_name, &photo, &recording]).unwrap();
+        }
+
+        let refusals = load_media_refusals(&conn, &RegistrationFilter::default()).unwrap();
+
+        assert_eq!(refusals.len(), 2);
+        assert_eq!(refusals[0].participant.last_name, "Brown".to_string());
+        assert!(!refusals[0].photo_consent);
+        assert!(refusals[0].recording_consent);
+        assert_eq!(refusals[1].participant.last_name, "Miller".to_string());
+        assert!(!refusals[1].recording_consent);
+
+        let paid = RegistrationFilter { payment: Some("paid"), ..RegistrationFilter::default() };
+        assert!(load_media_refusals(&conn, &paid).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_count_banquet_guests() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        assert_eq!(count_banquet_guests(&conn).unwrap(), 0);
+
+        for guests in &[2, 0, 1] {
+            conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                      city, phone, email_to, more_info, price_category, course_type, banquet_guests)
+                      VALUES ('sir', 'Smith', 'Test', 'Uni', 'Street', '1', '12345', 'City', '123', 'bob@smith.com', '', 'student', 'course1', $1)",
+                      &[guests]).unwrap();
+        }
+
+        assert_eq!(count_banquet_guests(&conn).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_load_room_requests() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        for &(last_name, room_sharing, roommate) in &[("Smith", "shared", "alice@brown.com"), ("Miller", "single", ""), ("Brown", "shared", "")] {
+            conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                      city, phone, email_to, more_info, price_category, course_type, room_sharing, roommate_email)
+                      VALUES ('sir', $1, 'Test', 'Uni', 'Street', '1', '12345', 'City', '123', 'bob@smith.com', '', 'student', 'course1', $2, $3)",
+                      &[&last_name, &room_sharing, &roommate]).unwrap();
+        }
+
+        let requests = load_room_requests(&conn).unwrap();
+
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].last_name, "Smith".to_string());
+        assert_eq!(requests[0].roommate_email, "alice@brown.com".to_string());
+        assert_eq!(requests[1].last_name, "Brown".to_string());
+    }
+
+    #[test]
+    fn test_fee_waivers() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        for &(last_name, reason, status) in &[("Smith", "Unemployed", "requested"), ("Miller", "", ""), ("Brown", "Student", "requested")] {
+            conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                      city, phone, email_to, more_info, price_category, course_type, fee, fee_waiver_reason, fee_waiver_status)
+                      VALUES ('sir', $1, 'Test', 'Uni', 'Street', '1', '12345', 'City', '123', 'bob@smith.com', '', 'student', 'course1', 5000, $2, $3)",
+                      &[&last_name, &reason, &status]).unwrap();
+        }
+
+        assert!(decide_fee_waiver(&conn, 1, true).unwrap());
+        assert!(!decide_fee_waiver(&conn, 1, false).unwrap());
+        assert!(!decide_fee_waiver(&conn, 2, true).unwrap());
+
+        let waivers = load_fee_waivers(&conn).unwrap();
+
+        assert_eq!(waivers.len(), 2);
+        assert_eq!(waivers[0].last_name, "Brown".to_string());
+        assert_eq!(waivers[0].status, "requested".to_string());
+        assert_eq!(waivers[0].fee, 5000);
+        assert_eq!(waivers[1].last_name, "Smith".to_string());
+        assert_eq!(waivers[1].status, "approved".to_string());
+        assert_eq!(waivers[1].fee, 0);
+    }
+
+    #[test]
+    fn test_travel_grants() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        for last_name in &["Smith", "Brown"] {
+            conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                      city, phone, email_to, more_info, price_category, course_type)
+                      VALUES ('sir', $1, 'Test', 'Uni', 'Street', '1', '12345', 'City', '123', 'bob@smith.com', '', 'student', 'course1')",
+                      &[last_name]).unwrap();
+        }
+
+        for registration_id in 1..3 {
+            insert_travel_grant(&conn, &TravelGrant {
+                id: 0,
+                registration_id,
+                title: "".to_string(),
+                first_name: "".to_string(),
+                last_name: "".to_string(),
+                email_to: "".to_string(),
+                amount: 30000,
+                motivation: "PhD student".to_string(),
+                cv_file: "cv.pdf".to_string(),
+                cv_name: "My CV.pdf".to_string(),
+                score: None,
+                award: 0,
+                submitted_at: "2017-01-01 12:00:00".to_string(),
+                notified_at: "".to_string()
+            }).unwrap();
+        }
+
+        // Only one application per registration
+        assert!(conn.execute("INSERT INTO travel_grant (registration_id, amount, motivation, cv_file, cv_name, submitted_at)
+                  VALUES (1, 100, '', '', '', '')", &[]).is_err());
+
+        assert!(review_travel_grant(&conn, 2, 8, 25000).unwrap());
+
+        let grants = load_travel_grants(&conn, None).unwrap();
+        assert_eq!(grants.len(), 2);
+        assert_eq!(grants[0].last_name, "Brown".to_string());
+        assert_eq!(grants[0].score, Some(8));
+        assert_eq!(grants[0].award, 25000);
+        assert_eq!(grants[1].score, None);
+
+        mark_travel_grant_notified(&conn, 2, "2017-02-01 12:00:00").unwrap();
+        assert!(!review_travel_grant(&conn, 2, 3, 0).unwrap());
+        assert_eq!(load_travel_grants(&conn, Some(2)).unwrap()[0].notified_at, "2017-02-01 12:00:00".to_string());
+    }
+
+    #[test]
+    fn test_abstracts() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        for &(last_name, presentation, authors) in &[("Smith", "talk", "Bob Smith\n Alice Brown \n"), ("Miller", "none", ""), ("Brown", "poster", "Alice Brown")] {
+            conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                      city, phone, email_to, more_info, price_category, course_type, presentation, presentation_title,
+                      presentation_authors, abstract_text)
+                      VALUES ('sir', $1, 'Test', 'Uni', 'Street', '1', '12345', 'City', '123', 'bob@smith.com', '', 'student', 'course1', $2, 'Title', $3, 'Text')",
+                      &[&last_name, &presentation, &authors]).unwrap();
+        }
+
+        add_presentation_topic(&conn, 1, "Seismology").unwrap();
+        add_presentation_topic(&conn, 1, "Geodynamics").unwrap();
+        add_presentation_topic(&conn, 1, "Seismology").unwrap();
+
+        assert!(assign_session(&conn, 3, "geo1", 1).unwrap());
+        assert!(assign_session(&conn, 1, "geo1", 2).unwrap());
+        assert!(!assign_session(&conn, 2, "geo1", 3).unwrap());
+
+        let abstracts = load_abstracts(&conn).unwrap();
+
+        assert_eq!(abstracts.len(), 2);
+        assert_eq!(abstracts[0].last_name, "Brown".to_string());
+        assert_eq!(abstracts[0].session, "geo1".to_string());
+        assert_eq!(abstracts[1].authors, vec!["Bob Smith".to_string(), "Alice Brown".to_string()]);
+        assert_eq!(abstracts[1].presenter(), "Test Smith".to_string());
+        assert_eq!(abstracts[1].topics, vec!["Seismology".to_string(), "Geodynamics".to_string()]);
+        assert!(abstracts[0].topics.is_empty());
+        assert_eq!(abstracts[1].file, "".to_string());
+
+        set_presentation_file(&conn, 1, "1_abc.pdf", "talk.pdf").unwrap();
+        let abstracts = load_abstracts(&conn).unwrap();
+        assert_eq!(abstracts[1].file, "1_abc.pdf".to_string());
+        assert_eq!(abstracts[1].file_name, "talk.pdf".to_string());
+
+        // A cancelled presenter is gone from the program, the admin still sees the abstract
+        assert!(update_registration_status(&conn, 3, "confirmed", "cancelled").unwrap());
+        let abstracts = load_abstracts(&conn).unwrap();
+        assert_eq!(abstracts.len(), 1);
+        assert_eq!(abstracts[0].last_name, "Smith".to_string());
+        assert_eq!(load_all_abstracts(&conn).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_newsletter() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        subscribe_newsletter(&conn, "bob@smith.com", "Bob Smith", "token1").unwrap();
+        subscribe_newsletter(&conn, "alice@smith.com", "Alice Smith", "token2").unwrap();
+        subscribe_newsletter(&conn, "bob@smith.com", "Bob Smith", "token3").unwrap();
+
+        let subscribers = load_newsletter_subscribers(&conn).unwrap();
+        assert_eq!(subscribers.len(), 2);
+        assert_eq!(subscribers[1].email, "bob@smith.com".to_string());
+        assert_eq!(subscribers[1].token, "token1".to_string());
+
+        assert!(suppress_by_token(&conn, "token1", "2017-03-01 10:00:00").unwrap());
+        assert!(!suppress_by_token(&conn, "token1", "2017-03-01 10:00:00").unwrap());
+        assert_eq!(load_newsletter_subscribers(&conn).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_settings() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        save_setting(&conn, "capacity", "200", "2017-01-10 12:00:00").unwrap();
+        save_setting(&conn, "banner", "Nur noch wenige Plätze", "2017-01-10 12:00:00").unwrap();
+        save_setting(&conn, "capacity", "250", "2017-01-11 12:00:00").unwrap();
+        assert_eq!(load_settings(&conn).unwrap(), vec![("banner".to_string(), "Nur noch wenige Plätze".to_string()),
+            ("capacity".to_string(), "250".to_string())]);
+
+        delete_setting(&conn, "banner").unwrap();
+        assert_eq!(load_settings(&conn).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_mail_templates() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        assert_eq!(load_mail_template(&conn, "confirmation").unwrap(), None);
+
+        save_mail_template(&conn, "confirmation", "Anmeldung", "{greeting}", "2017-01-10 12:00:00").unwrap();
+        save_mail_template(&conn, "confirmation", "Anmeldung {course}", "{greeting}\n", "2017-01-11 12:00:00").unwrap();
+        assert_eq!(load_mail_template(&conn, "confirmation").unwrap(),
+            Some(("Anmeldung {course}".to_string(), "{greeting}\n".to_string())));
+
+        delete_mail_template(&conn, "confirmation").unwrap();
+        assert_eq!(load_mail_template(&conn, "confirmation").unwrap(), None);
+    }
+
+    #[test]
+    fn test_outbox() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        let mut mail = OutboxMail {
+            id: 0,
+            registration_id: 3,
+            template: "status_cancelled".to_string(),
+            email_to: "bob@smith.com".to_string(),
+            subject: "Stornierung".to_string(),
+            body: "Sehr geehrter Herr Smith,".to_string(),
+            created_at: "2017-03-01 10:00:00".to_string(),
+            sent_at: String::new(),
+            error: String::new()
+        };
+
+        let first = insert_outbox(&conn, &mail).unwrap();
+        mail.template = "status_confirmed".to_string();
+        let second = insert_outbox(&conn, &mail).unwrap();
+        mark_outbox_result(&conn, first, "2017-03-01 10:00:01", "").unwrap();
+        mark_outbox_result(&conn, second, "", "Connection refused").unwrap();
+
+        let outbox = load_outbox(&conn, 10).unwrap();
+        assert_eq!(outbox.len(), 2);
+        assert_eq!(outbox[0].template, "status_confirmed".to_string());
+        assert_eq!(outbox[0].error, "Connection refused".to_string());
+        assert_eq!(outbox[1].id, first);
+        assert_eq!(outbox[1].sent_at, "2017-03-01 10:00:01".to_string());
+        assert_eq!(load_outbox(&conn, 1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_admin_tokens() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        insert_admin_token(&conn, "aaa", 1000, 5000).unwrap();
+        insert_admin_token(&conn, "bbb", 1000, 2000).unwrap();
+        insert_admin_token(&conn, "ccc", 1000, 5000).unwrap();
+
+        let token = load_admin_token(&conn, "aaa").unwrap().unwrap();
+        assert_eq!((token.created_at, token.expires_at, token.replaced_at), (1000, 5000, 0));
+        assert!(load_admin_token(&conn, "zzz").unwrap().is_none());
+
+        // Only the first of two concurrent requests replaces a token
+        assert!(mark_admin_token_replaced(&conn, "aaa", 1500).unwrap());
+        assert!(!mark_admin_token_replaced(&conn, "aaa", 1600).unwrap());
+        assert_eq!(load_admin_token(&conn, "aaa").unwrap().unwrap().replaced_at, 1500);
+
+        assert_eq!(delete_stale_admin_tokens(&conn, 3000, 2000).unwrap(), 2);
+        assert!(load_admin_token(&conn, "ccc").unwrap().is_some());
+        delete_admin_token(&conn, "ccc").unwrap();
+        assert!(load_admin_token(&conn, "ccc").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_login_links() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        for status in &["confirmed", "cancelled"] {
+            conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                      city, phone, email_to, email_normalized, more_info, price_category, course_type, status)
+                      VALUES ('sir', 'Smith', 'Bob', 'Uni', 'Street', '1', '12345', 'City', '123', 'Bob@Smith.com', 'bob@smith.com', '', 'student', 'course1', $1)",
+                      &[status]).unwrap();
+        }
+
+        // The cancelled registration is newer, but the one still taking a place is the one to log in to
+        assert_eq!(find_registration_by_email(&conn, "bob@smith.com").unwrap(), Some(1));
+        assert_eq!(find_registration_by_email(&conn, "alice@smith.com").unwrap(), None);
+        conn.execute("UPDATE registration SET status = 'cancelled' WHERE id = 1", &[]).unwrap();
+        assert_eq!(find_registration_by_email(&conn, "bob@smith.com").unwrap(), Some(2));
+
+        insert_login_link(&conn, "aaa", 1, 2000).unwrap();
+        insert_login_link(&conn, "bbb", 2, 1000).unwrap();
+        assert_eq!(find_login_link(&conn, "aaa", 1500).unwrap(), Some(1));
+        assert_eq!(find_login_link(&conn, "aaa", 2000).unwrap(), None);
+        assert_eq!(find_login_link(&conn, "zzz", 0).unwrap(), None);
+
+        assert_eq!(delete_expired_login_links(&conn, 1500).unwrap(), 1);
+        assert_eq!(find_login_link(&conn, "bbb", 0).unwrap(), None);
+    }
+
+    #[test]
+    fn test_drafts() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        save_draft(&conn, "draft1", "bob@smith.com", "{\"last_name\":\"Smith\"}", "2017-03-01 10:00:00", "2017-03-15 10:00:00").unwrap();
+        save_draft(&conn, "draft2", "alice@brown.com", "{}", "2017-03-01 10:00:00", "2017-03-02 10:00:00").unwrap();
+
+        assert_eq!(load_draft(&conn, "draft1", "2017-03-10 10:00:00").unwrap(), Some(Draft {
+            email: "bob@smith.com".to_string(),
+            form_data: "{\"last_name\":\"Smith\"}".to_string(),
+            expires_at: "2017-03-15 10:00:00".to_string()
+        }));
+        assert_eq!(load_draft(&conn, "draft2", "2017-03-10 10:00:00").unwrap(), None);
+        assert_eq!(load_draft(&conn, "unknown", "2017-03-10 10:00:00").unwrap(), None);
+
+        save_draft(&conn, "draft1", "bob@smith.com", "{}", "2017-03-10 10:00:00", "2017-03-24 10:00:00").unwrap();
+        assert_eq!(load_draft(&conn, "draft1", "2017-03-20 10:00:00").unwrap().unwrap().form_data, "{}".to_string());
+
+        assert_eq!(delete_expired_drafts(&conn, "2017-03-10 10:00:00").unwrap(), 1);
+        delete_draft(&conn, "draft1").unwrap();
+        assert_eq!(load_draft(&conn, "draft1", "2017-03-20 10:00:00").unwrap(), None);
+    }
+
+    #[test]
+    fn test_suppression() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        for &(last_name, email_to, token, undeliverable) in &[("Smith", "Bob@Smith.com", "token1", ""), ("Smith", "bob@smith.com", "token2", ""),
+                ("Brown", "alice@brown.com", "token3", ""), ("Miller", "carol@miller.com", "token4", "2017-03-01 10:00:00")] {
+            conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                      city, phone, email_to, email_normalized, more_info, price_category, course_type, token, email_undeliverable)
+                      VALUES ('sir', $1, 'Test', 'Uni', 'Street', '1', '12345', 'City', '123', $2, lower($2), '', 'student', 'course1', $3, $4)",
+                      &[&last_name, &email_to, &token, &undeliverable]).unwrap();
+        }
+        subscribe_newsletter(&conn, "alice@brown.com", "Alice Brown", "token5").unwrap();
+        subscribe_newsletter(&conn, "dave@jones.com", "Dave Jones", "token6").unwrap();
+
+        let recipients = load_bulk_recipients(&conn, &RegistrationFilter::default()).unwrap();
+        assert_eq!(recipients.len(), 2);
+        assert_eq!(recipients[0].name, "Test Brown".to_string());
+
+        assert!(!suppress_by_token(&conn, "", "2017-03-02 10:00:00").unwrap());
+        assert!(!suppress_by_token(&conn, "unknown", "2017-03-02 10:00:00").unwrap());
+
+        // The registration token also ends the newsletter subscription of the same address
+        assert!(suppress_by_token(&conn, "token3", "2017-03-02 10:00:00").unwrap());
+        assert!(suppress_by_token(&conn, "token3", "2017-03-03 10:00:00").unwrap());
+        assert_eq!(load_bulk_recipients(&conn, &RegistrationFilter::default()).unwrap().len(), 1);
+        assert_eq!(load_newsletter_subscribers(&conn).unwrap().len(), 1);
+
+        assert!(suppress_by_token(&conn, "token6", "2017-03-02 10:00:00").unwrap());
+        assert!(load_newsletter_subscribers(&conn).unwrap().is_empty());
+
+        let suppressed_at: String = conn.query_row("SELECT suppressed_at FROM suppression WHERE email = 'alice@brown.com'", &[], |row| row.get(0)).unwrap();
+        assert_eq!(suppressed_at, "2017-03-02 10:00:00".to_string());
+    }
+
+    #[cfg(feature = "payments")]
+    #[test]
+    fn test_unpaid_registrations() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        for &(last_name, fee, fee_waiver_status, payment_status, registered_at) in &[
+                ("Smith", 5000, "", "unpaid", "2017-01-02 10:00:00"), ("Brown", 0, "approved", "unpaid", "2017-01-01 10:00:00"),
+                ("Miller", 12000, "requested", "unpaid", "2017-01-01 10:00:00"), ("Jones", 12000, "", "paid", "2017-01-01 10:00:00"),
+                ("Black", 12000, "", "unpaid", "2017-01-01 12:00:00")] {
+            conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                      city, phone, email_to, more_info, price_category, course_type, fee, fee_waiver_status, payment_status, registered_at)
+                      VALUES ('sir', $1, 'Test', 'Uni', 'Street', '1', '12345', 'City', '123', 'bob@smith.com', '', 'student', 'course1',
+                      $2, $3, $4, $5)", &[&last_name, &fee, &fee_waiver_status, &payment_status, &registered_at]).unwrap();
+        }
+
+        let unpaid = load_unpaid_registrations(&conn).unwrap();
+        assert_eq!(unpaid.len(), 2);
+        assert_eq!(unpaid[0].last_name, "Black".to_string());
+        assert_eq!(unpaid[0].fee, 12000);
+        assert_eq!(unpaid[1].last_name, "Smith".to_string());
+
+        record_payment_reminder(&conn, unpaid[1].id, "2017-01-16 10:00:00").unwrap();
+        record_payment_reminder(&conn, unpaid[1].id, "2017-02-22 10:00:00").unwrap();
+
+        let unpaid = load_unpaid_registrations(&conn).unwrap();
+        assert_eq!(unpaid[0].payment_reminders, 0);
+        assert_eq!(unpaid[1].payment_reminders, 2);
+        assert_eq!(unpaid[1].last_payment_reminder, "2017-02-22 10:00:00".to_string());
+
+        assert!(set_payment_status(&conn, unpaid[0].id, "waived", "", "2017-02-23 10:00:00").unwrap());
+        assert!(!set_payment_status(&conn, unpaid[0].id, "paid", "cash", "2017-02-23 10:00:00").unwrap());
+        assert_eq!(load_unpaid_registrations(&conn).unwrap().len(), 1);
+        assert_eq!(load_payments(&conn).unwrap().len(), 1);
+
+        assert!(set_payment_status(&conn, unpaid[1].id, "paid", "transfer", "2017-02-24 10:00:00").unwrap());
+        let payments = load_payments(&conn).unwrap();
+        assert_eq!(payments.len(), 2);
+        assert_eq!(payments[0].last_name, "Jones".to_string());
+        assert_eq!(payments[1].fee, 5000);
+        assert_eq!(payments[1].paid_at, "2017-02-24 10:00:00".to_string());
+        assert_eq!(payments[1].payment_method, "transfer".to_string());
+    }
+
+    #[test]
+    fn test_reimbursement() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                  city, phone, email_to, more_info, price_category, course_type, email_normalized, token)
+                  VALUES ('sir', 'Smith', 'Bob', 'Uni', 'Street', '1', '12345', 'City', '123', 'Bob@Smith.com', '', 'student',
+                  'course1', 'bob@smith.com', '0123456789abcdef')", &[]).unwrap();
+
+        assert!(find_by_token(&conn, "").unwrap().is_none());
+        assert!(find_by_token(&conn, "fedcba9876543210").unwrap().is_none());
+
+        let owner = find_by_token(&conn, "0123456789abcdef").unwrap().unwrap();
+        assert_eq!(owner.email_normalized, "bob@smith.com".to_string());
+
+        let mut reimbursement = Reimbursement {
+            id: 0,
+            registration_id: owner.id,
+            first_name: "".to_string(),
+            last_name: "".to_string(),
+            account_holder: "Bob Smith".to_string(),
+            iban: "DE89370400440532013000".to_string(),
+            bic: "".to_string(),
+            amount: 12345,
+            description: "Train ticket".to_string(),
+            receipt_file: "receipt_1.pdf".to_string(),
+            receipt_name: "ticket.pdf".to_string(),
+            status: "submitted".to_string(),
+            submitted_at: "2017-01-01 12:00:00".to_string(),
+            updated_at: "2017-01-01 12:00:00".to_string()
+        };
+
+        reimbursement.id = insert_reimbursement(&conn, &reimbursement).unwrap();
+        update_reimbursement_status(&conn, reimbursement.id, "approved", "2017-01-02 12:00:00").unwrap();
+
+        reimbursement.first_name = "Bob".to_string();
+        reimbursement.last_name = "Smith".to_string();
+        reimbursement.status = "approved".to_string();
+        reimbursement.updated_at = "2017-01-02 12:00:00".to_string();
+
+        assert_eq!(load_reimbursements(&conn, Some(owner.id)).unwrap(), vec![reimbursement]);
+        assert_eq!(load_reimbursements(&conn, None).unwrap().len(), 1);
+        assert!(load_reimbursements(&conn, Some(owner.id + 1)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_merge_registrations() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        for &(email, phone, form_token) in &[("bob@smith.com", "123", "a1"), ("Bob@Smith.com", "", "b2")] {
+            conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                      city, email_to, email_normalized, phone, more_info, price_category, course_type, form_token)
+                      VALUES ('sir', 'Smith', 'Bob', 'Uni', 'Street', '1', '12345', 'City', $1, lower($1), $2, '', 'student', 'course1', $3)",
+                      &[&email, &phone, &form_token]).unwrap();
+        }
+
+        let candidates = load_duplicate_candidates(&conn).unwrap();
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].email_normalized, candidates[1].email_normalized);
+
+        book_social_event(&conn, 2, "banquet").unwrap();
+        add_presentation_topic(&conn, 1, "tectonics").unwrap();
+        add_presentation_topic(&conn, 2, "tectonics").unwrap();
+        add_presentation_topic(&conn, 2, "volcanology").unwrap();
+
+        assert!(merge_registrations(&conn, 1, 1, &[]).is_err());
+        assert!(merge_registrations(&conn, 1, 2, &["shoe_size".to_string()]).is_err());
+        assert!(merge_registrations(&conn, 1, 3, &[]).is_err());
+
+        merge_registrations(&conn, 1, 2, &["email_to".to_string(), "form_token".to_string()]).unwrap();
+
+        assert_eq!(count_registrations(&conn).unwrap(), 1);
+        let values = load_registration_values(&conn, 1).unwrap().unwrap();
+        let value = |column: &str| values.iter().find(|&(name, _)| name == column).unwrap().1.clone();
+        assert_eq!(value("email_to"), "Bob@Smith.com".to_string());
+        assert_eq!(value("phone"), "123".to_string());
+        assert_eq!(value("form_token"), "b2".to_string());
+        assert!(load_registration_values(&conn, 2).unwrap().is_none());
+
+        let bookings: i64 = conn.query_row("SELECT COUNT(*) FROM social_booking WHERE registration_id = 1", &[], |row| row.get(0)).unwrap();
+        let topics: i64 = conn.query_row("SELECT COUNT(*) FROM presentation_topic WHERE registration_id = 1", &[], |row| row.get(0)).unwrap();
+        assert_eq!((bookings, topics), (1, 2));
+    }
+
+    #[test]
+    fn test_delete_registration() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        for form_token in &["a1", "b2"] {
+            conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                      city, phone, email_to, more_info, price_category, course_type, form_token)
+                      VALUES ('sir', 'Smith', 'Bob', 'Uni', 'Street', '1', '12345', 'City', '123', 'bob@smith.com', '', 'student', 'course1', $1)",
+                      &[form_token]).unwrap();
+        }
+
+        book_social_event(&conn, 1, "banquet").unwrap();
+        add_presentation_topic(&conn, 1, "tectonics").unwrap();
+        add_presentation_topic(&conn, 2, "tectonics").unwrap();
+
+        let linked = count_linked_rows(&conn, 1).unwrap();
+        assert_eq!(linked.len(), LINKED_TABLES.len());
+        assert!(linked.contains(&("social_booking", 1)) && linked.contains(&("presentation_topic", 1)));
+
+        delete_registration(&conn, 1).unwrap();
+        assert_eq!(count_registrations(&conn).unwrap(), 1);
+        assert!(count_linked_rows(&conn, 1).unwrap().iter().all(|&(_, count)| count == 0));
+        assert!(count_linked_rows(&conn, 2).unwrap().contains(&("presentation_topic", 1)));
+
+        assert!(delete_registration(&conn, 1).is_err());
+        conn.execute_batch("BEGIN; COMMIT;").unwrap();
+    }
+
+    #[test]
+    fn test_update_own_details() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                  city, phone, email_to, more_info, price_category, course_type, presentation, presentation_title)
+                  VALUES ('sir', 'Smith', 'Bob', 'Uni', 'Street', '1', '12345', 'City', '123', 'bob@smith.com', '', 'student', 'course1',
+                  'talk', 'Plate tectonics')", &[]).unwrap();
+
+        assert!(update_own_details(&conn, 1, "Plate tectonics revisited", "Bob Smith", "Abstract", "Vegetarisch").unwrap());
+        assert!(!update_own_details(&conn, 2, "", "", "", "").unwrap());
+
+        let (title, more_info): (String, String) = conn.query_row("SELECT presentation_title, more_info FROM registration WHERE id = 1", &[],
+            |row| (row.get(0), row.get(1))).unwrap();
+        assert_eq!(title, "Plate tectonics revisited".to_string());
+        assert_eq!(more_info, "Vegetarisch".to_string());
+    }
+
+    #[test]
+    fn test_roster_changes() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        for (last_name, registered_at) in [("Smith", "2017-01-10 09:00:00"), ("Brown", "2017-01-20 09:00:00")] {
+            conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                      city, phone, email_to, more_info, price_category, course_type, registered_at)
+                      VALUES ('sir', $1, 'Test', 'Uni', 'Street', '1', '12345', 'City', '123', 'bob@smith.com', '', 'student', 'course1', $2)",
+                      &[&last_name, &registered_at]).unwrap();
+        }
+
+        let roster = load_roster(&conn, "2017-01-15 00:00:00").unwrap();
+        assert_eq!(roster.len(), 1);
+        assert_eq!(roster[0].last_name, "Smith".to_string());
+        assert_eq!(load_roster(&conn, "2017-02-01 00:00:00").unwrap().len(), 2);
+
+        assert!(update_registration_status(&conn, 1, "confirmed", "cancelled").unwrap());
+        assert!(!update_registration_status(&conn, 1, "confirmed", "cancelled").unwrap());
+        assert!(update_own_details(&conn, 2, "", "", "", "Vegetarisch").unwrap());
+
+        let changes = load_roster_changes(&conn).unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!((changes[0].registration_id, changes[0].kind.as_str(), changes[0].old_status.as_str(), changes[0].new_status.as_str()),
+            (1, "status", "confirmed", "cancelled"));
+        assert_eq!((changes[1].registration_id, changes[1].kind.as_str()), (2, "details"));
+    }
+
+    #[test]
+    fn test_merge_conflict_rolls_back() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        for _ in 0..2 {
+            conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                      city, phone, email_to, more_info, price_category, course_type)
+                      VALUES ('sir', 'Smith', 'Bob', 'Uni', 'Street', '1', '12345', 'City', '123', 'bob@smith.com', '', 'student', 'course1')",
+                      &[]).unwrap();
+        }
+
+        book_social_event(&conn, 2, "banquet").unwrap();
+        for id in 1..3 {
+            conn.execute("INSERT INTO travel_grant (registration_id, amount, motivation, cv_file, cv_name, submitted_at)
+                      VALUES ($1, 0, '', '', '', '')", &[&(id as i64)]).unwrap();
+        }
+
+        assert!(merge_registrations(&conn, 1, 2, &[]).is_err());
+        assert_eq!(count_registrations(&conn).unwrap(), 2);
+        let bookings: i64 = conn.query_row("SELECT COUNT(*) FROM social_booking WHERE registration_id = 2", &[], |row| row.get(0)).unwrap();
+        assert_eq!(bookings, 1);
+    }
+
+    // A table added later that refers to the registrations must be handled by the merge, otherwise its rows are lost
+    #[test]
+    fn test_linked_tables() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        let tables: Vec<String> = {
+            let mut stmt = conn.prepare("SELECT name FROM sqlite_master WHERE type = 'table'").unwrap();
+            let rows = stmt.query_map(&[], |row| row.get(0)).unwrap();
+            rows.collect::<Result<Vec<String>, _>>().unwrap()
+        };
+
+        for table in tables {
+            let mut stmt = conn.prepare(&format!("PRAGMA foreign_key_list({})", table)).unwrap();
+            let rows = stmt.query_map(&[], |row| (row.get::<i32, String>(2), row.get::<i32, String>(3))).unwrap();
+
+            for row in rows {
+                let (target, column) = row.unwrap();
+                if target == "registration" {
+                    assert!(LINKED_TABLES.iter().any(|&(linked, _)| linked == table), "{} is missing in LINKED_TABLES", table);
+                    assert_eq!(column, "registration_id".to_string());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use std::io;
+use std::io::Write;
+
+use chrono::{Datelike, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone};
+use chrono::format::{Item, StrftimeItems};
+
+
+// How timestamps are stored in the database, always the local time of the server
+pub const STORED_TIMESTAMP: &str = "%Y-%m-%d %H:%M:%S";
+const STORED_DATE: &str = "%Y-%m-%d";
+// Points in time of the configuration and /admin/settings, like banner_until, in the time of the server
+pub const CONFIGURED_TIME: &str = "%Y-%m-%d %H:%M";
+
+/// The current local time as it is stored in the database
+pub fn timestamp() -> String {
+    Local::now().format(STORED_TIMESTAMP).to_string()
+}
+
+pub const LOCALES: [&str; 2] = ["de", "en"];
+
+const MONTHS_DE: [&str; 12] = ["Januar", "Februar", "März", "April", "Mai", "Juni", "Juli", "August", "September",
+    "Oktober", "November", "Dezember"];
+const WEEKDAYS_DE: [&str; 7] = ["Montag", "Dienstag", "Mittwoch", "Donnerstag", "Freitag", "Samstag", "Sonntag"];
+
+/// How dates and times are shown in the pages, mails, the PDF participant list and the log file, see [Dates].
+/// Only the display changes, the database keeps STORED_TIMESTAMP.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DateFormat {
+    pub date: String,
+    pub date_time: String,
+    // "de" or "en", for the names of months and weekdays
+    pub locale: String,
+    // Seconds east of UTC, None for the time zone of the server
+    pub utc_offset: Option<i32>
+}
+
+impl Default for DateFormat {
+    fn default() -> DateFormat {
+        DateFormat {
+            date: "%d.%m.%Y".to_string(),
+            date_time: "%d.%m.%Y %H:%M:%S".to_string(),
+            locale: "de".to_string(),
+            utc_offset: None
+        }
+    }
+}
+
+// chrono can't write an unknown specifier like %Q and fails in the middle of formatting
+pub fn valid_format(format: &str) -> bool {
+    !format.is_empty() && StrftimeItems::new(format).all(|item| item != Item::Error)
+}
+
+// "local", "UTC" or an offset like "+01:00"
+pub fn parse_utc_offset(timezone: &str) -> Option<Option<i32>> {
+    let timezone = timezone.trim();
+
+    match timezone.to_lowercase().as_str() {
+        "local" => return Some(None),
+        "utc" => return Some(Some(0)),
+        _ => ()
+    }
+
+    let sign = match timezone.chars().next() {
+        Some('+') => 1,
+        Some('-') => -1,
+        _ => return None
+    };
+
+    let mut parts = timezone[1..].split(':');
+    let hours = parts.next().and_then(|hours| hours.parse::<i32>().ok())?;
+    let minutes = parts.next().map_or(Some(0), |minutes| minutes.parse::<i32>().ok())?;
+
+    if parts.next().is_some() || hours > 14 || !(0..=59).contains(&minutes) || hours < 0 {
+        return None;
+    }
+
+    Some(Some(sign * (hours * 3600 + minutes * 60)))
+}
+
+impl DateFormat {
+    // chrono only knows the English names, %B %b %A %a are filled in beforehand for German
+    fn localize(&self, format: &str, date: NaiveDate) -> String {
+        if self.locale != "de" {
+            return format.to_string();
+        }
+
+        let month = MONTHS_DE[date.month0() as usize];
+        let weekday = WEEKDAYS_DE[date.weekday().num_days_from_monday() as usize];
+
+        format.replace("%B", month).replace("%b", &month.chars().take(3).collect::<String>())
+            .replace("%A", weekday).replace("%a", &weekday.chars().take(2).collect::<String>())
+    }
+
+    fn format(&self, format: &str, time: NaiveDateTime) -> String {
+        time.format(&self.localize(format, time.date())).to_string()
+    }
+
+    // A local time of the server in the configured time zone
+    fn in_zone(&self, time: NaiveDateTime) -> NaiveDateTime {
+        match self.utc_offset {
+            Some(offset) => match Local.from_local_datetime(&time).earliest() {
+                Some(local) => local.with_timezone(&FixedOffset::east(offset)).naive_local(),
+                None => time
+            },
+            None => time
+        }
+    }
+
+    pub fn now(&self) -> String {
+        self.format(&self.date_time, self.in_zone(Local::now().naive_local()))
+    }
+
+    pub fn today(&self) -> String {
+        self.format(&self.date, self.in_zone(Local::now().naive_local()))
+    }
+
+    // A stored timestamp, with or without seconds. Anything else, like an empty column, is shown as it is.
+    pub fn timestamp(&self, stored: &str) -> String {
+        match NaiveDateTime::parse_from_str(stored, STORED_TIMESTAMP).or_else(|_| NaiveDateTime::parse_from_str(stored, "%Y-%m-%d %H:%M")) {
+            Ok(time) => self.format(&self.date_time, self.in_zone(time)),
+            Err(_) => stored.to_string()
+        }
+    }
+
+    // A day as in the configuration, e.g. the payment deadline. Days are not moved between time zones.
+    pub fn date(&self, stored: &str) -> String {
+        match NaiveDate::parse_from_str(stored, STORED_DATE) {
+            Ok(date) => self.format(&self.date, date.and_hms(0, 0, 0)),
+            Err(_) => stored.to_string()
+        }
+    }
+}
+
+/// Puts the current time in front of every log line, the loggers of simplelog only know the time of day
+pub struct TimestampWriter<W: Write> {
+    inner: W,
+    format: DateFormat,
+    line_start: bool
+}
+
+impl<W: Write> TimestampWriter<W> {
+    pub fn new(inner: W, format: DateFormat) -> TimestampWriter<W> {
+        TimestampWriter { inner, format, line_start: true }
+    }
+}
+
+impl<W: Write> Write for TimestampWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.line_start {
+            write!(self.inner, "{} ", self.format.now())?;
+        }
+
+        self.inner.write_all(buf)?;
+        self.line_start = buf.ends_with(b"\n");
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DateFormat, TimestampWriter, valid_format, parse_utc_offset};
+    use std::io::Write;
+
+    #[test]
+    fn test_format_stored() {
+        let mut format = DateFormat::default();
+        assert_eq!(format.timestamp("2017-03-28 09:05:00"), "28.03.2017 09:05:00".to_string());
+        assert_eq!(format.timestamp("2017-03-28 09:05"), "28.03.2017 09:05:00".to_string());
+        assert_eq!(format.timestamp(""), "".to_string());
+        assert_eq!(format.date("2017-03-01"), "01.03.2017".to_string());
+
+        format.date = "%A, %e. %B %Y".to_string();
+        assert_eq!(format.date("2017-03-01"), "Mittwoch,  1. März 2017".to_string());
+        format.locale = "en".to_string();
+        assert_eq!(format.date("2017-03-01"), "Wednesday,  1. March 2017".to_string());
+    }
+
+    #[test]
+    fn test_valid_format() {
+        assert!(valid_format("%d.%m.%Y %H:%M"));
+        assert!(!valid_format("%d.%m.%Q"));
+        assert!(!valid_format(""));
+    }
+
+    #[test]
+    fn test_parse_utc_offset() {
+        assert_eq!(parse_utc_offset("local"), Some(None));
+        assert_eq!(parse_utc_offset("UTC"), Some(Some(0)));
+        assert_eq!(parse_utc_offset("+01:00"), Some(Some(3600)));
+        assert_eq!(parse_utc_offset("-05:30"), Some(Some(-19800)));
+        assert_eq!(parse_utc_offset("+2"), Some(Some(7200)));
+        assert_eq!(parse_utc_offset("Europe/Berlin"), None);
+        assert_eq!(parse_utc_offset("+25:00"), None);
+    }
+
+    #[test]
+    fn test_timestamp_writer() {
+        let mut format = DateFormat::default();
+        format.date_time = "[time]".to_string();
+        let mut writer = TimestampWriter::new(Vec::new(), format);
+
+        write!(writer, "[INFO] ").unwrap();
+        writeln!(writer, "first").unwrap();
+        writeln!(writer, "second").unwrap();
+
+        assert_eq!(String::from_utf8(writer.inner).unwrap(), "[time] [INFO] first\n[time] second\n".to_string());
+    }
+}
@@ -0,0 +1,158 @@
+use openssl::base64::encode_block;
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sha::sha256;
+use openssl::sign::Signer;
+
+
+// Signed if present, in this order. Receivers only honor a one-click unsubscribe if its headers are signed.
+const SIGNED_HEADERS: [&str; 10] = ["from", "sender", "to", "cc", "subject", "date", "message-id", "mime-version",
+    "list-unsubscribe", "list-unsubscribe-post"];
+
+fn compress_whitespace(text: &str) -> String {
+    let mut result = String::new();
+    let mut in_whitespace = false;
+
+    for c in text.chars() {
+        if c == ' ' || c == '\t' {
+            in_whitespace = true;
+        } else {
+            if in_whitespace {
+                result.push(' ');
+                in_whitespace = false;
+            }
+            result.push(c);
+        }
+    }
+
+    if in_whitespace {
+        result.push(' ');
+    }
+
+    result
+}
+
+// "relaxed" header canonicalization (RFC 6376, 3.4.2), the header must already be unfolded
+fn canonicalize_header(name: &str, value: &str) -> String {
+    format!("{}:{}", name.trim().to_lowercase(), compress_whitespace(value).trim())
+}
+
+// "relaxed" body canonicalization (RFC 6376, 3.4.4)
+fn canonicalize_body(body: &str) -> String {
+    let mut lines: Vec<String> = body.split("\r\n").map(|line| compress_whitespace(line).trim_end().to_string()).collect();
+
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+
+    if lines.is_empty() {
+        String::new()
+    } else {
+        format!("{}\r\n", lines.join("\r\n"))
+    }
+}
+
+// Splits the rendered message into unfolded (name, value) pairs and the body
+fn split_message(message: &str) -> (Vec<(String, String)>, String) {
+    let (head, body) = match message.find("\r\n\r\n") {
+        Some(pos) => (&message[..pos], &message[pos + 4..]),
+        None => (message, "")
+    };
+
+    let mut headers: Vec<(String, String)> = Vec::new();
+
+    for line in head.split("\r\n") {
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some(&mut (_, ref mut value)) = headers.last_mut() {
+                value.push_str(line);
+            }
+        } else if let Some(pos) = line.find(':') {
+            headers.push((line[..pos].to_string(), line[pos + 1..].to_string()));
+        }
+    }
+
+    (headers, body.to_string())
+}
+
+// Returns the message with a DKIM-Signature header (rsa-sha256, relaxed/relaxed) in front of it.
+// The line endings are normalized to CRLF first, so that the signature matches what goes over the wire.
+pub fn sign_message(message: &str, domain: &str, selector: &str, private_key: &str, timestamp: i64) -> Result<String, ErrorStack> {
+    let message = message.replace("\r\n", "\n").replace('\n', "\r\n");
+    let (headers, body) = split_message(&message);
+
+    let body_hash = encode_block(&sha256(canonicalize_body(&body).as_bytes()));
+
+    let signed: Vec<&(String, String)> = SIGNED_HEADERS.iter()
+        .filter_map(|name| headers.iter().find(|&(header, _)| header.to_lowercase() == *name))
+        .collect();
+    let names: Vec<String> = signed.iter().map(|&(name, _)| name.to_lowercase()).collect();
+
+    let dkim_value = format!("v=1; a=rsa-sha256; c=relaxed/relaxed; d={}; s={}; t={}; h={}; bh={}; b=",
+        domain, selector, timestamp, names.join(":"), body_hash);
+
+    let mut data = String::new();
+    for &(name, value) in &signed {
+        data.push_str(&canonicalize_header(name, value));
+        data.push_str("\r\n");
+    }
+    // The signature header itself is signed without its trailing line break
+    data.push_str(&canonicalize_header("DKIM-Signature", &dkim_value));
+
+    let key = PKey::private_key_from_pem(private_key.as_bytes())?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    signer.update(data.as_bytes())?;
+    let signature = encode_block(&signer.sign_to_vec()?);
+
+    Ok(format!("DKIM-Signature: {}{}\r\n{}", dkim_value, signature, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{canonicalize_header, canonicalize_body, split_message, sign_message};
+    use openssl::base64::{encode_block, decode_block};
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::sha::sha256;
+    use openssl::sign::Verifier;
+
+    #[test]
+    fn test_canonicalize() {
+        assert_eq!(canonicalize_header("Subject", " Hello \t  World  "), "subject:Hello World".to_string());
+        assert_eq!(canonicalize_body(" C \r\nD \t E\r\n\r\n\r\n"), " C\r\nD E\r\n".to_string());
+        assert_eq!(canonicalize_body("\r\n"), "".to_string());
+        // Known hash of the empty body
+        assert_eq!(encode_block(&sha256(canonicalize_body("").as_bytes())), "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=".to_string());
+    }
+
+    #[test]
+    fn test_split_message() {
+        let (headers, body) = split_message("To: bob@example.org\r\nSubject: Long\r\n subject\r\n\r\nHello\r\n");
+
+        assert_eq!(headers, vec![("To".to_string(), " bob@example.org".to_string()), ("Subject".to_string(), " Long subject".to_string())]);
+        assert_eq!(body, "Hello\r\n".to_string());
+    }
+
+    #[test]
+    fn test_sign_message() {
+        let rsa = Rsa::generate(1024).unwrap();
+        let private_key = String::from_utf8(rsa.private_key_to_pem().unwrap()).unwrap();
+        let public_key = PKey::from_rsa(rsa).unwrap();
+
+        let signed = sign_message("From: alice@example.org\nTo: bob@example.org\nSubject: Test\n\nHello\n", "example.org", "mail", &private_key, 1500000000).unwrap();
+
+        assert!(signed.starts_with("DKIM-Signature: v=1; a=rsa-sha256; c=relaxed/relaxed; d=example.org; s=mail; t=1500000000; h=from:to:subject; bh="));
+        assert!(signed.ends_with("\r\n\r\nHello\r\n"));
+
+        let header_end = signed.find("\r\n").unwrap();
+        let value = &signed["DKIM-Signature: ".len()..header_end];
+        let pos = value.rfind("b=").unwrap();
+        let signature = decode_block(&value[pos + 2..]).unwrap();
+
+        let data = format!("from:alice@example.org\r\nto:bob@example.org\r\nsubject:Test\r\ndkim-signature:{}", &value[..pos + 2]);
+        let mut verifier = Verifier::new(MessageDigest::sha256(), &public_key).unwrap();
+        verifier.update(data.as_bytes()).unwrap();
+        assert!(verifier.verify(&signature).unwrap());
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+
+use iron::prelude::{Request, IronResult, Response, Set};
+use iron::status;
+
+use handlebars_iron::{Template};
+use params::Params;
+use plugin::Pluggable;
+use persistent::{Read, Write};
+use chrono::{Local, Duration};
+
+use ::DBConnection;
+use closing::{closed_page, unopened_page};
+use config::Configuration;
+use database::{lock_connection, save_draft, load_draft, delete_expired_drafts};
+use handler::{extract_string, form_snapshot, normalize_email, send_email, HandleError};
+use validation::valid_email;
+use dates::timestamp;
+use throttle::throttle_submission;
+use token::generate_token;
+
+
+// How long a saved draft can be resumed
+const DRAFT_DAYS: i64 = 14;
+
+pub fn handle_save_draft(req: &mut Request) -> IronResult<Response> {
+    if let Some(resp) = closed_page(req, status::Forbidden).or_else(|| unopened_page(req, status::Forbidden)) {
+        return Ok(resp);
+    }
+
+    let mut message = BTreeMap::new();
+    let mut response_status = status::Ok;
+
+    let text = match throttle_submission(req).and_then(|_| save_form_draft(req)) {
+        Ok(true) => "Ihr Entwurf wurde gespeichert. Den Link zum Fortsetzen der Anmeldung haben wir Ihnen per E-Mail geschickt.",
+        Ok(false) => "Ihr Entwurf wurde aktualisiert. Sie können die Anmeldung weiterhin über den Link aus unserer E-Mail fortsetzen.",
+        Err(HandleError::Validation(_)) => "Bitte geben Sie Ihre E-Mail-Adresse an, damit wir Ihnen den Link zu Ihrem Entwurf schicken können. \
+            Bitte gehen Sie zurück und ergänzen Sie die Angabe.",
+        Err(HandleError::Throttled(ip)) => {
+            info!("Too many drafts from '{}'", ip);
+            response_status = status::TooManyRequests;
+            "Von Ihrem Anschluss wurden in der letzten Stunde bereits sehr viele Formulare abgeschickt. Bitte versuchen Sie es später noch einmal."
+        }
+        Err(e) => {
+            error!("Error while saving draft: {:?}", e);
+            "Ein Fehler ist aufgetreten. Bitte versuchen Sie es später noch einmal."
+        }
+    };
+
+    message.insert("message".to_string(), text.to_string());
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("submit", message)).set_mut(response_status);
+    Ok(resp)
+}
+
+fn draft_mail(url: &str, expires_at: &str) -> String {
+    format!("Guten Tag,\n\n\
+        Ihre Anmeldung wurde als Entwurf gespeichert. Sie koennen sie bis {} ueber folgenden Link fortsetzen:\n\n\
+        {}\n\n\
+        Der Entwurf wird geloescht, sobald Sie die Anmeldung abschicken.\n", expires_at, url)
+}
+
+// Returns true if a new draft was created and its link sent, false if an existing draft was updated
+fn save_form_draft(req: &mut Request) -> Result<bool, HandleError> {
+    let map = req.get::<Params>()?;
+    let config = req.get::<Read<Configuration>>()?;
+
+    let email = extract_string(&map, "email_to").unwrap_or_default();
+    if !valid_email(&email) {
+        return Err(HandleError::Validation("email_to".to_string()));
+    }
+
+    // The form gets a new one-time token when it is resumed
+    let mut snapshot = form_snapshot(&map);
+    if let Some(fields) = snapshot.as_object_mut() {
+        fields.remove("form_token");
+        fields.remove("draft");
+    }
+
+    let now = Local::now();
+    let saved_at = now.format("%Y-%m-%d %H:%M:%S").to_string();
+    let expires_at = (now + Duration::days(DRAFT_DAYS)).format("%Y-%m-%d %H:%M:%S").to_string();
+
+    let (token, is_new) = {
+        let mutex = req.get::<Write<DBConnection>>()?;
+        let db_connection = lock_connection(&mutex)?;
+
+        delete_expired_drafts(&db_connection, &saved_at)?;
+
+        // The link of a resumed draft stays valid as long as it is saved for the same address
+        let existing = match extract_string(&map, "draft") {
+            Ok(token) => load_draft(&db_connection, &token, &saved_at)?
+                .and_then(|draft| if normalize_email(&draft.email) == normalize_email(&email) { Some(token) } else { None }),
+            Err(_) => None
+        };
+
+        let (token, is_new) = match existing {
+            Some(token) => (token, false),
+            None => (generate_token()?, true)
+        };
+
+        save_draft(&db_connection, &token, &email, &snapshot.to_string(), &saved_at, &expires_at)?;
+
+        (token, is_new)
+    };
+
+    if is_new {
+        let url = format!("{}/?draft={}", config.base_url, token);
+        send_email(&email, "Ihr Entwurf der Anmeldung", &draft_mail(&url, &expires_at[..10]), &config)?;
+        info!("Draft saved for '{}'", email);
+    }
+
+    Ok(is_new)
+}
+
+// The saved form data for the registration page, None if the draft doesn't exist or has expired
+pub fn draft_form_data(req: &mut Request, token: &str) -> Result<Option<String>, HandleError> {
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    Ok(load_draft(&db_connection, token, &timestamp())?.map(|draft| script_safe(&draft.form_data)))
+}
+
+// The JSON ends up in a <script> element, which must not be closed by the participant's input
+fn script_safe(json: &str) -> String {
+    json.replace('<', "\\u003c").replace('>', "\\u003e")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{draft_mail, script_safe};
+
+    #[test]
+    fn test_draft_mail() {
+        let mail = draft_mail("https://example.org/?draft=abc", "2017-03-15");
+        assert!(mail.contains("bis 2017-03-15"));
+        assert!(mail.contains("\n\nhttps://example.org/?draft=abc\n\n"));
+    }
+
+    #[test]
+    fn test_script_safe() {
+        assert_eq!(script_safe("{\"more_info\":\"</script><script>alert(1)\"}"),
+            "{\"more_info\":\"\\u003c/script\\u003e\\u003cscript\\u003ealert(1)\"}".to_string());
+    }
+}
@@ -0,0 +1,302 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+
+use chrono::Local;
+use rusqlite::{self, Connection};
+use rusqlite::types::{Value, ToSqlOutput, ToSql};
+use serde_json::{self, Map, Value as JsonValue};
+
+use database::Repository;
+use handler::HandleError;
+
+
+// Increased whenever the layout of the file changes, restore refuses files of a newer version.
+// New columns and tables don't count, older files just leave them at their defaults.
+const DUMP_VERSION: u64 = 1;
+
+const DUMP_FORMAT: &str = "conference_registration dump";
+
+#[derive(Debug)]
+pub enum DumpError {
+    Database(HandleError),
+    SQL(rusqlite::Error),
+    IO(io::Error),
+    JSON(serde_json::Error),
+    // Not a dump or from a newer version
+    Format(String),
+    // Restoring only works into an empty database, the table already has rows
+    NotEmpty(String)
+}
+
+impl From<HandleError> for DumpError {
+    fn from(e: HandleError) -> DumpError {
+        DumpError::Database(e)
+    }
+}
+
+impl From<rusqlite::Error> for DumpError {
+    fn from(e: rusqlite::Error) -> DumpError {
+        DumpError::SQL(e)
+    }
+}
+
+impl From<io::Error> for DumpError {
+    fn from(e: io::Error) -> DumpError {
+        DumpError::IO(e)
+    }
+}
+
+impl From<serde_json::Error> for DumpError {
+    fn from(e: serde_json::Error) -> DumpError {
+        DumpError::JSON(e)
+    }
+}
+
+fn table_names(db_connection: &Connection) -> Result<Vec<String>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare("
+        SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name")?;
+    let mut rows = stmt.query(&[])?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        result.push(row?.get(0));
+    }
+
+    Ok(result)
+}
+
+fn column_names(db_connection: &Connection, table: &str) -> Result<Vec<String>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare(&format!("PRAGMA table_info(\"{}\")", table))?;
+    let mut rows = stmt.query(&[])?;
+    let mut result = Vec::new();
+
+    while let Some(row) = rows.next() {
+        result.push(row?.get(1));
+    }
+
+    Ok(result)
+}
+
+fn to_json(value: Value) -> JsonValue {
+    match value {
+        Value::Null => JsonValue::Null,
+        Value::Integer(number) => json!(number),
+        Value::Real(number) => json!(number),
+        Value::Text(text) => JsonValue::String(text),
+        Value::Blob(bytes) => json!({ "blob": bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<String>() })
+    }
+}
+
+fn from_json(value: &JsonValue) -> Option<Value> {
+    match *value {
+        JsonValue::Null => Some(Value::Null),
+        JsonValue::Number(ref number) => number.as_i64().map(Value::Integer).or_else(|| number.as_f64().map(Value::Real)),
+        JsonValue::String(ref text) => Some(Value::Text(text.clone())),
+        JsonValue::Object(ref object) => {
+            let hex = object.get("blob")?.as_str()?;
+            if hex.len() % 2 != 0 {
+                return None;
+            }
+            (0..hex.len() / 2).map(|index| hex.get(index * 2..index * 2 + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+                .collect::<Option<Vec<u8>>>().map(Value::Blob)
+        }
+        _ => None
+    }
+}
+
+/// Every table with all its rows, as one JSON object per row keyed by the column names.
+/// Only plain values, so the file can be read into any other database.
+pub fn dump_database(db_connection: &Connection) -> Result<JsonValue, DumpError> {
+    let mut tables = Map::new();
+
+    for table in table_names(db_connection)? {
+        let columns = column_names(db_connection, &table)?;
+        let mut stmt = db_connection.prepare(&format!("SELECT * FROM \"{}\" ORDER BY rowid", table))?;
+        let mut rows = stmt.query(&[])?;
+        let mut result = Vec::new();
+
+        while let Some(row) = rows.next() {
+            let row = row?;
+            let mut object = Map::new();
+            for (index, column) in columns.iter().enumerate() {
+                object.insert(column.clone(), to_json(row.get_checked(index as i32)?));
+            }
+            result.push(JsonValue::Object(object));
+        }
+
+        tables.insert(table, JsonValue::Array(result));
+    }
+
+    Ok(json!({
+        "format": DUMP_FORMAT,
+        "version": DUMP_VERSION,
+        "application_version": env!("CARGO_PKG_VERSION"),
+        "created_at": Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        "tables": tables
+    }))
+}
+
+/// Reads a dump into a database that has the current schema and no data yet, all or nothing.
+/// Returns the number of rows.
+pub fn restore_database(db_connection: &Connection, dump: &JsonValue) -> Result<usize, DumpError> {
+    if dump["format"] != json!(DUMP_FORMAT) {
+        return Err(DumpError::Format("not a dump of the registration database".to_string()));
+    }
+
+    match dump["version"].as_u64() {
+        Some(version) if version <= DUMP_VERSION => (),
+        _ => return Err(DumpError::Format(format!("version {} can't be read, version {} or older is needed", dump["version"], DUMP_VERSION)))
+    }
+
+    let tables = dump["tables"].as_object().ok_or_else(|| DumpError::Format("no tables".to_string()))?;
+    let existing = table_names(db_connection)?;
+
+    // The registrations first, the other tables refer to them
+    let mut names: Vec<&String> = tables.keys().collect();
+    names.sort_by_key(|name| name.as_str() != "registration");
+
+    for name in &names {
+        if !existing.contains(name) {
+            return Err(DumpError::Format(format!("unknown table '{}'", name)));
+        }
+
+        let count: i64 = db_connection.query_row(&format!("SELECT COUNT(*) FROM \"{}\"", name), &[], |row| row.get(0))?;
+        if count > 0 {
+            return Err(DumpError::NotEmpty(name.to_string()));
+        }
+    }
+
+    db_connection.execute_batch("BEGIN")?;
+
+    match restore_tables(db_connection, tables, &names) {
+        Ok(count) => {
+            db_connection.execute_batch("COMMIT")?;
+            Ok(count)
+        }
+        Err(e) => {
+            db_connection.execute_batch("ROLLBACK")?;
+            Err(e)
+        }
+    }
+}
+
+fn restore_tables(db_connection: &Connection, tables: &Map<String, JsonValue>, names: &[&String]) -> Result<usize, DumpError> {
+    let mut count = 0;
+
+    for name in names {
+        let columns = column_names(db_connection, name)?;
+        let rows = tables[name.as_str()].as_array().ok_or_else(|| DumpError::Format(format!("table '{}' is not a list", name)))?;
+
+        for row in rows {
+            let row = row.as_object().ok_or_else(|| DumpError::Format(format!("a row of '{}' is not an object", name)))?;
+            let mut row_columns = Vec::new();
+            let mut values = Vec::new();
+
+            for (column, value) in row {
+                if !columns.contains(column) {
+                    return Err(DumpError::Format(format!("unknown column '{}.{}'", name, column)));
+                }
+                let value = from_json(value).ok_or_else(|| DumpError::Format(format!("invalid value in '{}.{}'", name, column)))?;
+                row_columns.push(format!("\"{}\"", column));
+                values.push(ToSqlOutput::Owned(value));
+            }
+
+            let placeholders: Vec<String> = (1..values.len() + 1).map(|index| format!("${}", index)).collect();
+            let parameters: Vec<&dyn ToSql> = values.iter().map(|value| value as &dyn ToSql).collect();
+
+            db_connection.execute(&format!("INSERT INTO \"{}\" ({}) VALUES ({})", name, row_columns.join(", "), placeholders.join(", ")),
+                &parameters)?;
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
+/// Writes the whole database to a JSON file, returns the number of rows
+pub fn dump(db_filename: &str, file_name: &str) -> Result<usize, DumpError> {
+    let repository = Repository::open(db_filename)?;
+    let dump = dump_database(repository.connection())?;
+    let count = dump["tables"].as_object().map_or(0, |tables| tables.values().filter_map(|rows| rows.as_array()).map(|rows| rows.len()).sum());
+
+    let mut file = File::create(file_name)?;
+    file.write_all(serde_json::to_string_pretty(&dump)?.as_bytes())?;
+
+    Ok(count)
+}
+
+/// Creates the database if needed and reads the file into it, returns the number of rows
+pub fn restore(db_filename: &str, file_name: &str) -> Result<usize, DumpError> {
+    let mut content = String::new();
+    File::open(file_name)?.read_to_string(&mut content)?;
+    let dump: JsonValue = serde_json::from_str(&content)?;
+
+    let repository = Repository::open(db_filename)?;
+    restore_database(repository.connection(), &dump)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dump_database, restore_database, to_json, from_json, DumpError};
+    use database::{init_database, save_setting, count_registrations};
+    use rusqlite::Connection;
+    use rusqlite::types::Value;
+
+    #[test]
+    fn test_values() {
+        for value in [Value::Null, Value::Integer(42), Value::Real(1.5), Value::Text("Müller".to_string()), Value::Blob(vec![0, 255, 16])] {
+            assert_eq!(from_json(&to_json(value.clone())), Some(value));
+        }
+
+        assert_eq!(from_json(&json!(true)), None);
+        assert_eq!(from_json(&json!({ "blob": "abc" })), None);
+    }
+
+    #[test]
+    fn test_dump_and_restore() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+        conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                  city, phone, email_to, more_info, price_category, course_type)
+                  VALUES ('sir', 'Smith', 'Bob', 'Uni', 'Street', '1', '12345', 'City', '123', 'bob@smith.com', '', 'student', 'course1')",
+                  &[]).unwrap();
+        save_setting(&conn, "banner", "Nur noch wenige Plätze", "2017-01-10 12:00:00").unwrap();
+
+        let dump = dump_database(&conn).unwrap();
+        assert_eq!(dump["tables"]["registration"][0]["last_name"], json!("Smith"));
+        assert_eq!(dump["tables"]["setting"][0]["value"], json!("Nur noch wenige Plätze"));
+
+        let copy = Connection::open_in_memory().unwrap();
+        init_database(&copy).unwrap();
+        assert_eq!(restore_database(&copy, &dump).unwrap(), 2);
+        assert_eq!(count_registrations(&copy).unwrap(), 1);
+        assert_eq!(dump_database(&copy).unwrap()["tables"], dump["tables"]);
+
+        // Not twice
+        match restore_database(&copy, &dump) {
+            Err(DumpError::NotEmpty(table)) => assert_eq!(table, "registration".to_string()),
+            other => panic!("Expected NotEmpty, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_restore_rejects_invalid_dumps() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        let mut dump = dump_database(&conn).unwrap();
+        dump["version"] = json!(99);
+        assert!(restore_database(&conn, &dump).is_err());
+
+        let dump = json!({ "format": "conference_registration dump", "version": 1, "tables": {
+            "registration": [{ "title": "sir", "last_name": "Smith", "first_name": "Bob", "institution": "Uni", "street": "Street",
+                "street_no": "1", "zip_code": "12345", "city": "City", "phone": "123", "email_to": "bob@smith.com",
+                "more_info": "", "price_category": "student", "course_type": "course1" }],
+            "setting": [{ "key": "banner", "shoe_size": 42 }]
+        }});
+        assert!(restore_database(&conn, &dump).is_err());
+
+        // The registration was rolled back
+        assert_eq!(count_registrations(&conn).unwrap(), 0);
+    }
+}
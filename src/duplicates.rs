@@ -0,0 +1,264 @@
+use std::collections::BTreeMap;
+
+use iron::prelude::{Request, IronResult, Response, Set};
+use iron::modifiers::RedirectRaw;
+use iron::status;
+
+use handlebars_iron::{Template};
+use serde_json::Value as JsonValue;
+use params::Params;
+use plugin::Pluggable;
+use persistent::{Read, Write};
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+
+use ::DBConnection;
+use admin::with_admin;
+use config::Configuration;
+use database::{lock_connection, load_duplicate_candidates, load_registration_values, merge_registrations, DuplicateCandidate};
+use handler::{HandleError, extract_string, router_param, not_found};
+
+
+// Typos allowed in the full name, and one typo per this many characters of an institution
+const NAME_DISTANCE: usize = 2;
+const INSTITUTION_CHARS_PER_TYPO: usize = 8;
+
+// "Müller-Lüdenscheidt " and "mueller ludenscheidt" are still different, but accents, case,
+// spaces and punctuation don't count
+fn fold(text: &str) -> String {
+    text.nfd().filter(|&c| !is_combining_mark(c)).flat_map(|c| c.to_lowercase()).filter(|c| c.is_alphanumeric()).collect()
+}
+
+// Levenshtein distance in characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..b.len() + 1).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + if a_char == *b_char { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+fn similar(a: &str, b: &str, max_distance: usize) -> bool {
+    !a.is_empty() && !b.is_empty() && edit_distance(a, b) <= max_distance
+}
+
+// First and last name are sometimes swapped, an institution is often given shorter the second time
+fn similar_person(a: &DuplicateCandidate, b: &DuplicateCandidate) -> bool {
+    let name_a = fold(&format!("{}{}", a.first_name, a.last_name));
+    let same_name = similar(&name_a, &fold(&format!("{}{}", b.first_name, b.last_name)), NAME_DISTANCE) ||
+        similar(&name_a, &fold(&format!("{}{}", b.last_name, b.first_name)), NAME_DISTANCE);
+
+    let (institution_a, institution_b) = (fold(&a.institution), fold(&b.institution));
+    let typos = institution_a.chars().count().max(institution_b.chars().count()) / INSTITUTION_CHARS_PER_TYPO;
+    let same_institution = similar(&institution_a, &institution_b, typos) ||
+        (!institution_a.is_empty() && !institution_b.is_empty() &&
+            (institution_a.contains(&institution_b) || institution_b.contains(&institution_a)));
+
+    same_name && same_institution
+}
+
+// Pairs of indices into candidates with the reason, the older registration first
+fn find_duplicates(candidates: &[DuplicateCandidate]) -> Vec<(usize, usize, &'static str)> {
+    let mut result = Vec::new();
+
+    for first in 0..candidates.len() {
+        for second in first + 1..candidates.len() {
+            let (a, b) = (&candidates[first], &candidates[second]);
+
+            if !a.email_normalized.is_empty() && a.email_normalized == b.email_normalized {
+                result.push((first, second, "Gleiche E-Mail-Adresse"));
+            } else if similar_person(a, b) {
+                result.push((first, second, "Ähnlicher Name und Institution"));
+            }
+        }
+    }
+
+    result
+}
+
+pub fn handle_duplicates(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "duplicates", duplicates_page)
+}
+
+fn duplicates_page(req: &mut Request) -> Result<Response, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    let map = req.get::<Params>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let candidates = load_duplicate_candidates(&db_connection)?;
+    let person = |candidate: &DuplicateCandidate| json!({
+        "id": candidate.id,
+        "name": format!("{} {}", candidate.first_name, candidate.last_name),
+        "institution": candidate.institution,
+        "email": candidate.email_normalized,
+        "registered_at": config.date_format.timestamp(&candidate.registered_at)
+    });
+
+    let pairs: Vec<JsonValue> = find_duplicates(&candidates).into_iter().map(|(first, second, reason)| json!({
+        "first": person(&candidates[first]),
+        "second": person(&candidates[second]),
+        "reason": reason
+    })).collect();
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("pairs".to_string(), json!(pairs));
+    data.insert("merged".to_string(), json!(extract_string(&map, "merged").is_ok()));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("duplicates", data)).set_mut(status::Ok);
+    Ok(resp)
+}
+
+fn merge_ids(req: &Request) -> Option<(i64, i64)> {
+    match (router_param(req, "keep").parse::<i64>(), router_param(req, "remove").parse::<i64>()) {
+        (Ok(keep), Ok(remove)) => Some((keep, remove)),
+        _ => None
+    }
+}
+
+// The columns in which the two registrations differ: name, value kept and value of the removed registration
+fn differences(keep: Vec<(String, String)>, remove: Vec<(String, String)>) -> Vec<(String, String, String)> {
+    keep.into_iter().zip(remove).filter(|&((_, ref kept), (_, ref removed))| kept != removed)
+        .map(|((column, kept), (_, removed))| (column, kept, removed)).collect()
+}
+
+pub fn handle_duplicate_merge(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "duplicate_merge", |req| merge_page(req, None))
+}
+
+// Every differing value is chosen explicitly, the kept registration's value is preselected unless it is empty
+fn merge_page(req: &mut Request, error: Option<String>) -> Result<Response, HandleError> {
+    let (keep, remove) = match merge_ids(req) {
+        Some(ids) => ids,
+        None => return Ok(not_found())
+    };
+
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let (kept, removed) = match (load_registration_values(&db_connection, keep)?, load_registration_values(&db_connection, remove)?) {
+        (Some(kept), Some(removed)) => (kept, removed),
+        _ => return Ok(not_found())
+    };
+
+    let columns: Vec<JsonValue> = differences(kept, removed).into_iter().map(|(column, kept, removed)| json!({
+        "column": column,
+        "keep": kept,
+        "remove": removed,
+        "prefer_remove": kept.is_empty()
+    })).collect();
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("keep".to_string(), json!(keep));
+    data.insert("remove".to_string(), json!(remove));
+    data.insert("columns".to_string(), json!(columns));
+    data.insert("error".to_string(), json!(error));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("duplicate_merge", data)).set_mut(if error.is_some() { status::BadRequest } else { status::Ok });
+    Ok(resp)
+}
+
+pub fn handle_duplicate_merge_submit(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "duplicate_merge_submit", merge)
+}
+
+fn merge(req: &mut Request) -> Result<Response, HandleError> {
+    let (keep, remove) = match merge_ids(req) {
+        Some(ids) => ids,
+        None => return Ok(not_found())
+    };
+
+    let map = req.get::<Params>()?;
+
+    let result = {
+        let mutex = req.get::<Write<DBConnection>>()?;
+        let db_connection = lock_connection(&mutex)?;
+
+        let (kept, removed) = match (load_registration_values(&db_connection, keep)?, load_registration_values(&db_connection, remove)?) {
+            (Some(kept), Some(removed)) => (kept, removed),
+            _ => return Ok(not_found())
+        };
+
+        // A column missing from the form keeps its value, so a stale form can't take anything unseen
+        let take: Vec<String> = differences(kept, removed).into_iter().map(|(column, _, _)| column)
+            .filter(|column| extract_string(&map, column).ok().is_some_and(|choice| choice == "remove")).collect();
+
+        merge_registrations(&db_connection, keep, remove, &take).map(|()| take)
+    };
+
+    match result {
+        Ok(take) => {
+            info!("Registration {} merged into {}, values taken over: {}", remove, keep, take.join(", "));
+            Ok(Response::with((status::Found, RedirectRaw("/admin/duplicates?merged=1".to_string()))))
+        }
+        Err(HandleError::Validation(message)) => merge_page(req, Some(message)),
+        Err(e) => Err(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fold, edit_distance, find_duplicates, differences};
+    use database::DuplicateCandidate;
+
+    fn candidate(id: i64, first_name: &str, last_name: &str, institution: &str, email: &str) -> DuplicateCandidate {
+        DuplicateCandidate {
+            id,
+            first_name: first_name.to_string(),
+            last_name: last_name.to_string(),
+            institution: institution.to_string(),
+            email_normalized: email.to_string(),
+            registered_at: "2017-01-01 12:00:00".to_string()
+        }
+    }
+
+    #[test]
+    fn test_fold() {
+        assert_eq!(fold(" Jürgen Müller-Lüdenscheidt"), "jurgenmullerludenscheidt".to_string());
+        assert_eq!(fold("Univ. of Potsdam"), "univofpotsdam".to_string());
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("smith", "smith"), 0);
+        assert_eq!(edit_distance("smith", "smyth"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        let candidates = vec![
+            candidate(1, "Bob", "Smith", "University of Potsdam", "bob@smith.com"),
+            candidate(2, "Alice", "Brown", "GFZ Potsdam", "alice@brown.com"),
+            candidate(3, "Bob", "Smyth", "Uni Potsdam", "bob.smith@uni-potsdam.de"),
+            candidate(4, "Bob", "Smith", "GFZ Potsdam", "b.smith@gfz.de"),
+            candidate(5, "Brown", "Alice", "GFZ", "alice@brown.com"),
+            candidate(6, "Jürgen", "Müller", "Technische Universitaet Muenchen", "jm@tum.de"),
+            candidate(7, "Jurgen", "Muller", "Technische Universität München", "juergen@mueller.de")
+        ];
+
+        assert_eq!(find_duplicates(&candidates), vec![
+            (1, 4, "Gleiche E-Mail-Adresse"),
+            (5, 6, "Ähnlicher Name und Institution")
+        ]);
+    }
+
+    #[test]
+    fn test_differences() {
+        let keep = vec![("first_name".to_string(), "Bob".to_string()), ("phone".to_string(), "".to_string())];
+        let remove = vec![("first_name".to_string(), "Bob".to_string()), ("phone".to_string(), "123".to_string())];
+
+        assert_eq!(differences(keep, remove), vec![("phone".to_string(), "".to_string(), "123".to_string())]);
+    }
+}
@@ -0,0 +1,166 @@
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, Condvar};
+use std::time::{Duration, Instant};
+
+use iron::prelude::{Request, IronResult, Response, Set};
+use iron::mime::Mime;
+use iron::response::WriteBody;
+use iron::status;
+
+use plugin::Pluggable;
+use persistent::Read;
+
+use ::AdminEvents;
+use admin::with_admin;
+use handler::HandleError;
+
+
+// Enough for a dashboard that reconnects during the registration rush
+const MAX_EVENTS: usize = 100;
+// In seconds, a comment line that lets us notice closed connections
+const HEARTBEAT_INTERVAL: u64 = 20;
+// In seconds. Every open stream occupies one of the server's worker threads,
+// the browser reconnects by itself after the stream ends.
+const STREAM_DURATION: u64 = 600;
+// In milliseconds
+const RECONNECT_DELAY: u64 = 5000;
+
+// The latest events with increasing ids, shared between the registration form and the open streams
+pub struct EventLog {
+    events: Mutex<(u64, Vec<(u64, String)>)>,
+    published: Condvar
+}
+
+impl EventLog {
+    pub fn new() -> EventLog {
+        EventLog {
+            events: Mutex::new((0, Vec::new())),
+            published: Condvar::new()
+        }
+    }
+
+    pub fn publish(&self, data: String) {
+        if let Ok(mut guard) = self.events.lock() {
+            let (ref mut last_id, ref mut events) = *guard;
+
+            *last_id += 1;
+            events.push((*last_id, data));
+
+            if events.len() > MAX_EVENTS {
+                events.remove(0);
+            }
+        }
+
+        self.published.notify_all();
+    }
+
+    fn last_id(&self) -> u64 {
+        self.events.lock().map(|guard| guard.0).unwrap_or(0)
+    }
+
+    // The events after the given id, waits up to the timeout if there are none yet
+    fn events_after(&self, seen: u64, timeout: Duration) -> Vec<(u64, String)> {
+        let mut guard = match self.events.lock() {
+            Ok(guard) => guard,
+            Err(_) => return Vec::new()
+        };
+
+        if guard.0 <= seen {
+            guard = match self.published.wait_timeout(guard, timeout) {
+                Ok((guard, _)) => guard,
+                Err(_) => return Vec::new()
+            };
+        }
+
+        guard.1.iter().filter(|&&(id, _)| id > seen).cloned().collect()
+    }
+}
+
+struct EventStream {
+    log: Arc<EventLog>,
+    seen: u64
+}
+
+fn format_event(id: u64, data: &str) -> String {
+    format!("id: {}\nevent: registration\ndata: {}\n\n", id, data)
+}
+
+impl WriteBody for EventStream {
+    fn write_body(&mut self, res: &mut dyn Write) -> io::Result<()> {
+        write!(res, "retry: {}\n\n", RECONNECT_DELAY)?;
+        res.flush()?;
+
+        let started = Instant::now();
+
+        while started.elapsed() < Duration::from_secs(STREAM_DURATION) {
+            let events = self.log.events_after(self.seen, Duration::from_secs(HEARTBEAT_INTERVAL));
+
+            if events.is_empty() {
+                write!(res, ": heartbeat\n\n")?;
+            }
+
+            for (id, data) in events {
+                res.write_all(format_event(id, &data).as_bytes())?;
+                self.seen = id;
+            }
+
+            res.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+// Server-sent events for the open admin pages, one event per new registration
+pub fn handle_admin_events(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "events", event_stream)
+}
+
+fn event_stream(req: &mut Request) -> Result<Response, HandleError> {
+    let log = req.get::<Read<AdminEvents>>()?;
+
+    // A reconnecting browser gets the events it missed in between
+    let seen = req.headers.get_raw("Last-Event-ID")
+        .and_then(|values| values.first())
+        .and_then(|value| String::from_utf8_lossy(value).trim().parse::<u64>().ok())
+        .unwrap_or_else(|| log.last_id());
+
+    let mut resp = Response::with(status::Ok);
+    resp.set_mut("text/event-stream".parse::<Mime>().unwrap());
+    resp.headers.set_raw("Cache-Control", vec![b"no-cache".to_vec()]);
+    resp.body = Some(Box::new(EventStream { log, seen }));
+
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EventLog, format_event, MAX_EVENTS};
+    use std::time::Duration;
+
+    #[test]
+    fn test_event_log() {
+        let log = EventLog::new();
+        assert_eq!(log.last_id(), 0);
+        assert!(log.events_after(0, Duration::from_millis(1)).is_empty());
+
+        log.publish("{\"total\":1}".to_string());
+        log.publish("{\"total\":2}".to_string());
+
+        assert_eq!(log.last_id(), 2);
+        assert_eq!(log.events_after(1, Duration::from_millis(1)), vec![(2, "{\"total\":2}".to_string())]);
+        assert_eq!(log.events_after(0, Duration::from_millis(1)).len(), 2);
+
+        for i in 0..MAX_EVENTS {
+            log.publish(i.to_string());
+        }
+        let events = log.events_after(0, Duration::from_millis(1));
+        assert_eq!(events.len(), MAX_EVENTS);
+        assert_eq!(events[0].0, 3);
+    }
+
+    #[test]
+    fn test_format_event() {
+        assert_eq!(format_event(7, "{\"total\":7}"), "id: 7\nevent: registration\ndata: {\"total\":7}\n\n".to_string());
+    }
+}
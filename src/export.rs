@@ -0,0 +1,458 @@
+use iron::prelude::{Request, Response, Set};
+use iron::mime::Mime;
+use iron::status;
+
+use plugin::Pluggable;
+use params::Params;
+use serde_json::{Map, Value};
+use serde_json;
+
+use handler::{extract_string, HandleError};
+
+const XLSX_TYPE: &str = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    Xlsx
+}
+
+impl ExportFormat {
+    fn from_name(name: &str) -> Option<ExportFormat> {
+        match name.trim().to_lowercase().as_str() {
+            "csv" => Some(ExportFormat::Csv),
+            "json" => Some(ExportFormat::Json),
+            "xlsx" => Some(ExportFormat::Xlsx),
+            _ => None
+        }
+    }
+
+    fn from_media_type(media_type: &str) -> Option<ExportFormat> {
+        match media_type {
+            "text/csv" | "text/*" | "*/*" => Some(ExportFormat::Csv),
+            "application/json" => Some(ExportFormat::Json),
+            XLSX_TYPE => Some(ExportFormat::Xlsx),
+            _ => None
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match *self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+            ExportFormat::Xlsx => "xlsx"
+        }
+    }
+}
+
+// The result of one export query, rendered into whatever format the client asked for
+pub struct Table {
+    pub file_stem: String,
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    pub separator: char
+}
+
+impl Table {
+    pub fn new(file_stem: &str, headers: &[&str]) -> Table {
+        Table {
+            file_stem: file_stem.to_string(),
+            headers: headers.iter().map(|header| header.to_string()).collect(),
+            rows: Vec::new(),
+            separator: ','
+        }
+    }
+
+    pub fn push(&mut self, row: &[&str]) {
+        self.rows.push(row.iter().map(|value| value.to_string()).collect());
+    }
+
+    pub fn to_csv(&self) -> String {
+        let headers: Vec<&str> = self.headers.iter().map(|header| header.as_str()).collect();
+        let mut body = csv_line_with(&headers, self.separator);
+
+        for row in &self.rows {
+            let values: Vec<&str> = row.iter().map(|value| value.as_str()).collect();
+            body.push_str(&csv_line_with(&values, self.separator));
+        }
+
+        body
+    }
+
+    // One object per row, keyed by the column headers
+    pub fn to_json(&self) -> String {
+        let rows: Vec<Value> = self.rows.iter().map(|row| {
+            let mut object = Map::new();
+            for (header, value) in self.headers.iter().zip(row) {
+                object.insert(header.clone(), Value::String(value.clone()));
+            }
+            Value::Object(object)
+        }).collect();
+
+        serde_json::to_string(&rows).unwrap_or_else(|_| "[]".to_string())
+    }
+
+    pub fn to_xlsx(&self) -> Vec<u8> {
+        let mut sheet = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+            <worksheet xmlns=\"http://schemas.openxmlformats.org/spreadsheetml/2006/main\"><sheetData>");
+
+        for (row_index, row) in Some(&self.headers).into_iter().chain(self.rows.iter()).enumerate() {
+            sheet.push_str(&format!("<row r=\"{}\">", row_index + 1));
+            for (column_index, value) in row.iter().enumerate() {
+                sheet.push_str(&format!("<c r=\"{}{}\" t=\"inlineStr\"><is><t xml:space=\"preserve\">{}</t></is></c>",
+                    column_name(column_index), row_index + 1, xml_escape(value)));
+            }
+            sheet.push_str("</row>");
+        }
+
+        sheet.push_str("</sheetData></worksheet>");
+
+        zip_archive(&[
+            ("[Content_Types].xml".to_string(), XLSX_CONTENT_TYPES.as_bytes().to_vec()),
+            ("_rels/.rels".to_string(), XLSX_RELS.as_bytes().to_vec()),
+            ("xl/workbook.xml".to_string(), XLSX_WORKBOOK.as_bytes().to_vec()),
+            ("xl/_rels/workbook.xml.rels".to_string(), XLSX_WORKBOOK_RELS.as_bytes().to_vec()),
+            ("xl/worksheets/sheet1.xml".to_string(), sheet.into_bytes())
+        ])
+    }
+}
+
+// An explicit ?format= wins over the Accept header, browsers following a link get CSV as before.
+// None means the client asked for a format we don't have.
+pub fn negotiate_format(format: Option<&str>, accept: Option<&str>) -> Option<ExportFormat> {
+    if let Some(format) = format {
+        return ExportFormat::from_name(format);
+    }
+
+    let accept = match accept {
+        Some(accept) if !accept.trim().is_empty() => accept,
+        _ => return Some(ExportFormat::Csv)
+    };
+
+    let mut ranges: Vec<(String, f32)> = accept.split(',').map(|range| {
+        let mut parts = range.split(';');
+        let media_type = parts.next().unwrap_or("").trim().to_lowercase();
+        let quality = parts.filter_map(|parameter| {
+            let parameter = parameter.trim();
+            parameter.strip_prefix("q=").and_then(|quality| quality.parse::<f32>().ok())
+        }).next().unwrap_or(1.0);
+        (media_type, quality)
+    }).collect();
+
+    // Stable, so equally preferred types keep the client's order
+    ranges.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(::std::cmp::Ordering::Equal));
+
+    ranges.iter()
+        .filter(|&&(_, quality)| quality > 0.0)
+        .filter_map(|(media_type, _)| ExportFormat::from_media_type(media_type))
+        .next()
+}
+
+pub fn export_response(req: &mut Request, table: &Table) -> Result<Response, HandleError> {
+    let accept = req.headers.get_raw("Accept")
+        .and_then(|values| values.first())
+        .map(|value| String::from_utf8_lossy(value).to_string());
+    let format = extract_string(req.get_ref::<Params>()?, "format").ok();
+
+    let format = match negotiate_format(format.as_deref(), accept.as_deref()) {
+        Some(format) => format,
+        None => return Ok(Response::with((status::NotAcceptable, "Available formats: csv, json, xlsx")))
+    };
+
+    let file_name = format!("{}.{}", table.file_stem, format.extension());
+
+    Ok(match format {
+        ExportFormat::Csv => csv_response(&file_name, table.to_csv()),
+        ExportFormat::Json => attachment_response(&file_name, "application/json; charset=utf-8", table.to_json().into_bytes()),
+        ExportFormat::Xlsx => attachment_response(&file_name, XLSX_TYPE, table.to_xlsx())
+    })
+}
+
+
+// Quotes the field if it contains a separator, a quote or a line break (RFC 4180).
+// A spreadsheet runs a cell starting with =, +, - or @ as a formula, such a value gets a leading apostrophe.
+fn csv_field(value: &str, separator: char) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+
+    if value.contains([separator, '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+// Some importers, like the ones of spreadsheets in German locale, expect a semicolon
+pub fn csv_line_with(fields: &[&str], separator: char) -> String {
+    let fields: Vec<String> = fields.iter().map(|field| csv_field(field, separator)).collect();
+    format!("{}\r\n", fields.join(&separator.to_string()))
+}
+
+pub fn csv_response(file_name: &str, body: String) -> Response {
+    attachment_response(file_name, "text/csv; charset=utf-8", body.into_bytes())
+}
+
+pub fn attachment_response(file_name: &str, content_type: &str, body: Vec<u8>) -> Response {
+    let content_type = content_type.parse::<Mime>().unwrap();
+    let mut resp = Response::with((status::Ok, body));
+
+    resp.set_mut(content_type);
+    resp.headers.set_raw("Content-Disposition", vec![format!("attachment; filename=\"{}\"", file_name).into_bytes()]);
+    resp
+}
+
+// The smallest workbook the spreadsheet programs accept: one sheet with inline strings
+const XLSX_CONTENT_TYPES: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+    <Types xmlns=\"http://schemas.openxmlformats.org/package/2006/content-types\">\
+    <Default Extension=\"rels\" ContentType=\"application/vnd.openxmlformats-package.relationships+xml\"/>\
+    <Default Extension=\"xml\" ContentType=\"application/xml\"/>\
+    <Override PartName=\"/xl/workbook.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml\"/>\
+    <Override PartName=\"/xl/worksheets/sheet1.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml\"/>\
+    </Types>";
+
+const XLSX_RELS: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+    <Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">\
+    <Relationship Id=\"rId1\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument\" Target=\"xl/workbook.xml\"/>\
+    </Relationships>";
+
+const XLSX_WORKBOOK: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+    <workbook xmlns=\"http://schemas.openxmlformats.org/spreadsheetml/2006/main\" \
+    xmlns:r=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships\">\
+    <sheets><sheet name=\"Export\" sheetId=\"1\" r:id=\"rId1\"/></sheets></workbook>";
+
+const XLSX_WORKBOOK_RELS: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+    <Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">\
+    <Relationship Id=\"rId1\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet\" Target=\"worksheets/sheet1.xml\"/>\
+    </Relationships>";
+
+// A, B, ..., Z, AA, AB, ...
+fn column_name(index: usize) -> String {
+    let mut index = index + 1;
+    let mut name = Vec::new();
+
+    while index > 0 {
+        name.insert(0, b'A' + ((index - 1) % 26) as u8);
+        index = (index - 1) / 26;
+    }
+
+    String::from_utf8(name).unwrap()
+}
+
+// Control characters other than tab and line breaks are not allowed in XML at all
+fn xml_escape(value: &str) -> String {
+    value.chars()
+        .filter(|&c| c >= ' ' || c == '\t' || c == '\n' || c == '\r')
+        .collect::<String>()
+        .replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
+
+fn push_u16(buffer: &mut Vec<u8>, value: u16) {
+    buffer.extend_from_slice(&[value as u8, (value >> 8) as u8]);
+}
+
+fn push_u32(buffer: &mut Vec<u8>, value: u32) {
+    buffer.extend_from_slice(&[value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]);
+}
+
+// A zip archive with the files stored uncompressed, which is all an xlsx file needs
+pub fn zip_archive(files: &[(String, Vec<u8>)]) -> Vec<u8> {
+    let mut result = Vec::new();
+    let mut directory = Vec::new();
+
+    for (name, content) in files {
+        let offset = result.len() as u32;
+        let crc = crc32(content);
+
+        // Version 2.0, no flags, stored, 1980-01-01 00:00
+        let mut common = Vec::new();
+        push_u16(&mut common, 20);
+        push_u16(&mut common, 0);
+        push_u16(&mut common, 0);
+        push_u16(&mut common, 0);
+        push_u16(&mut common, 0x21);
+        push_u32(&mut common, crc);
+        push_u32(&mut common, content.len() as u32);
+        push_u32(&mut common, content.len() as u32);
+        push_u16(&mut common, name.len() as u16);
+        push_u16(&mut common, 0);
+
+        push_u32(&mut result, 0x0403_4b50);
+        result.extend_from_slice(&common);
+        result.extend_from_slice(name.as_bytes());
+        result.extend_from_slice(content);
+
+        push_u32(&mut directory, 0x0201_4b50);
+        push_u16(&mut directory, 20);
+        directory.extend_from_slice(&common);
+        // Comment length, disk number, internal and external attributes
+        push_u16(&mut directory, 0);
+        push_u16(&mut directory, 0);
+        push_u16(&mut directory, 0);
+        push_u32(&mut directory, 0);
+        push_u32(&mut directory, offset);
+        directory.extend_from_slice(name.as_bytes());
+    }
+
+    let directory_offset = result.len() as u32;
+    result.extend_from_slice(&directory);
+
+    // End of central directory
+    push_u32(&mut result, 0x0605_4b50);
+    push_u16(&mut result, 0);
+    push_u16(&mut result, 0);
+    push_u16(&mut result, files.len() as u16);
+    push_u16(&mut result, files.len() as u16);
+    push_u32(&mut result, directory.len() as u32);
+    push_u32(&mut result, directory_offset);
+    push_u16(&mut result, 0);
+
+    result
+}
+
+fn tar_octal(value: u64, width: usize) -> Vec<u8> {
+    let mut field = format!("{:01$o}", value, width - 1).into_bytes();
+    field.push(0);
+    field
+}
+
+// A plain ustar archive, good enough for handing out a bunch of files in one download.
+// File names longer than 100 bytes are cut.
+pub fn tar_archive(files: &[(String, Vec<u8>)]) -> Vec<u8> {
+    let mut result = Vec::new();
+
+    for (name, content) in files {
+        let mut header = [0u8; 512];
+        let name = name.as_bytes();
+        let name = &name[..name.len().min(100)];
+
+        header[..name.len()].copy_from_slice(name);
+        header[100..108].copy_from_slice(&tar_octal(0o644, 8));
+        header[108..116].copy_from_slice(&tar_octal(0, 8));
+        header[116..124].copy_from_slice(&tar_octal(0, 8));
+        header[124..136].copy_from_slice(&tar_octal(content.len() as u64, 12));
+        header[136..148].copy_from_slice(&tar_octal(0, 12));
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+
+        // The checksum is computed with the checksum field filled with spaces
+        header[148..156].copy_from_slice(b"        ");
+        let checksum: u64 = header.iter().map(|byte| *byte as u64).sum();
+        header[148..155].copy_from_slice(&tar_octal(checksum, 7));
+
+        result.extend_from_slice(&header);
+        result.extend_from_slice(content);
+
+        let padding = (512 - content.len() % 512) % 512;
+        result.extend(vec![0u8; padding]);
+    }
+
+    // End of archive
+    result.extend(vec![0u8; 1024]);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{csv_line_with, tar_archive, zip_archive, crc32, column_name, xml_escape, negotiate_format, ExportFormat, Table};
+
+    #[test]
+    fn test_tar_archive() {
+        let archive = tar_archive(&[("slides.pdf".to_string(), b"hello".to_vec())]);
+
+        assert_eq!(archive.len(), 512 + 512 + 1024);
+        assert_eq!(&archive[0..10], b"slides.pdf");
+        assert_eq!(&archive[124..136], b"00000000005\0");
+        assert_eq!(&archive[257..262], b"ustar");
+        assert_eq!(&archive[512..517], b"hello");
+
+        let mut header = archive[0..512].to_vec();
+        let stored = String::from_utf8(header[148..154].to_vec()).unwrap();
+        for byte in &mut header[148..156] {
+            *byte = b' ';
+        }
+        let checksum: u64 = header.iter().map(|byte| *byte as u64).sum();
+        assert_eq!(u64::from_str_radix(&stored, 8).unwrap(), checksum);
+
+        assert_eq!(tar_archive(&[]).len(), 1024);
+    }
+
+    #[test]
+    fn test_zip_archive() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        let archive = zip_archive(&[("a.txt".to_string(), b"hello".to_vec()), ("b/c.xml".to_string(), b"<x/>".to_vec())]);
+
+        assert_eq!(&archive[0..4], &[0x50, 0x4b, 0x03, 0x04]);
+        assert_eq!(&archive[30..35], b"a.txt");
+        assert_eq!(&archive[35..40], b"hello");
+
+        // The end of central directory record points back to the two directory entries
+        let end = &archive[archive.len() - 22..];
+        assert_eq!(&end[0..4], &[0x50, 0x4b, 0x05, 0x06]);
+        assert_eq!(end[10], 2);
+        let directory_offset = end[16] as usize | (end[17] as usize) << 8;
+        assert_eq!(&archive[directory_offset..directory_offset + 4], &[0x50, 0x4b, 0x01, 0x02]);
+    }
+
+    #[test]
+    fn test_negotiate_format() {
+        assert_eq!(negotiate_format(None, None), Some(ExportFormat::Csv));
+        assert_eq!(negotiate_format(None, Some("text/html,application/xhtml+xml,*/*;q=0.8")), Some(ExportFormat::Csv));
+        assert_eq!(negotiate_format(None, Some("application/json")), Some(ExportFormat::Json));
+        assert_eq!(negotiate_format(None, Some("text/csv;q=0.5, application/vnd.openxmlformats-officedocument.spreadsheetml.sheet")),
+            Some(ExportFormat::Xlsx));
+        assert_eq!(negotiate_format(None, Some("application/json;q=0, text/csv")), Some(ExportFormat::Csv));
+        assert_eq!(negotiate_format(None, Some("application/pdf")), None);
+        assert_eq!(negotiate_format(Some("JSON"), Some("text/csv")), Some(ExportFormat::Json));
+        assert_eq!(negotiate_format(Some("pdf"), None), None);
+    }
+
+    #[test]
+    fn test_table() {
+        let mut table = Table::new("contacts", &["name", "phone"]);
+        table.push(&["Smith, Bob", "<123>"]);
+
+        assert_eq!(table.to_csv(), "name,phone\r\n\"Smith, Bob\",<123>\r\n".to_string());
+        assert_eq!(table.to_json(), "[{\"name\":\"Smith, Bob\",\"phone\":\"<123>\"}]".to_string());
+
+        let xlsx = table.to_xlsx();
+        assert_eq!(&xlsx[0..4], &[0x50, 0x4b, 0x03, 0x04]);
+        assert!(String::from_utf8_lossy(&xlsx).contains("<c r=\"B2\" t=\"inlineStr\"><is><t xml:space=\"preserve\">&lt;123&gt;</t></is></c>"));
+
+        assert_eq!(column_name(0), "A".to_string());
+        assert_eq!(column_name(25), "Z".to_string());
+        assert_eq!(column_name(27), "AB".to_string());
+        assert_eq!(xml_escape("a\u{1}b & \"c\""), "ab &amp; &quot;c&quot;".to_string());
+    }
+
+    #[test]
+    fn test_csv_line() {
+        assert_eq!(csv_line_with(&["Bob", "Smith"], ','), "Bob,Smith\r\n".to_string());
+        assert_eq!(csv_line_with(&["Smith, Bob", "say \"hi\""], ','), "\"Smith, Bob\",\"say \"\"hi\"\"\"\r\n".to_string());
+        assert_eq!(csv_line_with(&["two\nlines", ""], ','), "\"two\nlines\",\r\n".to_string());
+        assert_eq!(csv_line_with(&["Smith, Bob", "12,50"], ';'), "Smith, Bob;12,50\r\n".to_string());
+        assert_eq!(csv_line_with(&["a;b", "c"], ';'), "\"a;b\";c\r\n".to_string());
+
+        // Nothing a spreadsheet would run
+        assert_eq!(csv_line_with(&["=HYPERLINK(\"http://example.org\")", "+49 123", "-1", "@SUM(A1)", "a=b"], ','),
+            "\"'=HYPERLINK(\"\"http://example.org\"\")\",'+49 123,'-1,'@SUM(A1),a=b\r\n".to_string());
+    }
+}
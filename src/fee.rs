@@ -0,0 +1,49 @@
+use config::Configuration;
+
+
+pub fn format_amount(cents: i64) -> String {
+    format!("{},{:02} €", cents / 100, cents % 100)
+}
+
+// Fee in cents for the price category ("student" or "regular") plus the surcharges of the booked social events
+// and the banquet tickets for accompanying persons
+pub fn fee_total(price_category: &str, social_events: &[String], banquet_guests: i64, config: &Configuration) -> i64 {
+    let base = if price_category == "student" { config.fee_student } else { config.fee_regular };
+
+    let surcharges: i64 = config.social_events.iter()
+        .filter(|event| social_events.contains(&event.key))
+        .map(|event| event.price)
+        .sum();
+
+    base + surcharges + banquet_guests * config.banquet_guest_price
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_amount, fee_total};
+    use config::{example_configuration, SocialEvent};
+
+    #[test]
+    fn test_format_amount() {
+        assert_eq!(format_amount(12345), "123,45 €".to_string());
+        assert_eq!(format_amount(5), "0,05 €".to_string());
+    }
+
+    #[test]
+    fn test_fee_total() {
+        let mut config = example_configuration();
+        config.fee_student = 5000;
+        config.fee_regular = 12000;
+        config.social_events = vec![
+            SocialEvent { key: "hike".to_string(), name: "Hike".to_string(), capacity: 20, price: 0 },
+            SocialEvent { key: "banquet".to_string(), name: "Banquet".to_string(), capacity: 0, price: 4500 }
+        ];
+
+        config.banquet_guest_price = 4000;
+
+        assert_eq!(fee_total("student", &[], 0, &config), 5000);
+        assert_eq!(fee_total("regular", &["hike".to_string()], 0, &config), 12000);
+        assert_eq!(fee_total("regular", &["hike".to_string(), "banquet".to_string()], 0, &config), 16500);
+        assert_eq!(fee_total("regular", &["banquet".to_string()], 2, &config), 24500);
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+
+use iron::prelude::{Request, IronResult, Response, Set};
+use iron::modifiers::RedirectRaw;
+use iron::status;
+
+use handlebars_iron::{Template};
+use serde_json::Value as JsonValue;
+use params::Params;
+use plugin::Pluggable;
+use persistent::{Read, Write};
+
+use ::DBConnection;
+use admin::with_admin;
+use config::Configuration;
+use database::{lock_connection, load_fee_waivers, decide_fee_waiver, FeeWaiver};
+use fee::format_amount;
+use handler::{HandleError, extract_string, send_email, router_param, not_found};
+
+
+fn status_label(status: &str) -> &'static str {
+    match status {
+        "requested" => "Beantragt",
+        "approved" => "Genehmigt",
+        "denied" => "Abgelehnt",
+        _ => "-"
+    }
+}
+
+pub fn handle_fee_waivers(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "fee_waivers", fee_waivers_page)
+}
+
+fn fee_waivers_page(req: &mut Request) -> Result<Response, HandleError> {
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let waivers: Vec<JsonValue> = load_fee_waivers(&db_connection)?.iter().map(|waiver| json!({
+        "id": waiver.id,
+        "name": format!("{} {}", waiver.first_name, waiver.last_name),
+        "email": waiver.email_to,
+        "fee": format_amount(waiver.fee),
+        "reason": waiver.reason,
+        "status": status_label(&waiver.status),
+        "open": waiver.status == "requested"
+    })).collect();
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("waivers".to_string(), json!(waivers));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("fee_waivers", data)).set_mut(status::Ok);
+    Ok(resp)
+}
+
+pub fn handle_fee_waiver_decision(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "fee_waiver_decision", decide)
+}
+
+fn decide(req: &mut Request) -> Result<Response, HandleError> {
+    let id = match router_param(req, "id").parse::<i64>() {
+        Ok(id) => id,
+        Err(_) => return Ok(not_found())
+    };
+
+    let map = req.get::<Params>()?;
+    let approved = match extract_string(&map, "status")?.as_str() {
+        "approved" => true,
+        "denied" => false,
+        _ => return Err(HandleError::FormValue)
+    };
+
+    let waiver = {
+        let mutex = req.get::<Write<DBConnection>>()?;
+        let db_connection = lock_connection(&mutex)?;
+
+        let waiver = match load_fee_waivers(&db_connection)?.into_iter().find(|waiver| waiver.id == id) {
+            Some(waiver) => waiver,
+            None => return Ok(not_found())
+        };
+
+        if !decide_fee_waiver(&db_connection, id, approved)? {
+            info!("Fee waiver {}: already decided", id);
+            return Ok(Response::with((status::Conflict, "Fee waiver already decided")));
+        }
+
+        waiver
+    };
+
+    info!("Fee waiver {}: {}", id, if approved { "approved" } else { "denied" });
+
+    // The decision is stored, a failing mail server must not undo it
+    let config = req.get::<Read<Configuration>>()?;
+    let (subject, body) = decision_mail(&waiver, approved, &config.conference.organizer);
+    if let Err(e) = send_email(&waiver.email_to, &subject, &body, &config) {
+        error!("Fee waiver {}: could not send status email: {:?}", id, e);
+    }
+
+    Ok(Response::with((status::Found, RedirectRaw("/admin/fee_waivers".to_string()))))
+}
+
+fn decision_mail(waiver: &FeeWaiver, approved: bool, organizer: &str) -> (String, String) {
+    let greeting = if waiver.title == "sir" { format!("Sehr geehrter Herr {},", waiver.last_name) } else { format!("Sehr geehrte Frau {},", waiver.last_name) };
+    let decision = if approved {
+        "Ihr Antrag auf Erlass der Teilnahmegebuehr wurde genehmigt. Sie muessen keine Gebuehr bezahlen.".to_string()
+    } else {
+        format!("Ihr Antrag auf Erlass der Teilnahmegebuehr wurde leider abgelehnt. Die Gebuehr betraegt {}.", format_amount(waiver.fee))
+    };
+
+    ("Antrag auf Gebuehrenerlass".to_string(),
+     format!("{}\n\n{}\n\nMit freundlichen Gruessen,\n{}", greeting, decision, organizer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decision_mail;
+    use database::FeeWaiver;
+
+    #[test]
+    fn test_decision_mail() {
+        let waiver = FeeWaiver {
+            id: 1,
+            title: "madam".to_string(),
+            first_name: "Alice".to_string(),
+            last_name: "Smith".to_string(),
+            email_to: "alice@smith.com".to_string(),
+            fee: 5000,
+            reason: "Unemployed".to_string(),
+            status: "requested".to_string()
+        };
+
+        let (_, body) = decision_mail(&waiver, true, "die Fortbildungsorganisation");
+        assert!(body.starts_with("Sehr geehrte Frau Smith,"));
+        assert!(body.contains("genehmigt"));
+        assert!(body.ends_with("Gruessen,\ndie Fortbildungsorganisation"));
+
+        let (_, body) = decision_mail(&waiver, false, "die Fortbildungsorganisation");
+        assert!(body.contains("abgelehnt"));
+        assert!(body.contains("50,00 €"));
+    }
+}
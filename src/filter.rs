@@ -0,0 +1,132 @@
+use std::convert::TryFrom;
+
+use iron::prelude::Request;
+
+use serde_json::Value as JsonValue;
+use params::{Params, Map};
+use plugin::Pluggable;
+use chrono::NaiveDate;
+
+use handler::{HandleError, Presentation, extract_string, presentation_key};
+use status::{RegistrationStatus, ALL_STATUSES};
+
+
+// The payment_status column, with the labels of /admin/payments
+const PAYMENT_STATUSES: [(&str, &str); 3] = [("unpaid", "Offen"), ("paid", "Bezahlt"), ("waived", "Erlassen")];
+const PRESENTATIONS: [(&str, &str); 3] = [("none", "Kein Beitrag"), ("talk", "Vortrag"), ("poster", "Poster")];
+
+// Every query that takes a filter has these five parameters first, in this order, see sql_values
+pub const FILTER_SQL: &str = "($1 = '' OR status = $1) AND ($2 = '' OR presentation = $2) AND ($3 = '' OR payment_status = $3)
+    AND ($4 = '' OR substr(registered_at, 1, 10) >= $4) AND ($5 = '' OR substr(registered_at, 1, 10) <= $5)";
+
+/// Which registrations the participant list, its exports and the announcement cover. The same query parameters
+/// (?status=confirmed&presentation=talk&payment=unpaid&from=2017-01-01&until=2017-01-31) select the same
+/// registrations everywhere. Values that aren't offered are ignored, an empty filter selects all registrations.
+#[derive(Debug, Default, PartialEq)]
+pub struct RegistrationFilter {
+    pub status: Option<RegistrationStatus>,
+    pub presentation: Option<Presentation>,
+    // "unpaid", "paid" or "waived"
+    pub payment: Option<&'static str>,
+    // Days of registration as YYYY-MM-DD, both included
+    pub from: Option<String>,
+    pub until: Option<String>
+}
+
+// The label of a payment_status, the key itself for an unknown one
+pub fn payment_label(key: &str) -> &str {
+    PAYMENT_STATUSES.iter().find(|&&(payment, _)| payment == key).map_or(key, |&(_, label)| label)
+}
+
+fn extract_day(map: &Map, key: &str) -> Option<String> {
+    extract_string(map, key).ok().filter(|day| NaiveDate::parse_from_str(day, "%Y-%m-%d").is_ok())
+}
+
+impl RegistrationFilter {
+    pub fn from_map(map: &Map) -> RegistrationFilter {
+        let value = |key| extract_string(map, key).unwrap_or_default();
+
+        RegistrationFilter {
+            status: RegistrationStatus::from_name(&value("status")),
+            presentation: Presentation::try_from(value("presentation").as_str()).ok(),
+            payment: PAYMENT_STATUSES.iter().map(|&(key, _)| key).find(|&key| key == value("payment")),
+            from: extract_day(map, "from"),
+            until: extract_day(map, "until")
+        }
+    }
+
+    pub fn from_request(req: &mut Request) -> Result<RegistrationFilter, HandleError> {
+        Ok(RegistrationFilter::from_map(req.get_ref::<Params>()?))
+    }
+
+    fn pairs(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("status", self.status.map_or(String::new(), |status| status.name().to_string())),
+            ("presentation", self.presentation.as_ref().map_or(String::new(), |presentation| presentation_key(presentation).to_string())),
+            ("payment", self.payment.unwrap_or("").to_string()),
+            ("from", self.from.clone().unwrap_or_default()),
+            ("until", self.until.clone().unwrap_or_default())
+        ]
+    }
+
+    /// The parameters for FILTER_SQL, empty for "any"
+    pub fn sql_values(&self) -> Vec<String> {
+        self.pairs().into_iter().map(|(_, value)| value).collect()
+    }
+
+    /// For links from one page to another with the same filter, empty without a filter.
+    /// Only known keys and checked days get here, nothing needs to be escaped.
+    pub fn query_string(&self) -> String {
+        self.pairs().into_iter().filter(|(_, value)| !value.is_empty())
+            .map(|(key, value)| format!("{}={}", key, value)).collect::<Vec<String>>().join("&")
+    }
+
+    /// The choices of the filter form with the current ones selected
+    pub fn form_json(&self) -> JsonValue {
+        let option = |key: &str, label: &str, selected: bool| json!({ "key": key, "label": label, "selected": selected });
+        let presentation = self.presentation.as_ref().map_or("", |presentation| presentation_key(presentation));
+
+        json!({
+            "statuses": ALL_STATUSES.iter().map(|status| option(status.name(), status.label(), self.status == Some(*status))).collect::<Vec<JsonValue>>(),
+            "presentations": PRESENTATIONS.iter().map(|&(key, label)| option(key, label, key == presentation)).collect::<Vec<JsonValue>>(),
+            "payments": PAYMENT_STATUSES.iter().map(|&(key, label)| option(key, label, Some(key) == self.payment)).collect::<Vec<JsonValue>>(),
+            "from": self.from,
+            "until": self.until,
+            "query": self.query_string()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RegistrationFilter;
+    use handler::Presentation;
+    use status::RegistrationStatus;
+    use params::{Map, Value};
+
+    #[test]
+    fn test_from_map() {
+        assert_eq!(RegistrationFilter::from_map(&Map::new()), RegistrationFilter::default());
+        assert_eq!(RegistrationFilter::default().query_string(), "".to_string());
+
+        let mut map = Map::new();
+        map.assign("status", Value::String("confirmed".into())).unwrap();
+        map.assign("presentation", Value::String("talk".into())).unwrap();
+        map.assign("payment", Value::String("unpaid".into())).unwrap();
+        map.assign("from", Value::String("2017-01-01".into())).unwrap();
+        map.assign("until", Value::String("31.01.2017".into())).unwrap();
+
+        let filter = RegistrationFilter::from_map(&map);
+        assert_eq!(filter.status, Some(RegistrationStatus::Confirmed));
+        assert_eq!(filter.presentation, Some(Presentation::Talk));
+        assert_eq!(filter.payment, Some("unpaid"));
+        assert_eq!(filter.from, Some("2017-01-01".to_string()));
+        assert_eq!(filter.until, None);
+        assert_eq!(filter.query_string(), "status=confirmed&presentation=talk&payment=unpaid&from=2017-01-01".to_string());
+        assert_eq!(filter.sql_values(), vec!["confirmed".to_string(), "talk".to_string(), "unpaid".to_string(),
+            "2017-01-01".to_string(), "".to_string()]);
+
+        map.assign("payment", Value::String("'; DROP TABLE registration".into())).unwrap();
+        assert_eq!(RegistrationFilter::from_map(&map).payment, None);
+    }
+}
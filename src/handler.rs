@@ -1,27 +1,52 @@
 use std::collections::BTreeMap;
 use std::sync::{PoisonError, MutexGuard};
-use std::net::{Ipv4Addr, AddrParseError};
-use std::str::FromStr;
+use std::net::AddrParseError;
+use std::convert::TryFrom;
+use std::io;
+use std::fs;
+use std::path::Path;
 
 use iron::prelude::{Request, IronResult, Response, Set};
 use iron::status;
+use iron::modifiers::RedirectRaw;
+use iron::url::Url;
 
 use handlebars_iron::{Template};
+use serde::{Serialize, Serializer};
+use serde_json::Value as JsonValue;
 use params::{Params, Value, Map, ParamsError};
 use plugin::Pluggable;
 use persistent::{Read, Write, PersistentError};
+use router::Router;
 use rusqlite::Connection;
 use rusqlite;
+use unicode_normalization::UnicodeNormalization;
+use chrono::NaiveDateTime;
+use openssl::error::ErrorStack;
+use openssl::base64::encode_block;
 
-use lettre::email::EmailBuilder;
-use lettre::transport::smtp::{SecurityLevel, SmtpTransportBuilder};
-use lettre::transport::smtp::authentication::Mechanism;
-use lettre::transport::smtp::SUBMISSION_PORT;
-use lettre::transport::EmailTransport;
+use lettre::email::{Email, EmailBuilder, PartBuilder};
 use lettre;
 
-use ::DBConnection;
-use config::Configuration;
+use ::{DBConnection, AdminEvents, RegistrationHooks};
+use config::{Configuration, program_days};
+use dates::timestamp;
+use countries::{find_country, sorted_countries};
+use database::{lock_connection, load_registration_status, update_registration_status, count_linked_rows, record_roster_change, link_institution, delete_registration, find_by_form_token, delete_draft, subscribe_newsletter, book_social_event, count_social_bookings, add_presentation_topic, count_registrations};
+use accounting::invoice_number;
+use admin::with_admin;
+use fee::{fee_total, format_amount};
+use closing::{closed_page, unopened_page, preview_code};
+use draft::draft_form_data;
+use http::HttpError;
+use notifier::{configured_notifiers, notify_all};
+use mail_template::{mail_template, fill_template};
+use throttle::throttle_submission;
+use token::generate_token;
+use request_log::{redact, submit_entry};
+use status::{RegistrationStatus, status_label};
+use validation::{normalize_orcid, normalize_phone_number, valid_email};
+use mailer::{configured_mailer, OutgoingMail};
 
 
 #[derive(Debug)]
@@ -33,7 +58,20 @@ pub enum HandleError {
     SQL,
     Mail,
     SMTP,
-    IP
+    IP,
+    IO,
+    DKIM,
+    NotFound,
+    Validation(String),
+    SoldOut(String),
+    // Refused by a registration hook, with the message for the participant
+    Rejected(String),
+    // A notification channel like the webhook failed
+    HTTP,
+    // Too many submissions from this IP address
+    Throttled(String),
+    // A pending or confirmed registration with this email address exists
+    AlreadyRegistered
 }
 
 impl From<PersistentError> for HandleError {
@@ -48,8 +86,8 @@ impl From<ParamsError> for HandleError {
     }
 }
 
-impl<'a> From<PoisonError<MutexGuard<'a, Connection>>> for HandleError {
-    fn from(_: PoisonError<MutexGuard<'a, Connection>>) -> HandleError {
+impl<'a, T> From<PoisonError<MutexGuard<'a, T>>> for HandleError {
+    fn from(_: PoisonError<MutexGuard<'a, T>>) -> HandleError {
         HandleError::Mutex
     }
 }
@@ -72,6 +110,30 @@ impl From<lettre::transport::smtp::error::Error> for HandleError {
     }
 }
 
+impl From<io::Error> for HandleError {
+    fn from(_: io::Error) -> HandleError {
+        HandleError::IO
+    }
+}
+
+impl From<ErrorStack> for HandleError {
+    fn from(_: ErrorStack) -> HandleError {
+        HandleError::DKIM
+    }
+}
+
+impl From<HttpError> for HandleError {
+    fn from(_: HttpError) -> HandleError {
+        HandleError::HTTP
+    }
+}
+
+impl From<UnknownChoice> for HandleError {
+    fn from(e: UnknownChoice) -> HandleError {
+        HandleError::Validation(e.field.to_string())
+    }
+}
+
 impl From<AddrParseError> for HandleError {
     fn from(_: AddrParseError) -> HandleError {
         HandleError::IP
@@ -80,129 +142,946 @@ impl From<AddrParseError> for HandleError {
 
 
 #[derive(Debug, PartialEq)]
-enum PriceCategory {
+pub enum PriceCategory {
     Student,
     Regular
 }
 
 #[derive(Debug, PartialEq)]
-enum Title {
+pub enum Title {
     Sir,
     Madam
 }
 
 #[derive(Debug, PartialEq)]
-enum Course {
+pub enum Presentation {
+    None,
+    Talk,
+    Poster
+}
+
+pub fn presentation_key(presentation: &Presentation) -> &'static str {
+    match *presentation {
+        Presentation::None => "none",
+        Presentation::Talk => "talk",
+        Presentation::Poster => "poster"
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Course {
     Course1,
     Course2
 }
 
+fn course_key(course: &Course) -> &'static str {
+    if *course == Course::Course1 { "course1" } else { "course2" }
+}
+
+fn price_category_key(price_category: &PriceCategory) -> &'static str {
+    if *price_category == PriceCategory::Student { "student" } else { "regular" }
+}
+
+fn title_key(title: &Title) -> &'static str {
+    if *title == Title::Sir { "sir" } else { "madam" }
+}
+
+/// A value of a select field that the form doesn't offer, e.g. from a typo in a customized template
 #[derive(Debug, PartialEq)]
-struct Registration {
-    title: Title,
-    last_name: String,
-    first_name: String,
-    institution: String,
-    street: String,
-    street_no: String,
-    zip_code: String,
-    city: String,
-    phone: String,
-    email_to: String,
-    more_info: String,
-    price_category: PriceCategory,
-    course_type: Course
+pub struct UnknownChoice {
+    pub field: &'static str,
+    pub value: String
+}
+
+// The keys of the form and the database, see the *_key functions above
+macro_rules! parse_keys {
+    ($choice:ident, $field:expr, $($key:expr => $variant:ident),+) => {
+        impl<'a> TryFrom<&'a str> for $choice {
+            type Error = UnknownChoice;
+
+            fn try_from(key: &'a str) -> Result<$choice, UnknownChoice> {
+                match key {
+                    $($key => Ok($choice::$variant),)+
+                    _ => Err(UnknownChoice { field: $field, value: key.to_string() })
+                }
+            }
+        }
+    }
+}
+
+parse_keys!(PriceCategory, "price_category", "student" => Student, "regular" => Regular);
+parse_keys!(Title, "title", "sir" => Sir, "madam" => Madam);
+parse_keys!(Presentation, "presentation", "none" => None, "talk" => Talk, "poster" => Poster);
+parse_keys!(Course, "course_type", "course1" => Course1, "course2" => Course2);
+
+// With lenient_choices in [Basic] an unknown value becomes the last choice as it used to, otherwise the participant
+// is asked to correct the field. Either way it is logged, as it points to a broken form.
+fn parse_choice<T>(map: &Map, key: &str, fallback: T, config: &Configuration) -> Result<T, HandleError>
+    where T: for<'a> TryFrom<&'a str, Error = UnknownChoice> {
+
+    match T::try_from(extract_string(map, key)?.as_str()) {
+        Ok(choice) => Ok(choice),
+        Err(e) => {
+            warn!("Unknown value '{}' in field '{}'", e.value, e.field);
+            if config.lenient_choices { Ok(fallback) } else { Err(HandleError::from(e)) }
+        }
+    }
+}
+
+// A value the form never offers comes from a hand-crafted request, not from a participant
+fn not_offered(field: &str, value: &str) -> HandleError {
+    info!("Value '{}' not offered in field '{}'", value, field);
+    HandleError::Validation(field.to_string())
+}
+
+// Stored rows are shown anyway, an unknown value is only logged
+fn stored_choice<T>(id: i64, value: &str, fallback: T) -> T
+    where T: for<'a> TryFrom<&'a str, Error = UnknownChoice> {
+
+    T::try_from(value).unwrap_or_else(|e| {
+        warn!("Registration {}: unknown value '{}' in column '{}'", id, e.value, e.field);
+        fallback
+    })
+}
+
+// The choices are serialized as the keys the form and the database use
+macro_rules! serialize_as_key {
+    ($choice:ty, $key:ident) => {
+        impl Serialize for $choice {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str($key(self))
+            }
+        }
+    }
+}
+
+serialize_as_key!(PriceCategory, price_category_key);
+serialize_as_key!(Title, title_key);
+serialize_as_key!(Presentation, presentation_key);
+serialize_as_key!(Course, course_key);
+
+// Everything the participant entered, for the registration hooks and embedding applications,
+// e.g. serde_json::to_string(&registration)
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Registration {
+    pub title: Title,
+    pub last_name: String,
+    pub first_name: String,
+    pub preferred_name: String,
+    pub pronouns: String,
+    pub institution: String,
+    pub street: String,
+    pub street_no: String,
+    pub zip_code: String,
+    pub city: String,
+    pub phone: String,
+    pub mobile: String,
+    pub email_to: String,
+    // Also gets the confirmation, e.g. the secretary who books the travel, empty if nobody
+    pub confirmation_cc: String,
+    pub country: String,
+    pub more_info: String,
+    pub orcid: String,
+    pub tshirt_size: String,
+    pub emergency_name: String,
+    pub emergency_phone: String,
+    pub arrival_mode: String,
+    pub arrival_number: String,
+    pub arrival_time: String,
+    pub share_ride: bool,
+    pub children: i64,
+    pub children_ages: String,
+    pub child_care_days: String,
+    pub accessibility_options: String,
+    pub accessibility_needs: String,
+    pub code_of_conduct: String,
+    pub photo_consent: bool,
+    pub recording_consent: bool,
+    pub newsletter: bool,
+    pub social_events: Vec<String>,
+    pub banquet_guests: i64,
+    pub room_sharing: String,
+    pub roommate_email: String,
+    pub fee_waiver_reason: String,
+    pub presentation: Presentation,
+    pub presentation_title: String,
+    // One author per line
+    pub presentation_authors: String,
+    pub abstract_text: String,
+    // Only for talks: preferred length in minutes (0 for no preference), equipment and days the speaker can't present
+    pub talk_duration: i64,
+    pub talk_equipment: String,
+    pub unavailable_days: String,
+    pub topics: Vec<String>,
+    // In cents
+    pub fee: i64,
+    pub price_category: PriceCategory,
+    pub course_type: Course
 }
 
 
 pub fn handle_main(req: &mut Request) -> IronResult<Response> {
+    if let Some(resp) = closed_page(req, status::Ok).or_else(|| unopened_page(req, status::Ok)) {
+        return Ok(resp);
+    }
+
     let map = req.get_ref::<Params>().unwrap();
 
     let mut resp = Response::new();
 
-    info!("handle_main: {:?}", map);
+    info!("handle_main: {}", redact(map));
+
+    let draft_token = extract_string(map, "draft").ok();
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+
+    // Resumed from the link in the draft email, draft.js fills in the form
+    if let Some(token) = draft_token {
+        match draft_form_data(req, &token) {
+            Ok(Some(form_data)) => {
+                data.insert("draft_token".to_string(), json!(token));
+                data.insert("draft_json".to_string(), json!(form_data));
+            }
+            Ok(None) => {
+                data.insert("draft_expired".to_string(), json!(true));
+            }
+            Err(e) => error!("Could not load draft: {:?}", e)
+        }
+    }
+
+    // Identifies this copy of the form, see handle_form_data
+    if let Ok(form_token) = generate_token() {
+        data.insert("form_token".to_string(), json!(form_token));
+    }
+
+    if let Ok(config) = req.get::<Read<Configuration>>() {
+        // Carried along by the form, so that the submission before registration_open is accepted
+        if let Some(code) = preview_code(req, &config) {
+            data.insert("preview".to_string(), json!(code));
+        }
+        data.insert("course1".to_string(), json!(config.course1));
+        data.insert("program_days".to_string(), json!(program_days(&config)));
+        data.insert("topics".to_string(), json!(config.topics));
+        data.insert("offer_talk".to_string(), json!(config.presentations.iter().any(|key| key == "talk")));
+        data.insert("offer_poster".to_string(), json!(config.presentations.iter().any(|key| key == "poster")));
+        data.insert("equipment_options".to_string(), json!(EQUIPMENT_OPTIONS.iter().map(|&(key, label)| json!({
+            "key": key,
+            "label": label
+        })).collect::<Vec<JsonValue>>()));
+        data.insert("course2".to_string(), json!(config.course2));
+        data.insert("course1_field_trip".to_string(), json!(config.field_trips.iter().any(|key| key == "course1")));
+        data.insert("course2_field_trip".to_string(), json!(config.field_trips.iter().any(|key| key == "course2")));
+        data.insert("countries".to_string(), country_options(&config));
+        data.insert("tshirt_sizes".to_string(), json!(config.tshirt_sizes));
+        data.insert("child_care_days".to_string(), json!(config.child_care_days));
+        data.insert("code_of_conduct_url".to_string(), json!(config.code_of_conduct_url));
+        if config.banquet_max_guests > 0 {
+            data.insert("banquet_guests".to_string(), json!({
+                "max": config.banquet_max_guests,
+                "price": format_amount(config.banquet_guest_price)
+            }));
+        }
+        data.insert("social_events".to_string(), json!(config.social_events.iter().map(|event| json!({
+            "key": event.key,
+            "name": event.name,
+            "price": if event.price > 0 { format_amount(event.price) } else { String::new() }
+        })).collect::<Vec<JsonValue>>()));
+    }
 
-    let data: BTreeMap<String, String> = BTreeMap::new();
     resp.set_mut(Template::new("index", data)).set_mut(status::Ok);
     Ok(resp)
 }
 
+fn country_options(config: &Configuration) -> JsonValue {
+    sorted_countries(config.language).iter().map(|country| json!({
+        "code": country.code,
+        "name": country.name(config.language)
+    })).collect()
+}
+
 pub fn handle_submit(req: &mut Request) -> IronResult<Response> {
-    let mut message = BTreeMap::new();
+    if let Some(resp) = closed_page(req, status::Forbidden).or_else(|| unopened_page(req, status::Forbidden)) {
+        return Ok(resp);
+    }
 
-    match handle_form_data(req) {
-        Ok(_) => {
-            info!("Data handled successfully");
-            message.insert("message".to_string(), "Ihre Anmeldung war erfolgreich".to_string());
+    let mut message = BTreeMap::new();
+    let mut response_status = status::Ok;
+    let email = req.get_ref::<Params>().ok().and_then(|map| extract_string(map, "email_to").ok());
+    let email = email.as_deref();
+
+    match throttle_submission(req).and_then(|_| handle_form_data(req)) {
+        Ok((form_token, registration_id)) => {
+            info!("{}", submit_entry(email, "registered", None));
+            // Reloading the confirmation page must not send the form again
+            let location = match req.get::<Read<Configuration>>() {
+                Ok(ref config) if !config.thank_you_url.is_empty() => thank_you_url(config, registration_id),
+                _ => format!("/submit?form_token={}", form_token)
+            };
+            return Ok(Response::with((status::SeeOther, RedirectRaw(location))));
+        }
+        Err(HandleError::Validation(field)) => {
+            info!("{}", submit_entry(email, "invalid", Some(&field)));
+            message.insert("message".to_string(), format!("Ungültige Eingabe im Feld '{}'. Bitte gehen Sie zurück und korrigieren Sie die Angabe.", field));
+        }
+        Err(HandleError::SoldOut(name)) => {
+            info!("{}", submit_entry(email, "sold_out", Some(&name)));
+            message.insert("message".to_string(), format!("Leider ist '{}' bereits ausgebucht. Bitte gehen Sie zurück und ändern Sie Ihre Auswahl.", name));
+        }
+        Err(HandleError::Rejected(reason)) => {
+            info!("{}", submit_entry(email, "rejected", Some(&reason)));
+            message.insert("message".to_string(), reason);
+        }
+        Err(HandleError::AlreadyRegistered) => {
+            info!("{}", submit_entry(email, "duplicate", None));
+            message.insert("message".to_string(), "Mit dieser E-Mail-Adresse liegt bereits eine Anmeldung vor, eine zweite ist nicht nötig. \
+                Unter /login können Sie sich einen Link schicken lassen, mit dem Sie Ihre Anmeldung ansehen oder stornieren können.".to_string());
+            response_status = status::Conflict;
+        }
+        Err(HandleError::Throttled(ip)) => {
+            info!("{}", submit_entry(email, "throttled", Some(&ip)));
+            message.insert("message".to_string(), "Von Ihrem Anschluss wurden in der letzten Stunde bereits sehr viele Anmeldungen abgeschickt. \
+                Bitte versuchen Sie es später noch einmal oder wenden Sie sich direkt an die Organisatoren.".to_string());
+            response_status = status::TooManyRequests;
         }
         Err(e) => {
-            error!("Error while processing data: {:?}", e);
+            error!("{}", submit_entry(email, "error", Some(&format!("{:?}", e))));
             message.insert("message".to_string(), "Ein Fehler ist aufgetreten. Bitte versuchen Sie es später noch einmal.".to_string());
         }
     }
 
     let mut resp = Response::new();
 
-    resp.set_mut(Template::new("submit", message)).set_mut(status::Ok);
+    resp.set_mut(Template::new("submit", message)).set_mut(response_status);
     Ok(resp)
 }
 
-fn handle_form_data(req: &mut Request) -> Result<(), HandleError> {
-    let map = req.get::<Params>()?;
+// The page on the conference website, with the registration code appended to its query
+fn thank_you_url(config: &Configuration, registration_id: i64) -> String {
+    match Url::parse(&config.thank_you_url) {
+        Ok(mut url) => {
+            url.query_pairs_mut().append_pair("code", &invoice_number(config, registration_id));
+            url.into_string()
+        }
+        Err(_) => config.thank_you_url.clone()
+    }
+}
 
-    info!("handle_submit: {:?}", map);
+// The page after a successful registration
+// Without a form token this is a bookmark or a link to the form, not the page after submitting it
+pub fn handle_submit_result(req: &mut Request) -> IronResult<Response> {
+    let has_form_token = req.get_ref::<Params>().ok().is_some_and(|map| extract_string(map, "form_token").is_ok());
+    if !has_form_token {
+        return Ok(Response::with((status::Found, RedirectRaw("/".to_string()))));
+    }
 
-    let registration = map2registration(map)?;
+    let mut message = BTreeMap::new();
 
+    let registered = match submitted_form(req) {
+        Ok(registered) => registered,
+        Err(e) => {
+            error!("Error while looking up the registration: {:?}", e);
+            None
+        }
+    };
+
+    let text = match registered.as_deref() {
+        Some("waitlisted") => "Die Veranstaltung ist leider ausgebucht. Sie stehen auf der Warteliste und bekommen eine Nachricht, \
+            sobald ein Platz frei wird.",
+        Some(_) => "Ihre Anmeldung war erfolgreich",
+        None => "Es wurde keine Anmeldung gefunden."
+    };
+    message.insert("message".to_string(), text.to_string());
+    let registered = registered.is_some();
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("submit", message)).set_mut(if registered { status::Ok } else { status::NotFound });
+    Ok(resp)
+}
+
+// The status of the registration of the form, None if there is none
+fn submitted_form(req: &mut Request) -> Result<Option<String>, HandleError> {
+    let form_token = extract_string(req.get_ref::<Params>()?, "form_token").unwrap_or_default();
     let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    match find_by_form_token(&db_connection, &form_token)? {
+        Some(id) => Ok(load_registration_status(&db_connection, id)?),
+        None => Ok(None)
+    }
+}
+
+// Once capacity in [Basic] is reached new registrations go to the waitlist, a cancellation lets an admin promote one
+fn initial_status(places_taken: i64, config: &Configuration) -> RegistrationStatus {
+    if config.capacity > 0 && places_taken >= config.capacity {
+        RegistrationStatus::Waitlisted
+    } else if config.approval {
+        RegistrationStatus::Pending
+    } else {
+        RegistrationStatus::Confirmed
+    }
+}
 
-    let db_connection = mutex.lock()?;
+// Returns the form token and the id of the registration for the confirmation page
+fn handle_form_data(req: &mut Request) -> Result<(String, i64), HandleError> {
+    let map = req.get::<Params>()?;
 
-    insert_into_db(&*db_connection, &registration)?;
+    let form_token = extract_string(&map, "form_token").map_err(|_| HandleError::Validation("form_token".to_string()))?;
 
     let config = req.get::<Read<Configuration>>()?;
 
-    send_mail(&registration, &config)?;
+    let form_data = form_snapshot(&map).to_string();
+    let draft_token = extract_string(&map, "draft").ok();
 
-    Ok(())
+    let registration = map2registration(map, &config)?;
+
+    let hooks = req.get::<Read<RegistrationHooks>>()?;
+    hooks.before_insert(&registration)?;
+
+    let events = req.get::<Read<AdminEvents>>()?;
+
+    let mutex = req.get::<Write<DBConnection>>()?;
+
+    let db_connection = lock_connection(&mutex)?;
+
+    // A double click or a resent POST: the first submission was already registered and confirmed
+    if let Some(registration_id) = find_by_form_token(&db_connection, &form_token)? {
+        info!("Form '{}' was submitted again", form_token);
+        return Ok((form_token, registration_id));
+    }
+
+    // A second form after the confirmation page went unseen, the first registration counts
+    if !config.allow_duplicate_emails {
+        check_duplicate_email(&db_connection, &registration.email_to)?;
+    }
+
+    // Checked while holding the lock, so that two registrations can't take the last place at the same time
+    check_social_capacity(&db_connection, &registration.social_events, &config)?;
+
+    let status = initial_status(count_registrations(&db_connection)?, &config);
+    let token = insert_into_db(&db_connection, &registration, status, &form_data, &form_token)?;
+    let registration_id = find_by_form_token(&db_connection, &form_token)?.ok_or(HandleError::NotFound)?;
+
+    if let Some(draft_token) = draft_token {
+        delete_draft(&db_connection, &draft_token)?;
+    }
+
+    events.publish(registration_event(&registration, count_registrations(&db_connection)?).to_string());
+
+    // The notifications may wait for other servers, the next participant shouldn't
+    drop(db_connection);
+
+    hooks.after_registration(&registration, registration_id, &config);
+
+    notify_all(&configured_notifiers(&config), &registration, registration_id, &token, &config);
+
+    Ok((form_token, registration_id))
+}
+
+// Uploaded files are only recorded by name and size, their content is stored separately
+fn params_json(value: &Value) -> JsonValue {
+    match *value {
+        Value::Null => JsonValue::Null,
+        Value::Boolean(value) => json!(value),
+        Value::I64(value) => json!(value),
+        Value::U64(value) => json!(value),
+        Value::F64(value) => json!(value),
+        Value::String(ref value) => json!(value),
+        Value::File(ref file) => json!({ "filename": file.filename, "size": file.size }),
+        Value::Array(ref values) => JsonValue::Array(values.iter().map(params_json).collect()),
+        Value::Map(ref map) => form_snapshot(map)
+    }
+}
+
+pub fn form_snapshot(map: &Map) -> JsonValue {
+    JsonValue::Object(map.iter().map(|(key, value)| (key.clone(), params_json(value))).collect())
+}
+
+// What the admin pages show of a new registration, the same fields as the participant list
+fn registration_event(registration: &Registration, total: i64) -> JsonValue {
+    let name = if registration.preferred_name.is_empty() {
+        format!("{} {}", registration.first_name, registration.last_name)
+    } else {
+        registration.preferred_name.clone()
+    };
+
+    json!({
+        "total": total,
+        "name": name,
+        "pronouns": registration.pronouns,
+        "institution": registration.institution
+    })
+}
+
+fn normalize_string(value: &str) -> String {
+    value.trim().nfc().collect()
+}
+
+// Email addresses are compared case-insensitively, the original spelling is kept for display
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
 }
 
-fn extract_string(map: &Map, key: &str) -> Result<String, HandleError> {
+pub fn extract_string(map: &Map, key: &str) -> Result<String, HandleError> {
     match map.find(&[key]) {
-        Some(&Value::String(ref value)) => Ok(value.to_string()),
+        Some(Value::String(value)) => Ok(normalize_string(value)),
         _ => Err(HandleError::FormValue)
     }
 }
 
-fn map2registration(map: Map) -> Result<Registration, HandleError> {
+// A parameter of the route, empty if it isn't part of the route
+pub fn router_param(req: &Request, name: &str) -> String {
+    req.extensions.get::<Router>().and_then(|router| router.find(name)).unwrap_or("").to_string()
+}
+
+pub fn not_found() -> Response {
+    Response::with((status::NotFound, "Not found"))
+}
+
+// Optional fields may be missing from the form entirely
+fn extract_optional_string(map: &Map, key: &str) -> String {
+    extract_string(map, key).unwrap_or_default()
+}
+
+fn extract_orcid(map: &Map) -> Result<String, HandleError> {
+    let orcid = extract_optional_string(map, "orcid");
+
+    if orcid.is_empty() {
+        Ok(orcid)
+    } else {
+        normalize_orcid(&orcid).ok_or_else(|| HandleError::Validation("orcid".to_string()))
+    }
+}
+
+// A typo in the address would only show up when the confirmation mail fails, after the registration is stored
+fn extract_email_to(map: &Map) -> Result<String, HandleError> {
+    let email = extract_string(map, "email_to")?;
+
+    if valid_email(&email) {
+        Ok(email)
+    } else {
+        Err(HandleError::Validation("email_to".to_string()))
+    }
+}
+
+// Checked like the address of the participant
+fn extract_confirmation_cc(map: &Map) -> Result<String, HandleError> {
+    let email = extract_optional_string(map, "confirmation_cc");
+
+    if email.is_empty() || valid_email(&email) {
+        Ok(email)
+    } else {
+        Err(HandleError::Validation("confirmation_cc".to_string()))
+    }
+}
+
+fn extract_country(map: &Map) -> Result<String, HandleError> {
+    let country = extract_string(map, "country")?;
+
+    match find_country(&country) {
+        Some(country) => Ok(country.code.to_string()),
+        None => Err(not_offered("country", &country))
+    }
+}
+
+fn extract_mobile(map: &Map, config: &Configuration) -> String {
+    let mobile = extract_optional_string(map, "mobile");
+    normalize_phone_number(&mobile, &config.calling_code).unwrap_or(mobile)
+}
+
+// Empty means no T-shirt wanted, or the question is disabled
+fn extract_tshirt_size(map: &Map, config: &Configuration) -> Result<String, HandleError> {
+    let size = extract_optional_string(map, "tshirt_size");
+
+    if size.is_empty() || config.tshirt_sizes.contains(&size) {
+        Ok(size)
+    } else {
+        Err(not_offered("tshirt_size", &size))
+    }
+}
+
+// Only asked for courses with a field trip, where they are mandatory
+fn extract_emergency_contact(map: &Map, course: &Course, config: &Configuration) -> Result<(String, String), HandleError> {
+    if !config.field_trips.iter().any(|key| key == course_key(course)) {
+        return Ok((String::new(), String::new()));
+    }
+
+    let name = extract_optional_string(map, "emergency_name");
+    let phone = extract_optional_string(map, "emergency_phone");
+
+    if name.is_empty() {
+        Err(HandleError::Validation("emergency_name".to_string()))
+    } else if phone.is_empty() {
+        Err(HandleError::Validation("emergency_phone".to_string()))
+    } else {
+        Ok((name, normalize_phone_number(&phone, &config.calling_code).unwrap_or(phone)))
+    }
+}
+
+// The accepted version, mandatory if a code of conduct is configured
+fn extract_code_of_conduct(map: &Map, config: &Configuration) -> Result<String, HandleError> {
+    if config.code_of_conduct_version.is_empty() {
+        Ok(String::new())
+    } else if extract_checkbox(map, "code_of_conduct") {
+        Ok(config.code_of_conduct_version.clone())
+    } else {
+        Err(HandleError::Validation("code_of_conduct".to_string()))
+    }
+}
+
+fn extract_social_events(map: &Map, config: &Configuration) -> Result<Vec<String>, HandleError> {
+    let selected = extract_string_list(map, "social_events");
+
+    if let Some(key) = selected.iter().find(|key| !config.social_events.iter().any(|event| event.key == **key)) {
+        return Err(not_offered("social_events", key));
+    }
+
+    Ok(config.social_events.iter().filter(|event| selected.contains(&event.key)).map(|event| event.key.clone()).collect())
+}
+
+fn extract_banquet_guests(map: &Map, config: &Configuration) -> Result<i64, HandleError> {
+    let guests = extract_optional_string(map, "banquet_guests");
+
+    if config.banquet_max_guests == 0 || guests.is_empty() {
+        return Ok(0);
+    }
+
+    match guests.parse::<i64>() {
+        Ok(guests) if guests >= 0 && guests <= config.banquet_max_guests => Ok(guests),
+        _ => Err(HandleError::Validation("banquet_guests".to_string()))
+    }
+}
+
+// "single", "shared" or empty if no hotel room is needed, the roommate only makes sense for a shared room
+fn extract_room_sharing(map: &Map) -> Result<(String, String), HandleError> {
+    let room_sharing = extract_optional_string(map, "room_sharing");
+
+    match room_sharing.as_str() {
+        "" | "single" => Ok((room_sharing, String::new())),
+        "shared" => {
+            let roommate_email = normalize_email(&extract_optional_string(map, "roommate_email"));
+
+            if roommate_email.is_empty() || valid_email(&roommate_email) {
+                Ok((room_sharing, roommate_email))
+            } else {
+                Err(HandleError::Validation("roommate_email".to_string()))
+            }
+        }
+        _ => Err(not_offered("room_sharing", &room_sharing))
+    }
+}
+
+// The justification is required if a waiver is requested
+fn extract_fee_waiver(map: &Map) -> Result<String, HandleError> {
+    if !extract_checkbox(map, "fee_waiver") {
+        return Ok(String::new());
+    }
+
+    let reason = extract_optional_string(map, "fee_waiver_reason");
+
+    if reason.is_empty() {
+        Err(HandleError::Validation("fee_waiver_reason".to_string()))
+    } else {
+        Ok(reason)
+    }
+}
+
+// Title, authors and abstract are only asked for, and then required, if the participant presents something.
+// Only the kinds listed in presentations of [Program] are offered.
+fn extract_presentation(map: &Map, config: &Configuration) -> Result<(Presentation, String, String, String), HandleError> {
+    let presentation = match extract_optional_string(map, "presentation").as_str() {
+        "" | "none" => Presentation::None,
+        key if config.presentations.iter().any(|offered| offered == key) => Presentation::try_from(key)?,
+        key => return Err(not_offered("presentation", key))
+    };
+
+    if presentation == Presentation::None {
+        return Ok((Presentation::None, String::new(), String::new(), String::new()));
+    }
+
+    let mut fields = Vec::new();
+
+    for key in &["presentation_title", "presentation_authors", "abstract_text"] {
+        let value = extract_optional_string(map, key);
+        if value.is_empty() {
+            return Err(HandleError::Validation(key.to_string()));
+        }
+        fields.push(value);
+    }
+
+    let abstract_text = fields.pop().unwrap();
+    let authors = fields.pop().unwrap();
+    let title = fields.pop().unwrap();
+
+    Ok((presentation, title, authors, abstract_text))
+}
+
+const TALK_DURATIONS: [i64; 3] = [15, 20, 30];
+
+// Beyond the projector, which every room has
+pub const EQUIPMENT_OPTIONS: [(&str, &str); 4] = [
+    ("own_laptop", "Eigener Laptop"),
+    ("audio", "Tonwiedergabe"),
+    ("internet", "Internetzugang"),
+    ("whiteboard", "Tafel / Whiteboard")
+];
+
+// Scheduling wishes of the speakers for the program committee, ignored for posters
+fn extract_talk_preferences(map: &Map, presentation: &Presentation, config: &Configuration) -> Result<(i64, String, String), HandleError> {
+    if *presentation != Presentation::Talk {
+        return Ok((0, String::new(), String::new()));
+    }
+
+    let duration = match extract_optional_string(map, "talk_duration").as_str() {
+        "" => 0,
+        duration => match duration.parse::<i64>() {
+            Ok(duration) if TALK_DURATIONS.contains(&duration) => duration,
+            _ => return Err(not_offered("talk_duration", duration))
+        }
+    };
+
+    let selected = extract_string_list(map, "talk_equipment");
+
+    if let Some(option) = selected.iter().find(|option| !EQUIPMENT_OPTIONS.iter().any(|&(key, _)| key == *option)) {
+        return Err(not_offered("talk_equipment", option));
+    }
+
+    let equipment: Vec<&str> = EQUIPMENT_OPTIONS.iter().map(|&(key, _)| key).filter(|key| selected.iter().any(|option| option == key)).collect();
+
+    let days = program_days(config);
+    let selected = extract_string_list(map, "unavailable_days");
+
+    if let Some(day) = selected.iter().find(|day| !days.contains(day)) {
+        return Err(not_offered("unavailable_days", day));
+    }
+
+    let unavailable: Vec<&str> = days.iter().filter(|day| selected.contains(day)).map(|day| day.as_str()).collect();
+
+    Ok((duration, equipment.join(","), unavailable.join(", ")))
+}
+
+// At least one of the configured topics for every talk or poster
+fn extract_topics(map: &Map, presentation: &Presentation, config: &Configuration) -> Result<Vec<String>, HandleError> {
+    if *presentation == Presentation::None || config.topics.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let selected = extract_string_list(map, "topics");
+
+    if selected.is_empty() {
+        return Err(HandleError::Validation("topics".to_string()));
+    }
+
+    if let Some(topic) = selected.iter().find(|topic| !config.topics.contains(topic)) {
+        return Err(not_offered("topics", topic));
+    }
+
+    Ok(config.topics.iter().filter(|topic| selected.contains(topic)).cloned().collect())
+}
+
+fn check_social_capacity(db_connection: &Connection, social_events: &[String], config: &Configuration) -> Result<(), HandleError> {
+    let booked = count_social_bookings(db_connection)?;
+
+    for event in config.social_events.iter().filter(|event| event.capacity > 0 && social_events.contains(&event.key)) {
+        let count = booked.iter().find(|&(key, _)| *key == event.key).map_or(0, |&(_, count)| count);
+
+        if count >= event.capacity {
+            return Err(HandleError::SoldOut(event.name.clone()));
+        }
+    }
+
+    Ok(())
+}
+
+// A waitlisted registration counts too, a second one would skip the waiting list.
+// After a cancellation or rejection the participant can register again.
+fn check_duplicate_email(db_connection: &Connection, email_to: &str) -> Result<(), HandleError> {
+    let existing: i64 = db_connection.query_row("SELECT COUNT(*) FROM registration WHERE email_normalized = $1 AND status NOT IN ($2, $3)",
+        &[&normalize_email(email_to), &RegistrationStatus::Cancelled.name(), &RegistrationStatus::Rejected.name()], |row| row.get(0))?;
+
+    if existing > 0 {
+        Err(HandleError::AlreadyRegistered)
+    } else {
+        Ok(())
+    }
+}
+
+// Values of repeated "key[]" fields, like a group of checkboxes
+fn extract_string_list(map: &Map, key: &str) -> Vec<String> {
+    match map.find(&[key]) {
+        Some(Value::Array(values)) => values.iter().filter_map(|value| match *value {
+            Value::String(ref value) => Some(normalize_string(value)),
+            _ => None
+        }).collect(),
+        _ => Vec::new()
+    }
+}
+
+const MAX_CHILDREN: i64 = 10;
+
+// Only asked if child care is offered, the days come from "child_care_days[]" checkboxes
+fn extract_child_care(map: &Map, config: &Configuration) -> Result<(i64, String, String), HandleError> {
+    let children = extract_optional_string(map, "children");
+
+    if config.child_care_days.is_empty() || children.is_empty() {
+        return Ok((0, String::new(), String::new()));
+    }
+
+    let children = match children.parse::<i64>() {
+        Ok(children) if (0..=MAX_CHILDREN).contains(&children) => children,
+        _ => return Err(HandleError::Validation("children".to_string()))
+    };
+
+    if children == 0 {
+        return Ok((0, String::new(), String::new()));
+    }
+
+    let selected = extract_string_list(map, "child_care_days");
+
+    if selected.is_empty() {
+        return Err(HandleError::Validation("child_care_days".to_string()));
+    }
+
+    if let Some(day) = selected.iter().find(|day| !config.child_care_days.contains(day)) {
+        return Err(not_offered("child_care_days", day));
+    }
+
+    // In the configured order, no matter how the browser sends them
+    let days: Vec<&str> = config.child_care_days.iter().filter(|day| selected.contains(day)).map(|day| day.as_str()).collect();
+
+    Ok((children, extract_optional_string(map, "children_ages"), days.join(", ")))
+}
+
+// The common needs offered as checkboxes, anything else goes into the free text field
+pub const ACCESSIBILITY_OPTIONS: [(&str, &str); 3] = [
+    ("step_free", "Stufenloser Zugang"),
+    ("sign_language", "Gebärdensprachdolmetschung"),
+    ("dietary_equipment", "Zugang zu Kühlschrank / Küche für besondere Ernährung")
+];
+
+fn extract_accessibility_options(map: &Map) -> Result<String, HandleError> {
+    let selected = extract_string_list(map, "accessibility");
+
+    if let Some(option) = selected.iter().find(|option| !ACCESSIBILITY_OPTIONS.iter().any(|&(key, _)| key == *option)) {
+        return Err(not_offered("accessibility", option));
+    }
+
+    let options: Vec<&str> = ACCESSIBILITY_OPTIONS.iter().map(|&(key, _)| key).filter(|key| selected.iter().any(|option| option == key)).collect();
+
+    Ok(options.join(","))
+}
+
+const ARRIVAL_MODES: [&str; 4] = ["car", "train", "plane", "other"];
+
+fn extract_arrival_mode(map: &Map) -> Result<String, HandleError> {
+    let mode = extract_optional_string(map, "arrival_mode");
+
+    if mode.is_empty() || ARRIVAL_MODES.contains(&mode.as_str()) {
+        Ok(mode)
+    } else {
+        Err(not_offered("arrival_mode", &mode))
+    }
+}
+
+// From an <input type="datetime-local">, stored as "YYYY-MM-DD HH:MM" so that it sorts as text
+fn extract_arrival_time(map: &Map) -> Result<String, HandleError> {
+    let time = extract_optional_string(map, "arrival_time");
+
+    if time.is_empty() {
+        return Ok(time);
+    }
+
+    match NaiveDateTime::parse_from_str(&time, "%Y-%m-%dT%H:%M") {
+        Ok(time) => Ok(time.format("%Y-%m-%d %H:%M").to_string()),
+        Err(_) => Err(HandleError::Validation("arrival_time".to_string()))
+    }
+}
+
+// Unchecked checkboxes are not submitted at all
+fn extract_checkbox(map: &Map, key: &str) -> bool {
+    !extract_optional_string(map, key).is_empty()
+}
+
+fn map2registration(map: Map, config: &Configuration) -> Result<Registration, HandleError> {
+    let course_type = parse_choice(&map, "course_type", Course::Course2, config)?;
+    let (emergency_name, emergency_phone) = extract_emergency_contact(&map, &course_type, config)?;
+    let (children, children_ages, child_care_days) = extract_child_care(&map, config)?;
+    let price_category = parse_choice(&map, "price_category", PriceCategory::Regular, config)?;
+    let social_events = extract_social_events(&map, config)?;
+    let banquet_guests = extract_banquet_guests(&map, config)?;
+    let (room_sharing, roommate_email) = extract_room_sharing(&map)?;
+    let (presentation, presentation_title, presentation_authors, abstract_text) = extract_presentation(&map, config)?;
+    let (talk_duration, talk_equipment, unavailable_days) = extract_talk_preferences(&map, &presentation, config)?;
+    let topics = extract_topics(&map, &presentation, config)?;
+    let fee = fee_total(price_category_key(&price_category), &social_events, banquet_guests, config);
+
     let result = Registration{
-        title: if extract_string(&map, "title")? == "sir".to_string() { Title::Sir }
-               else { Title::Madam },
+        title: parse_choice(&map, "title", Title::Madam, config)?,
         last_name: extract_string(&map, "last_name")?,
         first_name: extract_string(&map, "first_name")?,
+        preferred_name: extract_optional_string(&map, "preferred_name"),
+        pronouns: extract_optional_string(&map, "pronouns"),
         institution: extract_string(&map, "institution")?,
         street: extract_string(&map, "street")?,
         street_no: extract_string(&map, "street_no")?,
         zip_code: extract_string(&map, "zip_code")?,
         city: extract_string(&map, "city")?,
         phone: extract_string(&map, "phone")?,
-        email_to: extract_string(&map, "email_to")?,
+        mobile: extract_mobile(&map, config),
+        email_to: extract_email_to(&map)?,
+        confirmation_cc: extract_confirmation_cc(&map)?,
+        country: extract_country(&map)?,
         more_info: extract_string(&map, "more_info")?,
-        price_category: if extract_string(&map, "price_category")? == "student".to_string() { PriceCategory::Student }
-        else { PriceCategory::Regular },
-        course_type: if extract_string(&map, "course_type")? == "course1".to_string() { Course::Course1 }
-        else { Course::Course2 }
+        orcid: extract_orcid(&map)?,
+        tshirt_size: extract_tshirt_size(&map, config)?,
+        emergency_name,
+        emergency_phone,
+        arrival_mode: extract_arrival_mode(&map)?,
+        arrival_number: extract_optional_string(&map, "arrival_number"),
+        arrival_time: extract_arrival_time(&map)?,
+        share_ride: extract_checkbox(&map, "share_ride"),
+        children,
+        children_ages,
+        child_care_days,
+        accessibility_options: extract_accessibility_options(&map)?,
+        accessibility_needs: extract_optional_string(&map, "accessibility_needs"),
+        code_of_conduct: extract_code_of_conduct(&map, config)?,
+        photo_consent: extract_checkbox(&map, "photo_consent"),
+        recording_consent: extract_checkbox(&map, "recording_consent"),
+        newsletter: extract_checkbox(&map, "newsletter"),
+        social_events,
+        banquet_guests,
+        room_sharing,
+        roommate_email,
+        fee_waiver_reason: extract_fee_waiver(&map)?,
+        presentation,
+        presentation_title,
+        presentation_authors,
+        abstract_text,
+        talk_duration,
+        talk_equipment,
+        unavailable_days,
+        topics,
+        fee,
+        price_category,
+        course_type
     };
 
     Ok(result)
 }
 
-fn insert_into_db(db_connection: &Connection, registration: &Registration) -> Result<(), HandleError> {
-    let title = if registration.title == Title::Sir { "sir".to_string() } else { "madam".to_string() };
-    let price_category = if registration.price_category == PriceCategory::Student { "student".to_string() } else { "regular".to_string() };
-    let course_type = if registration.course_type == Course::Course1 { "course1".to_string() } else { "course2".to_string() };
-
-    db_connection.execute("
+// Returns the token for the participant's personal links
+fn insert_into_db(db_connection: &Connection, registration: &Registration, status: RegistrationStatus, form_data: &str, form_token: &str)
+    -> Result<String, HandleError> {
+    let title = title_key(&registration.title);
+    let price_category = price_category_key(&registration.price_category);
+    let course_type = course_key(&registration.course_type);
+    let email_normalized = normalize_email(&registration.email_to);
+    let token = generate_token()?;
+    let fee_waiver_status = if registration.fee_waiver_reason.is_empty() { "" } else { "requested" };
+    let registered_at = timestamp();
+    let code_of_conduct_accepted = if registration.code_of_conduct.is_empty() { String::new() } else { registered_at.clone() };
+
+    db_connection.prepare_cached("
          INSERT INTO registration (
            title,
            last_name,
@@ -216,9 +1095,52 @@ fn insert_into_db(db_connection: &Connection, registration: &Registration) -> Re
            email_to,
            more_info,
            price_category,
-           course_type
-         ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
-         ",&[
+           course_type,
+           email_normalized,
+           orcid,
+           country,
+           mobile,
+           preferred_name,
+           pronouns,
+           tshirt_size,
+           emergency_name,
+           emergency_phone,
+           token,
+           arrival_mode,
+           arrival_number,
+           arrival_time,
+           share_ride,
+           children,
+           children_ages,
+           child_care_days,
+           accessibility_options,
+           accessibility_needs,
+           code_of_conduct_version,
+           code_of_conduct_accepted,
+           photo_consent,
+           recording_consent,
+           fee,
+           banquet_guests,
+           room_sharing,
+           roommate_email,
+           fee_waiver_reason,
+           fee_waiver_status,
+           presentation,
+           presentation_title,
+           presentation_authors,
+           abstract_text,
+           talk_duration,
+           talk_equipment,
+           unavailable_days,
+           registered_at,
+           form_data,
+           form_token,
+           confirmation_cc,
+           status
+         ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23,
+           $24, $25, $26, $27, $28, $29, $30, $31, $32, $33, $34, $35, $36, $37, $38, $39, $40, $41, $42,
+           $43, $44, $45, $46, $47, $48, $49, $50, $51, $52, $53, $54)
+         ")?.execute(&[
              &title,
              &registration.last_name,
              &registration.first_name,
@@ -231,51 +1153,615 @@ fn insert_into_db(db_connection: &Connection, registration: &Registration) -> Re
              &registration.email_to,
              &registration.more_info,
              &price_category,
-             &course_type
+             &course_type,
+             &email_normalized,
+             &registration.orcid,
+             &registration.country,
+             &registration.mobile,
+             &registration.preferred_name,
+             &registration.pronouns,
+             &registration.tshirt_size,
+             &registration.emergency_name,
+             &registration.emergency_phone,
+             &token,
+             &registration.arrival_mode,
+             &registration.arrival_number,
+             &registration.arrival_time,
+             &registration.share_ride,
+             &registration.children,
+             &registration.children_ages,
+             &registration.child_care_days,
+             &registration.accessibility_options,
+             &registration.accessibility_needs,
+             &registration.code_of_conduct,
+             &code_of_conduct_accepted,
+             &registration.photo_consent,
+             &registration.recording_consent,
+             &registration.fee,
+             &registration.banquet_guests,
+             &registration.room_sharing,
+             &registration.roommate_email,
+             &registration.fee_waiver_reason,
+             &fee_waiver_status,
+             &presentation_key(&registration.presentation),
+             &registration.presentation_title,
+             &registration.presentation_authors,
+             &registration.abstract_text,
+             &registration.talk_duration,
+             &registration.talk_equipment,
+             &registration.unavailable_days,
+             &registered_at,
+             &form_data,
+             &form_token,
+             &registration.confirmation_cc,
+             &status.name()
          ])?;
 
+    let registration_id = db_connection.last_insert_rowid();
+    link_institution(db_connection, registration_id)?;
+
+    for event in &registration.social_events {
+        book_social_event(db_connection, registration_id, event)?;
+    }
+
+    for topic in &registration.topics {
+        add_presentation_topic(db_connection, registration_id, topic)?;
+    }
+
+    if registration.newsletter {
+        let name = format!("{} {}", registration.first_name, registration.last_name);
+        subscribe_newsletter(db_connection, &email_normalized, &name, &generate_token()?)?;
+    }
+
+    Ok(token)
+}
+
+fn load_list(db_connection: &Connection, sql: &str, id: i64) -> Result<Vec<String>, rusqlite::Error> {
+    let mut stmt = db_connection.prepare(sql)?;
+    let rows = stmt.query_map(&[&id], |row| row.get(0))?;
+    rows.collect()
+}
+
+// The inverse of insert_into_db, with the token of the personal links
+pub fn load_registration(db_connection: &Connection, id: i64) -> Result<Option<(Registration, String)>, HandleError> {
+    let mut stmt = db_connection.prepare("
+        SELECT title, last_name, first_name, preferred_name, pronouns, institution, street, street_no, zip_code, city,
+          phone, mobile, email_to, country, more_info, orcid, tshirt_size, emergency_name, emergency_phone,
+          arrival_mode, arrival_number, arrival_time, share_ride, children, children_ages, child_care_days,
+          accessibility_options, accessibility_needs, code_of_conduct_version, photo_consent, recording_consent,
+          banquet_guests, room_sharing, roommate_email, fee_waiver_reason, presentation, presentation_title,
+          presentation_authors, abstract_text, talk_duration, talk_equipment, unavailable_days, fee,
+          price_category, course_type, token, email_normalized, confirmation_cc
+        FROM registration WHERE id = $1")?;
+    let mut rows = stmt.query(&[&id])?;
+
+    let row = match rows.next() {
+        Some(row) => row?,
+        None => return Ok(None)
+    };
+
+    let social_events = load_list(db_connection, "SELECT event FROM social_booking WHERE registration_id = $1 ORDER BY rowid", id)?;
+    let topics = load_list(db_connection, "SELECT topic FROM presentation_topic WHERE registration_id = $1 ORDER BY rowid", id)?;
+    let email_normalized: String = row.get(46);
+    let newsletter: i64 = db_connection.query_row("SELECT COUNT(*) FROM newsletter WHERE email = $1", &[&email_normalized], |row| row.get(0))?;
+
+    let registration = Registration {
+        title: stored_choice(id, &row.get::<i32, String>(0), Title::Madam),
+        last_name: row.get(1),
+        first_name: row.get(2),
+        preferred_name: row.get(3),
+        pronouns: row.get(4),
+        institution: row.get(5),
+        street: row.get(6),
+        street_no: row.get(7),
+        zip_code: row.get(8),
+        city: row.get(9),
+        phone: row.get(10),
+        mobile: row.get(11),
+        email_to: row.get(12),
+        confirmation_cc: row.get(47),
+        country: row.get(13),
+        more_info: row.get(14),
+        orcid: row.get(15),
+        tshirt_size: row.get(16),
+        emergency_name: row.get(17),
+        emergency_phone: row.get(18),
+        arrival_mode: row.get(19),
+        arrival_number: row.get(20),
+        arrival_time: row.get(21),
+        share_ride: row.get(22),
+        children: row.get(23),
+        children_ages: row.get(24),
+        child_care_days: row.get(25),
+        accessibility_options: row.get(26),
+        accessibility_needs: row.get(27),
+        code_of_conduct: row.get(28),
+        photo_consent: row.get(29),
+        recording_consent: row.get(30),
+        newsletter: newsletter > 0,
+        social_events,
+        banquet_guests: row.get(31),
+        room_sharing: row.get(32),
+        roommate_email: row.get(33),
+        fee_waiver_reason: row.get(34),
+        presentation: stored_choice(id, &row.get::<i32, String>(35), Presentation::None),
+        presentation_title: row.get(36),
+        presentation_authors: row.get(37),
+        abstract_text: row.get(38),
+        talk_duration: row.get(39),
+        talk_equipment: row.get(40),
+        unavailable_days: row.get(41),
+        topics,
+        fee: row.get(42),
+        price_category: stored_choice(id, &row.get::<i32, String>(43), PriceCategory::Regular),
+        course_type: stored_choice(id, &row.get::<i32, String>(44), Course::Course2)
+    };
+
+    Ok(Some((registration, row.get(45))))
+}
+
+// The text fields an organizer can correct in /admin/edit/:id, with the labels of the form. Title, country and
+// T-shirt size are selects. Course, fee and presentation have their own pages and stay as registered.
+const EDITABLE_FIELDS: [(&str, &str); 14] = [
+    ("first_name", "Vorname"), ("last_name", "Nachname"), ("preferred_name", "Name auf dem Namensschild"), ("pronouns", "Pronomen"),
+    ("institution", "Institution"), ("street", "Straße"), ("street_no", "Hausnummer"), ("zip_code", "PLZ"), ("city", "Ort"),
+    ("phone", "Telefon"), ("mobile", "Mobiltelefon"), ("email_to", "E-Mail"), ("confirmation_cc", "Bestätigung zusätzlich an"),
+    ("more_info", "Weitere Informationen")
+];
+
+fn editable_value(registration: &Registration, key: &str) -> String {
+    match key {
+        "title" => title_key(&registration.title).to_string(),
+        "first_name" => registration.first_name.clone(),
+        "last_name" => registration.last_name.clone(),
+        "preferred_name" => registration.preferred_name.clone(),
+        "pronouns" => registration.pronouns.clone(),
+        "institution" => registration.institution.clone(),
+        "street" => registration.street.clone(),
+        "street_no" => registration.street_no.clone(),
+        "zip_code" => registration.zip_code.clone(),
+        "city" => registration.city.clone(),
+        "phone" => registration.phone.clone(),
+        "mobile" => registration.mobile.clone(),
+        "email_to" => registration.email_to.clone(),
+        "confirmation_cc" => registration.confirmation_cc.clone(),
+        "country" => registration.country.clone(),
+        "more_info" => registration.more_info.clone(),
+        "tshirt_size" => registration.tshirt_size.clone(),
+        _ => String::new()
+    }
+}
+
+fn extract_required(map: &Map, key: &str) -> Result<String, HandleError> {
+    let value = extract_string(map, key)?;
+
+    if value.is_empty() {
+        Err(HandleError::Validation(key.to_string()))
+    } else {
+        Ok(value)
+    }
+}
+
+// Takes the corrections of /admin/edit/:id over, checked like the fields of a new registration
+fn apply_corrections(map: &Map, registration: &mut Registration, config: &Configuration) -> Result<(), HandleError> {
+    let email_to = extract_required(map, "email_to")?;
+    if !valid_email(&email_to) {
+        return Err(HandleError::Validation("email_to".to_string()));
+    }
+
+    registration.title = parse_choice(map, "title", Title::Madam, config)?;
+    registration.first_name = extract_required(map, "first_name")?;
+    registration.last_name = extract_required(map, "last_name")?;
+    registration.preferred_name = extract_optional_string(map, "preferred_name");
+    registration.pronouns = extract_optional_string(map, "pronouns");
+    registration.institution = extract_required(map, "institution")?;
+    registration.street = extract_string(map, "street")?;
+    registration.street_no = extract_string(map, "street_no")?;
+    registration.zip_code = extract_string(map, "zip_code")?;
+    registration.city = extract_string(map, "city")?;
+    registration.phone = extract_string(map, "phone")?;
+    registration.mobile = extract_mobile(map, config);
+    registration.email_to = email_to;
+    registration.confirmation_cc = extract_confirmation_cc(map)?;
+    registration.country = extract_country(map)?;
+    registration.more_info = extract_optional_string(map, "more_info");
+    registration.tshirt_size = extract_tshirt_size(map, config)?;
+
+    Ok(())
+}
+
+/// Writes the fields of EDITABLE_FIELDS and the selects back, email_normalized follows the new address.
+/// The newsletter subscription keeps its address, it has its own unsubscribe link.
+pub fn update_registration(db_connection: &Connection, id: i64, registration: &Registration) -> Result<(), HandleError> {
+    let title = title_key(&registration.title);
+    let email_normalized = normalize_email(&registration.email_to);
+
+    let changed = db_connection.execute("
+        UPDATE registration SET title = $1, first_name = $2, last_name = $3, preferred_name = $4, pronouns = $5,
+          institution = $6, street = $7, street_no = $8, zip_code = $9, city = $10, phone = $11, mobile = $12,
+          email_to = $13, email_normalized = $14, confirmation_cc = $15, country = $16, more_info = $17, tshirt_size = $18
+        WHERE id = $19",
+        &[&title, &registration.first_name, &registration.last_name, &registration.preferred_name, &registration.pronouns,
+          &registration.institution, &registration.street, &registration.street_no, &registration.zip_code, &registration.city,
+          &registration.phone, &registration.mobile, &registration.email_to, &email_normalized, &registration.confirmation_cc,
+          &registration.country, &registration.more_info, &registration.tshirt_size, &id])?;
+
+    if changed == 0 {
+        Err(HandleError::NotFound)
+    } else {
+        link_institution(db_connection, id)?;
+        Ok(record_roster_change(db_connection, id, "details", "", "")?)
+    }
+}
+
+fn edit_id(req: &Request) -> Option<i64> {
+    router_param(req, "id").parse::<i64>().ok()
+}
+
+pub fn handle_edit_registration(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "edit_registration", |req| edit_page(req, None))
+}
+
+// After an invalid field the form shows what was entered, with that field marked
+fn edit_page(req: &mut Request, error: Option<String>) -> Result<Response, HandleError> {
+    let id = match edit_id(req) {
+        Some(id) => id,
+        None => return Ok(not_found())
+    };
+
+    let config = req.get::<Read<Configuration>>()?;
+    let map = req.get::<Params>()?;
+
+    let registration = {
+        let mutex = req.get::<Write<DBConnection>>()?;
+        let db_connection = lock_connection(&mutex)?;
+
+        match load_registration(&db_connection, id)? {
+            Some((registration, _)) => registration,
+            None => return Ok(not_found())
+        }
+    };
+
+    let value = |key: &str| if error.is_some() { extract_optional_string(&map, key) } else { editable_value(&registration, key) };
+    let option = |key: &str, label: &str, selected: &str| json!({ "key": key, "label": label, "selected": key == selected });
+
+    let fields: Vec<JsonValue> = EDITABLE_FIELDS.iter().map(|&(key, label)| json!({
+        "key": key,
+        "label": label,
+        "value": value(key),
+        "invalid": error.as_ref().is_some_and(|field| field == key)
+    })).collect();
+
+    let page_status = if error.is_some() { status::BadRequest } else { status::Ok };
+    let title = value("title");
+    let country = value("country");
+    let tshirt_size = value("tshirt_size");
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("id".to_string(), json!(id));
+    data.insert("fields".to_string(), json!(fields));
+    data.insert("titles".to_string(), json!(vec![option("madam", "Frau", &title), option("sir", "Herr", &title)]));
+    data.insert("countries".to_string(), json!(sorted_countries(config.language).iter()
+        .map(|entry| option(entry.code, entry.name(config.language), &country)).collect::<Vec<JsonValue>>()));
+    data.insert("tshirt_sizes".to_string(), json!(config.tshirt_sizes.iter()
+        .map(|size| option(size, size, &tshirt_size)).collect::<Vec<JsonValue>>()));
+    data.insert("error".to_string(), json!(error.map(|field|
+        EDITABLE_FIELDS.iter().find(|&&(key, _)| key == field).map_or(field.clone(), |&(_, label)| label.to_string()))));
+    data.insert("saved".to_string(), json!(extract_string(&map, "saved").is_ok()));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("edit_registration", data)).set_mut(page_status);
+    Ok(resp)
+}
+
+pub fn handle_edit_registration_submit(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "edit_registration_submit", save_registration)
+}
+
+fn save_registration(req: &mut Request) -> Result<Response, HandleError> {
+    let id = match edit_id(req) {
+        Some(id) => id,
+        None => return Ok(not_found())
+    };
+
+    let config = req.get::<Read<Configuration>>()?;
+    let map = req.get::<Params>()?;
+
+    let result = {
+        let mutex = req.get::<Write<DBConnection>>()?;
+        let db_connection = lock_connection(&mutex)?;
+
+        let mut registration = match load_registration(&db_connection, id)? {
+            Some((registration, _)) => registration,
+            None => return Ok(not_found())
+        };
+
+        apply_corrections(&map, &mut registration, &config).and_then(|()| update_registration(&db_connection, id, &registration))
+    };
+
+    match result {
+        Ok(()) => {
+            info!("Registration {} corrected in /admin/edit", id);
+            Ok(Response::with((status::Found, RedirectRaw(format!("/admin/edit/{}?saved=1", id)))))
+        }
+        Err(HandleError::Validation(field)) => {
+            info!("Invalid correction of registration {} in field '{}'", id, field);
+            edit_page(req, Some(field))
+        }
+        Err(e) => Err(e)
+    }
+}
+
+// The tables of count_linked_rows as the confirmation page names them
+const LINKED_LABELS: [(&str, &str); 7] = [
+    ("reimbursement", "Reisekostenerstattungen"), ("social_booking", "Buchungen im Rahmenprogramm"),
+    ("presentation_topic", "Themen des Beitrags"), ("travel_grant", "Anträge auf ein Reisestipendium"),
+    ("outbox", "E-Mails im Postausgang"), ("login_link", "Login-Links"), ("roster_change", "Einträge der Änderungslisten")
+];
+
+pub fn handle_delete_registration(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "delete_registration", delete_page)
+}
+
+// Offers the cancellation, which keeps the row, next to the deletion and lists what would be deleted with it
+fn delete_page(req: &mut Request) -> Result<Response, HandleError> {
+    let id = match edit_id(req) {
+        Some(id) => id,
+        None => return Ok(not_found())
+    };
+
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let (registration, current) = match (load_registration(&db_connection, id)?, load_registration_status(&db_connection, id)?) {
+        (Some((registration, _)), Some(current)) => (registration, current),
+        _ => return Ok(not_found())
+    };
+
+    let linked: Vec<JsonValue> = count_linked_rows(&db_connection, id)?.into_iter().filter(|&(_, count)| count > 0)
+        .map(|(table, count)| json!({
+            "label": LINKED_LABELS.iter().find(|&&(key, _)| key == table).map_or(table, |&(_, label)| label),
+            "count": count
+        })).collect();
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("id".to_string(), json!(id));
+    data.insert("name".to_string(), json!(format!("{} {}", registration.first_name, registration.last_name)));
+    data.insert("email".to_string(), json!(registration.email_to));
+    data.insert("status".to_string(), json!(status_label(&current)));
+    data.insert("can_cancel".to_string(), json!(RegistrationStatus::from_name(&current)
+        .is_some_and(|status| status.can_change_to(RegistrationStatus::Cancelled))));
+    data.insert("linked".to_string(), json!(linked));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("delete_registration", data)).set_mut(status::Ok);
+    Ok(resp)
+}
+
+pub fn handle_delete_registration_submit(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "delete_registration_submit", delete)
+}
+
+fn delete(req: &mut Request) -> Result<Response, HandleError> {
+    let id = match edit_id(req) {
+        Some(id) => id,
+        None => return Ok(not_found())
+    };
+
+    // The checkboxes of the confirmation page
+    let (confirmed, erase) = {
+        let map = req.get_ref::<Params>()?;
+        (extract_string(map, "confirm").is_ok(), extract_string(map, "erase").is_ok())
+    };
+
+    if !confirmed {
+        return Ok(Response::with((status::Found, RedirectRaw(format!("/admin/delete/{}", id)))));
+    }
+
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    // Only an erasure request or a test entry removes the row, everything else stays as cancelled
+    // so that the registration still shows up in /admin/roster_changes
+    if !erase {
+        let current = match load_registration_status(&db_connection, id)? {
+            Some(current) => current,
+            None => return Ok(not_found())
+        };
+
+        let cancellable = RegistrationStatus::from_name(&current).is_some_and(|status| status.can_change_to(RegistrationStatus::Cancelled));
+        if cancellable && update_registration_status(&db_connection, id, &current, RegistrationStatus::Cancelled.name())? {
+            info!("Registration {} cancelled in /admin/delete", id);
+        }
+
+        return Ok(Response::with((status::Found, RedirectRaw("/admin".to_string()))));
+    }
+
+    match delete_registration(&db_connection, id) {
+        Ok(()) => {
+            info!("Registration {} erased in /admin/delete", id);
+            Ok(Response::with((status::Found, RedirectRaw("/admin".to_string()))))
+        }
+        Err(HandleError::NotFound) => Ok(not_found()),
+        Err(e) => Err(e)
+    }
+}
+
+// The placeholders of the confirmation template
+pub fn confirmation_values(registration: &Registration, token: &str, config: &Configuration) -> Vec<(&'static str, String)> {
+    let course = if registration.course_type == Course::Course1 { &config.course1 } else { &config.course2 };
+    let greeting = if registration.title == Title::Sir { format!("Sehr geehrter Herr {},", registration.last_name) } else { format!("Sehr geehrte Frau {},", registration.last_name) };
+    let price = if registration.price_category == PriceCategory::Student { "Student".to_string() } else { "Regulaer".to_string() };
+    let reimbursement = if config.reimbursement_eligible.contains(&normalize_email(&registration.email_to)) {
+        format!("\n\nIhre Reisekosten koennen Sie nach der Veranstaltung hier einreichen:\n {}/reimbursement/{}", config.base_url, token)
+    } else {
+        String::new()
+    };
+    let social: Vec<String> = config.social_events.iter()
+        .filter(|event| registration.social_events.contains(&event.key))
+        .map(|event| format!("\n Rahmenprogramm: {}", event.name))
+        .collect();
+    let guests = if registration.banquet_guests > 0 { format!("\n Bankettkarten fuer Begleitpersonen: {}", registration.banquet_guests) } else { String::new() };
+    let fee = if registration.fee > 0 { format!("\n Gebuehr: {}", format_amount(registration.fee)) } else { String::new() };
+    let travel_grant = if config.travel_grant_max > 0 {
+        format!("\n\nEinen Reisekostenzuschuss koennen Sie hier beantragen:\n {}/travel_grant/{}", config.base_url, token)
+    } else {
+        String::new()
+    };
+    let edit = format!("\n\nIhre Angaben zum Beitrag und Ihre Hinweise an die Organisatoren koennen Sie hier aendern:\n {}/edit/{}",
+        config.base_url, token);
+    let slides = match registration.presentation {
+        Presentation::Talk => format!("\n\nIhre Vortragsfolien koennen Sie vor der Veranstaltung hier hochladen:\n {}/slides/{}", config.base_url, token),
+        Presentation::Poster => format!("\n\nIhr Poster (PDF) fuer die virtuelle Postergalerie koennen Sie hier hochladen:\n {}/poster/{}", config.base_url, token),
+        Presentation::None => String::new()
+    };
+
+    let mut values = vec![
+        ("greeting", greeting),
+        ("course", course.clone()),
+        ("category", price),
+        ("details", format!("{}{}{}", social.concat(), guests, fee)),
+        ("links", format!("{}{}{}{}", edit, reimbursement, travel_grant, slides))
+    ];
+    values.extend(config.conference.mail_values());
+    values
+}
+
+// The text can be changed at /admin/mail_templates
+pub fn send_mail(registration: &Registration, id: i64, token: &str, config: &Configuration) -> Result<(), HandleError> {
+    // Called without the server's connection, like the background tasks
+    let db_connection = Connection::open(&config.db_filename)?;
+    let name = match load_registration_status(&db_connection, id)?.as_deref() {
+        Some("waitlisted") => "waitlist_received",
+        _ if config.approval => "approval_received",
+        _ => "confirmation"
+    };
+    let template = mail_template(&db_connection, name, config)?;
+    let values = confirmation_values(registration, token, config);
+
+    let copy_to: Vec<&str> = if registration.confirmation_cc.is_empty() { Vec::new() } else { vec![&registration.confirmation_cc] };
+
+    let email = build_email(&registration.email_to, &copy_to, &fill_template(&template.subject, &values),
+        &fill_template(&template.body, &values), &config.confirmation_attachments, config)?;
+
+    deliver(email, config)
+}
+
+// Every mail goes to the participant with a copy to the organizers
+pub fn send_email(email_to: &str, subject: &str, body: &str, config: &Configuration) -> Result<(), HandleError> {
+    deliver(build_email(email_to, &[], subject, body, &[], config)?, config)
+}
+
+fn attachment_type(file_name: &str) -> &'static str {
+    match Path::new(file_name).extension().and_then(|extension| extension.to_str()).map(|extension| extension.to_lowercase()) {
+        Some(ref extension) if extension == "pdf" => "application/pdf",
+        Some(ref extension) if extension == "png" => "image/png",
+        Some(ref extension) if extension == "jpg" || extension == "jpeg" => "image/jpeg",
+        Some(ref extension) if extension == "txt" => "text/plain",
+        Some(ref extension) if extension == "ics" => "text/calendar",
+        _ => "application/octet-stream"
+    }
+}
 
-    Ok(())
+// Base64 in lines of 76 characters as MIME requires
+fn base64_lines(content: &[u8]) -> String {
+    let encoded = encode_block(content);
+    let lines: Vec<&str> = (0..encoded.len()).step_by(76).map(|start| &encoded[start..encoded.len().min(start + 76)]).collect();
+    lines.join("\r\n")
 }
 
-fn send_mail(registration: &Registration, config: &Configuration) -> Result<(), HandleError> {
-    let course = if registration.course_type == Course::Course1 { &config.course1 } else { &config.course2 };
-    let subject = format!("Anmeldungsbestaetigung: TGAG Fortbildung - {}", course);
-    let greeting = if registration.title == Title::Sir { format!("Sehr geehrter Herr {},", registration.last_name) } else { format!("Sehr geehrte Frau {},", registration.last_name) };
-    let price = if registration.price_category == PriceCategory::Student { "Student".to_string() } else { "Regulaer".to_string() };
-    let body = format!("{}\n\nSie haben sich fuer den folgenden Kurs angemeldet:\n\n Zeitpunkt: {}\n Kategorie: {}\n\nMit freundlichen Gruessen,\ndie Fortbildungsorganisation", greeting, course, price);
+// With attachments the text becomes the first part of a multipart/mixed mail. The files are read for every
+// mail, so they can be replaced while the server is running. The parts are joined here, because lettre's
+// add_child loses the Content-Type header of the whole mail.
+fn build_email(email_to: &str, copy_to: &[&str], subject: &str, body: &str, attachments: &[String], config: &Configuration) -> Result<Email, HandleError> {
+    let mut builder = EmailBuilder::new()
+                    .to(email_to)
+                    .from(config.email_from.as_str())
+                    .subject(subject);
+
+    for email in config.organizer_emails.iter().map(|email| email.as_str()).chain(copy_to.iter().cloned()) {
+        builder.add_cc(email);
+    }
+
+    if attachments.is_empty() {
+        builder.set_body(body);
+        return Ok(builder.build()?);
+    }
+
+    let boundary = generate_token()?;
+    let mut parts = vec![PartBuilder::new().header(("Content-Type", "text/plain; charset=utf-8")).body(body).build().as_string()];
+
+    for file_name in attachments {
+        let content = fs::read(file_name)?;
+        let name = Path::new(file_name).file_name().and_then(|name| name.to_str()).unwrap_or("attachment").replace('"', "");
+
+        parts.push(PartBuilder::new()
+            .header(("Content-Type", attachment_type(file_name)))
+            .header(("Content-Disposition", format!("attachment; filename=\"{}\"", name).as_str()))
+            .header(("Content-Transfer-Encoding", "base64"))
+            .body(&base64_lines(&content))
+            .build().as_string());
+    }
+
+    builder.add_header(("Content-Type", format!("multipart/mixed; boundary=\"{}\"", boundary).as_str()));
+    builder.set_body(&format!("{}--{}--", parts.iter().map(|part| format!("--{}\r\n{}\r\n", boundary, part)).collect::<String>(), boundary));
+
+    Ok(builder.build()?)
+}
 
-    let email_to = registration.email_to.as_str();
-    let email_from = config.email_from.as_str();
+// Announcements are not copied to the organizers and carry the unsubscribe link
+// in the body as well as in the headers for one-click unsubscribe (RFC 2369, RFC 8058)
+pub fn send_bulk_email(email_to: &str, subject: &str, body: &str, unsubscribe_url: &str, config: &Configuration) -> Result<(), HandleError> {
+    let list_unsubscribe = format!("<{}>", unsubscribe_url);
+    let body = format!("{}\n\n-- \nSie moechten keine Rundmails mehr erhalten? Hier abbestellen:\n{}\n", body, unsubscribe_url);
 
     let email = EmailBuilder::new()
                     .to(email_to)
-                    .from(email_from)
-                    .cc(email_from)
+                    .from(config.email_from.as_str())
+                    .header(("List-Unsubscribe", list_unsubscribe.as_str()))
+                    .header(("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"))
+                    .header(("Precedence", "bulk"))
                     .body(&body)
-                    .subject(&subject)
+                    .subject(subject)
                     .build()?;
 
-    let host_ip = Ipv4Addr::from_str(&config.email_server)?;
+    deliver(email, config)
+}
 
-    let mut mailer = SmtpTransportBuilder::new((host_ip, SUBMISSION_PORT))?
-        .hello_name(&config.email_hello)
-        .credentials(&config.email_username, &config.email_password)
-        .security_level(SecurityLevel::AlwaysEncrypt)
-        .smtp_utf8(true)
-        .authentication_mechanism(Mechanism::CramMd5)
-        .connection_reuse(true).build();
+// For the administrators only, see alert.rs
+pub fn send_alert(recipients: &[String], subject: &str, body: &str, config: &Configuration) -> Result<(), HandleError> {
+    let mut builder = EmailBuilder::new()
+                    .from(config.email_from.as_str())
+                    .subject(subject)
+                    .body(body);
 
-    mailer.send(email)?;
+    for email in recipients {
+        builder.add_to(email.as_str());
+    }
 
-    Ok(())
+    deliver(builder.build()?, config)
+}
+
+fn deliver(email: Email, config: &Configuration) -> Result<(), HandleError> {
+    let mailer = configured_mailer(config);
+    let mail = OutgoingMail::new(email, config)?;
+
+    debug!("Mail to {} via {}", mail.to.join(", "), mailer.name());
+    mailer.send(&mail, config)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{extract_string, map2registration, insert_into_db, send_mail, Registration, PriceCategory, Title, Course};
-    use config::{load_configuration};
+    use super::{normalize_string, normalize_email, extract_string, extract_email_to, extract_confirmation_cc, extract_tshirt_size, extract_emergency_contact, extract_child_care, extract_accessibility_options, extract_code_of_conduct, extract_social_events, extract_banquet_guests, extract_room_sharing, extract_fee_waiver, extract_presentation, extract_talk_preferences, extract_topics, check_social_capacity, check_duplicate_email, initial_status, extract_arrival_mode, extract_arrival_time, extract_checkbox, map2registration, insert_into_db, load_registration, apply_corrections, update_registration, form_snapshot, thank_you_url, send_mail, build_email, Registration, PriceCategory, Title, Presentation, Course, HandleError, UnknownChoice};
+    use std::convert::TryFrom;
+    use config::{example_configuration, Configuration, SocialEvent, Session};
+    use std::fs;
+    use database::{init_database, find_by_form_token, load_newsletter_subscribers, book_social_event, count_social_bookings};
+    use status::RegistrationStatus;
     use params::{Value, Map};
+    use openssl::base64::encode_block;
+    use lettre::email::SendableEmail;
+    use serde_json;
 
     use rusqlite::Connection;
 
@@ -289,6 +1775,54 @@ mod tests {
         assert_eq!(result, "Bob".to_string());
     }
 
+    #[test]
+    fn test_extract_string_normalized() {
+        let mut map = Map::new();
+        map.assign("last_name", Value::String(" Mu\u{0308}ller  ".into())).unwrap();
+        let result = extract_string(&map, "last_name").unwrap();
+
+        assert_eq!(result, "M\u{00FC}ller".to_string());
+    }
+
+    #[test]
+    fn test_thank_you_url() {
+        let mut config = example_configuration();
+        config.invoice_prefix = "TAG 2017/".to_string();
+
+        config.thank_you_url = "https://conference.example.org/thanks".to_string();
+        assert_eq!(thank_you_url(&config, 42), "https://conference.example.org/thanks?code=TAG+2017%2F00042".to_string());
+
+        config.thank_you_url = "https://conference.example.org/thanks?lang=en".to_string();
+        assert_eq!(thank_you_url(&config, 7), "https://conference.example.org/thanks?lang=en&code=TAG+2017%2F00007".to_string());
+    }
+
+    #[test]
+    fn test_form_snapshot() {
+        let mut map = Map::new();
+        map.assign("last_name", Value::String(" Mu\u{0308}ller  ".into())).unwrap();
+        map.assign("topics[]", Value::String("Geodynamics".into())).unwrap();
+        map.assign("topics[]", Value::String("Seismology".into())).unwrap();
+
+        // Exactly as submitted, without the normalization of the parsed registration
+        assert_eq!(form_snapshot(&map), json!({
+            "last_name": " Mu\u{0308}ller  ",
+            "topics": ["Geodynamics", "Seismology"]
+        }));
+    }
+
+    #[test]
+    fn test_normalize_string() {
+        assert_eq!(normalize_string("\tBob Smith\n"), "Bob Smith".to_string());
+        assert_eq!(normalize_string("Mu\u{0308}ller"), normalize_string("M\u{00FC}ller"));
+        assert_eq!(normalize_string("   "), "".to_string());
+    }
+
+    #[test]
+    fn test_normalize_email() {
+        assert_eq!(normalize_email(" Bob@Smith.com "), "bob@smith.com".to_string());
+        assert_eq!(normalize_email("bob@smith.com"), normalize_email("BOB@SMITH.COM"));
+    }
+
     #[test]
     fn test_map2registration1() {
         let mut map = Map::new();
@@ -301,29 +1835,79 @@ mod tests {
         map.assign("zip_code", Value::String("12345".into())).unwrap();
         map.assign("city", Value::String("some_city".into())).unwrap();
         map.assign("phone", Value::String("1234567890".into())).unwrap();
+        map.assign("mobile", Value::String("0171 / 123 45 67".into())).unwrap();
         map.assign("email_to", Value::String("bob@smith.com".into())).unwrap();
+        map.assign("confirmation_cc", Value::String("secretary@smith.com".into())).unwrap();
+        map.assign("country", Value::String("DE".into())).unwrap();
         map.assign("more_info", Value::String("Some more information".into())).unwrap();
+        map.assign("orcid", Value::String("https://orcid.org/0000-0002-1825-0097".into())).unwrap();
         map.assign("price_category", Value::String("student".into())).unwrap();
         map.assign("course_type", Value::String("course1".into())).unwrap();
 
-        let result = map2registration(map).unwrap();
+        let result = map2registration(map, &example_configuration()).unwrap();
         let expected = Registration{
             title: Title::Sir,
             last_name: "Smith".to_string(),
             first_name: "Bob".to_string(),
+            preferred_name: "".to_string(),
+            pronouns: "".to_string(),
             institution: "Some university".to_string(),
             street: "some_street".to_string(),
             street_no: "12".to_string(),
             zip_code: "12345".to_string(),
             city: "some_city".to_string(),
             phone: "1234567890".to_string(),
+            mobile: "+491711234567".to_string(),
             email_to: "bob@smith.com".to_string(),
+            confirmation_cc: "secretary@smith.com".to_string(),
+            country: "DE".to_string(),
             more_info: "Some more information".to_string(),
+            orcid: "0000-0002-1825-0097".to_string(),
+            tshirt_size: "".to_string(),
+            emergency_name: "".to_string(),
+            emergency_phone: "".to_string(),
+            arrival_mode: "".to_string(),
+            arrival_number: "".to_string(),
+            arrival_time: "".to_string(),
+            share_ride: false,
+            children: 0,
+            children_ages: "".to_string(),
+            child_care_days: "".to_string(),
+            accessibility_options: "".to_string(),
+            accessibility_needs: "".to_string(),
+            code_of_conduct: "".to_string(),
+            photo_consent: false,
+            recording_consent: false,
+            newsletter: false,
+            social_events: Vec::new(),
+            banquet_guests: 0,
+            room_sharing: "".to_string(),
+            roommate_email: "".to_string(),
+            fee_waiver_reason: "".to_string(),
+            presentation: Presentation::None,
+            presentation_title: "".to_string(),
+            presentation_authors: "".to_string(),
+            abstract_text: "".to_string(),
+            talk_duration: 0,
+            talk_equipment: "".to_string(),
+            unavailable_days: "".to_string(),
+            topics: Vec::new(),
+            fee: 0,
             price_category: PriceCategory::Student,
             course_type: Course::Course1
         };
 
         assert_eq!(result, expected);
+
+        // Serialized with the keys of the form, as the hooks get it
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["title"], json!("sir"));
+        assert_eq!(json["presentation"], json!("none"));
+        assert_eq!(json["price_category"], json!("student"));
+        assert_eq!(json["course_type"], json!("course1"));
+        assert_eq!(json["last_name"], json!("Smith"));
+        assert_eq!(json["topics"], json!([]));
+        assert_eq!(json["fee"], json!(0));
     }
 
     #[test]
@@ -332,6 +1916,8 @@ mod tests {
         map.assign("title", Value::String("madam".into())).unwrap();
         map.assign("last_name", Value::String("Smith".into())).unwrap();
         map.assign("first_name", Value::String("Alice".into())).unwrap();
+        map.assign("preferred_name", Value::String(" Ali ".into())).unwrap();
+        map.assign("pronouns", Value::String("she/her".into())).unwrap();
         map.assign("institution", Value::String("Some university".into())).unwrap();
         map.assign("street", Value::String("some_street".into())).unwrap();
         map.assign("street_no", Value::String("15".into())).unwrap();
@@ -339,23 +1925,60 @@ mod tests {
         map.assign("city", Value::String("some_city".into())).unwrap();
         map.assign("phone", Value::String("999999999".into())).unwrap();
         map.assign("email_to", Value::String("alice@smith.com".into())).unwrap();
+        map.assign("country", Value::String("at".into())).unwrap();
         map.assign("more_info", Value::String("Some more information".into())).unwrap();
         map.assign("price_category", Value::String("student".into())).unwrap();
         map.assign("course_type", Value::String("course1".into())).unwrap();
 
-        let result = map2registration(map).unwrap();
+        let result = map2registration(map, &example_configuration()).unwrap();
         let expected = Registration{
             title: Title::Madam,
             last_name: "Smith".to_string(),
             first_name: "Alice".to_string(),
+            preferred_name: "Ali".to_string(),
+            pronouns: "she/her".to_string(),
             institution: "Some university".to_string(),
             street: "some_street".to_string(),
             street_no: "15".to_string(),
             zip_code: "11111".to_string(),
             city: "some_city".to_string(),
             phone: "999999999".to_string(),
+            mobile: "".to_string(),
             email_to: "alice@smith.com".to_string(),
+            confirmation_cc: "".to_string(),
+            country: "AT".to_string(),
             more_info: "Some more information".to_string(),
+            orcid: "".to_string(),
+            tshirt_size: "".to_string(),
+            emergency_name: "".to_string(),
+            emergency_phone: "".to_string(),
+            arrival_mode: "".to_string(),
+            arrival_number: "".to_string(),
+            arrival_time: "".to_string(),
+            share_ride: false,
+            children: 0,
+            children_ages: "".to_string(),
+            child_care_days: "".to_string(),
+            accessibility_options: "".to_string(),
+            accessibility_needs: "".to_string(),
+            code_of_conduct: "".to_string(),
+            photo_consent: false,
+            recording_consent: false,
+            newsletter: false,
+            social_events: Vec::new(),
+            banquet_guests: 0,
+            room_sharing: "".to_string(),
+            roommate_email: "".to_string(),
+            fee_waiver_reason: "".to_string(),
+            presentation: Presentation::None,
+            presentation_title: "".to_string(),
+            presentation_authors: "".to_string(),
+            abstract_text: "".to_string(),
+            talk_duration: 0,
+            talk_equipment: "".to_string(),
+            unavailable_days: "".to_string(),
+            topics: Vec::new(),
+            fee: 0,
             price_category: PriceCategory::Student,
             course_type: Course::Course1
         };
@@ -375,24 +1998,62 @@ mod tests {
         map.assign("zip_code", Value::String("12345".into())).unwrap();
         map.assign("city", Value::String("some_city".into())).unwrap();
         map.assign("phone", Value::String("1234567890".into())).unwrap();
+        map.assign("mobile", Value::String("only at the reception".into())).unwrap();
         map.assign("email_to", Value::String("bob@smith.com".into())).unwrap();
+        map.assign("country", Value::String("DE".into())).unwrap();
         map.assign("more_info", Value::String("Some more information".into())).unwrap();
         map.assign("price_category", Value::String("regular".into())).unwrap();
         map.assign("course_type", Value::String("course1".into())).unwrap();
 
-        let result = map2registration(map).unwrap();
+        let result = map2registration(map, &example_configuration()).unwrap();
         let expected = Registration{
             title: Title::Sir,
             last_name: "Brown".to_string(),
             first_name: "Tim".to_string(),
+            preferred_name: "".to_string(),
+            pronouns: "".to_string(),
             institution: "Some university".to_string(),
             street: "some_street".to_string(),
             street_no: "12".to_string(),
             zip_code: "12345".to_string(),
             city: "some_city".to_string(),
             phone: "1234567890".to_string(),
+            mobile: "only at the reception".to_string(),
             email_to: "bob@smith.com".to_string(),
+            confirmation_cc: "".to_string(),
+            country: "DE".to_string(),
             more_info: "Some more information".to_string(),
+            orcid: "".to_string(),
+            tshirt_size: "".to_string(),
+            emergency_name: "".to_string(),
+            emergency_phone: "".to_string(),
+            arrival_mode: "".to_string(),
+            arrival_number: "".to_string(),
+            arrival_time: "".to_string(),
+            share_ride: false,
+            children: 0,
+            children_ages: "".to_string(),
+            child_care_days: "".to_string(),
+            accessibility_options: "".to_string(),
+            accessibility_needs: "".to_string(),
+            code_of_conduct: "".to_string(),
+            photo_consent: false,
+            recording_consent: false,
+            newsletter: false,
+            social_events: Vec::new(),
+            banquet_guests: 0,
+            room_sharing: "".to_string(),
+            roommate_email: "".to_string(),
+            fee_waiver_reason: "".to_string(),
+            presentation: Presentation::None,
+            presentation_title: "".to_string(),
+            presentation_authors: "".to_string(),
+            abstract_text: "".to_string(),
+            talk_duration: 0,
+            talk_equipment: "".to_string(),
+            unavailable_days: "".to_string(),
+            topics: Vec::new(),
+            fee: 0,
             price_category: PriceCategory::Regular,
             course_type: Course::Course1
         };
@@ -413,23 +2074,65 @@ mod tests {
         map.assign("city", Value::String("some_city".into())).unwrap();
         map.assign("phone", Value::String("1234567890".into())).unwrap();
         map.assign("email_to", Value::String("bob@smith.com".into())).unwrap();
+        map.assign("country", Value::String("DE".into())).unwrap();
         map.assign("more_info", Value::String("Some more information".into())).unwrap();
         map.assign("price_category", Value::String("student".into())).unwrap();
         map.assign("course_type", Value::String("course2".into())).unwrap();
+        map.assign("arrival_mode", Value::String("plane".into())).unwrap();
+        map.assign("arrival_number", Value::String("LH 123".into())).unwrap();
+        map.assign("arrival_time", Value::String("2017-03-28T09:45".into())).unwrap();
+        map.assign("share_ride", Value::String("on".into())).unwrap();
+        map.assign("recording_consent", Value::String("yes".into())).unwrap();
 
-        let result = map2registration(map).unwrap();
+        let result = map2registration(map, &example_configuration()).unwrap();
         let expected = Registration{
             title: Title::Sir,
             last_name: "Smith".to_string(),
             first_name: "Bob".to_string(),
+            preferred_name: "".to_string(),
+            pronouns: "".to_string(),
             institution: "Some university".to_string(),
             street: "some_street".to_string(),
             street_no: "12".to_string(),
             zip_code: "12345".to_string(),
             city: "some_city".to_string(),
             phone: "1234567890".to_string(),
+            mobile: "".to_string(),
             email_to: "bob@smith.com".to_string(),
+            confirmation_cc: "".to_string(),
+            country: "DE".to_string(),
             more_info: "Some more information".to_string(),
+            orcid: "".to_string(),
+            tshirt_size: "".to_string(),
+            emergency_name: "".to_string(),
+            emergency_phone: "".to_string(),
+            arrival_mode: "plane".to_string(),
+            arrival_number: "LH 123".to_string(),
+            arrival_time: "2017-03-28 09:45".to_string(),
+            share_ride: true,
+            children: 0,
+            children_ages: "".to_string(),
+            child_care_days: "".to_string(),
+            accessibility_options: "".to_string(),
+            accessibility_needs: "".to_string(),
+            code_of_conduct: "".to_string(),
+            photo_consent: false,
+            recording_consent: true,
+            newsletter: false,
+            social_events: Vec::new(),
+            banquet_guests: 0,
+            room_sharing: "".to_string(),
+            roommate_email: "".to_string(),
+            fee_waiver_reason: "".to_string(),
+            presentation: Presentation::None,
+            presentation_title: "".to_string(),
+            presentation_authors: "".to_string(),
+            abstract_text: "".to_string(),
+            talk_duration: 0,
+            talk_equipment: "".to_string(),
+            unavailable_days: "".to_string(),
+            topics: Vec::new(),
+            fee: 0,
             price_category: PriceCategory::Student,
             course_type: Course::Course2
         };
@@ -437,6 +2140,387 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn test_map2registration5() {
+        let mut map = Map::new();
+        map.assign("title", Value::String("sir".into())).unwrap();
+        map.assign("last_name", Value::String("Smith".into())).unwrap();
+        map.assign("first_name", Value::String("Bob".into())).unwrap();
+        map.assign("institution", Value::String("Some university".into())).unwrap();
+        map.assign("street", Value::String("some_street".into())).unwrap();
+        map.assign("street_no", Value::String("12".into())).unwrap();
+        map.assign("zip_code", Value::String("12345".into())).unwrap();
+        map.assign("city", Value::String("some_city".into())).unwrap();
+        map.assign("phone", Value::String("1234567890".into())).unwrap();
+        map.assign("email_to", Value::String("bob@smith.com".into())).unwrap();
+        map.assign("country", Value::String("DE".into())).unwrap();
+        map.assign("more_info", Value::String("Some more information".into())).unwrap();
+        map.assign("orcid", Value::String("0000-0002-1825-0098".into())).unwrap();
+        map.assign("price_category", Value::String("student".into())).unwrap();
+        map.assign("course_type", Value::String("course2".into())).unwrap();
+
+        match map2registration(map, &example_configuration()) {
+            Err(HandleError::Validation(field)) => assert_eq!(field, "orcid".to_string()),
+            result => panic!("Unexpected result: {:?}", result)
+        }
+    }
+
+    #[test]
+    fn test_map2registration_unknown_choice() {
+        let mut map = Map::new();
+        map.assign("title", Value::String("sri".into())).unwrap();
+        map.assign("last_name", Value::String("Smith".into())).unwrap();
+        map.assign("first_name", Value::String("Bob".into())).unwrap();
+        map.assign("institution", Value::String("Some university".into())).unwrap();
+        map.assign("street", Value::String("some_street".into())).unwrap();
+        map.assign("street_no", Value::String("12".into())).unwrap();
+        map.assign("zip_code", Value::String("12345".into())).unwrap();
+        map.assign("city", Value::String("some_city".into())).unwrap();
+        map.assign("phone", Value::String("1234567890".into())).unwrap();
+        map.assign("email_to", Value::String("bob@smith.com".into())).unwrap();
+        map.assign("country", Value::String("DE".into())).unwrap();
+        map.assign("more_info", Value::String("Some more information".into())).unwrap();
+                map.assign("price_category", Value::String("student".into())).unwrap();
+        map.assign("course_type", Value::String("course2".into())).unwrap();
+
+        match map2registration(map.clone(), &example_configuration()) {
+            Err(HandleError::Validation(field)) => assert_eq!(field, "title".to_string()),
+            result => panic!("Unexpected result: {:?}", result)
+        }
+
+        let mut config = example_configuration();
+        config.lenient_choices = true;
+        assert_eq!(map2registration(map, &config).unwrap().title, Title::Madam);
+    }
+
+    #[test]
+    fn test_map2registration6() {
+        let mut map = Map::new();
+        map.assign("title", Value::String("madam".into())).unwrap();
+        map.assign("last_name", Value::String("Smith".into())).unwrap();
+        map.assign("first_name", Value::String("Alice".into())).unwrap();
+        map.assign("institution", Value::String("Some university".into())).unwrap();
+        map.assign("street", Value::String("some_street".into())).unwrap();
+        map.assign("street_no", Value::String("15".into())).unwrap();
+        map.assign("zip_code", Value::String("11111".into())).unwrap();
+        map.assign("city", Value::String("some_city".into())).unwrap();
+        map.assign("phone", Value::String("999999999".into())).unwrap();
+        map.assign("email_to", Value::String("alice@smith.com".into())).unwrap();
+        map.assign("country", Value::String("Germany".into())).unwrap();
+        map.assign("more_info", Value::String("Some more information".into())).unwrap();
+        map.assign("price_category", Value::String("student".into())).unwrap();
+        map.assign("course_type", Value::String("course1".into())).unwrap();
+
+        match map2registration(map, &example_configuration()) {
+            Err(HandleError::Validation(field)) => assert_eq!(field, "country".to_string()),
+            result => panic!("Unexpected result: {:?}", result)
+        }
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        assert_eq!(Title::try_from("sir"), Ok(Title::Sir));
+        assert_eq!(Course::try_from("course2"), Ok(Course::Course2));
+        assert_eq!(Presentation::try_from("poster"), Ok(Presentation::Poster));
+        assert_eq!(PriceCategory::try_from("Student"), Err(UnknownChoice { field: "price_category", value: "Student".to_string() }));
+    }
+
+    #[test]
+    fn test_extract_confirmation_cc() {
+        let mut map = Map::new();
+        assert_eq!(extract_confirmation_cc(&map).unwrap(), "".to_string());
+
+        map.assign("confirmation_cc", Value::String(" secretary@smith.com ".into())).unwrap();
+        assert_eq!(extract_confirmation_cc(&map).unwrap(), "secretary@smith.com".to_string());
+
+        map.assign("confirmation_cc", Value::String("secretary".into())).unwrap();
+        assert!(extract_confirmation_cc(&map).is_err());
+        map.assign("confirmation_cc", Value::String("secretary@smith".into())).unwrap();
+        assert!(extract_confirmation_cc(&map).is_err());
+    }
+
+    #[test]
+    fn test_extract_email_to() {
+        let mut map = Map::new();
+        assert!(extract_email_to(&map).is_err());
+
+        map.assign("email_to", Value::String(" bob@smith.com ".into())).unwrap();
+        assert_eq!(extract_email_to(&map).unwrap(), "bob@smith.com".to_string());
+
+        for invalid in &["bob", "bob@smith", "bob@@smith.com", "bob smith@smith.com", "bob@smith..com", "bob@smith.com, alice@smith.com"] {
+            map.assign("email_to", Value::String(invalid.to_string())).unwrap();
+            match extract_email_to(&map) {
+                Err(HandleError::Validation(field)) => assert_eq!(field, "email_to".to_string()),
+                result => panic!("Unexpected result for {}: {:?}", invalid, result)
+            }
+        }
+    }
+
+    #[test]
+    fn test_extract_tshirt_size() {
+        let mut config = example_configuration();
+        let mut map = Map::new();
+        map.assign("tshirt_size", Value::String("M".into())).unwrap();
+
+        assert!(extract_tshirt_size(&map, &config).is_err());
+
+        config.tshirt_sizes = vec!["S".to_string(), "M".to_string()];
+        assert_eq!(extract_tshirt_size(&map, &config).unwrap(), "M".to_string());
+        assert_eq!(extract_tshirt_size(&Map::new(), &config).unwrap(), "".to_string());
+
+        map.assign("tshirt_size", Value::String("XXXL".into())).unwrap();
+        assert!(extract_tshirt_size(&map, &config).is_err());
+    }
+
+    #[test]
+    fn test_extract_emergency_contact() {
+        let mut config = example_configuration();
+        let mut map = Map::new();
+        map.assign("emergency_name", Value::String("Alice Smith".into())).unwrap();
+
+        assert_eq!(extract_emergency_contact(&map, &Course::Course2, &config).unwrap(), ("".to_string(), "".to_string()));
+
+        config.field_trips = vec!["course2".to_string()];
+        assert_eq!(extract_emergency_contact(&map, &Course::Course1, &config).unwrap(), ("".to_string(), "".to_string()));
+
+        match extract_emergency_contact(&map, &Course::Course2, &config) {
+            Err(HandleError::Validation(field)) => assert_eq!(field, "emergency_phone".to_string()),
+            result => panic!("Unexpected result: {:?}", result)
+        }
+
+        map.assign("emergency_phone", Value::String("07071 1234".into())).unwrap();
+        assert_eq!(extract_emergency_contact(&map, &Course::Course2, &config).unwrap(), ("Alice Smith".to_string(), "+4970711234".to_string()));
+    }
+
+    #[test]
+    fn test_extract_child_care() {
+        let mut config = example_configuration();
+        let mut map = Map::new();
+        map.assign("children", Value::String("2".into())).unwrap();
+        map.assign("children_ages", Value::String("3, 5".into())).unwrap();
+        map.assign("child_care_days[]", Value::String("Tuesday".into())).unwrap();
+        map.assign("child_care_days[]", Value::String("Monday".into())).unwrap();
+
+        // Not offered
+        assert_eq!(extract_child_care(&map, &config).unwrap(), (0, "".to_string(), "".to_string()));
+
+        config.child_care_days = vec!["Monday".to_string(), "Tuesday".to_string(), "Wednesday".to_string()];
+        assert_eq!(extract_child_care(&map, &config).unwrap(), (2, "3, 5".to_string(), "Monday, Tuesday".to_string()));
+        assert_eq!(extract_child_care(&Map::new(), &config).unwrap(), (0, "".to_string(), "".to_string()));
+
+        map.assign("child_care_days[]", Value::String("Sunday".into())).unwrap();
+        assert!(extract_child_care(&map, &config).is_err());
+
+        let mut map = Map::new();
+        map.assign("children", Value::String("1".into())).unwrap();
+        assert!(extract_child_care(&map, &config).is_err());
+
+        let mut map = Map::new();
+        map.assign("children", Value::String("eleven".into())).unwrap();
+        assert!(extract_child_care(&map, &config).is_err());
+    }
+
+    #[test]
+    fn test_extract_accessibility_options() {
+        let mut map = Map::new();
+        map.assign("accessibility[]", Value::String("sign_language".into())).unwrap();
+        map.assign("accessibility[]", Value::String("step_free".into())).unwrap();
+
+        assert_eq!(extract_accessibility_options(&map).unwrap(), "step_free,sign_language".to_string());
+        assert_eq!(extract_accessibility_options(&Map::new()).unwrap(), "".to_string());
+
+        map.assign("accessibility[]", Value::String("jetpack".into())).unwrap();
+        assert!(extract_accessibility_options(&map).is_err());
+    }
+
+    #[test]
+    fn test_extract_code_of_conduct() {
+        let mut config = example_configuration();
+        let mut map = Map::new();
+
+        assert_eq!(extract_code_of_conduct(&map, &config).unwrap(), "".to_string());
+
+        config.code_of_conduct_version = "2017-01".to_string();
+        assert!(extract_code_of_conduct(&map, &config).is_err());
+
+        map.assign("code_of_conduct", Value::String("yes".into())).unwrap();
+        assert_eq!(extract_code_of_conduct(&map, &config).unwrap(), "2017-01".to_string());
+    }
+
+    #[test]
+    fn test_extract_social_events() {
+        let mut config = example_configuration();
+        config.social_events = vec![
+            SocialEvent { key: "hike".to_string(), name: "Hike".to_string(), capacity: 1, price: 0 },
+            SocialEvent { key: "banquet".to_string(), name: "Banquet".to_string(), capacity: 0, price: 4500 }
+        ];
+
+        let mut map = Map::new();
+        map.assign("social_events[]", Value::String("banquet".into())).unwrap();
+        map.assign("social_events[]", Value::String("hike".into())).unwrap();
+        assert_eq!(extract_social_events(&map, &config).unwrap(), vec!["hike".to_string(), "banquet".to_string()]);
+
+        map.assign("social_events[]", Value::String("opera".into())).unwrap();
+        assert!(extract_social_events(&map, &config).is_err());
+
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+        let events = vec!["hike".to_string(), "banquet".to_string()];
+
+        assert!(check_social_capacity(&conn, &events, &config).is_ok());
+        book_social_event(&conn, 1, "hike").unwrap();
+        book_social_event(&conn, 2, "banquet").unwrap();
+        match check_social_capacity(&conn, &events, &config) {
+            Err(HandleError::SoldOut(name)) => assert_eq!(name, "Hike".to_string()),
+            _ => panic!("Hike should be sold out")
+        }
+        assert!(check_social_capacity(&conn, &["banquet".to_string()], &config).is_ok());
+    }
+
+    #[test]
+    fn test_extract_banquet_guests() {
+        let mut config = example_configuration();
+        let mut map = Map::new();
+        map.assign("banquet_guests", Value::String("2".into())).unwrap();
+
+        assert_eq!(extract_banquet_guests(&map, &config).unwrap(), 0);
+
+        config.banquet_max_guests = 4;
+        assert_eq!(extract_banquet_guests(&map, &config).unwrap(), 2);
+        assert_eq!(extract_banquet_guests(&Map::new(), &config).unwrap(), 0);
+
+        map.assign("banquet_guests", Value::String("5".into())).unwrap();
+        assert!(extract_banquet_guests(&map, &config).is_err());
+    }
+
+    #[test]
+    fn test_extract_room_sharing() {
+        let mut map = Map::new();
+        map.assign("room_sharing", Value::String("shared".into())).unwrap();
+        map.assign("roommate_email", Value::String(" Alice@Smith.com ".into())).unwrap();
+
+        assert_eq!(extract_room_sharing(&map).unwrap(), ("shared".to_string(), "alice@smith.com".to_string()));
+        assert_eq!(extract_room_sharing(&Map::new()).unwrap(), ("".to_string(), "".to_string()));
+
+        map.assign("room_sharing", Value::String("single".into())).unwrap();
+        assert_eq!(extract_room_sharing(&map).unwrap(), ("single".to_string(), "".to_string()));
+
+        map.assign("room_sharing", Value::String("shared".into())).unwrap();
+        map.assign("roommate_email", Value::String("Alice Smith".into())).unwrap();
+        assert!(extract_room_sharing(&map).is_err());
+
+        map.assign("room_sharing", Value::String("suite".into())).unwrap();
+        assert!(extract_room_sharing(&map).is_err());
+    }
+
+    #[test]
+    fn test_extract_fee_waiver() {
+        let mut map = Map::new();
+        map.assign("fee_waiver_reason", Value::String("Unemployed".into())).unwrap();
+
+        assert_eq!(extract_fee_waiver(&map).unwrap(), "".to_string());
+
+        map.assign("fee_waiver", Value::String("yes".into())).unwrap();
+        assert_eq!(extract_fee_waiver(&map).unwrap(), "Unemployed".to_string());
+
+        let mut map = Map::new();
+        map.assign("fee_waiver", Value::String("yes".into())).unwrap();
+        assert!(extract_fee_waiver(&map).is_err());
+    }
+
+    #[test]
+    fn test_extract_presentation() {
+        let mut config = example_configuration();
+        let mut map = Map::new();
+        assert_eq!(extract_presentation(&map, &config).unwrap(), (Presentation::None, "".to_string(), "".to_string(), "".to_string()));
+
+        // Leftovers from switching back to "none" in the form are dropped
+        map.assign("presentation", Value::String("none".into())).unwrap();
+        map.assign("presentation_title", Value::String("Plate tectonics".into())).unwrap();
+        assert_eq!(extract_presentation(&map, &config).unwrap(), (Presentation::None, "".to_string(), "".to_string(), "".to_string()));
+
+        map.assign("presentation", Value::String("talk".into())).unwrap();
+        assert!(extract_presentation(&map, &config).is_err());
+
+        map.assign("presentation_authors", Value::String("Bob Smith\nAlice Brown".into())).unwrap();
+        map.assign("abstract_text", Value::String("We show that...".into())).unwrap();
+        assert_eq!(extract_presentation(&map, &config).unwrap(), (Presentation::Talk, "Plate tectonics".to_string(),
+            "Bob Smith\nAlice Brown".to_string(), "We show that...".to_string()));
+
+        map.assign("presentation", Value::String("keynote".into())).unwrap();
+        assert!(extract_presentation(&map, &config).is_err());
+
+        // Talks are valid keys, but not offered in this form
+        config.presentations = vec!["poster".to_string()];
+        map.assign("presentation", Value::String("talk".into())).unwrap();
+        match extract_presentation(&map, &config) {
+            Err(HandleError::Validation(field)) => assert_eq!(field, "presentation".to_string()),
+            _ => panic!("talk must not be accepted")
+        }
+    }
+
+    #[test]
+    fn test_extract_talk_preferences() {
+        let mut config = example_configuration();
+        config.sessions = vec![Session {
+            key: "geo1".to_string(),
+            name: "Geodynamics".to_string(),
+            day: "2017-03-01".to_string(),
+            start: "09:00".to_string(),
+            end: "10:30".to_string(),
+            room: "".to_string()
+        }];
+
+        let mut map = Map::new();
+        map.assign("talk_duration", Value::String("20".into())).unwrap();
+        map.assign("talk_equipment[]", Value::String("whiteboard".into())).unwrap();
+        map.assign("talk_equipment[]", Value::String("audio".into())).unwrap();
+        map.assign("unavailable_days[]", Value::String("2017-03-01".into())).unwrap();
+
+        assert_eq!(extract_talk_preferences(&map, &Presentation::Talk, &config).unwrap(),
+            (20, "audio,whiteboard".to_string(), "2017-03-01".to_string()));
+        assert_eq!(extract_talk_preferences(&map, &Presentation::Poster, &config).unwrap(), (0, "".to_string(), "".to_string()));
+
+        map.assign("talk_duration", Value::String("90".into())).unwrap();
+        assert!(extract_talk_preferences(&map, &Presentation::Talk, &config).is_err());
+
+        map.assign("talk_duration", Value::String("".into())).unwrap();
+        map.assign("unavailable_days[]", Value::String("2017-03-05".into())).unwrap();
+        assert!(extract_talk_preferences(&map, &Presentation::Talk, &config).is_err());
+    }
+
+    #[test]
+    fn test_extract_topics() {
+        let mut config = example_configuration();
+        let mut map = Map::new();
+
+        assert_eq!(extract_topics(&map, &Presentation::Talk, &config).unwrap(), Vec::<String>::new());
+
+        config.topics = vec!["Geodynamics".to_string(), "Seismology".to_string()];
+        assert!(extract_topics(&map, &Presentation::Talk, &config).is_err());
+        assert_eq!(extract_topics(&map, &Presentation::None, &config).unwrap(), Vec::<String>::new());
+
+        map.assign("topics[]", Value::String("Seismology".into())).unwrap();
+        map.assign("topics[]", Value::String("Geodynamics".into())).unwrap();
+        assert_eq!(extract_topics(&map, &Presentation::Poster, &config).unwrap(), vec!["Geodynamics".to_string(), "Seismology".to_string()]);
+
+        map.assign("topics[]", Value::String("Astrology".into())).unwrap();
+        assert!(extract_topics(&map, &Presentation::Poster, &config).is_err());
+    }
+
+    #[test]
+    fn test_extract_arrival() {
+        let mut map = Map::new();
+        map.assign("arrival_mode", Value::String("bicycle".into())).unwrap();
+        map.assign("arrival_time", Value::String("28.03.2017 09:45".into())).unwrap();
+
+        assert!(extract_arrival_mode(&map).is_err());
+        assert!(extract_arrival_time(&map).is_err());
+        assert_eq!(extract_arrival_mode(&Map::new()).unwrap(), "".to_string());
+        assert_eq!(extract_arrival_time(&Map::new()).unwrap(), "".to_string());
+        assert!(!extract_checkbox(&Map::new(), "share_ride"));
+    }
+
     #[test]
     fn test_insert_into_db1() {
         let conn = Connection::open_in_memory().unwrap();
@@ -444,36 +2528,57 @@ mod tests {
             title: Title::Sir,
             last_name: "Smith".to_string(),
             first_name: "Bob".to_string(),
+            preferred_name: "Bobby".to_string(),
+            pronouns: "he/him".to_string(),
             institution: "Some university".to_string(),
             street: "Somestreet".to_string(),
             street_no: "15".to_string(),
             zip_code: "12345".to_string(),
             city: "Somewhere".to_string(),
             phone: "123456789".to_string(),
+            mobile: "+491711234567".to_string(),
             email_to: "bob.smith@somewhere.com".to_string(),
+            confirmation_cc: "Secretary@Somewhere.com".to_string(),
+            country: "DE".to_string(),
             more_info: "Some more information".to_string(),
+            orcid: "0000-0002-1694-233X".to_string(),
+            tshirt_size: "XL".to_string(),
+            emergency_name: "Alice Smith".to_string(),
+            emergency_phone: "+4970711234".to_string(),
+            arrival_mode: "train".to_string(),
+            arrival_number: "ICE 597".to_string(),
+            arrival_time: "2017-03-28 14:10".to_string(),
+            share_ride: true,
+            children: 2,
+            children_ages: "3, 5".to_string(),
+            child_care_days: "Monday, Tuesday".to_string(),
+            accessibility_options: "step_free".to_string(),
+            accessibility_needs: "Hearing loop".to_string(),
+            code_of_conduct: "2017-01".to_string(),
+            photo_consent: true,
+            recording_consent: false,
+            newsletter: true,
+            social_events: vec!["hike".to_string()],
+            banquet_guests: 2,
+            room_sharing: "shared".to_string(),
+            roommate_email: "alice@smith.com".to_string(),
+            fee_waiver_reason: "Unemployed".to_string(),
+            presentation: Presentation::Poster,
+            presentation_title: "Plate tectonics".to_string(),
+            presentation_authors: "Bob Smith".to_string(),
+            abstract_text: "We show that...".to_string(),
+            talk_duration: 0,
+            talk_equipment: "".to_string(),
+            unavailable_days: "".to_string(),
+            topics: vec!["Geodynamics".to_string()],
+            fee: 12000,
             price_category: PriceCategory::Student,
             course_type: Course::Course1
         };
 
-        conn.execute("CREATE TABLE registration (
-                  id              INTEGER PRIMARY KEY,
-                  title           TEXT NOT NULL,
-                  last_name       TEXT NOT NULL,
-                  first_name      TEXT NOT NULL,
-                  institution     TEXT NOT NULL,
-                  street          TEXT NOT NULL,
-                  street_no       TEXT NOT NULL,
-                  zip_code        TEXT NOT NULL,
-                  city            TEXT NOT NULL,
-                  phone           TEXT NOT NULL,
-                  email_to        TEXT NOT NULL,
-                  more_info       TEXT NOT NULL,
-                  price_category  TEXT NOT NULL,
-                  course_type     TEXT NOT NULL
-                  )", &[]).unwrap();
-
-        assert!(insert_into_db(&conn, &reg).is_ok());
+        init_database(&conn).unwrap();
+
+        let token = insert_into_db(&conn, &reg, RegistrationStatus::Confirmed, "{\"last_name\":\"Smith \"}", "form1").unwrap();
 
         let mut stmt = conn.prepare("SELECT * FROM registration").unwrap();
         let mut rows = stmt.query(&[]).unwrap();
@@ -493,28 +2598,203 @@ mod tests {
         assert_eq!(result.get::<i32, String>(11), "Some more information");
         assert_eq!(result.get::<i32, String>(12), "student");
         assert_eq!(result.get::<i32, String>(13), "course1");
+        assert_eq!(result.get::<i32, String>(14), "bob.smith@somewhere.com");
+        assert_eq!(result.get::<i32, String>(15), "0000-0002-1694-233X");
+        assert_eq!(result.get::<i32, String>(16), "DE");
+        assert_eq!(result.get::<i32, String>(17), "+491711234567");
+        assert_eq!(result.get::<i32, String>(18), "Bobby");
+        assert_eq!(result.get::<i32, String>(19), "he/him");
+        assert_eq!(result.get::<i32, String>(20), "XL");
+        assert_eq!(result.get::<i32, String>(21), "Alice Smith");
+        assert_eq!(result.get::<i32, String>(22), "+4970711234");
+        assert_eq!(result.get::<i32, String>(23), token);
+        assert_eq!(result.get::<i32, String>(24), "train");
+        assert_eq!(result.get::<i32, String>(25), "ICE 597");
+        assert_eq!(result.get::<i32, String>(26), "2017-03-28 14:10");
+        assert!(result.get::<i32, bool>(27));
+        assert_eq!(result.get::<i32, i64>(28), 2);
+        assert_eq!(result.get::<i32, String>(29), "3, 5");
+        assert_eq!(result.get::<i32, String>(30), "Monday, Tuesday");
+        assert_eq!(result.get::<i32, String>(31), "step_free");
+        assert_eq!(result.get::<i32, String>(32), "Hearing loop");
+        assert_eq!(result.get::<i32, String>(33), "2017-01");
+        assert_eq!(result.get::<i32, String>(34).len(), "2017-01-01 12:00:00".len());
+        assert!(result.get::<i32, bool>(35));
+        assert!(!result.get::<i32, bool>(36));
+
+        assert_eq!(result.get::<i32, i64>(37), 12000);
+        assert_eq!(result.get::<i32, i64>(38), 2);
+        assert_eq!(result.get::<i32, String>(39), "shared");
+        assert_eq!(result.get::<i32, String>(40), "alice@smith.com");
+        assert_eq!(result.get::<i32, String>(41), "Unemployed");
+        assert_eq!(result.get::<i32, String>(42), "requested");
+        assert_eq!(result.get::<i32, String>(43), "poster");
+        assert_eq!(result.get::<i32, String>(44), "Plate tectonics");
+        assert_eq!(result.get::<i32, String>(45), "Bob Smith");
+        assert_eq!(result.get::<i32, String>(46), "We show that...");
+
+        let form_data: String = conn.query_row("SELECT form_data FROM registration WHERE id = 1", &[], |row| row.get(0)).unwrap();
+        assert_eq!(form_data, "{\"last_name\":\"Smith \"}".to_string());
+        assert_eq!(find_by_form_token(&conn, "form1").unwrap(), Some(1));
+        assert_eq!(find_by_form_token(&conn, "form2").unwrap(), None);
+
+        let topic: String = conn.query_row("SELECT topic FROM presentation_topic WHERE registration_id = 1", &[], |row| row.get(0)).unwrap();
+        assert_eq!(topic, "Geodynamics".to_string());
+        assert_eq!(count_social_bookings(&conn).unwrap(), vec![("hike".to_string(), 1)]);
+
+        let subscribers = load_newsletter_subscribers(&conn).unwrap();
+        assert_eq!(subscribers.len(), 1);
+        assert_eq!(subscribers[0].email, "bob.smith@somewhere.com".to_string());
+
+        assert_eq!(load_registration(&conn, 1).unwrap(), Some((reg, token)));
+        assert_eq!(load_registration(&conn, 2).unwrap(), None);
+    }
+
+    #[test]
+    fn test_check_duplicate_email() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+        assert!(check_duplicate_email(&conn, "bob@smith.com").is_ok());
+
+        conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                  city, phone, email_to, email_normalized, more_info, price_category, course_type, status)
+                  VALUES ('sir', 'Smith', 'Bob', 'Uni', 'Street', '1', '12345', 'City', '123', 'Bob@Smith.com', 'bob@smith.com', '', 'student', 'course1', 'cancelled')",
+                  &[]).unwrap();
+        assert!(check_duplicate_email(&conn, "bob@smith.com").is_ok());
+
+        conn.execute("UPDATE registration SET status = 'waitlisted'", &[]).unwrap();
+        match check_duplicate_email(&conn, " BOB@smith.com") {
+            Err(HandleError::AlreadyRegistered) => (),
+            result => panic!("Unexpected result: {:?}", result)
+        }
+        assert!(check_duplicate_email(&conn, "alice@smith.com").is_ok());
+    }
+
+    #[test]
+    fn test_initial_status() {
+        let mut config = example_configuration();
+        assert_eq!(initial_status(1000, &config), RegistrationStatus::Confirmed);
+
+        config.capacity = 250;
+        assert_eq!(initial_status(249, &config), RegistrationStatus::Confirmed);
+        assert_eq!(initial_status(250, &config), RegistrationStatus::Waitlisted);
+
+        config.approval = true;
+        assert_eq!(initial_status(10, &config), RegistrationStatus::Pending);
+        assert_eq!(initial_status(260, &config), RegistrationStatus::Waitlisted);
+    }
+
+    #[test]
+    fn test_update_registration() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+        let config = example_configuration();
+
+        let mut map = Map::new();
+        map.assign("title", Value::String("sir".into())).unwrap();
+        map.assign("last_name", Value::String("Smith".into())).unwrap();
+        map.assign("first_name", Value::String("Bob".into())).unwrap();
+        map.assign("institution", Value::String("Some university".into())).unwrap();
+        map.assign("street", Value::String("some_street".into())).unwrap();
+        map.assign("street_no", Value::String("12".into())).unwrap();
+        map.assign("zip_code", Value::String("12345".into())).unwrap();
+        map.assign("city", Value::String("some_city".into())).unwrap();
+        map.assign("phone", Value::String("1234567890".into())).unwrap();
+        map.assign("email_to", Value::String("bob@smiht.com".into())).unwrap();
+        map.assign("country", Value::String("DE".into())).unwrap();
+        map.assign("more_info", Value::String("".into())).unwrap();
+        map.assign("price_category", Value::String("student".into())).unwrap();
+        map.assign("course_type", Value::String("course1".into())).unwrap();
+
+        let reg = map2registration(map.clone(), &config).unwrap();
+        let token = insert_into_db(&conn, &reg, RegistrationStatus::Confirmed, "{}", "form1").unwrap();
+
+        map.assign("last_name", Value::String("Smyth".into())).unwrap();
+        map.assign("email_to", Value::String(" Bob@Smith.com".into())).unwrap();
+        let (mut corrected, _) = load_registration(&conn, 1).unwrap().unwrap();
+        apply_corrections(&map, &mut corrected, &config).unwrap();
+        update_registration(&conn, 1, &corrected).unwrap();
+
+        let (stored, stored_token) = load_registration(&conn, 1).unwrap().unwrap();
+        assert_eq!(stored.last_name, "Smyth".to_string());
+        assert_eq!(stored.email_to, "Bob@Smith.com".to_string());
+        assert_eq!(stored.price_category, PriceCategory::Student);
+        assert_eq!(stored_token, token);
+        let email_normalized: String = conn.query_row("SELECT email_normalized FROM registration WHERE id = 1", &[], |row| row.get(0)).unwrap();
+        assert_eq!(email_normalized, "bob@smith.com".to_string());
+
+        map.assign("email_to", Value::String("bob.smith.com".into())).unwrap();
+        match apply_corrections(&map, &mut corrected, &config) {
+            Err(HandleError::Validation(field)) => assert_eq!(field, "email_to".to_string()),
+            result => panic!("Unexpected result: {:?}", result)
+        }
+
+        match update_registration(&conn, 2, &reg) {
+            Err(HandleError::NotFound) => (),
+            result => panic!("Unexpected result: {:?}", result)
+        }
     }
 
     #[test]
     fn test_insert_into_db2() {
-        let conn = Connection::open("registration_database.sqlite3").unwrap();
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
         let reg = Registration {
             title: Title::Sir,
             last_name: "Smith".to_string(),
             first_name: "Bob".to_string(),
+            preferred_name: "".to_string(),
+            pronouns: "".to_string(),
             institution: "Some university".to_string(),
             street: "Somestreet".to_string(),
             street_no: "15".to_string(),
             zip_code: "12345".to_string(),
             city: "Somewhere".to_string(),
             phone: "123456789".to_string(),
+            mobile: "".to_string(),
             email_to: "bob.smith@somewhere.com".to_string(),
+            confirmation_cc: "".to_string(),
+            country: "DE".to_string(),
             more_info: "Some more information".to_string(),
+            orcid: "".to_string(),
+            tshirt_size: "".to_string(),
+            emergency_name: "".to_string(),
+            emergency_phone: "".to_string(),
+            arrival_mode: "".to_string(),
+            arrival_number: "".to_string(),
+            arrival_time: "".to_string(),
+            share_ride: false,
+            children: 0,
+            children_ages: "".to_string(),
+            child_care_days: "".to_string(),
+            accessibility_options: "".to_string(),
+            accessibility_needs: "".to_string(),
+            code_of_conduct: "".to_string(),
+            photo_consent: false,
+            recording_consent: false,
+            newsletter: false,
+            social_events: Vec::new(),
+            banquet_guests: 0,
+            room_sharing: "".to_string(),
+            roommate_email: "".to_string(),
+            fee_waiver_reason: "".to_string(),
+            presentation: Presentation::None,
+            presentation_title: "".to_string(),
+            presentation_authors: "".to_string(),
+            abstract_text: "".to_string(),
+            talk_duration: 0,
+            talk_equipment: "".to_string(),
+            unavailable_days: "".to_string(),
+            topics: Vec::new(),
+            fee: 0,
             price_category: PriceCategory::Student,
             course_type: Course::Course2
         };
 
-        assert!(insert_into_db(&conn, &reg).is_ok());
+        assert!(insert_into_db(&conn, &reg, RegistrationStatus::Pending, "{}", "").is_ok());
+        // As with approval in [Basic]
+        assert_eq!(conn.query_row("SELECT status FROM registration WHERE city = 'Somewhere'", &[], |row| row.get::<i32, String>(0)).unwrap(),
+            "pending".to_string());
 
         let mut stmt = conn.prepare("SELECT * FROM registration WHERE city = 'Somewhere'").unwrap();
         let mut rows = stmt.query(&[]).unwrap();
@@ -537,54 +2817,171 @@ mod tests {
         conn.execute("DELETE FROM registration WHERE city = 'Somewhere';", &[]).unwrap();
     }
 
+    #[test]
+    fn test_build_email() {
+        let config = example_configuration();
+
+        let message = build_email("alice@smith.com", &["secretary@smith.com"], "Hallo", "Text", &[], &config).unwrap().message();
+        assert!(message.contains("Cc: <bob@smith.com>, <secretary@smith.com>\r\n"));
+        assert!(!message.contains("multipart"));
+
+        let attachments = vec!["Cargo.toml".to_string()];
+        let message = build_email("alice@smith.com", &[], "Hallo", "Text", &attachments, &config).unwrap().message();
+        assert!(message.contains("Content-Type: multipart/mixed; boundary=\""));
+        assert!(message.contains("Content-Type: text/plain; charset=utf-8\r\n\r\nText\r\n"));
+        assert!(message.contains("Content-Disposition: attachment; filename=\"Cargo.toml\"\r\n"));
+        assert!(message.contains(&encode_block(b"[package]")[..8]));
+
+        assert!(build_email("alice@smith.com", &[], "Hallo", "Text", &["venue_map.pdf".to_string()], &config).is_err());
+    }
+
+    // Mails of send_mail go to files in a folder of their own, the database in memory lives as long as the connection
+    fn file_mail_configuration(name: &str) -> (Configuration, Connection) {
+        let mut config = example_configuration();
+        config.email_transport = "file".to_string();
+        config.email_folder = format!("{}_mails", name);
+        config.db_filename = format!("file:{}?mode=memory&cache=shared", name);
+        let _ = fs::remove_dir_all(&config.email_folder);
+
+        let conn = Connection::open(&config.db_filename).unwrap();
+        init_database(&conn).unwrap();
+        (config, conn)
+    }
+
+    fn sent_mail(folder: &str) -> String {
+        let files: Vec<_> = fs::read_dir(folder).unwrap().map(|entry| entry.unwrap().path()).collect();
+        assert_eq!(files.len(), 1);
+
+        let mail = fs::read_to_string(&files[0]).unwrap();
+        fs::remove_dir_all(folder).unwrap();
+        mail
+    }
+
     #[test]
     fn test_send_mail1() {
-        let config = load_configuration("test_config2.ini").unwrap();
+        let (config, _conn) = file_mail_configuration("test_send_mail1");
 
         let reg = Registration {
             title: Title::Sir,
             last_name: "Smith".to_string(),
             first_name: "Bob".to_string(),
+            preferred_name: "".to_string(),
+            pronouns: "".to_string(),
             institution: "Some university".to_string(),
             street: "Somestreet".to_string(),
             street_no: "15".to_string(),
             zip_code: "12345".to_string(),
             city: "Somewhere".to_string(),
             phone: "123456789".to_string(),
+            mobile: "".to_string(),
             email_to: "bob.smith@somewhere.com".to_string(),
+            confirmation_cc: "".to_string(),
+            country: "DE".to_string(),
             more_info: "Some more information".to_string(),
+            orcid: "".to_string(),
+            tshirt_size: "".to_string(),
+            emergency_name: "".to_string(),
+            emergency_phone: "".to_string(),
+            arrival_mode: "".to_string(),
+            arrival_number: "".to_string(),
+            arrival_time: "".to_string(),
+            share_ride: false,
+            children: 0,
+            children_ages: "".to_string(),
+            child_care_days: "".to_string(),
+            accessibility_options: "".to_string(),
+            accessibility_needs: "".to_string(),
+            code_of_conduct: "".to_string(),
+            photo_consent: false,
+            recording_consent: false,
+            newsletter: false,
+            social_events: Vec::new(),
+            banquet_guests: 0,
+            room_sharing: "".to_string(),
+            roommate_email: "".to_string(),
+            fee_waiver_reason: "".to_string(),
+            presentation: Presentation::None,
+            presentation_title: "".to_string(),
+            presentation_authors: "".to_string(),
+            abstract_text: "".to_string(),
+            talk_duration: 0,
+            talk_equipment: "".to_string(),
+            unavailable_days: "".to_string(),
+            topics: Vec::new(),
+            fee: 0,
             price_category: PriceCategory::Student,
             course_type: Course::Course2
         };
 
-        let result = send_mail(&reg, &config);
+        let result = send_mail(&reg, 1, "0123456789abcdef", &config);
 
         assert!(result.is_ok());
+        let mail = sent_mail(&config.email_folder);
+        assert!(mail.contains("bob.smith@somewhere.com") && mail.contains("Sehr geehrter Herr Smith,"));
+        assert!(mail.contains(&config.course2));
     }
 
     #[test]
     fn test_send_mail2() {
-        let config = load_configuration("test_config2.ini").unwrap();
+        let (config, _conn) = file_mail_configuration("test_send_mail2");
 
         let reg = Registration {
             title: Title::Madam,
             last_name: "Smith".to_string(),
             first_name: "Jane".to_string(),
+            preferred_name: "".to_string(),
+            pronouns: "".to_string(),
             institution: "Some university".to_string(),
             street: "Somestreet".to_string(),
             street_no: "15".to_string(),
             zip_code: "12345".to_string(),
             city: "Somewhere".to_string(),
             phone: "123456789".to_string(),
+            mobile: "".to_string(),
             email_to: "bob.smith@somewhere.com".to_string(),
+            confirmation_cc: "".to_string(),
+            country: "DE".to_string(),
             more_info: "Some more information".to_string(),
+            orcid: "".to_string(),
+            tshirt_size: "".to_string(),
+            emergency_name: "".to_string(),
+            emergency_phone: "".to_string(),
+            arrival_mode: "".to_string(),
+            arrival_number: "".to_string(),
+            arrival_time: "".to_string(),
+            share_ride: false,
+            children: 0,
+            children_ages: "".to_string(),
+            child_care_days: "".to_string(),
+            accessibility_options: "".to_string(),
+            accessibility_needs: "".to_string(),
+            code_of_conduct: "".to_string(),
+            photo_consent: false,
+            recording_consent: false,
+            newsletter: false,
+            social_events: Vec::new(),
+            banquet_guests: 0,
+            room_sharing: "".to_string(),
+            roommate_email: "".to_string(),
+            fee_waiver_reason: "".to_string(),
+            presentation: Presentation::None,
+            presentation_title: "".to_string(),
+            presentation_authors: "".to_string(),
+            abstract_text: "".to_string(),
+            talk_duration: 0,
+            talk_equipment: "".to_string(),
+            unavailable_days: "".to_string(),
+            topics: Vec::new(),
+            fee: 0,
             price_category: PriceCategory::Regular,
             course_type: Course::Course1
         };
 
-        let result = send_mail(&reg, &config);
+        let result = send_mail(&reg, 1, "0123456789abcdef", &config);
 
         assert!(result.is_ok());
+        let mail = sent_mail(&config.email_folder);
+        assert!(mail.contains("Sehr geehrte Frau Smith,") && mail.contains(&config.course1));
     }
 
 
@@ -0,0 +1,126 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use serde_json::Value as JsonValue;
+
+use accounting::invoice_number;
+use config::Configuration;
+use handler::{Registration, HandleError};
+
+
+// Shown if a check rejects a registration without giving a reason
+const DEFAULT_REJECTION: &str = "Ihre Anmeldung konnte leider nicht angenommen werden. Bitte wenden Sie sich an die Organisatoren.";
+
+// Returns the message for the participant if the registration is not accepted
+pub type Check = Box<dyn Fn(&Registration) -> Result<(), String> + Send + Sync>;
+// Gets the stored registration and its id
+pub type Action = Box<dyn Fn(&Registration, i64) + Send + Sync>;
+
+/// Small site-specific checks and processing that don't deserve a config option of their own.
+/// Either added as closures or as external commands from `[Hooks]`, which get the registration as JSON on stdin.
+pub struct Hooks {
+    checks: Vec<Check>,
+    actions: Vec<Action>,
+    before_insert_command: String,
+    after_registration_command: String
+}
+
+impl Hooks {
+    /// The external commands of `[Hooks]`, without any checks or actions
+    pub fn new(config: &Configuration) -> Hooks {
+        Hooks {
+            checks: Vec::new(),
+            actions: Vec::new(),
+            before_insert_command: config.hook_before_insert.clone(),
+            after_registration_command: config.hook_after_registration.clone()
+        }
+    }
+
+    /// Adds a check that runs before a registration is stored, `Err` refuses the registration
+    /// and its message is shown to the participant
+    pub fn add_check<F>(&mut self, check: F) where F: Fn(&Registration) -> Result<(), String> + Send + Sync + 'static {
+        self.checks.push(Box::new(check));
+    }
+
+    /// Adds an action that runs after a registration was stored, it gets the registration's id
+    pub fn add_action<F>(&mut self, action: F) where F: Fn(&Registration, i64) + Send + Sync + 'static {
+        self.actions.push(Box::new(action));
+    }
+
+    // Runs before the registration is stored. The command rejects it with a non-zero exit code,
+    // the first line it prints is shown to the participant.
+    pub fn before_insert(&self, registration: &Registration) -> Result<(), HandleError> {
+        for check in &self.checks {
+            check(registration).map_err(HandleError::Rejected)?;
+        }
+
+        if !self.before_insert_command.is_empty() {
+            let (success, output) = run_command(&self.before_insert_command, &json!(registration))?;
+            if !success {
+                return Err(HandleError::Rejected(rejection_message(&output)));
+            }
+        }
+
+        Ok(())
+    }
+
+    // The registration is already stored and confirmed, so failures are only logged
+    pub fn after_registration(&self, registration: &Registration, id: i64, config: &Configuration) {
+        for action in &self.actions {
+            action(registration, id);
+        }
+
+        if !self.after_registration_command.is_empty() {
+            let mut data = json!(registration);
+            data["id"] = json!(id);
+            data["code"] = json!(invoice_number(config, id));
+
+            match run_command(&self.after_registration_command, &data) {
+                Ok((true, _)) => (),
+                Ok((false, output)) => error!("Hook '{}' failed for registration {}: {}", self.after_registration_command, id, output.trim()),
+                Err(e) => error!("Could not run hook '{}': {:?}", self.after_registration_command, e)
+            }
+        }
+    }
+}
+
+// Returns whether the command succeeded and what it printed
+fn run_command(command_line: &str, data: &JsonValue) -> Result<(bool, String), HandleError> {
+    let mut parts = command_line.split_whitespace();
+    let program = parts.next().ok_or(HandleError::IO)?;
+
+    let mut child = Command::new(program).args(parts).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // A command that doesn't need the data may exit without reading it
+        let _ = stdin.write_all(data.to_string().as_bytes());
+    }
+
+    let output = child.wait_with_output()?;
+
+    Ok((output.status.success(), String::from_utf8_lossy(&output.stdout).into_owned()))
+}
+
+fn rejection_message(output: &str) -> String {
+    output.lines().map(|line| line.trim()).find(|line| !line.is_empty()).unwrap_or(DEFAULT_REJECTION).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run_command, rejection_message, DEFAULT_REJECTION};
+
+    #[test]
+    fn test_run_command() {
+        let data = json!({ "email_to": "bob@smith.com" });
+
+        assert_eq!(run_command("cat", &data).unwrap(), (true, "{\"email_to\":\"bob@smith.com\"}".to_string()));
+        assert!(!run_command("sh -c false", &data).unwrap().0);
+        assert!(run_command("does_not_exist_hook", &data).is_err());
+    }
+
+    #[test]
+    fn test_rejection_message() {
+        assert_eq!(rejection_message("\n  Nur für Mitglieder des Instituts.\nmore\n"), "Nur für Mitglieder des Instituts.".to_string());
+        assert_eq!(rejection_message(""), DEFAULT_REJECTION.to_string());
+    }
+}
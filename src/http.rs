@@ -0,0 +1,155 @@
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use iron::url::{Url, ParseError};
+use openssl::error::ErrorStack;
+use openssl::ssl::{SslConnector, SslMethod, HandshakeError};
+
+
+// In seconds, for connecting, sending and waiting for the answer
+const TIMEOUT: u64 = 10;
+
+#[derive(Debug)]
+pub enum HttpError {
+    Url,
+    IO,
+    Tls,
+    // The server answered with something else than 2xx
+    Status
+}
+
+impl From<ParseError> for HttpError {
+    fn from(_: ParseError) -> HttpError {
+        HttpError::Url
+    }
+}
+
+impl From<io::Error> for HttpError {
+    fn from(_: io::Error) -> HttpError {
+        HttpError::IO
+    }
+}
+
+impl From<ErrorStack> for HttpError {
+    fn from(_: ErrorStack) -> HttpError {
+        HttpError::Tls
+    }
+}
+
+impl From<HandshakeError<TcpStream>> for HttpError {
+    fn from(_: HandshakeError<TcpStream>) -> HttpError {
+        HttpError::Tls
+    }
+}
+
+// authorization is the value of the header, empty for none
+fn request_text(url: &Url, content_type: &str, authorization: &str, body: &str) -> String {
+    let path = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string()
+    };
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or(""), port),
+        None => url.host_str().unwrap_or("").to_string()
+    };
+
+    let authorization = if authorization.is_empty() { String::new() } else { format!("Authorization: {}\r\n", authorization) };
+
+    format!("POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path, host, content_type, authorization, body.len(), body)
+}
+
+// "HTTP/1.1 204 No Content"
+fn status_code(response: &[u8]) -> Option<u16> {
+    let response = String::from_utf8_lossy(response);
+    let status_line = response.lines().next()?;
+
+    if !status_line.starts_with("HTTP/") {
+        return None;
+    }
+
+    status_line.split_whitespace().nth(1)?.parse::<u16>().ok()
+}
+
+// Only the status line of the answer is read
+fn exchange<S: Read + Write>(stream: &mut S, request: &str) -> Result<Vec<u8>, HttpError> {
+    stream.write_all(request.as_bytes())?;
+    stream.flush()?;
+
+    let mut response = Vec::new();
+    let mut buffer = [0u8; 256];
+
+    while !response.windows(2).any(|window| window == b"\r\n") && response.len() < 1024 {
+        let size = stream.read(&mut buffer)?;
+        if size == 0 {
+            break;
+        }
+        response.extend_from_slice(&buffer[..size]);
+    }
+
+    Ok(response)
+}
+
+// Just enough HTTP/1.1 to deliver a webhook, over TLS for https URLs
+pub fn post(url: &str, content_type: &str, body: &str) -> Result<(), HttpError> {
+    post_authorized(url, content_type, "", body)
+}
+
+// For APIs that want a key, e.g. "Bearer <key>"
+pub fn post_authorized(url: &str, content_type: &str, authorization: &str, body: &str) -> Result<(), HttpError> {
+    let url = Url::parse(url)?;
+    let host = url.host_str().ok_or(HttpError::Url)?.to_string();
+    let port = url.port_or_known_default().ok_or(HttpError::Url)?;
+
+    let mut tcp = TcpStream::connect((host.as_str(), port))?;
+    tcp.set_read_timeout(Some(Duration::from_secs(TIMEOUT)))?;
+    tcp.set_write_timeout(Some(Duration::from_secs(TIMEOUT)))?;
+
+    let request = request_text(&url, content_type, authorization, body);
+
+    let response = match url.scheme() {
+        "https" => {
+            let connector = SslConnector::builder(SslMethod::tls())?.build();
+            exchange(&mut connector.connect(&host, tcp)?, &request)?
+        }
+        "http" => exchange(&mut tcp, &request)?,
+        _ => return Err(HttpError::Url)
+    };
+
+    match status_code(&response) {
+        Some(code) if (200..300).contains(&code) => Ok(()),
+        Some(code) => {
+            error!("'{}' answered with status {}", url, code);
+            Err(HttpError::Status)
+        }
+        None => Err(HttpError::IO)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{request_text, status_code};
+    use iron::url::Url;
+
+    #[test]
+    fn test_request_text() {
+        let url = Url::parse("https://hooks.example.org:8443/registration?key=abc").unwrap();
+
+        assert_eq!(request_text(&url, "application/json", "", "{}"),
+            "POST /registration?key=abc HTTP/1.1\r\nHost: hooks.example.org:8443\r\nContent-Type: application/json\r\n\
+             Content-Length: 2\r\nConnection: close\r\n\r\n{}".to_string());
+
+        let url = Url::parse("https://hooks.example.org").unwrap();
+        assert!(request_text(&url, "application/json", "", "").starts_with("POST / HTTP/1.1\r\nHost: hooks.example.org\r\n"));
+        assert!(request_text(&url, "application/json", "Bearer abc", "").contains("\r\nAuthorization: Bearer abc\r\nContent-Length: 0\r\n"));
+    }
+
+    #[test]
+    fn test_status_code() {
+        assert_eq!(status_code(b"HTTP/1.1 204 No Content\r\n"), Some(204));
+        assert_eq!(status_code(b"HTTP/1.0 500 Internal Server Error\r\nContent-Length: 0\r\n\r\n"), Some(500));
+        assert_eq!(status_code(b"SSH-2.0-OpenSSH\r\n"), None);
+        assert_eq!(status_code(b""), None);
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+
+use iron::prelude::{Request, IronResult, Response, Set};
+use iron::modifiers::RedirectRaw;
+use iron::status;
+
+use handlebars_iron::{Template};
+use serde_json::Value as JsonValue;
+use params::Params;
+use plugin::Pluggable;
+use persistent::Write;
+use rusqlite::Connection;
+
+use ::DBConnection;
+use admin::{with_admin, yes_no};
+use database::{lock_connection, load_institutions, load_institution_links, add_institution, add_institution_alias,
+    remove_institution_alias, link_institutions, Institution};
+use export::{export_response, Table};
+use handler::{HandleError, extract_string, not_found};
+use validation::normalize_institution;
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct InstitutionCount {
+    pub name: String,
+    // false for a name that isn't an alias of a curated institution yet
+    pub curated: bool,
+    pub count: i64
+}
+
+// Participants per institution: the linked ones under the canonical name, the others by how they typed it,
+// case and spacing ignored and the first spelling shown. Most participants first.
+pub fn institution_counts(links: &[(i64, String)], institutions: &[Institution]) -> Vec<InstitutionCount> {
+    let mut counts: BTreeMap<(i64, String), InstitutionCount> = BTreeMap::new();
+
+    for &(institution_id, ref typed) in links {
+        let entry = match institutions.iter().find(|institution| institution.id == institution_id) {
+            Some(institution) => counts.entry((institution.id, String::new()))
+                .or_insert_with(|| InstitutionCount { name: institution.name.clone(), curated: true, count: 0 }),
+            None => counts.entry((0, normalize_institution(typed)))
+                .or_insert_with(|| InstitutionCount { name: typed.trim().to_string(), curated: false, count: 0 })
+        };
+
+        entry.count += 1;
+    }
+
+    let mut result: Vec<InstitutionCount> = counts.into_values().collect();
+    result.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase())));
+    result
+}
+
+pub fn load_institution_counts(db_connection: &Connection) -> Result<Vec<InstitutionCount>, HandleError> {
+    Ok(institution_counts(&load_institution_links(db_connection)?, &load_institutions(db_connection)?))
+}
+
+pub fn handle_admin_institutions(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "admin_institutions", institutions_page)
+}
+
+fn institutions_page(req: &mut Request) -> Result<Response, HandleError> {
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let institutions = load_institutions(&db_connection)?;
+    let counts = load_institution_counts(&db_connection)?;
+
+    let curated: Vec<JsonValue> = institutions.iter().map(|institution| json!({
+        "id": institution.id,
+        "name": institution.name,
+        "aliases": institution.aliases,
+        "count": counts.iter().find(|count| count.curated && count.name == institution.name).map_or(0, |count| count.count)
+    })).collect();
+
+    let unmatched: Vec<&InstitutionCount> = counts.iter().filter(|count| !count.curated).collect();
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("institutions".to_string(), json!(curated));
+    data.insert("unmatched".to_string(), json!(unmatched.iter().map(|count| json!({ "name": count.name, "count": count.count })).collect::<Vec<JsonValue>>()));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("institutions", data)).set_mut(status::Ok);
+    Ok(resp)
+}
+
+pub fn handle_admin_institutions_submit(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "admin_institutions_submit", change_institutions)
+}
+
+// action=add with name, action=alias with institution and alias, action=remove_alias with alias.
+// The registrations are linked again after every change.
+fn change_institutions(req: &mut Request) -> Result<Response, HandleError> {
+    let map = req.get::<Params>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let non_empty = |key: &str| extract_string(&map, key).ok().filter(|value| !value.is_empty()).ok_or(HandleError::FormValue);
+
+    match extract_string(&map, "action")?.as_str() {
+        "add" => {
+            let name = non_empty("name")?;
+            if add_institution(&db_connection, &name)?.is_none() {
+                return Ok(Response::with((status::Conflict, "Diese Institution gibt es bereits")));
+            }
+            info!("Institution '{}' added", name);
+        }
+        "alias" => {
+            let alias = non_empty("alias")?;
+            let institution = non_empty("institution")?.parse::<i64>().map_err(|_| HandleError::FormValue)?;
+            if !add_institution_alias(&db_connection, institution, &alias)? {
+                return Ok(not_found());
+            }
+            info!("Institution {}: alias '{}' added", institution, alias);
+        }
+        "remove_alias" => {
+            let alias = non_empty("alias")?;
+            if !remove_institution_alias(&db_connection, &alias)? {
+                return Ok(not_found());
+            }
+            info!("Alias '{}' removed", alias);
+        }
+        _ => return Err(HandleError::FormValue)
+    }
+
+    let linked = link_institutions(&db_connection)?;
+    info!("Institutions of {} registrations changed", linked);
+
+    Ok(Response::with((status::Found, RedirectRaw("/admin/institutions".to_string()))))
+}
+
+pub fn handle_export_institutions(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "export_institutions", export_institutions)
+}
+
+fn export_institutions(req: &mut Request) -> Result<Response, HandleError> {
+    let counts = {
+        let mutex = req.get::<Write<DBConnection>>()?;
+        let db_connection = lock_connection(&mutex)?;
+        load_institution_counts(&db_connection)?
+    };
+
+    let mut table = Table::new("institutions", &["institution", "participants", "curated"]);
+
+    for count in &counts {
+        table.push(&[&count.name, &count.count.to_string(), yes_no(count.curated)]);
+    }
+
+    export_response(req, &table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{institution_counts, InstitutionCount};
+    use database::{init_database, add_institution, add_institution_alias, remove_institution_alias, load_institutions,
+        link_institutions, link_institution, load_institution_links};
+    use rusqlite::Connection;
+
+    fn count(name: &str, curated: bool, count: i64) -> InstitutionCount {
+        InstitutionCount { name: name.to_string(), curated, count }
+    }
+
+    #[test]
+    fn test_institutions() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        for institution in &["Uni Tübingen", "University of Tübingen", "uni  tübingen", "MPI Stuttgart", "ETH Zürich"] {
+            conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                      city, phone, email_to, more_info, price_category, course_type)
+                      VALUES ('sir', 'Smith', 'Test', $1, 'Street', '1', '12345', 'City', '123', 'bob@smith.com', '', 'student', 'course1')",
+                      &[institution]).unwrap();
+        }
+
+        let tuebingen = add_institution(&conn, "Eberhard Karls Universität Tübingen").unwrap().unwrap();
+        assert_eq!(add_institution(&conn, "eberhard karls  universität tübingen").unwrap(), None);
+        assert!(add_institution_alias(&conn, tuebingen, "Uni Tübingen").unwrap());
+        assert!(add_institution_alias(&conn, tuebingen, "University of Tübingen").unwrap());
+        assert!(!add_institution_alias(&conn, 99, "MPI Stuttgart").unwrap());
+        assert_eq!(link_institutions(&conn).unwrap(), 3);
+        assert_eq!(link_institutions(&conn).unwrap(), 0);
+
+        let institutions = load_institutions(&conn).unwrap();
+        assert_eq!(institutions.len(), 1);
+        assert_eq!(institutions[0].aliases, vec!["Uni Tübingen".to_string(), "University of Tübingen".to_string()]);
+
+        assert_eq!(institution_counts(&load_institution_links(&conn).unwrap(), &institutions), vec![
+            count("Eberhard Karls Universität Tübingen", true, 3), count("ETH Zürich", false, 1), count("MPI Stuttgart", false, 1)
+        ]);
+
+        // The canonical name can't be removed, an alias can
+        assert!(!remove_institution_alias(&conn, "Eberhard Karls Universität Tübingen").unwrap());
+        assert!(remove_institution_alias(&conn, "UNI TÜBINGEN").unwrap());
+        assert_eq!(link_institutions(&conn).unwrap(), 2);
+
+        conn.execute("UPDATE registration SET institution = 'University of Tübingen' WHERE id = 1", &[]).unwrap();
+        link_institution(&conn, 1).unwrap();
+        let linked: i64 = conn.query_row("SELECT institution_id FROM registration WHERE id = 1", &[], |row| row.get(0)).unwrap();
+        assert_eq!(linked, tuebingen);
+    }
+}
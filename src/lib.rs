@@ -0,0 +1,449 @@
+//! The registration engine: form, storage, emails and admin pages of a conference registration.
+//!
+//! `run_server` serves everything on its own, `registration_handler` gives an Iron handler
+//! for mounting the registration into a larger conference site.
+
+extern crate iron;
+extern crate hyper;
+extern crate router;
+extern crate mount;
+extern crate staticfile;
+extern crate rusqlite;
+extern crate handlebars_iron;
+extern crate params;
+extern crate plugin;
+#[macro_use] extern crate log;
+extern crate simplelog;
+extern crate persistent;
+extern crate lettre;
+extern crate ini;
+extern crate chrono;
+extern crate rand;
+extern crate openssl;
+extern crate unicode_normalization;
+extern crate serde;
+#[macro_use] extern crate serde_derive;
+#[macro_use] extern crate serde_json;
+
+// System modules
+
+use std::path::Path;
+use std::time::Duration;
+
+// External modules
+
+use iron::prelude::{Iron, Chain};
+use iron::Timeouts;
+use iron::typemap::Key;
+use router::Router;
+use mount::Mount;
+use staticfile::Static;
+use rusqlite::Connection;
+use iron::error::HttpError;
+use handlebars_iron::{HandlebarsEngine, DirectorySource, MemorySource};
+use persistent::{Read, Write};
+
+
+// Local modules
+
+mod abstracts;
+mod accounting;
+mod alert;
+mod approval;
+mod admin;
+mod banner;
+mod body_limit;
+mod bounce;
+mod cache;
+mod closing;
+mod api;
+mod conference;
+mod config;
+mod countries;
+mod cors;
+mod dashboard;
+mod database;
+mod dates;
+#[cfg(feature = "dkim")]
+mod dkim;
+mod draft;
+mod dump;
+mod duplicates;
+mod events;
+mod export;
+mod fee;
+mod fee_waiver;
+mod filter;
+mod handler;
+mod mail_template;
+mod mailer;
+mod hooks;
+mod http;
+mod institutions;
+mod login;
+mod newsletter;
+mod notifier;
+mod outbox;
+mod participant_edit;
+#[cfg(feature = "payments")]
+mod payment;
+#[cfg(feature = "pdf")]
+mod pdf;
+mod presentation;
+mod program;
+mod recover;
+mod reimbursement;
+mod remember;
+mod request_log;
+mod roster;
+mod scaffold;
+mod settings;
+mod smtp_check;
+mod status;
+mod test_server;
+mod throttle;
+mod token;
+mod travel_grant;
+mod upload;
+mod validation;
+
+use bounce::start_bounce_polling;
+use events::{EventLog, handle_admin_events};
+use banner::BannerHelper;
+use conference::ConferenceHelper;
+use cors::Cors;
+use cache::QueryCache;
+use body_limit::BodyLimit;
+use alert::RequestIds;
+use recover::CatchPanic;
+use scaffold::{bundled_templates, BundledFiles};
+use throttle::Throttle;
+use settings::{RuntimeConfiguration, handle_settings, handle_settings_submit};
+#[cfg(feature = "payments")]
+use payment::{start_payment_reminders, handle_overdue_payments, handle_payment_reminder, handle_payment_status};
+use handler::{handle_main, handle_submit, handle_submit_result, handle_edit_registration, handle_edit_registration_submit,
+    handle_delete_registration, handle_delete_registration_submit};
+use draft::handle_save_draft;
+use mail_template::{handle_mail_templates, handle_mail_template_submit, handle_email_preview};
+use smtp_check::{handle_smtp_check, handle_smtp_check_submit};
+use status::handle_registration_status;
+use approval::handle_approvals;
+use dashboard::handle_dashboard;
+use login::{handle_login, handle_login_submit, handle_login_link, handle_login_link_submit};
+use participant_edit::{handle_participant_edit, handle_participant_edit_submit};
+use outbox::handle_outbox;
+use remember::{handle_remember, handle_remember_submit, handle_admin_login, handle_admin_login_submit};
+use api::{handle_institutions, handle_program_json, handle_count, handle_public_stats, handle_participants_json};
+use accounting::handle_export_payments;
+use roster::{handle_roster_changes, handle_export_roster_changes};
+use institutions::{handle_admin_institutions, handle_admin_institutions_submit, handle_export_institutions};
+use duplicates::{handle_duplicates, handle_duplicate_merge, handle_duplicate_merge_submit};
+use abstracts::{handle_admin_abstracts, handle_admin_abstract_session, handle_export_abstracts, handle_session_suggestions};
+use fee_waiver::{handle_fee_waivers, handle_fee_waiver_decision};
+use travel_grant::{handle_travel_grant, handle_travel_grant_submit, handle_admin_travel_grants,
+    handle_admin_travel_grant_review, handle_admin_travel_grant_cv, handle_admin_travel_grant_notify};
+use presentation::{handle_presentation_upload, handle_presentation_upload_submit, handle_session_slides, handle_poster_gallery,
+    handle_admin_poster_gallery, handle_poster_file};
+use program::handle_program;
+use newsletter::{handle_unsubscribe, handle_unsubscribe_submit, handle_export_newsletter, handle_announcement, handle_announcement_send};
+use reimbursement::{handle_reimbursement, handle_reimbursement_submit, handle_admin_reimbursements,
+    handle_admin_reimbursement_status, handle_admin_reimbursement_receipt};
+use admin::{handle_stats, handle_daily_registrations, handle_participants, handle_export_emergency, handle_export_media_consent, handle_arrivals,
+    handle_child_care, handle_accessibility, handle_rooms, handle_export_participants};
+#[cfg(feature = "pdf")]
+use admin::handle_export_participants_pdf;
+
+pub struct DBConnection;
+
+impl Key for DBConnection { type Value = Connection; }
+
+// Cached result of /api/v1/count: when it was counted (Unix time) and the number of registrations
+pub struct RegistrationCount;
+
+impl Key for RegistrationCount { type Value = Option<(i64, i64)>; }
+
+// New registrations, streamed to the open admin pages
+pub struct AdminEvents;
+
+impl Key for AdminEvents { type Value = EventLog; }
+
+// Results of the admin pages' aggregate queries until the next write
+pub struct AdminCache;
+
+impl Key for AdminCache { type Value = QueryCache; }
+
+// Recent submissions of the registration form per IP address
+pub struct SubmissionThrottle;
+
+impl Key for SubmissionThrottle { type Value = Throttle; }
+
+// Site-specific checks and processing of new registrations
+pub struct RegistrationHooks;
+
+impl Key for RegistrationHooks { type Value = Hooks; }
+
+impl Key for Configuration { type Value = Configuration; }
+
+// The configuration with the settings changed at /admin/settings
+pub struct RuntimeSettings;
+
+impl Key for RuntimeSettings { type Value = RuntimeConfiguration; }
+
+pub use alert::AlertLogger;
+pub use config::{load_configuration, bundled_configuration, in_memory_configuration, Configuration, ConfigError};
+pub use database::Repository;
+pub use dates::{DateFormat, TimestampWriter};
+pub use dump::{dump, restore, DumpError};
+pub use handler::{Registration, Title, PriceCategory, Presentation, Course, HandleError};
+pub use hooks::Hooks;
+pub use scaffold::init_site;
+pub use test_server::{test_server, TestServer, TestResponse};
+
+/// Why the registration server could not be started
+#[derive(Debug)]
+pub enum ServerError {
+    /// The database could not be opened or its tables could not be created
+    Database(HandleError),
+    /// The templates in `template_folder` could not be loaded, with the reason
+    Templates(String),
+    /// Could not listen on `socket_addr`
+    Listen(HttpError)
+}
+
+// 0 switches the timeout off
+fn timeout(seconds: u64) -> Option<Duration> {
+    if seconds == 0 { None } else { Some(Duration::from_secs(seconds)) }
+}
+
+// Payment reminders and the overdue payments page
+#[cfg(feature = "payments")]
+fn add_payment_routes(router: &mut Router) {
+    router.get("/admin/payments", handle_overdue_payments, "overdue_payments");
+    router.post("/admin/payments/:id/reminder", handle_payment_reminder, "payment_reminder");
+    router.post("/admin/payments/:id/status", handle_payment_status, "payment_status");
+}
+
+#[cfg(not(feature = "payments"))]
+fn add_payment_routes(_router: &mut Router) {
+}
+
+#[cfg(feature = "pdf")]
+fn add_pdf_routes(router: &mut Router) {
+    router.get("/admin/export/participants/pdf", handle_export_participants_pdf, "export_participants_pdf");
+}
+
+#[cfg(not(feature = "pdf"))]
+fn add_pdf_routes(_router: &mut Router) {
+}
+
+/// The whole registration site as an Iron handler: the form, the API, the admin pages and the
+/// static files. Also starts the background tasks for bounces and payment reminders.
+/// Every new registration goes through `hooks`.
+pub fn registration_handler(config: &Configuration, hooks: Hooks) -> Result<Chain, ServerError> {
+    let repository = Repository::open(&config.db_filename).map_err(ServerError::Database)?;
+    let runtime = RuntimeConfiguration::new(config.clone(), repository.connection()).map_err(ServerError::Database)?;
+
+    if !config.bounce_server.is_empty() {
+        start_bounce_polling(config.clone());
+    }
+
+    #[cfg(feature = "payments")]
+    {
+        if config.payment_first_reminder > 0 || config.payment_deadline_reminder > 0 {
+            start_payment_reminders(config.clone());
+        }
+    }
+
+    let mut hbse = HandlebarsEngine::new();
+    if config.bundled_files {
+        hbse.add(Box::new(MemorySource(bundled_templates())));
+    } else {
+        hbse.add(Box::new(DirectorySource::new(&config.template_folder, ".hbs")));
+    }
+    hbse.handlebars_mut().register_helper("conference", Box::new(ConferenceHelper::new(&config.conference)));
+    hbse.handlebars_mut().register_helper("banner", Box::new(BannerHelper::new(&runtime)));
+
+    hbse.reload().map_err(|e| ServerError::Templates(e.to_string()))?;
+
+    let mut router = Router::new();
+
+    router.get("/", handle_main, "index");
+    router.post("/", handle_main, "index");
+
+    router.get("/submit", handle_submit_result, "submit_result");
+    router.post("/submit", handle_submit, "submit");
+    router.post("/draft", handle_save_draft, "draft");
+
+    router.get("/program", handle_program, "program");
+
+    router.get("/api/institutions", handle_institutions, "institutions");
+    router.get("/api/v1/program", handle_program_json, "program_json");
+    router.get("/api/v1/count", handle_count, "count");
+    router.get("/api/v1/stats", handle_public_stats, "public_stats");
+    router.get("/api/v1/participants", handle_participants_json, "participants_json");
+
+    router.get("/reimbursement/:token", handle_reimbursement, "reimbursement");
+    router.post("/reimbursement/:token", handle_reimbursement_submit, "reimbursement_submit");
+
+    router.get("/travel_grant/:token", handle_travel_grant, "travel_grant");
+    router.post("/travel_grant/:token", handle_travel_grant_submit, "travel_grant_submit");
+
+    router.get("/slides/:token", handle_presentation_upload, "slides");
+    router.post("/slides/:token", handle_presentation_upload_submit, "slides_submit");
+    router.get("/poster/:token", handle_presentation_upload, "poster");
+    router.post("/poster/:token", handle_presentation_upload_submit, "poster_submit");
+
+    router.get("/posters", handle_poster_gallery, "posters");
+    router.get("/posters/:id/pdf", handle_poster_file, "poster_file");
+
+    router.get("/login", handle_login, "login");
+    router.post("/login", handle_login_submit, "login_submit");
+    router.get("/login/:token", handle_login_link, "login_link");
+    router.post("/login/:token", handle_login_link_submit, "login_link_submit");
+
+    router.get("/edit/:token", handle_participant_edit, "participant_edit");
+    router.post("/edit/:token", handle_participant_edit_submit, "participant_edit_submit");
+
+    router.get("/unsubscribe/:token", handle_unsubscribe, "unsubscribe");
+    router.post("/unsubscribe/:token", handle_unsubscribe_submit, "unsubscribe_submit");
+
+    router.get("/admin", handle_dashboard, "dashboard");
+    router.get("/admin/stats", handle_stats, "stats");
+    router.get("/admin/events", handle_admin_events, "admin_events");
+    router.get("/admin/stats/daily", handle_daily_registrations, "daily_registrations");
+    router.get("/admin/participants", handle_participants, "participants");
+    router.post("/admin/registrations/:id/status", handle_registration_status, "registration_status");
+    router.get("/admin/edit/:id", handle_edit_registration, "edit_registration");
+    router.post("/admin/edit/:id", handle_edit_registration_submit, "edit_registration_submit");
+    router.get("/admin/delete/:id", handle_delete_registration, "delete_registration");
+    router.post("/admin/delete/:id", handle_delete_registration_submit, "delete_registration_submit");
+    router.get("/admin/approvals", handle_approvals, "approvals");
+    router.get("/admin/outbox", handle_outbox, "outbox");
+    router.get("/admin/login", handle_admin_login, "admin_login");
+    router.post("/admin/login", handle_admin_login_submit, "admin_login_submit");
+    router.get("/admin/remember", handle_remember, "remember");
+    router.post("/admin/remember", handle_remember_submit, "remember_submit");
+    router.get("/admin/arrivals", handle_arrivals, "arrivals");
+    router.get("/admin/child_care", handle_child_care, "child_care");
+    router.get("/admin/accessibility", handle_accessibility, "accessibility");
+    router.get("/admin/rooms", handle_rooms, "rooms");
+    router.get("/admin/institutions", handle_admin_institutions, "admin_institutions");
+    router.post("/admin/institutions", handle_admin_institutions_submit, "admin_institutions_submit");
+    router.get("/admin/roster_changes", handle_roster_changes, "roster_changes");
+    router.get("/admin/duplicates", handle_duplicates, "duplicates");
+    router.get("/admin/duplicates/:keep/:remove", handle_duplicate_merge, "duplicate_merge");
+    router.post("/admin/duplicates/:keep/:remove", handle_duplicate_merge_submit, "duplicate_merge_submit");
+    router.get("/admin/export/emergency", handle_export_emergency, "export_emergency");
+    router.get("/admin/export/media_consent", handle_export_media_consent, "export_media_consent");
+    router.get("/admin/export/participants", handle_export_participants, "export_participants");
+    router.get("/admin/export/newsletter", handle_export_newsletter, "export_newsletter");
+    router.get("/admin/export/payments", handle_export_payments, "export_payments");
+    router.get("/admin/export/roster_changes", handle_export_roster_changes, "export_roster_changes");
+    router.get("/admin/export/institutions", handle_export_institutions, "export_institutions");
+    router.get("/admin/settings", handle_settings, "settings");
+    router.post("/admin/settings", handle_settings_submit, "settings_submit");
+    router.get("/admin/mail_templates", handle_mail_templates, "mail_templates");
+    router.post("/admin/mail_templates/:name", handle_mail_template_submit, "mail_template_submit");
+    router.get("/admin/email-preview/:template", handle_email_preview, "email_preview");
+    router.get("/admin/smtp-check", handle_smtp_check, "smtp_check");
+    router.post("/admin/smtp-check", handle_smtp_check_submit, "smtp_check_submit");
+    router.get("/admin/announcement", handle_announcement, "announcement");
+    router.post("/admin/announcement", handle_announcement_send, "announcement_send");
+    router.get("/admin/export/abstracts", handle_export_abstracts, "export_abstracts");
+    router.get("/admin/abstracts", handle_admin_abstracts, "admin_abstracts");
+    router.get("/admin/abstracts/suggestions", handle_session_suggestions, "session_suggestions");
+    router.post("/admin/abstracts/:id", handle_admin_abstract_session, "admin_abstract_session");
+    router.get("/admin/sessions/:key/slides", handle_session_slides, "session_slides");
+    router.get("/admin/posters", handle_admin_poster_gallery, "admin_posters");
+    router.get("/admin/fee_waivers", handle_fee_waivers, "fee_waivers");
+    add_payment_routes(&mut router);
+    add_pdf_routes(&mut router);
+    router.post("/admin/fee_waivers/:id", handle_fee_waiver_decision, "fee_waiver_decision");
+    router.get("/admin/travel_grants", handle_admin_travel_grants, "admin_travel_grants");
+    router.post("/admin/travel_grants/notify", handle_admin_travel_grant_notify, "admin_travel_grant_notify");
+    router.post("/admin/travel_grants/:id", handle_admin_travel_grant_review, "admin_travel_grant_review");
+    router.get("/admin/travel_grants/:id/cv", handle_admin_travel_grant_cv, "admin_travel_grant_cv");
+    router.get("/admin/reimbursements", handle_admin_reimbursements, "admin_reimbursements");
+    router.post("/admin/reimbursements/:id", handle_admin_reimbursement_status, "admin_reimbursement_status");
+    router.get("/admin/reimbursements/:id/receipt", handle_admin_reimbursement_receipt, "admin_reimbursement_receipt");
+
+    let mut mount = Mount::new();
+
+    mount.mount("/", router);
+    if config.bundled_files {
+        mount.mount("/css/", BundledFiles::new("css"));
+        mount.mount("/js/", BundledFiles::new("js"));
+    } else {
+        mount.mount("/css/", Static::new(Path::new("css/")));
+        mount.mount("/js/", Static::new(Path::new("js/")));
+    }
+
+    let mut chain1 = Chain::new(mount);
+    // Inside of the HandlebarsEngine, which renders the error page
+    chain1.link_around(CatchPanic);
+    chain1.link_after(hbse);
+
+    let mut chain2 = Chain::new(chain1);
+    chain2.link(Write::<DBConnection>::both(repository.into_connection()));
+    chain2.link(Write::<RegistrationCount>::both(None));
+    chain2.link(Read::<AdminEvents>::both(EventLog::new()));
+    chain2.link(Write::<SubmissionThrottle>::both(Throttle::new()));
+    chain2.link(Write::<AdminCache>::both(QueryCache::new()));
+    chain2.link(Read::<RegistrationHooks>::both(hooks));
+
+    let mut chain3 = Chain::new(chain2);
+    chain3.link(Read::<RuntimeSettings>::both(runtime.clone()));
+    // Provides Read<Configuration> for the handlers
+    chain3.link_before(runtime);
+
+    if !config.cors_origins.is_empty() {
+        chain3.link_around(Cors::new(config));
+    }
+
+    chain3.link_around(RequestIds);
+
+    // Outermost, so that nothing reads the body of a rejected request
+    chain3.link_around(BodyLimit::new(config));
+
+    Ok(chain3)
+}
+
+/// Serves the registration site on `socket_addr` of the configuration, with the external hooks
+/// of `[Hooks]`. Doesn't return while the server is running.
+pub fn run_server(config: Configuration) -> Result<(), ServerError> {
+    let hooks = Hooks::new(&config);
+    run_server_with_hooks(config, hooks)
+}
+
+/// Like `run_server`, with checks and actions of the embedding application added to `hooks`
+pub fn run_server_with_hooks(config: Configuration, hooks: Hooks) -> Result<(), ServerError> {
+    let mut server = Iron::new(registration_handler(&config, hooks)?);
+
+    server.timeouts = Timeouts {
+        keep_alive: timeout(config.server_keep_alive),
+        read: timeout(config.server_read_timeout),
+        write: timeout(config.server_write_timeout)
+    };
+
+    if config.server_threads > 0 {
+        server.threads = config.server_threads;
+    }
+
+    // Blocks until the server stops
+    server.http(config.socket_addr).map_err(ServerError::Listen)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::registration_handler;
+    use config::{bundled_configuration, in_memory_configuration};
+    use hooks::Hooks;
+
+    #[test]
+    fn test_in_memory_handler() {
+        let config = in_memory_configuration(bundled_configuration().unwrap());
+        assert!(registration_handler(&config, Hooks::new(&config)).is_ok());
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+
+use iron::prelude::{Request, IronResult, Response, Set};
+use iron::status;
+
+use handlebars_iron::{Template};
+use serde_json::Value as JsonValue;
+use params::Params;
+use plugin::Pluggable;
+use persistent::{Read, Write};
+use chrono::Local;
+
+use ::DBConnection;
+use closing::closed_page;
+use config::Configuration;
+use database::{lock_connection, find_registration_by_email, insert_login_link, find_login_link, delete_expired_login_links,
+    load_registration_status, update_registration_status};
+use handler::{HandleError, Course, extract_string, normalize_email, load_registration, send_email, router_param};
+use mail_template::{mail_template, fill_template, login_values};
+use outbox::send_recorded;
+use remember::token_hash;
+use status::{RegistrationStatus, status_change_mail};
+use throttle::throttle_submission;
+use token::generate_token;
+
+
+// How long the link from the mail can be used
+pub const LINK_SECONDS: i64 = 30 * 60;
+
+fn render_login_page(data: BTreeMap<String, JsonValue>, response_status: status::Status) -> Response {
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("login", data)).set_mut(response_status);
+    resp
+}
+
+fn message_page(message: &str, response_status: status::Status) -> Response {
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("message".to_string(), json!(message));
+    render_login_page(data, response_status)
+}
+
+pub fn handle_login(req: &mut Request) -> IronResult<Response> {
+    if let Some(resp) = closed_page(req, status::Ok) {
+        return Ok(resp);
+    }
+
+    Ok(render_login_page(BTreeMap::new(), status::Ok))
+}
+
+// The answer is the same whether the address is known or not, so the form can't be used to find out who registered
+pub fn handle_login_submit(req: &mut Request) -> IronResult<Response> {
+    if let Some(resp) = closed_page(req, status::Forbidden) {
+        return Ok(resp);
+    }
+
+    match throttle_submission(req).and_then(|_| send_login_link(req)) {
+        Ok(()) => Ok(message_page("Falls zu dieser Adresse eine Anmeldung existiert, haben wir Ihnen einen Link per E-Mail geschickt.", status::Ok)),
+        Err(HandleError::Throttled(ip)) => {
+            info!("Too many login links requested from '{}'", ip);
+            Ok(message_page("Von Ihrem Anschluss wurden in der letzten Stunde bereits sehr viele Anfragen abgeschickt. \
+                Bitte versuchen Sie es später noch einmal.", status::TooManyRequests))
+        }
+        Err(e) => {
+            error!("Error while sending a login link: {:?}", e);
+            Ok(message_page("Ein Fehler ist aufgetreten. Bitte versuchen Sie es später noch einmal.", status::Ok))
+        }
+    }
+}
+
+fn send_login_link(req: &mut Request) -> Result<(), HandleError> {
+    let email = normalize_email(&extract_string(req.get_ref::<Params>()?, "email")?);
+    let config = req.get::<Read<Configuration>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+
+    let (registration, subject, body) = {
+        let db_connection = lock_connection(&mutex)?;
+        let now = Local::now().timestamp();
+        delete_expired_login_links(&db_connection, now)?;
+
+        let registration = match find_registration_by_email(&db_connection, &email)? {
+            Some(id) => match load_registration(&db_connection, id)? {
+                Some((registration, _)) => (id, registration),
+                None => return Ok(())
+            },
+            None => {
+                info!("Login link requested for an unknown address");
+                return Ok(());
+            }
+        };
+
+        let token = generate_token()?;
+        insert_login_link(&db_connection, &token_hash(&token), registration.0, now + LINK_SECONDS)?;
+
+        let template = mail_template(&db_connection, "login_link", &config)?;
+        let values = login_values(&registration.1, &format!("{}/login/{}", config.base_url, token), &config);
+        (registration, fill_template(&template.subject, &values), fill_template(&template.body, &values))
+    };
+
+    // Not recorded in the outbox, the link would be readable there as long as it is valid
+    send_email(&registration.1.email_to, &subject, &body, &config)?;
+    info!("Login link sent for registration {}", registration.0);
+    Ok(())
+}
+
+// The registration the link in the URL belongs to, None if it is unknown or expired
+fn linked_registration(req: &mut Request) -> Result<Option<i64>, HandleError> {
+    let token = router_param(req, "token");
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    Ok(find_login_link(&db_connection, &token_hash(&token), Local::now().timestamp())?)
+}
+
+fn registration_page(req: &mut Request, id: i64, message: &str) -> Result<Response, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let (registration, token, current) = match (load_registration(&db_connection, id)?, load_registration_status(&db_connection, id)?) {
+        (Some((registration, token)), Some(current)) => (registration, token, RegistrationStatus::from_name(&current)),
+        _ => return Err(HandleError::NotFound)
+    };
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("token".to_string(), json!(router_param(req, "token")));
+    data.insert("message".to_string(), json!(message));
+    data.insert("registration".to_string(), json!({
+        "name": format!("{} {}", registration.first_name, registration.last_name),
+        "institution": registration.institution,
+        "email": registration.email_to,
+        "course": if registration.course_type == Course::Course1 { config.course1.clone() } else { config.course2.clone() },
+        "status": current.map_or("", |status| status.label()),
+        // The personal page of the confirmation mail, where the details can be changed
+        "edit_url": format!("/edit/{}", token),
+        "can_cancel": current.is_some_and(|status| status.can_change_to(RegistrationStatus::Cancelled))
+    }));
+
+    Ok(render_login_page(data, status::Ok))
+}
+
+// Runs f for the registration of the link, an expired link leads back to the form for a new one
+fn with_link<F>(req: &mut Request, f: F) -> IronResult<Response>
+    where F: FnOnce(&mut Request, i64) -> Result<Response, HandleError> {
+
+    let result = linked_registration(req).and_then(|id| match id {
+        Some(id) => f(req, id).map(Some),
+        None => Ok(None)
+    });
+
+    match result {
+        Ok(Some(resp)) => Ok(resp),
+        Ok(None) => Ok(message_page("Dieser Link ist abgelaufen oder ungültig. Sie können unten einen neuen anfordern.", status::NotFound)),
+        Err(e) => {
+            error!("Error on the page of a login link: {:?}", e);
+            Ok(message_page("Ein Fehler ist aufgetreten. Bitte versuchen Sie es später noch einmal.", status::Ok))
+        }
+    }
+}
+
+pub fn handle_login_link(req: &mut Request) -> IronResult<Response> {
+    if let Some(resp) = closed_page(req, status::Ok) {
+        return Ok(resp);
+    }
+
+    with_link(req, |req, id| registration_page(req, id, ""))
+}
+
+pub fn handle_login_link_submit(req: &mut Request) -> IronResult<Response> {
+    if let Some(resp) = closed_page(req, status::Forbidden) {
+        return Ok(resp);
+    }
+
+    with_link(req, |req, id| {
+        let message = cancel_registration(req, id)?;
+        registration_page(req, id, message)
+    })
+}
+
+// The same change an admin makes at /admin/participants, including the mail
+fn cancel_registration(req: &mut Request, id: i64) -> Result<&'static str, HandleError> {
+    if extract_string(req.get_ref::<Params>()?, "action")? != "cancel" {
+        return Err(HandleError::FormValue);
+    }
+
+    let config = req.get::<Read<Configuration>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+
+    let mail = {
+        let db_connection = lock_connection(&mutex)?;
+
+        let current = load_registration_status(&db_connection, id)?.unwrap_or_default();
+        match RegistrationStatus::from_name(&current) {
+            Some(status) if status.can_change_to(RegistrationStatus::Cancelled) &&
+                update_registration_status(&db_connection, id, &current, RegistrationStatus::Cancelled.name())? => {
+                info!("Registration {}: cancelled by the participant", id);
+                status_change_mail(&db_connection, id, status, RegistrationStatus::Cancelled, &config)?
+            }
+            _ => return Ok("Diese Anmeldung kann nicht mehr storniert werden.")
+        }
+    };
+
+    if let Some(mail) = mail {
+        send_recorded(&mutex, &mail, &config)?;
+    }
+
+    Ok("Ihre Anmeldung wurde storniert.")
+}
@@ -0,0 +1,419 @@
+use std::collections::BTreeMap;
+
+use iron::prelude::{Request, IronResult, Response, Set};
+use iron::modifiers::RedirectRaw;
+use iron::status;
+
+use handlebars_iron::{Template};
+use serde_json::Value as JsonValue;
+use params::{Params, Map};
+use plugin::Pluggable;
+use persistent::{Read, Write};
+use rusqlite::Connection;
+
+use ::DBConnection;
+use admin::with_admin;
+use conference::CONFERENCE_KEYS;
+use config::Configuration;
+use database::{lock_connection, load_mail_template, save_mail_template, delete_mail_template, load_registration_names};
+use fee::format_amount;
+use handler::{HandleError, Registration, Title, Course, extract_string, load_registration, confirmation_values, router_param, not_found};
+use login::LINK_SECONDS;
+use dates::timestamp;
+
+
+// The texts that can be changed in the browser: name, title on the admin page and placeholders
+pub const MAIL_TEMPLATES: [(&str, &str, &[&str]); 11] = [
+    ("confirmation", "Anmeldebestätigung", &["greeting", "course", "category", "details", "links"]),
+    ("approval_received", "Anmeldung eingegangen (mit Freigabe)", &["greeting", "course", "category", "details", "links"]),
+    ("waitlist_received", "Anmeldung auf der Warteliste (ausgebucht)", &["greeting", "course", "category", "details", "links"]),
+    ("payment_first", "Erste Zahlungserinnerung", &["name", "amount", "deadline"]),
+    ("payment_deadline", "Zahlungserinnerung vor der Frist", &["name", "amount", "deadline"]),
+    ("status_confirmed", "Anmeldung bestätigt", &["greeting", "course"]),
+    ("status_promoted", "Platz von der Warteliste", &["greeting", "course"]),
+    ("status_waitlisted", "Auf der Warteliste", &["greeting", "course"]),
+    ("status_cancelled", "Anmeldung storniert", &["greeting", "course"]),
+    ("status_rejected", "Anmeldung abgelehnt", &["greeting", "course"]),
+    ("login_link", "Link zur eigenen Anmeldung", &["greeting", "course", "link", "minutes"])
+];
+
+const CONFIRMATION_SUBJECT: &str = "Anmeldungsbestaetigung: {conference} - {course}";
+// details are the social program, banquet tickets and fee, links the personal pages of the participant, each starting on a new line
+const CONFIRMATION_BODY: &str = "{greeting}\n\nSie haben sich fuer den folgenden Kurs angemeldet:\n\n \
+    Zeitpunkt: {course}\n Kategorie: {category}{details}{links}\n\nMit freundlichen Gruessen,\n{organizer}";
+
+// Instead of the confirmation with approval in [Basic], the decision follows as status_confirmed or status_rejected
+const APPROVAL_SUBJECT: &str = "Anmeldung eingegangen: {conference} - {course}";
+const APPROVAL_BODY: &str = "{greeting}\n\nIhre Anmeldung fuer den folgenden Kurs ist eingegangen:\n\n \
+    Zeitpunkt: {course}\n Kategorie: {category}{details}{links}\n\nDie Teilnehmerzahl ist begrenzt, das Organisationskomitee \
+    prueft die Anmeldungen. Sie erhalten eine weitere Nachricht, sobald ueber Ihre Teilnahme entschieden ist.\n\n\
+    Mit freundlichen Gruessen,\n{organizer}";
+
+// Sent instead of the confirmation once capacity in [Basic] is reached
+const WAITLIST_SUBJECT: &str = "Warteliste: {conference} - {course}";
+const WAITLIST_BODY: &str = "{greeting}\n\nder folgende Kurs ist leider ausgebucht:\n\n \
+    Zeitpunkt: {course}\n Kategorie: {category}{details}{links}\n\nSie stehen auf der Warteliste und bekommen eine Nachricht, \
+    sobald ein Platz frei wird.\n\nMit freundlichen Gruessen,\n{organizer}";
+
+// The templates sent right after the form, they take the values of confirmation_values
+const FORM_TEMPLATES: [&str; 3] = ["confirmation", "approval_received", "waitlist_received"];
+
+// Sent when an admin changes the status of a registration, see status.rs: name, subject and body
+const STATUS_TEMPLATES: [(&str, &str, &str); 5] = [
+    ("status_confirmed", "Anmeldung bestaetigt: {conference} - {course}",
+        "{greeting}\n\nIhre Anmeldung fuer den Kurs am {course} ist jetzt bestaetigt.\n\nMit freundlichen Gruessen,\n{organizer}"),
+    ("status_promoted", "Platz frei geworden: {conference} - {course}",
+        "{greeting}\n\nfuer den Kurs am {course} ist ein Platz frei geworden. Ihre Anmeldung von der Warteliste ist damit \
+        bestaetigt.\n\nMit freundlichen Gruessen,\n{organizer}"),
+    ("status_waitlisted", "Warteliste: {conference} - {course}",
+        "{greeting}\n\nder Kurs am {course} ist leider ausgebucht. Sie stehen auf der Warteliste und bekommen eine Nachricht, \
+        sobald ein Platz frei wird.\n\nMit freundlichen Gruessen,\n{organizer}"),
+    ("status_cancelled", "Stornierung: {conference} - {course}",
+        "{greeting}\n\nIhre Anmeldung fuer den Kurs am {course} wurde storniert.\n\nMit freundlichen Gruessen,\n{organizer}"),
+    ("status_rejected", "Ihre Anmeldung: {conference} - {course}",
+        "{greeting}\n\nleider koennen wir Ihnen fuer den Kurs am {course} keinen Platz anbieten. Vielen Dank fuer Ihr \
+        Interesse.\n\nMit freundlichen Gruessen,\n{organizer}")
+];
+
+// Sent when a participant asks for a link at /login, see login.rs
+const LOGIN_SUBJECT: &str = "Ihre Anmeldung: {conference} - {course}";
+const LOGIN_BODY: &str = "{greeting}\n\nmit dem folgenden Link koennen Sie Ihre Anmeldung ansehen oder stornieren:\n\n {link}\n\n\
+    Der Link ist {minutes} Minuten gueltig. Falls Sie ihn nicht angefordert haben, koennen Sie diese E-Mail ignorieren.\n\n\
+    Mit freundlichen Gruessen,\n{organizer}";
+
+#[derive(Debug, PartialEq)]
+pub struct MailTemplate {
+    pub subject: String,
+    pub body: String
+}
+
+// The built-in confirmation and the reminder texts of [Payment]
+fn default_template(name: &str, config: &Configuration) -> MailTemplate {
+    if let Some(&(_, subject, body)) = STATUS_TEMPLATES.iter().find(|&&(template, _, _)| template == name) {
+        return MailTemplate { subject: subject.to_string(), body: body.to_string() };
+    }
+
+    match name {
+        "payment_first" => MailTemplate { subject: config.payment_reminder_subject.clone(), body: config.payment_first_template.clone() },
+        "payment_deadline" => MailTemplate { subject: config.payment_reminder_subject.clone(), body: config.payment_deadline_template.clone() },
+        "login_link" => MailTemplate { subject: LOGIN_SUBJECT.to_string(), body: LOGIN_BODY.to_string() },
+        "approval_received" => MailTemplate { subject: APPROVAL_SUBJECT.to_string(), body: APPROVAL_BODY.to_string() },
+        "waitlist_received" => MailTemplate { subject: WAITLIST_SUBJECT.to_string(), body: WAITLIST_BODY.to_string() },
+        _ => MailTemplate { subject: CONFIRMATION_SUBJECT.to_string(), body: CONFIRMATION_BODY.to_string() }
+    }
+}
+
+// The text saved at /admin/mail_templates, otherwise the default
+pub fn mail_template(db_connection: &Connection, name: &str, config: &Configuration) -> Result<MailTemplate, HandleError> {
+    Ok(match load_mail_template(db_connection, name)? {
+        Some((subject, body)) => MailTemplate { subject, body },
+        None => default_template(name, config)
+    })
+}
+
+// Replaces {key} by its value, unknown placeholders are left as they are
+pub fn fill_template(text: &str, values: &[(&str, String)]) -> String {
+    values.iter().fold(text.to_string(), |text, &(key, ref value)| text.replace(&format!("{{{}}}", key), value))
+}
+
+// The placeholders of both payment reminders
+pub fn payment_values(name: &str, fee: i64, config: &Configuration) -> Vec<(&'static str, String)> {
+    let mut values = vec![
+        ("name", name.to_string()),
+        ("amount", format_amount(fee)),
+        ("deadline", config.date_format.date(&config.payment_deadline))
+    ];
+    values.extend(config.conference.mail_values());
+    values
+}
+
+// The placeholders of the status mails
+pub fn status_values(registration: &Registration, config: &Configuration) -> Vec<(&'static str, String)> {
+    let mut values = vec![
+        ("greeting", if registration.title == Title::Sir { format!("Sehr geehrter Herr {},", registration.last_name) } else { format!("Sehr geehrte Frau {},", registration.last_name) }),
+        ("course", if registration.course_type == Course::Course1 { config.course1.clone() } else { config.course2.clone() })
+    ];
+    values.extend(config.conference.mail_values());
+    values
+}
+
+// The placeholders of the mail with the link from /login
+pub fn login_values(registration: &Registration, link: &str, config: &Configuration) -> Vec<(&'static str, String)> {
+    let mut values = status_values(registration, config);
+    values.push(("link", link.to_string()));
+    values.push(("minutes", (LINK_SECONDS / 60).to_string()));
+    values
+}
+
+// Everything in braces that is still left after filling in, usually a misspelled placeholder
+fn unknown_placeholders(text: &str) -> Vec<String> {
+    let mut result: Vec<String> = Vec::new();
+
+    for (start, _) in text.match_indices('{') {
+        let rest = &text[start + 1..];
+        if let Some(end) = rest.find('}') {
+            let key = &rest[..end];
+            if !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '_') && !result.iter().any(|known| known == key) {
+                result.push(key.to_string());
+            }
+        }
+    }
+
+    result
+}
+
+// For the preview, no real participant's data is shown
+fn sample_values(name: &str, config: &Configuration) -> Vec<(&'static str, String)> {
+    let mut values = if FORM_TEMPLATES.contains(&name) {
+        vec![
+            ("greeting", "Sehr geehrte Frau Muster,".to_string()),
+            ("course", config.course1.clone()),
+            ("category", "Regulaer".to_string()),
+            ("details", format!("\n Gebuehr: {}", format_amount(12000))),
+            ("links", format!("\n\nIhre Angaben zum Beitrag und Ihre Hinweise an die Organisatoren koennen Sie hier aendern:\n {0}/edit/beispiel\
+                \n\nIhre Vortragsfolien koennen Sie vor der Veranstaltung hier hochladen:\n {0}/slides/beispiel", config.base_url))
+        ]
+    } else if name.starts_with("status_") {
+        vec![("greeting", "Sehr geehrte Frau Muster,".to_string()), ("course", config.course1.clone())]
+    } else if name == "login_link" {
+        vec![
+            ("greeting", "Sehr geehrte Frau Muster,".to_string()),
+            ("course", config.course1.clone()),
+            ("link", format!("{}/login/beispiel", config.base_url)),
+            ("minutes", (LINK_SECONDS / 60).to_string())
+        ]
+    } else {
+        return payment_values("Erika Muster", 12000, config);
+    };
+    values.extend(config.conference.mail_values());
+    values
+}
+
+fn template_json(name: &str, title: &str, placeholders: &[&str], template: &MailTemplate, customized: bool, config: &Configuration) -> JsonValue {
+    let values = sample_values(name, config);
+    let placeholders: Vec<String> = placeholders.iter().chain(CONFERENCE_KEYS.iter()).map(|key| format!("{{{}}}", key)).collect();
+
+    json!({
+        "name": name,
+        "title": title,
+        "placeholders": placeholders.join(", "),
+        "subject": template.subject,
+        "body": template.body,
+        "customized": customized,
+        "preview_subject": fill_template(&template.subject, &values),
+        "preview_body": fill_template(&template.body, &values)
+    })
+}
+
+pub fn handle_mail_templates(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "mail_templates", |req| mail_templates_page(req, None))
+}
+
+// The edited text replaces the stored one of its template, for the preview before saving
+fn mail_templates_page(req: &mut Request, edited: Option<(String, MailTemplate)>) -> Result<Response, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    let map = req.get::<Params>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let mut templates = Vec::new();
+
+    for &(name, title, placeholders) in MAIL_TEMPLATES.iter() {
+        let customized = load_mail_template(&db_connection, name)?.is_some();
+
+        let mut template = match edited {
+            Some((ref edited_name, ref template)) if edited_name == name => {
+                template_json(name, title, placeholders, template, customized, &config)
+            }
+            _ => template_json(name, title, placeholders, &mail_template(&db_connection, name, &config)?, customized, &config)
+        };
+
+        template["previewed"] = json!(edited.as_ref().is_some_and(|(edited_name, _)| edited_name == name));
+        templates.push(template);
+    }
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("templates".to_string(), json!(templates));
+    data.insert("saved".to_string(), json!(extract_string(&map, "saved").unwrap_or_default()));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("mail_templates", data)).set_mut(status::Ok);
+    Ok(resp)
+}
+
+// One form per template, the button decides between preview, save and reset to the default
+pub fn handle_mail_template_submit(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "mail_template_submit", change_mail_template)
+}
+
+fn submitted_template(map: &Map) -> Result<MailTemplate, HandleError> {
+    // Browsers send the line breaks of a textarea as CRLF
+    let subject = extract_string(map, "subject")?;
+    let body = extract_string(map, "body")?.replace("\r\n", "\n");
+
+    if subject.is_empty() || body.is_empty() {
+        return Err(HandleError::FormValue);
+    }
+
+    Ok(MailTemplate { subject, body })
+}
+
+fn change_mail_template(req: &mut Request) -> Result<Response, HandleError> {
+    let name = router_param(req, "name");
+
+    if !MAIL_TEMPLATES.iter().any(|&(template, _, _)| template == name) {
+        return Ok(not_found());
+    }
+
+    let map = req.get::<Params>()?;
+    let action = extract_string(&map, "action")?;
+
+    match action.as_str() {
+        "preview" => return mail_templates_page(req, Some((name, submitted_template(&map)?))),
+        "save" => {
+            let template = submitted_template(&map)?;
+            let mutex = req.get::<Write<DBConnection>>()?;
+            let db_connection = lock_connection(&mutex)?;
+            save_mail_template(&db_connection, &name, &template.subject, &template.body, &timestamp())?;
+            info!("Mail template '{}' changed", name);
+        }
+        "reset" => {
+            let mutex = req.get::<Write<DBConnection>>()?;
+            let db_connection = lock_connection(&mutex)?;
+            delete_mail_template(&db_connection, &name)?;
+            info!("Mail template '{}' reset", name);
+        }
+        _ => return Err(HandleError::FormValue)
+    }
+
+    Ok(Response::with((status::Found, RedirectRaw(format!("/admin/mail_templates?saved={}", name)))))
+}
+
+pub fn handle_email_preview(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "email_preview", email_preview)
+}
+
+// The stored text filled with the data of ?registration=ID or the sample data, nothing is sent
+fn email_preview(req: &mut Request) -> Result<Response, HandleError> {
+    let name = router_param(req, "template");
+
+    let title = match MAIL_TEMPLATES.iter().find(|&&(template, _, _)| template == name) {
+        Some(&(_, title, _)) => title,
+        None => return Ok(not_found())
+    };
+
+    let config = req.get::<Read<Configuration>>()?;
+    let map = req.get::<Params>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let selected = extract_string(&map, "registration").ok().and_then(|id| id.parse::<i64>().ok());
+    let values = match selected {
+        Some(id) => match load_registration(&db_connection, id)? {
+            Some((ref registration, ref token)) if FORM_TEMPLATES.contains(&name.as_str()) => {
+                confirmation_values(registration, token, &config)
+            }
+            Some((ref registration, _)) if name.starts_with("status_") => status_values(registration, &config),
+            Some((ref registration, _)) if name == "login_link" => login_values(registration, &format!("{}/login/beispiel", config.base_url), &config),
+            Some((ref registration, _)) => {
+                payment_values(&format!("{} {}", registration.first_name, registration.last_name), registration.fee, &config)
+            }
+            None => return Ok(not_found())
+        },
+        None => sample_values(&name, &config)
+    };
+
+    let template = mail_template(&db_connection, &name, &config)?;
+    let (subject, body) = (fill_template(&template.subject, &values), fill_template(&template.body, &values));
+    let unknown: Vec<String> = unknown_placeholders(&format!("{}\n{}", subject, body)).iter().map(|key| format!("{{{}}}", key)).collect();
+
+    let registrations: Vec<JsonValue> = load_registration_names(&db_connection)?.into_iter().map(|(id, registration_name)| json!({
+        "id": id,
+        "name": registration_name,
+        "selected": selected == Some(id)
+    })).collect();
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("name".to_string(), json!(name));
+    data.insert("title".to_string(), json!(title));
+    data.insert("registrations".to_string(), json!(registrations));
+    data.insert("sample".to_string(), json!(selected.is_none()));
+    data.insert("subject".to_string(), json!(subject));
+    data.insert("body".to_string(), json!(body));
+    data.insert("unknown".to_string(), json!(unknown.join(", ")));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("email_preview", data)).set_mut(status::Ok);
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{mail_template, default_template, fill_template, sample_values, unknown_placeholders, MailTemplate, MAIL_TEMPLATES, CONFIRMATION_BODY};
+    use config::example_configuration;
+    use database::{init_database, save_mail_template};
+    use rusqlite::Connection;
+
+    #[test]
+    fn test_fill_template() {
+        let values = vec![("name", "Bob Smith".to_string()), ("amount", "120,50 €".to_string())];
+
+        assert_eq!(fill_template("Hallo {name}, bitte ueberweisen Sie {amount}. {unknown}", &values),
+            "Hallo Bob Smith, bitte ueberweisen Sie 120,50 €. {unknown}".to_string());
+    }
+
+    #[test]
+    fn test_conference_values() {
+        let mut config = example_configuration();
+        config.conference.name = "Earthshape meeting".to_string();
+
+        for &(name, _, _) in MAIL_TEMPLATES.iter().filter(|&&(name, _, _)| !name.starts_with("payment_")) {
+            let template = default_template(name, &config);
+            let values = sample_values(name, &config);
+            let text = format!("{}\n{}", fill_template(&template.subject, &values), fill_template(&template.body, &values));
+
+            assert!(unknown_placeholders(&text).is_empty());
+            assert!(text.ends_with("Gruessen,\ndie Fortbildungsorganisation"));
+        }
+
+        let template = default_template("status_confirmed", &config);
+        assert!(fill_template(&template.subject, &sample_values("status_confirmed", &config)).contains("Earthshape meeting"));
+    }
+
+    #[test]
+    fn test_mail_template() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+        let mut config = example_configuration();
+        config.payment_reminder_subject = "Erinnerung".to_string();
+        config.payment_first_template = "Hallo {name}".to_string();
+
+        assert_eq!(mail_template(&conn, "confirmation", &config).unwrap().body, CONFIRMATION_BODY.to_string());
+        assert_eq!(mail_template(&conn, "payment_first", &config).unwrap(),
+            MailTemplate { subject: "Erinnerung".to_string(), body: "Hallo {name}".to_string() });
+
+        save_mail_template(&conn, "payment_first", "Zahlung", "Guten Tag {name}", "2017-01-10 12:00:00").unwrap();
+        assert_eq!(mail_template(&conn, "payment_first", &config).unwrap(),
+            MailTemplate { subject: "Zahlung".to_string(), body: "Guten Tag {name}".to_string() });
+    }
+
+    #[test]
+    fn test_sample_values() {
+        let config = example_configuration();
+
+        // Every placeholder shows up in the preview
+        for &(name, _, placeholders) in MAIL_TEMPLATES.iter() {
+            let values = sample_values(name, &config);
+            assert!(placeholders.iter().all(|key| values.iter().any(|&(value_key, _)| value_key == *key)));
+        }
+    }
+
+    #[test]
+    fn test_unknown_placeholders() {
+        assert_eq!(unknown_placeholders("Hallo {nmae}, {amount} bis {deadline_} {nmae}"),
+            vec!["nmae".to_string(), "amount".to_string(), "deadline_".to_string()]);
+
+        // Braces in normal text are no placeholders
+        assert!(unknown_placeholders("Preise {siehe Webseite} { } {} ohne Ende {").is_empty());
+    }
+}
@@ -0,0 +1,194 @@
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use chrono::Local;
+use lettre::email::{Email, SendableEmail, SimpleSendableEmail};
+use lettre::transport::smtp::{SecurityLevel, SmtpTransportBuilder};
+use lettre::transport::smtp::authentication::Mechanism;
+use lettre::transport::smtp::SUBMISSION_PORT;
+use lettre::transport::EmailTransport;
+
+use config::Configuration;
+#[cfg(feature = "dkim")]
+use dkim::sign_message;
+use handler::HandleError;
+use http::post_authorized;
+
+
+pub const MAIL_TRANSPORTS: [&str; 4] = ["smtp", "http", "file", "none"];
+
+static FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A mail ready to leave the server: the envelope and the complete message, DKIM signed if configured
+#[derive(Debug, PartialEq)]
+pub struct OutgoingMail {
+    pub from: String,
+    pub to: Vec<String>,
+    pub message: String
+}
+
+impl OutgoingMail {
+    pub fn new(email: Email, config: &Configuration) -> Result<OutgoingMail, HandleError> {
+        Ok(OutgoingMail { from: email.from_address(), to: email.to_addresses(), message: signed(email.message(), config)? })
+    }
+}
+
+#[cfg(feature = "dkim")]
+fn signed(message: String, config: &Configuration) -> Result<String, HandleError> {
+    if config.dkim_private_key.is_empty() {
+        return Ok(message);
+    }
+
+    let signed = sign_message(&message, &config.dkim_domain, &config.dkim_selector, &config.dkim_private_key, Local::now().timestamp())?;
+    Ok(signed)
+}
+
+// load_configuration already warned about a [DKIM] section
+#[cfg(not(feature = "dkim"))]
+fn signed(message: String, _config: &Configuration) -> Result<String, HandleError> {
+    Ok(message)
+}
+
+// How mails leave the server, transport in [EMail] picks one. A new way only needs an implementation
+// and an entry in configured_mailer.
+pub trait Mailer {
+    // Used in the log
+    fn name(&self) -> &'static str;
+    fn send(&self, mail: &OutgoingMail, config: &Configuration) -> Result<(), HandleError>;
+}
+
+// The mail server of [EMail], TLS and CRAM-MD5 are required
+pub struct SmtpMailer;
+
+impl Mailer for SmtpMailer {
+    fn name(&self) -> &'static str {
+        "smtp"
+    }
+
+    fn send(&self, mail: &OutgoingMail, config: &Configuration) -> Result<(), HandleError> {
+        let host_ip = Ipv4Addr::from_str(&config.email_server)?;
+
+        let mut mailer = SmtpTransportBuilder::new((host_ip, SUBMISSION_PORT))?
+            .hello_name(&config.email_hello)
+            .credentials(&config.email_username, &config.email_password)
+            .security_level(SecurityLevel::AlwaysEncrypt)
+            .smtp_utf8(true)
+            .authentication_mechanism(Mechanism::CramMd5)
+            .connection_reuse(true).build();
+
+        mailer.send(SimpleSendableEmail::new(&mail.from, mail.to.clone(), &mail.message))?;
+        Ok(())
+    }
+}
+
+// A mail service where the server can't reach SMTP: the message is posted as JSON with from, to and
+// message, the key goes into the Authorization header
+pub struct HttpMailer {
+    url: String,
+    key: String
+}
+
+impl Mailer for HttpMailer {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    fn send(&self, mail: &OutgoingMail, _config: &Configuration) -> Result<(), HandleError> {
+        let authorization = if self.key.is_empty() { String::new() } else { format!("Bearer {}", self.key) };
+        let payload = json!({ "from": mail.from, "to": mail.to, "message": mail.message });
+
+        post_authorized(&self.url, "application/json", &authorization, &payload.to_string())?;
+        Ok(())
+    }
+}
+
+/// The number in the name of a file of transport = file, counting up with every mail the process writes
+pub fn mail_file_number(file_name: &str) -> Option<usize> {
+    if !file_name.ends_with(".eml") {
+        return None;
+    }
+
+    file_name[..file_name.len() - 4].rsplit('-').next().and_then(|number| number.parse::<usize>().ok())
+}
+
+// One .eml file per mail in mail_folder, for a staging server or a mail client that picks them up
+pub struct FileMailer {
+    folder: String
+}
+
+impl Mailer for FileMailer {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn send(&self, mail: &OutgoingMail, _config: &Configuration) -> Result<(), HandleError> {
+        fs::create_dir_all(&self.folder)?;
+
+        // The counter keeps mails of the same second apart
+        let file_name = format!("{}-{}.eml", Local::now().format("%Y%m%d-%H%M%S"), FILE_COUNTER.fetch_add(1, Ordering::SeqCst));
+        fs::write(Path::new(&self.folder).join(file_name), &mail.message)?;
+        Ok(())
+    }
+}
+
+// Only logged (recipients and size)
+pub struct NoMailer;
+
+impl Mailer for NoMailer {
+    fn name(&self) -> &'static str {
+        "none"
+    }
+
+    fn send(&self, mail: &OutgoingMail, _config: &Configuration) -> Result<(), HandleError> {
+        info!("transport none in [EMail]: mail to {} not sent, {} bytes", mail.to.join(", "), mail.message.len());
+        Ok(())
+    }
+}
+
+// The transport of [EMail], the config only accepts the names in MAIL_TRANSPORTS
+pub fn configured_mailer(config: &Configuration) -> Box<dyn Mailer> {
+    match config.email_transport.as_str() {
+        "http" => Box::new(HttpMailer { url: config.email_api_url.clone(), key: config.email_api_key.clone() }),
+        "file" => Box::new(FileMailer { folder: config.email_folder.clone() }),
+        "none" => Box::new(NoMailer),
+        _ => Box::new(SmtpMailer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{configured_mailer, mail_file_number, OutgoingMail};
+    use config::example_configuration;
+    use std::fs;
+
+    #[test]
+    fn test_file_mailer() {
+        let folder = "test_mailer_folder";
+        let _ = fs::remove_dir_all(folder);
+
+        let mut config = example_configuration();
+        config.email_transport = "file".to_string();
+        config.email_folder = folder.to_string();
+
+        let mailer = configured_mailer(&config);
+        assert_eq!(mailer.name(), "file");
+
+        let mail = OutgoingMail { from: "bob@smith.com".to_string(), to: vec!["alice@example.org".to_string()],
+            message: "Subject: Test\r\n\r\nHallo".to_string() };
+        mailer.send(&mail, &config).unwrap();
+        mailer.send(&mail, &config).unwrap();
+
+        let files: Vec<_> = fs::read_dir(folder).unwrap().map(|entry| entry.unwrap().path()).collect();
+        assert_eq!(files.len(), 2);
+        assert!(files.iter().all(|file| file.extension().unwrap() == "eml"));
+        assert_eq!(fs::read_to_string(&files[0]).unwrap(), mail.message);
+        assert!(files.iter().all(|file| mail_file_number(&file.file_name().unwrap().to_string_lossy()).is_some()));
+        assert_eq!(mail_file_number("20170115-090000-12.eml"), Some(12));
+        assert_eq!(mail_file_number("notes.txt"), None);
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
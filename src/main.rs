@@ -1,89 +1,266 @@
-extern crate iron;
-extern crate router;
-extern crate mount;
-extern crate staticfile;
-extern crate rusqlite;
-extern crate handlebars_iron;
-extern crate params;
-extern crate plugin;
-#[macro_use] extern crate log;
+extern crate conference_registration;
 extern crate simplelog;
-extern crate persistent;
-extern crate lettre;
-extern crate ini;
+#[cfg(unix)] extern crate libc;
 
-// System modules
-
-use std::error::Error;
-use std::path::Path;
+use std::env;
 use std::fs::File;
+use std::path::Path;
+use std::process;
 
-// External modules
+use simplelog::{WriteLogger, SimpleLogger, CombinedLogger, SharedLogger, LogLevelFilter, Config};
 
-use iron::prelude::{Iron, Chain};
-use iron::typemap::Key;
-use router::Router;
-use mount::Mount;
-use staticfile::Static;
-use rusqlite::Connection;
-use handlebars_iron::{HandlebarsEngine, DirectorySource};
-use simplelog::{WriteLogger, LogLevelFilter, Config};
-use persistent::{Read, Write};
+use conference_registration::{load_configuration, bundled_configuration, in_memory_configuration, run_server, init_site, dump, restore, Configuration, TimestampWriter, AlertLogger};
 
+mod daemon;
 
-// Local modules
 
-mod config;
-mod handler;
+const USAGE: &str = "Usage: conference_registration [--config FILE] [--daemon [--pid-file FILE]] [--service] [--in-memory]
+       conference_registration init FOLDER
+       conference_registration [--config FILE] dump FILE
+       conference_registration [--config FILE] restore FILE
 
-use config::{load_configuration, Configuration};
-use handler::{handle_main, handle_submit};
+    init FOLDER      set up a new site: example configuration, templates, scripts, styles and database
+    dump FILE        write all tables of the database to a JSON file
+    restore FILE     read a JSON file written by dump into an empty database
 
-pub struct DBConnection;
+    --config FILE    configuration to use, default registration_config.ini
+    --daemon         run in the background (Unix)
+    --pid-file FILE  where --daemon writes the process id, default registration.pid
+    --service        run under the Windows service manager, from the folder of the executable
+    --in-memory      try out or test the server: empty database in memory, mails only logged, built-in templates,
+                     the example configuration if FILE doesn't exist";
 
-impl Key for DBConnection { type Value = Connection; }
+// Done instead of running the server
+#[derive(Debug, PartialEq)]
+enum Command {
+    Init(String),
+    Dump(String),
+    Restore(String)
+}
 
-impl Key for Configuration { type Value = Configuration; }
+#[derive(Debug, PartialEq)]
+struct Options {
+    command: Option<Command>,
+    config_file: String,
+    daemon: bool,
+    pid_file: String,
+    service: bool,
+    in_memory: bool
+}
 
-fn main() {
-    let _ = WriteLogger::init(LogLevelFilter::Info, Config::default(), File::create("registration.log").unwrap());
+fn parse_args<I: Iterator<Item=String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options {
+        command: None,
+        config_file: "registration_config.ini".to_string(),
+        daemon: false,
+        pid_file: "registration.pid".to_string(),
+        service: false,
+        in_memory: false
+    };
 
-    let config_file = "registration_config.ini";
-    let config = match load_configuration(config_file) {
-        Ok(configuration) => configuration,
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "init" if options.command.is_none() => options.command = Some(Command::Init(args.next().ok_or("init needs a folder")?)),
+            "dump" if options.command.is_none() => options.command = Some(Command::Dump(args.next().ok_or("dump needs a file name")?)),
+            "restore" if options.command.is_none() => options.command = Some(Command::Restore(args.next().ok_or("restore needs a file name")?)),
+            "--config" => options.config_file = args.next().ok_or("--config needs a file name")?,
+            "--daemon" => options.daemon = true,
+            "--pid-file" => options.pid_file = args.next().ok_or("--pid-file needs a file name")?,
+            "--service" => options.service = true,
+            "--in-memory" => options.in_memory = true,
+            _ => return Err(format!("Unknown argument: '{}'", arg))
+        }
+    }
+
+    if options.daemon && options.service {
+        return Err("--daemon and --service can't be combined".to_string());
+    }
+
+    if options.command.is_some() && (options.daemon || options.service) {
+        return Err("init, dump and restore don't run in the background".to_string());
+    }
+
+    if options.command.is_some() && options.in_memory {
+        return Err("--in-memory only applies to the server".to_string());
+    }
+
+    Ok(options)
+}
+
+fn options() -> Options {
+    match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    }
+}
+
+fn init_logging(config: &Configuration) {
+    let mut loggers: Vec<Box<dyn SharedLogger>> = Vec::new();
+
+    if config.log_target == "file" || config.log_target == "both" {
+        match File::create(&config.log_file) {
+            // The date and time of [Dates] instead of simplelog's time of day
+            Ok(file) => loggers.push(WriteLogger::new(LogLevelFilter::Info, Config { time: None, ..Config::default() },
+                TimestampWriter::new(file, config.date_format.clone()))),
+            Err(e) => eprintln!("Could not create log file '{}': {}", config.log_file, e)
+        }
+    }
+
+    if config.log_target == "stdout" || config.log_target == "both" {
+        loggers.push(SimpleLogger::new(LogLevelFilter::Info, Config::default()));
+    }
+
+    if !config.alert_emails.is_empty() {
+        loggers.push(AlertLogger::new(config));
+    }
+
+    let _ = CombinedLogger::init(loggers);
+}
+
+// Also the entry point of the Windows service, which can't be given any arguments
+fn serve() {
+    let options = options();
+    let config_file = options.config_file;
+
+    let config = if options.in_memory && !Path::new(&config_file).exists() {
+        bundled_configuration()
+    } else {
+        load_configuration(&config_file)
+    };
+
+    let config = match config {
+        Ok(configuration) => if options.in_memory { in_memory_configuration(configuration) } else { configuration },
         Err(_) => panic!("Could not open configuration file: '{}'", config_file)
     };
 
-    let db_conn = Connection::open(&config.db_filename).unwrap();
+    init_logging(&config);
 
-    let mut hbse = HandlebarsEngine::new();
-    hbse.add(Box::new(DirectorySource::new(&config.template_folder, ".hbs")));
+    if let Err(e) = run_server(config) {
+        panic!("Could not start the registration server: {:?}", e);
+    }
+}
 
-    if let Err(r) = hbse.reload() {
-        panic!("{}", r.description());
+#[cfg(unix)]
+fn run_in_background(options: &Options) {
+    if options.service {
+        eprintln!("--service is only available on Windows, use --daemon");
+        process::exit(2);
     }
 
-    let mut router = Router::new();
+    if let Err(e) = daemon::daemonize(&options.pid_file) {
+        eprintln!("Could not start as daemon: {}", e);
+        process::exit(1);
+    }
 
-    router.get("/", handle_main, "index");
-    router.post("/", handle_main, "index");
+    serve();
+}
 
-    router.get("/submit", handle_submit, "submit");
-    router.post("/submit", handle_submit, "submit");
+#[cfg(windows)]
+fn run_in_background(options: &Options) {
+    if options.daemon {
+        eprintln!("--daemon is only available on Unix, install the server as a service and use --service");
+        process::exit(2);
+    }
 
-    let mut mount = Mount::new();
+    if let Err(e) = daemon::run_as_service(serve) {
+        eprintln!("Could not connect to the service manager: {}", e);
+        process::exit(1);
+    }
+}
 
-    mount.mount("/", router);
-    mount.mount("/css/", Static::new(Path::new("css/")));
+fn init(folder: &str) {
+    match init_site(Path::new(folder)) {
+        Ok(written) => {
+            for file in &written {
+                println!("Created {}", file);
+            }
+            println!("Edit {} and start the server there", Path::new(folder).join("registration_config.ini").display());
+        }
+        Err(e) => {
+            eprintln!("Could not set up '{}': {:?}", folder, e);
+            process::exit(1);
+        }
+    }
+}
 
-    let mut chain1 = Chain::new(mount);
-    chain1.link_after(hbse);
+// The server should not be running during a restore, it wouldn't see the new data in its caches
+fn dump_or_restore(command: &Command, config_file: &str) {
+    let config = match load_configuration(config_file) {
+        Ok(configuration) => configuration,
+        Err(e) => {
+            eprintln!("Could not open configuration file '{}': {:?}", config_file, e);
+            process::exit(1);
+        }
+    };
 
-    let mut chain2 = Chain::new(chain1);
-    chain2.link(Write::<DBConnection>::both(db_conn));
+    let result = match *command {
+        Command::Dump(ref file) => dump(&config.db_filename, file).map(|count| format!("Wrote {} rows to {}", count, file)),
+        Command::Restore(ref file) => restore(&config.db_filename, file).map(|count| format!("Restored {} rows into {}", count, config.db_filename)),
+        Command::Init(_) => return
+    };
 
-    let mut chain3 = Chain::new(chain2);
-    chain3.link(Read::<Configuration>::both(config.clone()));
+    match result {
+        Ok(message) => println!("{}", message),
+        Err(e) => {
+            eprintln!("Failed: {:?}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn main() {
+    let options = options();
+
+    if let Some(Command::Init(ref folder)) = options.command {
+        init(folder);
+    } else if let Some(ref command) = options.command {
+        dump_or_restore(command, &options.config_file);
+    } else if options.daemon || options.service {
+        run_in_background(&options);
+    } else {
+        serve();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_args, Options, Command};
 
-    Iron::new(chain3).http(&config.socket_addr).unwrap();
+    fn args(values: &[&str]) -> ::std::vec::IntoIter<String> {
+        values.iter().map(|value| value.to_string()).collect::<Vec<String>>().into_iter()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(parse_args(args(&[])).unwrap(), Options {
+            command: None,
+            config_file: "registration_config.ini".to_string(),
+            daemon: false,
+            pid_file: "registration.pid".to_string(),
+            service: false,
+            in_memory: false
+        });
+
+        let options = parse_args(args(&["--daemon", "--pid-file", "/run/registration.pid", "--config", "/etc/registration.ini"])).unwrap();
+        assert!(options.daemon);
+        assert_eq!(options.pid_file, "/run/registration.pid".to_string());
+        assert_eq!(options.config_file, "/etc/registration.ini".to_string());
+
+        assert_eq!(parse_args(args(&["init", "site"])).unwrap().command, Some(Command::Init("site".to_string())));
+        assert_eq!(parse_args(args(&["--config", "site.ini", "dump", "backup.json"])).unwrap().command, Some(Command::Dump("backup.json".to_string())));
+        assert_eq!(parse_args(args(&["restore", "backup.json"])).unwrap().command, Some(Command::Restore("backup.json".to_string())));
+
+        assert!(parse_args(args(&["--config"])).is_err());
+        assert!(parse_args(args(&["init"])).is_err());
+        assert!(parse_args(args(&["--daemon", "--service"])).is_err());
+        assert!(parse_args(args(&["--daemon", "dump", "backup.json"])).is_err());
+        assert!(parse_args(args(&["dump", "a.json", "restore", "b.json"])).is_err());
+        assert!(parse_args(args(&["--verbose"])).is_err());
+
+        assert!(parse_args(args(&["--in-memory", "--config", "site.ini"])).unwrap().in_memory);
+        assert!(parse_args(args(&["--in-memory", "dump", "backup.json"])).is_err());
+    }
 }
@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+
+use iron::prelude::{Request, IronResult, Response, Set};
+use iron::modifiers::RedirectRaw;
+use iron::status;
+
+use handlebars_iron::{Template};
+use serde_json::Value as JsonValue;
+use params::Params;
+use plugin::Pluggable;
+use persistent::{Read, Write};
+
+use ::DBConnection;
+use admin::with_admin;
+use config::Configuration;
+use database::{lock_connection, suppress_by_token, load_newsletter_subscribers, load_bulk_recipients};
+use export::{export_response, Table};
+use filter::RegistrationFilter;
+use handler::{HandleError, extract_string, send_bulk_email, router_param, not_found};
+use dates::timestamp;
+
+
+fn unsubscribe_url(config: &Configuration, token: &str) -> String {
+    format!("{}/unsubscribe/{}", config.base_url, token)
+}
+
+fn render_unsubscribe_page(token: &str, message: &str) -> Response {
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("token".to_string(), json!(token));
+    data.insert("message".to_string(), json!(message));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("unsubscribe", data)).set_mut(status::Ok);
+    resp
+}
+
+// Only shows a button, mail scanners that follow the link must not unsubscribe anybody.
+// Mail clients with one-click unsubscribe (RFC 8058) post to the same address directly.
+pub fn handle_unsubscribe(req: &mut Request) -> IronResult<Response> {
+    let token = router_param(req, "token");
+
+    if token.is_empty() {
+        return Ok(not_found());
+    }
+
+    Ok(render_unsubscribe_page(&token, ""))
+}
+
+pub fn handle_unsubscribe_submit(req: &mut Request) -> IronResult<Response> {
+    let token = router_param(req, "token");
+
+    let message = match unsubscribe(req, &token) {
+        Ok(true) => {
+            info!("Address added to the suppression list");
+            "Sie erhalten keine Rundmails mehr von uns."
+        }
+        Ok(false) => "Dieser Link ist ungültig oder die Adresse wurde bereits abgemeldet.",
+        Err(e) => {
+            error!("Error while unsubscribing: {:?}", e);
+            "Ein Fehler ist aufgetreten. Bitte versuchen Sie es später noch einmal."
+        }
+    };
+
+    Ok(render_unsubscribe_page("", message))
+}
+
+fn unsubscribe(req: &mut Request, token: &str) -> Result<bool, HandleError> {
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    Ok(suppress_by_token(&db_connection, token, &timestamp())?)
+}
+
+pub fn handle_announcement(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "announcement", announcement_page)
+}
+
+fn announcement_page(req: &mut Request) -> Result<Response, HandleError> {
+    let map = req.get::<Params>()?;
+    let filter = RegistrationFilter::from_map(&map);
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("recipients".to_string(), json!(load_bulk_recipients(&db_connection, &filter)?.len()));
+    data.insert("filter".to_string(), filter.form_json());
+    data.insert("sent".to_string(), json!(extract_string(&map, "sent").unwrap_or_default()));
+    data.insert("failed".to_string(), json!(extract_string(&map, "failed").unwrap_or_default()));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("announcement", data)).set_mut(status::Ok);
+    Ok(resp)
+}
+
+// Goes to every address the filter of the participant list selects that has neither bounced nor been unsubscribed
+pub fn handle_announcement_send(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "announcement_send", send_announcement)
+}
+
+fn send_announcement(req: &mut Request) -> Result<Response, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    let map = req.get::<Params>()?;
+    let subject = extract_string(&map, "subject")?;
+    let body = extract_string(&map, "body")?;
+    let filter = RegistrationFilter::from_map(&map);
+
+    if subject.is_empty() || body.is_empty() {
+        return Err(HandleError::FormValue);
+    }
+
+    let recipients = {
+        let mutex = req.get::<Write<DBConnection>>()?;
+        let db_connection = lock_connection(&mutex)?;
+        load_bulk_recipients(&db_connection, &filter)?
+    };
+
+    let mut sent = 0;
+    let mut failed = 0;
+
+    for recipient in recipients {
+        match send_bulk_email(&recipient.email_to, &subject, &body, &unsubscribe_url(&config, &recipient.token), &config) {
+            Ok(_) => sent += 1,
+            Err(e) => {
+                error!("Could not send announcement to '{}': {:?}", recipient.email_to, e);
+                failed += 1;
+            }
+        }
+    }
+
+    info!("Announcement '{}' sent to {} addresses, {} failed", subject, sent, failed);
+
+    let query = filter.query_string();
+    let location = format!("/admin/announcement?sent={}&failed={}{}{}", sent, failed, if query.is_empty() { "" } else { "&" }, query);
+    Ok(Response::with((status::Found, RedirectRaw(location))))
+}
+
+// Only the opted-in addresses, each with its personal unsubscribe link for the announcement
+pub fn handle_export_newsletter(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "export_newsletter", export_newsletter)
+}
+
+fn export_newsletter(req: &mut Request) -> Result<Response, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let mut table = Table::new("newsletter", &["email", "name", "unsubscribe_url"]);
+
+    for subscriber in load_newsletter_subscribers(&db_connection)? {
+        table.push(&[&subscriber.email, &subscriber.name, &unsubscribe_url(&config, &subscriber.token)]);
+    }
+
+    export_response(req, &table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::unsubscribe_url;
+    use config::example_configuration;
+
+    #[test]
+    fn test_unsubscribe_url() {
+        assert_eq!(unsubscribe_url(&example_configuration(), "abc123"), "http://127.0.0.1:1234/unsubscribe/abc123".to_string());
+    }
+}
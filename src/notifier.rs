@@ -0,0 +1,219 @@
+use serde_json::Value as JsonValue;
+
+use accounting::invoice_number;
+use config::Configuration;
+use fee::format_amount;
+use handler::{send_mail, Registration, Course, HandleError};
+use http::post;
+
+
+pub const NOTIFICATION_CHANNELS: [&str; 4] = ["email", "webhook", "slack", "log"];
+
+// Runs after a registration was stored. A new channel only needs an implementation
+// and an entry in configured_notifiers.
+pub trait Notifier {
+    // Used in the log
+    fn name(&self) -> &'static str;
+    // The token gives access to the participant's personal pages, it must not leave the confirmation email
+    fn notify(&self, registration: &Registration, id: i64, token: &str, config: &Configuration) -> Result<(), HandleError>;
+}
+
+// What the organizers' channels learn about a new registration
+#[derive(Debug, PartialEq)]
+struct Summary {
+    code: String,
+    name: String,
+    email: String,
+    institution: String,
+    course: String,
+    fee: String
+}
+
+fn summary(registration: &Registration, id: i64, config: &Configuration) -> Summary {
+    Summary {
+        code: invoice_number(config, id),
+        name: format!("{} {}", registration.first_name, registration.last_name),
+        email: registration.email_to.clone(),
+        institution: registration.institution.clone(),
+        course: if registration.course_type == Course::Course1 { config.course1.clone() } else { config.course2.clone() },
+        fee: format_amount(registration.fee)
+    }
+}
+
+fn webhook_payload(summary: &Summary) -> JsonValue {
+    json!({
+        "event": "registration",
+        "code": summary.code,
+        "name": summary.name,
+        "email": summary.email,
+        "institution": summary.institution,
+        "course": summary.course,
+        "fee": summary.fee
+    })
+}
+
+// Slack's incoming webhooks take the message as "text"
+fn slack_payload(summary: &Summary) -> JsonValue {
+    json!({
+        "text": format!("Neue Anmeldung {}: {} ({}), {}", summary.code, summary.name, summary.institution, summary.course)
+    })
+}
+
+// The confirmation email to the participant
+pub struct EmailNotifier;
+
+impl Notifier for EmailNotifier {
+    fn name(&self) -> &'static str {
+        "email"
+    }
+
+    fn notify(&self, registration: &Registration, id: i64, token: &str, config: &Configuration) -> Result<(), HandleError> {
+        send_mail(registration, id, token, config)
+    }
+}
+
+pub struct WebhookNotifier {
+    url: String
+}
+
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    fn notify(&self, registration: &Registration, id: i64, _token: &str, config: &Configuration) -> Result<(), HandleError> {
+        post(&self.url, "application/json", &webhook_payload(&summary(registration, id, config)).to_string())?;
+        Ok(())
+    }
+}
+
+pub struct SlackNotifier {
+    url: String
+}
+
+impl Notifier for SlackNotifier {
+    fn name(&self) -> &'static str {
+        "slack"
+    }
+
+    fn notify(&self, registration: &Registration, id: i64, _token: &str, config: &Configuration) -> Result<(), HandleError> {
+        post(&self.url, "application/json", &slack_payload(&summary(registration, id, config)).to_string())?;
+        Ok(())
+    }
+}
+
+pub struct LogNotifier;
+
+impl Notifier for LogNotifier {
+    fn name(&self) -> &'static str {
+        "log"
+    }
+
+    fn notify(&self, registration: &Registration, id: i64, _token: &str, config: &Configuration) -> Result<(), HandleError> {
+        let summary = summary(registration, id, config);
+        info!("New registration {}: {} ({}), {}", summary.code, summary.name, summary.institution, summary.course);
+        Ok(())
+    }
+}
+
+// The channels of [Notifications], the config only accepts the names in NOTIFICATION_CHANNELS
+pub fn configured_notifiers(config: &Configuration) -> Vec<Box<dyn Notifier>> {
+    config.notification_channels.iter().filter_map(|channel| -> Option<Box<dyn Notifier>> {
+        match channel.as_str() {
+            "email" => Some(Box::new(EmailNotifier)),
+            "webhook" => Some(Box::new(WebhookNotifier { url: config.webhook_url.clone() })),
+            "slack" => Some(Box::new(SlackNotifier { url: config.slack_webhook_url.clone() })),
+            "log" => Some(Box::new(LogNotifier)),
+            _ => None
+        }
+    }).collect()
+}
+
+// Every channel is tried, a failing webhook doesn't keep the confirmation email from being sent.
+// The registration is already stored, so failures are only logged. Returns how many channels failed.
+pub fn notify_all(notifiers: &[Box<dyn Notifier>], registration: &Registration, id: i64, token: &str, config: &Configuration) -> usize {
+    notifiers.iter().filter(|notifier| match notifier.notify(registration, id, token, config) {
+        Ok(()) => false,
+        Err(e) => {
+            error!("Notification '{}' for registration {} failed: {:?}", notifier.name(), id, e);
+            true
+        }
+    }).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use super::{configured_notifiers, notify_all, webhook_payload, slack_payload, Notifier, Summary};
+    use config::{example_configuration, Configuration};
+    use database::init_database;
+    use handler::{load_registration, Registration, HandleError};
+    use rusqlite::Connection;
+
+    struct TestNotifier {
+        fails: bool,
+        calls: Rc<Cell<usize>>
+    }
+
+    impl Notifier for TestNotifier {
+        fn name(&self) -> &'static str {
+            "test"
+        }
+
+        fn notify(&self, _registration: &Registration, _id: i64, _token: &str, _config: &Configuration) -> Result<(), HandleError> {
+            self.calls.set(self.calls.get() + 1);
+            if self.fails { Err(HandleError::NotFound) } else { Ok(()) }
+        }
+    }
+
+    #[test]
+    fn test_configured_notifiers() {
+        let mut config = example_configuration();
+        let names: Vec<&str> = configured_notifiers(&config).iter().map(|notifier| notifier.name()).collect();
+        assert_eq!(names, vec!["email"]);
+
+        config.notification_channels = vec!["log".to_string(), "slack".to_string(), "webhook".to_string()];
+        let names: Vec<&str> = configured_notifiers(&config).iter().map(|notifier| notifier.name()).collect();
+        assert_eq!(names, vec!["log", "slack", "webhook"]);
+    }
+
+    #[test]
+    fn test_payloads() {
+        let summary = Summary {
+            code: "00042".to_string(),
+            name: "Bob Smith".to_string(),
+            email: "bob@smith.com".to_string(),
+            institution: "Some university".to_string(),
+            course: "Course 1".to_string(),
+            fee: "120,00 €".to_string()
+        };
+
+        assert_eq!(webhook_payload(&summary)["code"], json!("00042"));
+        assert_eq!(webhook_payload(&summary)["email"], json!("bob@smith.com"));
+        assert_eq!(slack_payload(&summary), json!({ "text": "Neue Anmeldung 00042: Bob Smith (Some university), Course 1" }));
+    }
+
+    #[test]
+    fn test_notify_all() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+        conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+                  city, phone, email_to, more_info, price_category, course_type)
+                  VALUES ('sir', 'Smith', 'Bob', 'Uni', 'Street', '1', '12345', 'City', '123', 'bob@smith.com', '', 'student', 'course1')",
+                  &[]).unwrap();
+        let (registration, token) = load_registration(&conn, 1).unwrap().unwrap();
+
+        // The channels after a failing one still get the registration
+        let calls = Rc::new(Cell::new(0));
+        let notifiers: Vec<Box<dyn Notifier>> = vec![
+            Box::new(TestNotifier { fails: true, calls: calls.clone() }),
+            Box::new(TestNotifier { fails: false, calls: calls.clone() }),
+            Box::new(TestNotifier { fails: true, calls: calls.clone() })
+        ];
+
+        assert_eq!(notify_all(&notifiers, &registration, 1, &token, &example_configuration()), 2);
+        assert_eq!(calls.get(), 3);
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use iron::prelude::{Request, IronResult, Response, Set};
+use iron::status;
+
+use handlebars_iron::{Template};
+use serde_json::Value as JsonValue;
+use plugin::Pluggable;
+use persistent::{Read, Write};
+use rusqlite::Connection;
+
+use ::DBConnection;
+use admin::with_admin;
+use config::Configuration;
+use database::{lock_connection, insert_outbox, mark_outbox_result, load_outbox, OutboxMail};
+use handler::{HandleError, send_email};
+use dates::timestamp;
+
+
+// The page only shows the latest mails, the table keeps all of them
+const OUTBOX_PAGE_SIZE: u32 = 200;
+
+// Records the mail, sends it and stores the answer. The lock is not held while the mail server is busy,
+// and a failing mail server doesn't undo whatever the mail is about.
+pub fn send_recorded(mutex: &Mutex<Connection>, mail: &OutboxMail, config: &Configuration) -> Result<(), HandleError> {
+    let id = insert_outbox(&*lock_connection(mutex)?, mail)?;
+
+    let error = match send_email(&mail.email_to, &mail.subject, &mail.body, config) {
+        Ok(()) => String::new(),
+        Err(e) => {
+            error!("Mail '{}' to registration {} not sent: {:?}", mail.template, mail.registration_id, e);
+            format!("{:?}", e)
+        }
+    };
+
+    let sent_at = if error.is_empty() { timestamp() } else { String::new() };
+    mark_outbox_result(&*lock_connection(mutex)?, id, &sent_at, &error)?;
+    Ok(())
+}
+
+pub fn handle_outbox(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "outbox", outbox_page)
+}
+
+fn outbox_page(req: &mut Request) -> Result<Response, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let mails: Vec<JsonValue> = load_outbox(&db_connection, OUTBOX_PAGE_SIZE)?.iter().map(|mail| json!({
+        "registration_id": mail.registration_id,
+        "template": mail.template,
+        "email_to": mail.email_to,
+        "subject": mail.subject,
+        "body": mail.body,
+        "created_at": config.date_format.timestamp(&mail.created_at),
+        "sent_at": config.date_format.timestamp(&mail.sent_at),
+        "error": mail.error
+    })).collect();
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("mails".to_string(), json!(mails));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("outbox", data)).set_mut(status::Ok);
+    Ok(resp)
+}
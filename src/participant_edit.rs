@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+
+use iron::prelude::{Request, IronResult, Response, Set};
+use iron::modifiers::RedirectRaw;
+use iron::status;
+
+use handlebars_iron::{Template};
+use serde_json::Value as JsonValue;
+use params::{Params, Map};
+use plugin::Pluggable;
+use persistent::Write;
+
+use ::DBConnection;
+use closing::closed_page;
+use database::{lock_connection, find_by_token, load_registration_status, update_own_details};
+use handler::{HandleError, Presentation, Registration, extract_string, load_registration, router_param};
+use status::RegistrationStatus;
+
+
+// What a participant may change without the organizers, the rest of the registration is fixed at /edit/:token.
+// Title, authors and abstract are required like in the form, they are only shown for a talk or a poster.
+const PRESENTATION_FIELDS: [(&str, &str); 3] = [
+    ("presentation_title", "Titel"), ("presentation_authors", "Autoren (eine Person pro Zeile)"), ("abstract_text", "Zusammenfassung")
+];
+
+fn render_edit_page(data: BTreeMap<String, JsonValue>, response_status: status::Status) -> Response {
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("participant_edit", data)).set_mut(response_status);
+    resp
+}
+
+fn message_page(message: &str, response_status: status::Status) -> Response {
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("message".to_string(), json!(message));
+    render_edit_page(data, response_status)
+}
+
+fn own_value(registration: &Registration, key: &str) -> String {
+    match key {
+        "presentation_title" => registration.presentation_title.clone(),
+        "presentation_authors" => registration.presentation_authors.clone(),
+        "abstract_text" => registration.abstract_text.clone(),
+        _ => registration.more_info.clone()
+    }
+}
+
+// A cancelled or rejected registration is only shown, the program committee no longer expects the contribution
+fn can_edit(current: Option<RegistrationStatus>) -> bool {
+    current.is_some_and(|status| status != RegistrationStatus::Cancelled && status != RegistrationStatus::Rejected)
+}
+
+fn edit_page(req: &mut Request, id: i64, error: Option<String>) -> Result<Response, HandleError> {
+    let map = req.get::<Params>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let (registration, current) = match (load_registration(&db_connection, id)?, load_registration_status(&db_connection, id)?) {
+        (Some((registration, _)), Some(current)) => (registration, RegistrationStatus::from_name(&current)),
+        _ => return Err(HandleError::NotFound)
+    };
+
+    // After an invalid input the participant's text is kept, not the stored one
+    let value = |key: &str| if error.is_some() { extract_string(&map, key).unwrap_or_default() } else { own_value(&registration, key) };
+
+    let fields: Vec<JsonValue> = if registration.presentation == Presentation::None { Vec::new() } else {
+        PRESENTATION_FIELDS.iter().map(|&(key, label)| json!({
+            "key": key,
+            "label": label,
+            "value": value(key),
+            // Like the form, a line for the title and text areas for the others
+            "rows": match key { "presentation_title" => 0, "abstract_text" => 8, _ => 3 },
+            "invalid": error.as_ref().is_some_and(|field| field == key)
+        })).collect()
+    };
+
+    let page_status = if error.is_some() { status::BadRequest } else { status::Ok };
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("token".to_string(), json!(router_param(req, "token")));
+    data.insert("name".to_string(), json!(format!("{} {}", registration.first_name, registration.last_name)));
+    data.insert("editable".to_string(), json!(can_edit(current)));
+    data.insert("fields".to_string(), json!(fields));
+    data.insert("more_info".to_string(), json!(value("more_info")));
+    data.insert("error".to_string(), json!(error.map(|field|
+        PRESENTATION_FIELDS.iter().find(|&&(key, _)| key == field).map_or(field.clone(), |&(_, label)| label.to_string()))));
+    data.insert("saved".to_string(), json!(extract_string(&map, "saved").is_ok()));
+
+    Ok(render_edit_page(data, page_status))
+}
+
+fn required(map: &Map, key: &str) -> Result<String, HandleError> {
+    match extract_string(map, key) {
+        Ok(ref value) if !value.is_empty() => Ok(value.clone()),
+        _ => Err(HandleError::Validation(key.to_string()))
+    }
+}
+
+fn save(req: &mut Request, id: i64) -> Result<Response, HandleError> {
+    let map = req.get::<Params>()?;
+
+    let result = {
+        let mutex = req.get::<Write<DBConnection>>()?;
+        let db_connection = lock_connection(&mutex)?;
+
+        let registration = match load_registration(&db_connection, id)? {
+            Some((registration, _)) => registration,
+            None => return Err(HandleError::NotFound)
+        };
+
+        let current = load_registration_status(&db_connection, id)?.and_then(|current| RegistrationStatus::from_name(&current));
+        if !can_edit(current) {
+            return Ok(message_page("Diese Anmeldung kann nicht mehr geändert werden.", status::Forbidden));
+        }
+
+        let more_info = extract_string(&map, "more_info").unwrap_or_default();
+
+        if registration.presentation == Presentation::None {
+            update_own_details(&db_connection, id, "", "", "", &more_info).map_err(HandleError::from)
+        } else {
+            required(&map, "presentation_title").and_then(|title| {
+                let authors = required(&map, "presentation_authors")?;
+                let abstract_text = required(&map, "abstract_text")?;
+                Ok(update_own_details(&db_connection, id, &title, &authors, &abstract_text, &more_info)?)
+            })
+        }
+    };
+
+    match result {
+        Ok(true) => {
+            info!("Registration {}: details changed by the participant", id);
+            Ok(Response::with((status::Found, RedirectRaw(format!("/edit/{}?saved=1", router_param(req, "token"))))))
+        }
+        Ok(false) => Err(HandleError::NotFound),
+        Err(HandleError::Validation(field)) => edit_page(req, id, Some(field)),
+        Err(e) => Err(e)
+    }
+}
+
+// Runs f for the registration the token of the URL belongs to
+fn with_token<F>(req: &mut Request, f: F) -> IronResult<Response>
+    where F: FnOnce(&mut Request, i64) -> Result<Response, HandleError> {
+
+    let token = router_param(req, "token");
+    let owner = req.get::<Write<DBConnection>>().map_err(HandleError::from)
+        .and_then(|mutex| Ok(find_by_token(&*lock_connection(&mutex)?, &token)?));
+
+    let result = owner.and_then(|owner| match owner {
+        Some(owner) => f(req, owner.id).map(Some),
+        None => Ok(None)
+    });
+
+    match result {
+        Ok(Some(resp)) => Ok(resp),
+        Ok(None) | Err(HandleError::NotFound) => Ok(message_page("Dieser Link ist ungültig. Bitte verwenden Sie den Link aus Ihrer Anmeldebestätigung.", status::NotFound)),
+        Err(e) => {
+            error!("Error on the edit page of a participant: {:?}", e);
+            Ok(message_page("Ein Fehler ist aufgetreten. Bitte versuchen Sie es später noch einmal.", status::Ok))
+        }
+    }
+}
+
+// The personal link from the confirmation mail, the same token as /slides/:token and /reimbursement/:token
+pub fn handle_participant_edit(req: &mut Request) -> IronResult<Response> {
+    if let Some(resp) = closed_page(req, status::Ok) {
+        return Ok(resp);
+    }
+
+    with_token(req, |req, id| edit_page(req, id, None))
+}
+
+pub fn handle_participant_edit_submit(req: &mut Request) -> IronResult<Response> {
+    if let Some(resp) = closed_page(req, status::Forbidden) {
+        return Ok(resp);
+    }
+
+    with_token(req, save)
+}
@@ -0,0 +1,351 @@
+use std::collections::BTreeMap;
+use std::thread;
+use std::time::Duration as StdDuration;
+
+use iron::prelude::{Request, IronResult, Response, Set};
+use iron::modifiers::RedirectRaw;
+use iron::status;
+
+use handlebars_iron::{Template};
+use serde_json::Value as JsonValue;
+use params::Params;
+use plugin::Pluggable;
+use persistent::{Read, Write};
+use chrono::{NaiveDate, NaiveDateTime, Duration, Local};
+use rusqlite::Connection;
+
+use ::DBConnection;
+use admin::with_admin;
+use config::Configuration;
+use database::{lock_connection, load_unpaid_registrations, record_payment_reminder, set_payment_status, UnpaidRegistration};
+use fee::format_amount;
+use handler::{HandleError, extract_string, send_email, router_param, not_found};
+use mail_template::{mail_template, fill_template, payment_values, MailTemplate};
+use settings::effective_configuration;
+use dates::timestamp;
+
+
+// In minutes
+const CHECK_INTERVAL: u64 = 60;
+
+pub const PAYMENT_METHODS: [&str; 3] = ["transfer", "cash", "card"];
+
+// Used for reminders sent from the overdue report if no text is configured
+const DEFAULT_TEMPLATE: &str = "Guten Tag {name},\n\n\
+    fuer Ihre Anmeldung haben wir bisher noch keine Zahlung erhalten. \
+    Bitte ueberweisen Sie die Teilnahmegebuehr von {amount}.\n\n\
+    Mit freundlichen Gruessen,\n{organizer}\n";
+
+#[derive(Debug, PartialEq)]
+enum Reminder {
+    // Some days after the registration
+    First,
+    // Shortly before the deadline
+    Deadline,
+    // Sent by the treasurer from the overdue report
+    Manual
+}
+
+fn parse_timestamp(time: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S").ok()
+}
+
+// Nobody gets more than one reminder within a day. The deadline reminder is sent once in the days
+// before the deadline, even if the first reminder has been sent already or is skipped because the
+// registration came in late.
+fn reminder_due(registration: &UnpaidRegistration, config: &Configuration, now: NaiveDateTime) -> Option<Reminder> {
+    // Would only bounce again
+    if !registration.email_undeliverable.is_empty() {
+        return None;
+    }
+
+    let registered_at = parse_timestamp(&registration.registered_at)?;
+    let last_reminder = parse_timestamp(&registration.last_payment_reminder);
+
+    if last_reminder.is_some_and(|last| now < last + Duration::days(1)) {
+        return None;
+    }
+
+    if config.payment_deadline_reminder > 0 {
+        if let Ok(deadline) = NaiveDate::parse_from_str(&config.payment_deadline, "%Y-%m-%d") {
+            let window_start = deadline.and_hms(0, 0, 0) - Duration::days(config.payment_deadline_reminder);
+
+            if now >= window_start && now <= deadline.and_hms(23, 59, 59) {
+                if last_reminder.is_none_or(|last| last < window_start) {
+                    return Some(Reminder::Deadline);
+                }
+                return None;
+            }
+        }
+    }
+
+    if config.payment_first_reminder > 0 && registration.payment_reminders == 0 &&
+        now >= registered_at + Duration::days(config.payment_first_reminder) {
+        return Some(Reminder::First);
+    }
+
+    None
+}
+
+// The text saved at /admin/mail_templates, otherwise the one of [Payment]
+fn reminder_template(db_connection: &Connection, reminder: &Reminder, config: &Configuration) -> Result<MailTemplate, HandleError> {
+    match *reminder {
+        Reminder::First => mail_template(db_connection, "payment_first", config),
+        Reminder::Deadline => mail_template(db_connection, "payment_deadline", config),
+        Reminder::Manual => {
+            for name in &["payment_first", "payment_deadline"] {
+                let template = mail_template(db_connection, name, config)?;
+                if !template.body.is_empty() {
+                    return Ok(template);
+                }
+            }
+
+            Ok(MailTemplate { subject: config.payment_reminder_subject.clone(), body: DEFAULT_TEMPLATE.to_string() })
+        }
+    }
+}
+
+fn reminder_mail(registration: &UnpaidRegistration, template: &str, config: &Configuration) -> String {
+    fill_template(template, &payment_values(&format!("{} {}", registration.first_name, registration.last_name), registration.fee, config))
+}
+
+fn send_reminders(config: &Configuration) -> Result<usize, HandleError> {
+    // Separate from the web server's connection, like the bounce polling
+    let db_connection = Connection::open(&config.db_filename)?;
+    // The deadline may have been changed at /admin/settings
+    let config = &effective_configuration(&db_connection, config)?;
+    let now = Local::now().naive_local();
+    let mut sent = 0;
+
+    for registration in load_unpaid_registrations(&db_connection)? {
+        let reminder = match reminder_due(&registration, config, now) {
+            Some(reminder) => reminder,
+            None => continue
+        };
+
+        let template = reminder_template(&db_connection, &reminder, config)?;
+
+        // A failed mail is tried again with the next check
+        match send_email(&registration.email_to, &template.subject, &reminder_mail(&registration, &template.body, config), config) {
+            Ok(_) => {
+                record_payment_reminder(&db_connection, registration.id, &timestamp())?;
+                info!("Registration {}: payment reminder ({:?}) sent", registration.id, reminder);
+                sent += 1;
+            }
+            Err(e) => error!("Registration {}: could not send payment reminder: {:?}", registration.id, e)
+        }
+    }
+
+    Ok(sent)
+}
+
+fn is_overdue(registration: &UnpaidRegistration, days: i64, now: NaiveDateTime) -> bool {
+    parse_timestamp(&registration.registered_at).is_some_and(|registered_at| now >= registered_at + Duration::days(days))
+}
+
+// Replaces the list of open payments the treasurer used to build from the exports
+pub fn handle_overdue_payments(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "overdue_payments", overdue_payments_page)
+}
+
+fn overdue_payments_page(req: &mut Request) -> Result<Response, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    let map = req.get::<Params>()?;
+    let days = match extract_string(&map, "days").ok().and_then(|days| days.parse::<i64>().ok()) {
+        Some(days) if days >= 0 => days,
+        _ => config.payment_overdue_days
+    };
+
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let now = Local::now().naive_local();
+    let overdue: Vec<UnpaidRegistration> = load_unpaid_registrations(&db_connection)?.into_iter()
+        .filter(|registration| is_overdue(registration, days, now))
+        .collect();
+
+    let registrations: Vec<JsonValue> = overdue.iter().map(|registration| json!({
+        "id": registration.id,
+        "name": format!("{} {}", registration.first_name, registration.last_name),
+        "email": registration.email_to,
+        "fee": format_amount(registration.fee),
+        "registered_at": config.date_format.timestamp(&registration.registered_at),
+        "reminders": registration.payment_reminders,
+        "last_reminder": config.date_format.timestamp(&registration.last_payment_reminder),
+        "undeliverable": !registration.email_undeliverable.is_empty()
+    })).collect();
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("days".to_string(), json!(days));
+    data.insert("registrations".to_string(), json!(registrations));
+    data.insert("total".to_string(), json!(format_amount(overdue.iter().map(|registration| registration.fee).sum())));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("overdue_payments", data)).set_mut(status::Ok);
+    Ok(resp)
+}
+
+pub fn handle_payment_reminder(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "payment_reminder", send_manual_reminder)
+}
+
+fn send_manual_reminder(req: &mut Request) -> Result<Response, HandleError> {
+    let id = match router_param(req, "id").parse::<i64>() {
+        Ok(id) => id,
+        Err(_) => return Ok(not_found())
+    };
+
+    let config = req.get::<Read<Configuration>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let registration = match load_unpaid_registrations(&db_connection)?.into_iter().find(|registration| registration.id == id) {
+        Some(registration) => registration,
+        None => return Ok(not_found())
+    };
+
+    if !registration.email_undeliverable.is_empty() {
+        return Ok(Response::with((status::Conflict, "Email address is undeliverable")));
+    }
+
+    let template = reminder_template(&db_connection, &Reminder::Manual, &config)?;
+    send_email(&registration.email_to, &template.subject, &reminder_mail(&registration, &template.body, &config), &config)?;
+    record_payment_reminder(&db_connection, id, &timestamp())?;
+    info!("Registration {}: payment reminder sent from the overdue report", id);
+
+    Ok(Response::with((status::Found, RedirectRaw("/admin/payments".to_string()))))
+}
+
+pub fn handle_payment_status(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "payment_status", change_payment_status)
+}
+
+fn change_payment_status(req: &mut Request) -> Result<Response, HandleError> {
+    let id = match router_param(req, "id").parse::<i64>() {
+        Ok(id) => id,
+        Err(_) => return Ok(not_found())
+    };
+
+    let map = req.get::<Params>()?;
+    let status = extract_string(&map, "status")?;
+    let method = if status == "paid" { extract_string(&map, "method")? } else { String::new() };
+
+    if (status != "paid" && status != "waived") || (status == "paid" && !PAYMENT_METHODS.contains(&method.as_str())) {
+        return Err(HandleError::FormValue);
+    }
+
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    if !set_payment_status(&db_connection, id, &status, &method, &timestamp())? {
+        info!("Registration {}: payment status already set", id);
+        return Ok(Response::with((status::Conflict, "Payment status already set")));
+    }
+
+    info!("Registration {}: marked as {}", id, status);
+
+    Ok(Response::with((status::Found, RedirectRaw("/admin/payments".to_string()))))
+}
+
+// Runs for the lifetime of the server if one of the reminders is configured in [Payment]
+pub fn start_payment_reminders(config: Configuration) {
+    thread::spawn(move || loop {
+        match send_reminders(&config) {
+            Ok(sent) => info!("Unpaid registrations checked, {} reminders sent", sent),
+            Err(e) => error!("Could not send payment reminders: {:?}", e)
+        }
+
+        thread::sleep(StdDuration::from_secs(CHECK_INTERVAL * 60));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{reminder_due, reminder_template, reminder_mail, is_overdue, parse_timestamp, Reminder, DEFAULT_TEMPLATE};
+    use config::example_configuration;
+    use database::{init_database, save_mail_template, UnpaidRegistration};
+    use rusqlite::Connection;
+
+    fn registration(payment_reminders: i64, last_payment_reminder: &str) -> UnpaidRegistration {
+        UnpaidRegistration {
+            id: 1,
+            title: "sir".to_string(),
+            first_name: "Bob".to_string(),
+            last_name: "Smith".to_string(),
+            email_to: "bob@smith.com".to_string(),
+            fee: 12050,
+            registered_at: "2017-01-10 12:00:00".to_string(),
+            payment_reminders,
+            last_payment_reminder: last_payment_reminder.to_string(),
+            email_undeliverable: "".to_string()
+        }
+    }
+
+    #[test]
+    fn test_reminder_due() {
+        let mut config = example_configuration();
+        config.payment_deadline = "2017-03-01".to_string();
+        config.payment_first_reminder = 14;
+        config.payment_deadline_reminder = 7;
+
+        let at = |time: &str| parse_timestamp(time).unwrap();
+
+        assert_eq!(reminder_due(&registration(0, ""), &config, at("2017-01-24 11:59:59")), None);
+        assert_eq!(reminder_due(&registration(0, ""), &config, at("2017-01-24 12:00:00")), Some(Reminder::First));
+        assert_eq!(reminder_due(&registration(1, "2017-01-24 12:00:00"), &config, at("2017-02-10 12:00:00")), None);
+
+        assert_eq!(reminder_due(&registration(1, "2017-01-24 12:00:00"), &config, at("2017-02-22 00:00:00")), Some(Reminder::Deadline));
+        assert_eq!(reminder_due(&registration(2, "2017-02-22 00:00:00"), &config, at("2017-02-25 12:00:00")), None);
+        // Not twice within a day, even if the first reminder was late
+        assert_eq!(reminder_due(&registration(1, "2017-02-21 23:00:00"), &config, at("2017-02-22 10:00:00")), None);
+        assert_eq!(reminder_due(&registration(1, "2017-02-21 23:00:00"), &config, at("2017-02-22 23:00:00")), Some(Reminder::Deadline));
+        // The deadline reminder replaces a first reminder that is still pending
+        assert_eq!(reminder_due(&registration(0, ""), &config, at("2017-03-01 18:00:00")), Some(Reminder::Deadline));
+
+        // After the deadline only a pending first reminder is still sent
+        assert_eq!(reminder_due(&registration(1, "2017-02-22 00:00:00"), &config, at("2017-03-02 00:00:00")), None);
+
+        let mut bounced = registration(0, "");
+        bounced.email_undeliverable = "2017-01-10 12:05:00".to_string();
+        assert_eq!(reminder_due(&bounced, &config, at("2017-01-24 12:00:00")), None);
+
+        config.payment_first_reminder = 0;
+        assert_eq!(reminder_due(&registration(0, ""), &config, at("2017-02-10 12:00:00")), None);
+    }
+
+    #[test]
+    fn test_is_overdue() {
+        let at = |time: &str| parse_timestamp(time).unwrap();
+
+        assert!(!is_overdue(&registration(0, ""), 30, at("2017-02-09 11:59:59")));
+        assert!(is_overdue(&registration(0, ""), 30, at("2017-02-09 12:00:00")));
+        assert!(is_overdue(&registration(0, ""), 0, at("2017-01-10 12:00:00")));
+    }
+
+    #[test]
+    fn test_reminder_mail() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        let mut config = example_configuration();
+        config.payment_deadline = "2017-03-01".to_string();
+        config.payment_first_template = "Hallo {name}, bitte ueberweisen Sie {amount}.".to_string();
+        config.payment_deadline_template = "Hallo {name}, bitte ueberweisen Sie {amount} bis zum {deadline}.".to_string();
+
+        let mail = |reminder: Reminder, config: &_| reminder_mail(&registration(0, ""), &reminder_template(&conn, &reminder, config).unwrap().body, config);
+
+        assert_eq!(mail(Reminder::First, &config), "Hallo Bob Smith, bitte ueberweisen Sie 120,50 €.".to_string());
+        assert_eq!(mail(Reminder::Deadline, &config), "Hallo Bob Smith, bitte ueberweisen Sie 120,50 € bis zum 01.03.2017.".to_string());
+        assert_eq!(mail(Reminder::Manual, &config), "Hallo Bob Smith, bitte ueberweisen Sie 120,50 €.".to_string());
+
+        config.payment_first_template = "".to_string();
+        config.payment_deadline_template = "".to_string();
+        assert!(DEFAULT_TEMPLATE.contains("{amount}"));
+        assert!(mail(Reminder::Manual, &config).contains("Guten Tag Bob Smith,"));
+
+        // A text changed in the browser wins over the file
+        save_mail_template(&conn, "payment_deadline", "Frist", "Bis {deadline}: {amount}", "2017-01-10 12:00:00").unwrap();
+        assert_eq!(mail(Reminder::Manual, &config), "Bis 01.03.2017: 120,50 €".to_string());
+        assert_eq!(reminder_template(&conn, &Reminder::Deadline, &config).unwrap().subject, "Frist".to_string());
+    }
+}
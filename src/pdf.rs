@@ -0,0 +1,251 @@
+// A4 pages of plain text in the built-in Helvetica, which every PDF viewer has, so nothing needs to be embedded.
+// Text is encoded as WinAnsi (Windows-1252): umlauts and the usual punctuation work, other characters become '?'.
+
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 56.0;
+// Below the page header
+const BODY_TOP: f32 = PAGE_HEIGHT - MARGIN - 30.0;
+const BODY_BOTTOM: f32 = MARGIN + 20.0;
+
+const HEADING_SIZE: f32 = 11.0;
+const TEXT_SIZE: f32 = 10.0;
+const HEADING_SPACING: f32 = 22.0;
+const LINE_SPACING: f32 = 14.0;
+const INDENT: f32 = 12.0;
+
+// Widths of Helvetica for the printable ASCII characters in 1/1000 of the font size,
+// everything else is assumed to be as wide as a digit
+const ASCII_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556,
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556,
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556,
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584
+];
+
+// Bold is a bit wider, the lengths are only used for keeping text inside the margins
+fn text_width(text: &str, size: f32) -> f32 {
+    let units: u32 = text.chars().map(|c| match c {
+        ' '..='~' => ASCII_WIDTHS[c as usize - 32] as u32,
+        _ => 556
+    }).sum();
+
+    units as f32 * size * 1.05 / 1000.0
+}
+
+// Cuts the text with "..." so it fits into width
+fn fit_text(text: &str, size: f32, width: f32) -> String {
+    if text_width(text, size) <= width {
+        return text.to_string();
+    }
+
+    let mut result: String = text.to_string();
+    while !result.is_empty() && text_width(&format!("{}...", result), size) > width {
+        result.pop();
+    }
+
+    format!("{}...", result.trim_end())
+}
+
+fn win_ansi(c: char) -> u8 {
+    match c {
+        ' '..='~' | '\u{a0}'..='\u{ff}' => c as u8,
+        '€' => 0x80,
+        '‚' => 0x82,
+        '„' => 0x84,
+        '…' => 0x85,
+        '‘' => 0x91,
+        '’' => 0x92,
+        '“' => 0x93,
+        '”' => 0x94,
+        '–' => 0x96,
+        '—' => 0x97,
+        _ => b'?'
+    }
+}
+
+// A PDF string literal in parentheses
+fn pdf_string(text: &str) -> Vec<u8> {
+    let mut result = vec![b'('];
+
+    for byte in text.chars().map(win_ansi) {
+        if byte == b'(' || byte == b')' || byte == b'\\' {
+            result.push(b'\\');
+        }
+        result.push(byte);
+    }
+
+    result.push(b')');
+    result
+}
+
+fn show_text(content: &mut Vec<u8>, font: &str, size: f32, x: f32, y: f32, text: &str) {
+    content.extend_from_slice(format!("BT /{} {} Tf {:.1} {:.1} Td ", font, size, x, y).as_bytes());
+    content.extend_from_slice(&pdf_string(text));
+    content.extend_from_slice(b" Tj ET\n");
+}
+
+/// A list of headings, each followed by its lines, broken into pages with a header
+/// (title and date) and the page numbers at the bottom
+pub struct Document {
+    title: String,
+    date: String,
+    pages: Vec<Vec<u8>>,
+    y: f32,
+    heading: Option<String>
+}
+
+impl Document {
+    pub fn new(title: &str, date: &str) -> Document {
+        Document {
+            title: title.to_string(),
+            date: date.to_string(),
+            pages: vec![Vec::new()],
+            y: BODY_TOP,
+            heading: None
+        }
+    }
+
+    fn new_page(&mut self) {
+        self.pages.push(Vec::new());
+        self.y = BODY_TOP;
+    }
+
+    fn current(&mut self) -> &mut Vec<u8> {
+        self.pages.last_mut().unwrap()
+    }
+
+    fn write_heading(&mut self, text: &str) {
+        let (y, text) = (self.y - HEADING_SPACING + LINE_SPACING, fit_text(text, HEADING_SIZE, PAGE_WIDTH - 2.0 * MARGIN));
+        show_text(self.current(), "F2", HEADING_SIZE, MARGIN, y, &text);
+        self.y = y - LINE_SPACING;
+    }
+
+    /// Starts a new group, on a new page if there is no room for the heading and at least one line
+    pub fn heading(&mut self, text: &str) {
+        if self.y - HEADING_SPACING - LINE_SPACING < BODY_BOTTOM {
+            self.new_page();
+        }
+
+        self.heading = Some(text.to_string());
+        self.write_heading(text);
+    }
+
+    /// A group continued on the next page gets its heading again
+    pub fn line(&mut self, text: &str) {
+        if self.y < BODY_BOTTOM {
+            self.new_page();
+            if let Some(heading) = self.heading.clone() {
+                self.write_heading(&format!("{} (Forts.)", heading));
+            }
+        }
+
+        let (y, text) = (self.y, fit_text(text, TEXT_SIZE, PAGE_WIDTH - 2.0 * MARGIN - INDENT));
+        show_text(self.current(), "F1", TEXT_SIZE, MARGIN + INDENT, y, &text);
+        self.y -= LINE_SPACING;
+    }
+
+    pub fn to_pdf(&self) -> Vec<u8> {
+        let page_count = self.pages.len();
+        // Catalog, page tree and the two fonts come first, then a page and its content for every page
+        let first_page = 5;
+        let mut objects: Vec<Vec<u8>> = Vec::new();
+
+        let kids: Vec<String> = (0..page_count).map(|index| format!("{} 0 R", first_page + 2 * index)).collect();
+        objects.push(b"<< /Type /Catalog /Pages 2 0 R >>".to_vec());
+        objects.push(format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), page_count).into_bytes());
+        objects.push(b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_vec());
+        objects.push(b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>".to_vec());
+
+        for (index, body) in self.pages.iter().enumerate() {
+            let mut content = Vec::new();
+            let date_x = PAGE_WIDTH - MARGIN - text_width(&self.date, TEXT_SIZE);
+            let footer = format!("Seite {} von {}", index + 1, page_count);
+            let footer_x = (PAGE_WIDTH - text_width(&footer, TEXT_SIZE)) / 2.0;
+
+            show_text(&mut content, "F2", 14.0, MARGIN, PAGE_HEIGHT - MARGIN, &self.title);
+            show_text(&mut content, "F1", TEXT_SIZE, date_x, PAGE_HEIGHT - MARGIN, &self.date);
+            content.extend_from_slice(format!("0.5 w {:.1} {:.1} m {:.1} {:.1} l S\n",
+                MARGIN, PAGE_HEIGHT - MARGIN - 8.0, PAGE_WIDTH - MARGIN, PAGE_HEIGHT - MARGIN - 8.0).as_bytes());
+            content.extend_from_slice(body);
+            show_text(&mut content, "F1", TEXT_SIZE, footer_x, MARGIN - 20.0, &footer);
+
+            objects.push(format!("<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+                /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+                PAGE_WIDTH, PAGE_HEIGHT, first_page + 2 * index + 1).into_bytes());
+
+            let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+            stream.extend_from_slice(&content);
+            stream.extend_from_slice(b"\nendstream");
+            objects.push(stream);
+        }
+
+        pdf_file(&objects)
+    }
+}
+
+// Numbers the objects from 1 and adds the cross-reference table, object 1 is the catalog
+fn pdf_file(objects: &[Vec<u8>]) -> Vec<u8> {
+    let mut result = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::new();
+
+    for (index, object) in objects.iter().enumerate() {
+        offsets.push(result.len());
+        result.extend_from_slice(format!("{} 0 obj\n", index + 1).as_bytes());
+        result.extend_from_slice(object);
+        result.extend_from_slice(b"\nendobj\n");
+    }
+
+    let xref = result.len();
+    result.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+    for offset in offsets {
+        result.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+
+    result.extend_from_slice(format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref).as_bytes());
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Document, pdf_string, fit_text};
+
+    #[test]
+    fn test_pdf_string() {
+        assert_eq!(pdf_string("Müller (Uni)"), b"(M\xfcller \\(Uni\\))".to_vec());
+        assert_eq!(pdf_string("€ 日本"), b"(\x80 ??)".to_vec());
+    }
+
+    #[test]
+    fn test_fit_text() {
+        assert_eq!(fit_text("Bob Smith", 10.0, 200.0), "Bob Smith".to_string());
+
+        let long = "Institute for Geophysics and Extraterrestrial Physics, Technische Universität Braunschweig";
+        let cut = fit_text(long, 10.0, 200.0);
+        assert!(cut.ends_with("...") && cut.len() < long.len());
+    }
+
+    #[test]
+    fn test_document() {
+        let mut document = Document::new("Teilnehmerliste", "01.03.2017");
+        document.heading("University of Potsdam");
+        for _ in 0..100 {
+            document.line("Bob Smith");
+        }
+
+        let pdf = document.to_pdf();
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(pdf.starts_with(b"%PDF-1.4\n"));
+        assert!(pdf.ends_with(b"%%EOF\n"));
+        assert!(text.contains("/Count 3"));
+        assert!(text.contains("(Seite 3 von 3)"));
+        assert!(text.contains("(University of Potsdam \\(Forts.\\))"));
+
+        // The cross-reference table points at the objects
+        let xref = text.find("xref\n").unwrap();
+        let first_offset: usize = text[xref..].lines().nth(3).unwrap()[..10].parse().unwrap();
+        assert!(text[first_offset..].starts_with("1 0 obj"));
+    }
+}
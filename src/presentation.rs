@@ -0,0 +1,335 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Read as IoRead;
+
+use iron::prelude::{Request, IronResult, Response, Set};
+use iron::status;
+
+use handlebars_iron::{Template};
+use serde_json::Value as JsonValue;
+use params::Params;
+use plugin::Pluggable;
+use persistent::{Read, Write};
+
+use ::DBConnection;
+use abstracts::topic_options;
+use admin::{with_admin, is_admin, unauthorized};
+use config::Configuration;
+use database::{lock_connection, find_by_token, load_abstracts, set_presentation_file, Abstract};
+use export::{attachment_response, tar_archive};
+use handler::{HandleError, extract_string, router_param, not_found};
+use upload::{store_upload, upload_path, download_response, SLIDE_EXTENSIONS};
+
+
+const SLIDES_FOLDER: &str = "slides";
+const POSTERS_FOLDER: &str = "posters";
+const POSTER_EXTENSIONS: [&str; 1] = ["pdf"];
+
+fn upload_folder(abs: &Abstract) -> &'static str {
+    if abs.presentation == "poster" { POSTERS_FOLDER } else { SLIDES_FOLDER }
+}
+
+// Speakers upload their slides, poster presenters a PDF of their poster
+fn speaker(req: &mut Request) -> Result<Abstract, HandleError> {
+    let token = router_param(req, "token");
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let owner = find_by_token(&db_connection, &token)?.ok_or(HandleError::NotFound)?;
+
+    load_abstracts(&db_connection)?.into_iter()
+        .find(|abs| abs.id == owner.id)
+        .ok_or(HandleError::NotFound)
+}
+
+fn render_speaker_page(req: &mut Request, abs: &Abstract, message: &str) -> Result<Response, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    let session = config.sessions.iter().find(|session| session.key == abs.session);
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("name".to_string(), json!(abs.presenter()));
+    data.insert("token".to_string(), json!(router_param(req, "token")));
+    data.insert("title".to_string(), json!(abs.title));
+    data.insert("session".to_string(), json!(session.map(|session| format!("{}, {} {} - {}", session.name, session.day, session.start, session.end))));
+    data.insert("file_name".to_string(), json!(abs.file_name));
+    data.insert("poster".to_string(), json!(abs.presentation == "poster"));
+    data.insert("message".to_string(), json!(message));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("presentation_upload", data)).set_mut(status::Ok);
+    Ok(resp)
+}
+
+fn error_response(e: HandleError) -> Response {
+    match e {
+        HandleError::NotFound => not_found(),
+        e => {
+            error!("Error in presentation upload page: {:?}", e);
+            Response::with((status::InternalServerError, "Internal server error"))
+        }
+    }
+}
+
+pub fn handle_presentation_upload(req: &mut Request) -> IronResult<Response> {
+    let result = speaker(req).and_then(|abs| render_speaker_page(req, &abs, ""));
+
+    Ok(result.unwrap_or_else(error_response))
+}
+
+pub fn handle_presentation_upload_submit(req: &mut Request) -> IronResult<Response> {
+    let abs = match speaker(req) {
+        Ok(abs) => abs,
+        Err(e) => return Ok(error_response(e))
+    };
+
+    let (abs, message) = match upload_slides(req, &abs) {
+        Ok(abs) => {
+            info!("Presentation file uploaded for registration {}", abs.id);
+            (abs, "Ihre Datei wurde hochgeladen.".to_string())
+        }
+        Err(HandleError::Validation(field)) => {
+            info!("Invalid value in presentation upload field '{}'", field);
+            let types = if abs.presentation == "poster" { "PDF-Datei" } else { "PDF- oder PPTX-Datei" };
+            (abs, format!("Bitte wählen Sie eine {} mit höchstens 10 MB aus.", types))
+        }
+        Err(e) => {
+            error!("Error while uploading presentation file: {:?}", e);
+            (abs, "Ein Fehler ist aufgetreten. Bitte versuchen Sie es später noch einmal.".to_string())
+        }
+    };
+
+    Ok(render_speaker_page(req, &abs, &message).unwrap_or_else(error_response))
+}
+
+// A new upload replaces the previous one
+fn upload_slides(req: &mut Request, abs: &Abstract) -> Result<Abstract, HandleError> {
+    let map = req.get::<Params>()?;
+    let config = req.get::<Read<Configuration>>()?;
+
+    let extensions: &[&str] = if abs.presentation == "poster" { &POSTER_EXTENSIONS } else { &SLIDE_EXTENSIONS };
+    let (file, file_name) = store_upload(&map, "file", upload_folder(abs), abs.id, extensions, &config)?;
+
+    {
+        let mutex = req.get::<Write<DBConnection>>()?;
+        let db_connection = lock_connection(&mutex)?;
+
+        set_presentation_file(&db_connection, abs.id, &file, &file_name)?;
+    }
+
+    if !abs.file.is_empty() {
+        if let Err(e) = fs::remove_file(upload_path(upload_folder(abs), &abs.file, &config)) {
+            error!("Could not remove old presentation file '{}': {:?}", abs.file, e);
+        }
+    }
+
+    speaker(req)
+}
+
+// "01_Smith_my talk.pdf" keeps the files in the order of the session
+fn bundle_name(abs: &Abstract) -> String {
+    let name: String = format!("{}_{}", abs.last_name, abs.file_name).chars()
+        .filter(|c| c.is_alphanumeric() || *c == '.' || *c == '-' || *c == '_' || *c == ' ').collect();
+
+    format!("{:02}_{}", abs.position, name)
+}
+
+pub fn handle_session_slides(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "session_slides", session_slides)
+}
+
+// All slides of one session in a single archive for the session chair and the AV desk
+fn session_slides(req: &mut Request) -> Result<Response, HandleError> {
+    let key = router_param(req, "key");
+    let config = req.get::<Read<Configuration>>()?;
+
+    if !config.sessions.iter().any(|session| session.key == key) {
+        return Ok(not_found());
+    }
+
+    let abstracts = {
+        let mutex = req.get::<Write<DBConnection>>()?;
+        let db_connection = lock_connection(&mutex)?;
+        load_abstracts(&db_connection)?
+    };
+
+    let mut files = Vec::new();
+
+    for abs in abstracts.iter().filter(|abs| abs.session == key && abs.presentation == "talk" && !abs.file.is_empty()) {
+        let mut content = Vec::new();
+        File::open(upload_path(SLIDES_FOLDER, &abs.file, &config))?.read_to_end(&mut content)?;
+        files.push((bundle_name(abs), content));
+    }
+
+    info!("Slides of session '{}' exported: {} files", key, files.len());
+
+    Ok(attachment_response(&format!("{}_slides.tar", key), "application/x-tar", tar_archive(&files)))
+}
+
+// Posters grouped by topic in the configured order, a poster with several topics shows up in each group.
+// Posters without a topic come last, unless only one topic is asked for.
+fn poster_groups(topics: &[String], abstracts: &[Abstract], filter: &str) -> Vec<JsonValue> {
+    let poster2json = |abs: &&Abstract| json!({
+        "id": abs.id,
+        "title": abs.title,
+        "authors": abs.authors.join(", "),
+        "abstract": abs.text,
+        "has_file": !abs.file.is_empty()
+    });
+
+    let posters: Vec<&Abstract> = abstracts.iter().filter(|abs| abs.presentation == "poster").collect();
+    let mut groups = Vec::new();
+
+    for topic in topics.iter().filter(|topic| filter.is_empty() || *topic == filter) {
+        let topic_posters: Vec<JsonValue> = posters.iter().filter(|abs| abs.topics.contains(topic)).map(&poster2json).collect();
+
+        if !topic_posters.is_empty() {
+            groups.push(json!({ "name": topic, "posters": topic_posters }));
+        }
+    }
+
+    let unclassified: Vec<JsonValue> = posters.iter()
+        .filter(|abs| !abs.topics.iter().any(|topic| topics.contains(topic)))
+        .map(&poster2json).collect();
+
+    if filter.is_empty() && !unclassified.is_empty() {
+        groups.push(json!({ "name": "Weitere Poster", "posters": unclassified }));
+    }
+
+    groups
+}
+
+fn gallery_page(req: &mut Request) -> Result<Response, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let map = req.get::<Params>()?;
+    let filter = extract_string(&map, "topic").unwrap_or_default();
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("groups".to_string(), json!(poster_groups(&config.topics, &load_abstracts(&db_connection)?, &filter)));
+    data.insert("topics".to_string(), topic_options(&config.topics, &filter));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("posters", data)).set_mut(status::Ok);
+    Ok(resp)
+}
+
+// Only public if configured in [Program], otherwise the admins see it at /admin/posters
+pub fn handle_poster_gallery(req: &mut Request) -> IronResult<Response> {
+    let public = match req.get::<Read<Configuration>>() {
+        Ok(config) => config.public_poster_gallery,
+        Err(_) => false
+    };
+
+    if !public {
+        return Ok(not_found());
+    }
+
+    Ok(gallery_page(req).unwrap_or_else(error_response))
+}
+
+pub fn handle_admin_poster_gallery(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "admin_posters", gallery_page)
+}
+
+pub fn handle_poster_file(req: &mut Request) -> IronResult<Response> {
+    let allowed = match req.get::<Read<Configuration>>() {
+        Ok(config) => config.public_poster_gallery || is_admin(req, &config),
+        Err(_) => false
+    };
+
+    if !allowed {
+        return Ok(unauthorized());
+    }
+
+    Ok(poster_file(req).unwrap_or_else(error_response))
+}
+
+fn poster_file(req: &mut Request) -> Result<Response, HandleError> {
+    let id = router_param(req, "id").parse::<i64>().map_err(|_| HandleError::NotFound)?;
+    let config = req.get::<Read<Configuration>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let abs = load_abstracts(&db_connection)?.into_iter()
+        .find(|abs| abs.id == id && abs.presentation == "poster" && !abs.file.is_empty())
+        .ok_or(HandleError::NotFound)?;
+
+    download_response(POSTERS_FOLDER, &abs.file, &abs.file_name, &config).ok_or(HandleError::NotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bundle_name, poster_groups};
+    use database::Abstract;
+
+    #[test]
+    fn test_bundle_name() {
+        let abs = Abstract {
+            id: 1,
+            first_name: "Alice".to_string(),
+            last_name: "Brown".to_string(),
+            presentation: "talk".to_string(),
+            title: "Plate tectonics".to_string(),
+            authors: vec!["Alice Brown".to_string()],
+            text: "Text".to_string(),
+            session: "geo1".to_string(),
+            position: 3,
+            file: "1_abc.pdf".to_string(),
+            file_name: "../my talk.pdf".to_string(),
+            talk_duration: 0,
+            talk_equipment: "".to_string(),
+            unavailable_days: "".to_string(),
+            status: "confirmed".to_string(),
+
+            topics: Vec::new()
+        };
+
+        assert_eq!(bundle_name(&abs), "03_Brown_..my talk.pdf".to_string());
+    }
+
+    #[test]
+    fn test_poster_groups() {
+        let topics = vec!["Geodynamics".to_string(), "Seismology".to_string()];
+
+        let create_abstract = |id: i64, presentation: &str, topics: &[&str]| Abstract {
+            id,
+            first_name: "Alice".to_string(),
+            last_name: "Brown".to_string(),
+            presentation: presentation.to_string(),
+            title: format!("Title {}", id),
+            authors: vec!["Alice Brown".to_string()],
+            text: "Text".to_string(),
+            session: "".to_string(),
+            position: 0,
+            file: if id == 1 { "1_abc.pdf".to_string() } else { "".to_string() },
+            file_name: "poster.pdf".to_string(),
+            talk_duration: 0,
+            talk_equipment: "".to_string(),
+            unavailable_days: "".to_string(),
+            status: "confirmed".to_string(),
+
+            topics: topics.iter().map(|topic| topic.to_string()).collect()
+        };
+
+        let abstracts = vec![create_abstract(1, "poster", &["Seismology", "Geodynamics"]), create_abstract(2, "talk", &["Geodynamics"]),
+            create_abstract(3, "poster", &[]), create_abstract(4, "poster", &["Seismology"])];
+
+        let groups = poster_groups(&topics, &abstracts, "");
+
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0]["name"], json!("Geodynamics"));
+        assert_eq!(groups[0]["posters"].as_array().unwrap().len(), 1);
+        assert_eq!(groups[0]["posters"][0]["has_file"], json!(true));
+        assert_eq!(groups[1]["name"], json!("Seismology"));
+        assert_eq!(groups[1]["posters"].as_array().unwrap().len(), 2);
+        assert_eq!(groups[2]["name"], json!("Weitere Poster"));
+        assert_eq!(groups[2]["posters"][0]["id"], json!(3));
+
+        let groups = poster_groups(&topics, &abstracts, "Seismology");
+
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0]["posters"][1]["id"], json!(4));
+    }
+}
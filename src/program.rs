@@ -0,0 +1,140 @@
+use std::collections::BTreeMap;
+
+use iron::prelude::{Request, IronResult, Response, Set};
+use iron::status;
+
+use handlebars_iron::{Template};
+use serde_json::Value as JsonValue;
+use plugin::Pluggable;
+use persistent::{Read, Write};
+
+use ::DBConnection;
+use config::{Configuration, Session};
+use database::{lock_connection, load_abstracts, Abstract};
+use handler::HandleError;
+
+
+fn contribution(abs: &Abstract) -> JsonValue {
+    json!({
+        "id": abs.id,
+        "presentation": abs.presentation,
+        "title": abs.title,
+        "authors": abs.authors,
+        "author_names": abs.authors.join(", "),
+        "poster": abs.presentation == "poster",
+        "presenter": abs.presenter(),
+        "abstract": abs.text
+    })
+}
+
+// The program is built from the current session assignments on every request,
+// so any change on /admin/abstracts shows up immediately.
+// Days and sessions keep the chronological order of [Program], unassigned abstracts are left out.
+pub fn program_data(sessions: &[Session], abstracts: &[Abstract]) -> Vec<JsonValue> {
+    let mut days: Vec<(String, Vec<JsonValue>)> = Vec::new();
+
+    for session in sessions {
+        let contributions: Vec<JsonValue> = abstracts.iter()
+            .filter(|abs| abs.session == session.key)
+            .map(contribution)
+            .collect();
+
+        let session_data = json!({
+            "key": session.key,
+            "name": session.name,
+            "start": session.start,
+            "end": session.end,
+            "room": session.room,
+            "contributions": contributions
+        });
+
+        if let Some(&mut (ref day, ref mut day_sessions)) = days.last_mut() {
+            if *day == session.day {
+                day_sessions.push(session_data);
+                continue;
+            }
+        }
+
+        days.push((session.day.clone(), vec![session_data]));
+    }
+
+    days.into_iter().map(|(day, sessions)| json!({
+        "day": day,
+        "sessions": sessions
+    })).collect()
+}
+
+pub fn handle_program(req: &mut Request) -> IronResult<Response> {
+    match program_page(req) {
+        Ok(resp) => Ok(resp),
+        Err(e) => {
+            error!("Error while showing program: {:?}", e);
+            Ok(Response::with((status::InternalServerError, "Internal server error")))
+        }
+    }
+}
+
+fn program_page(req: &mut Request) -> Result<Response, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("days".to_string(), json!(program_data(&config.sessions, &load_abstracts(&db_connection)?)));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("program", data)).set_mut(status::Ok);
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::program_data;
+    use config::Session;
+    use database::Abstract;
+
+    #[test]
+    fn test_program_data() {
+        let create_session = |key: &str, day: &str| Session {
+            key: key.to_string(),
+            name: key.to_string(),
+            day: day.to_string(),
+            start: "09:00".to_string(),
+            end: "10:30".to_string(),
+            room: "".to_string()
+        };
+
+        let sessions = vec![create_session("geo1", "2017-03-01"), create_session("geo2", "2017-03-01"), create_session("geo3", "2017-03-02")];
+
+        let create_abstract = |id: i64, session: &str| Abstract {
+            id,
+            first_name: "Alice".to_string(),
+            last_name: "Brown".to_string(),
+            presentation: "talk".to_string(),
+            title: format!("Title {}", id),
+            authors: vec!["Alice Brown".to_string()],
+            text: "Text".to_string(),
+            session: session.to_string(),
+            position: 0,
+            file: "".to_string(),
+            file_name: "".to_string(),
+            talk_duration: 0,
+            talk_equipment: "".to_string(),
+            unavailable_days: "".to_string(),
+            status: "confirmed".to_string(),
+
+            topics: Vec::new()
+        };
+
+        let abstracts = vec![create_abstract(1, "geo3"), create_abstract(2, ""), create_abstract(3, "geo1")];
+        let days = program_data(&sessions, &abstracts);
+
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0]["day"], json!("2017-03-01"));
+        assert_eq!(days[0]["sessions"].as_array().unwrap().len(), 2);
+        assert_eq!(days[0]["sessions"][0]["contributions"][0]["title"], json!("Title 3"));
+        assert_eq!(days[0]["sessions"][1]["contributions"], json!([]));
+        assert_eq!(days[1]["sessions"][0]["contributions"][0]["id"], json!(1));
+        assert_eq!(days[1]["sessions"][0]["contributions"].as_array().unwrap().len(), 1);
+    }
+}
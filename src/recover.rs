@@ -0,0 +1,73 @@
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+
+use iron::prelude::{Request, IronResult, Response, Set};
+use iron::middleware::{AroundMiddleware, Handler};
+use iron::status;
+
+use handlebars_iron::Template;
+use serde_json::Value as JsonValue;
+
+use alert::current_request;
+
+
+/// Turns a panic in a handler into the error page. Without it hyper drops the connection and the participant
+/// only sees an error of the browser, possibly after filling in the whole form.
+pub struct CatchPanic;
+
+struct CatchPanicHandler {
+    handler: Box<dyn Handler>
+}
+
+// The argument of panic!, unwrap and expect give a String
+fn panic_message(payload: &dyn Any) -> String {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => payload.downcast_ref::<String>().cloned().unwrap_or_else(|| "unknown panic".to_string())
+    }
+}
+
+impl Handler for CatchPanicHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        // The handlers share nothing but the persistent state, the database mutex recovers in lock_connection
+        match panic::catch_unwind(AssertUnwindSafe(|| self.handler.handle(req))) {
+            Ok(result) => result,
+            Err(payload) => {
+                let request = current_request();
+                error!("Request {} panicked: {}", request.as_ref().map_or("-", |request| request.as_str()), panic_message(&*payload));
+
+                let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+                data.insert("request".to_string(), json!(request.and_then(|request| request.split(' ').next().map(|id| id.to_string()))));
+
+                let mut resp = Response::new();
+                resp.set_mut(Template::new("error", data)).set_mut(status::InternalServerError);
+                Ok(resp)
+            }
+        }
+    }
+}
+
+impl AroundMiddleware for CatchPanic {
+    fn around(self, handler: Box<dyn Handler>) -> Box<dyn Handler> {
+        Box::new(CatchPanicHandler { handler })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::panic_message;
+    use std::panic;
+
+    #[test]
+    fn test_panic_message() {
+        let payload = panic::catch_unwind(|| panic!("static message")).unwrap_err();
+        assert_eq!(panic_message(&*payload), "static message".to_string());
+
+        let payload = panic::catch_unwind(|| panic!("formatted {}", 42)).unwrap_err();
+        assert_eq!(panic_message(&*payload), "formatted 42".to_string());
+
+        let payload = panic::catch_unwind(|| panic::panic_any(42)).unwrap_err();
+        assert_eq!(panic_message(&*payload), "unknown panic".to_string());
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::BTreeMap;
+
+use iron::prelude::{Request, IronResult, Response, Set};
+use iron::modifiers::RedirectRaw;
+use iron::status;
+
+use handlebars_iron::{Template};
+use serde_json::Value as JsonValue;
+use params::{Params, Map};
+use plugin::Pluggable;
+use persistent::{Read, Write};
+
+use ::DBConnection;
+use admin::with_admin;
+use config::Configuration;
+use dates::{DateFormat, timestamp};
+use fee::format_amount;
+use database::{lock_connection, find_by_token, insert_reimbursement, load_reimbursements, update_reimbursement_status, Reimbursement, TokenOwner};
+use handler::{HandleError, extract_string, router_param, not_found};
+use upload::{store_upload, download_response, DOCUMENT_EXTENSIONS};
+use validation::{normalize_iban, parse_amount};
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Status {
+    Submitted,
+    Approved,
+    Rejected,
+    Paid
+}
+
+impl Status {
+    fn parse(status: &str) -> Option<Status> {
+        match status {
+            "submitted" => Some(Status::Submitted),
+            "approved" => Some(Status::Approved),
+            "rejected" => Some(Status::Rejected),
+            "paid" => Some(Status::Paid),
+            _ => None
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match *self {
+            Status::Submitted => "submitted",
+            Status::Approved => "approved",
+            Status::Rejected => "rejected",
+            Status::Paid => "paid"
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match *self {
+            Status::Submitted => "Eingereicht",
+            Status::Approved => "Genehmigt",
+            Status::Rejected => "Abgelehnt",
+            Status::Paid => "Ausgezahlt"
+        }
+    }
+
+    // Only approved requests get paid, a rejected or paid request is final
+    fn can_change_to(&self, next: Status) -> bool {
+        matches!((*self, next),
+            (Status::Submitted, Status::Approved) |
+            (Status::Submitted, Status::Rejected) |
+            (Status::Approved, Status::Paid) |
+            (Status::Approved, Status::Rejected))
+    }
+}
+
+fn reimbursement2json(reimbursement: &Reimbursement, dates: &DateFormat) -> JsonValue {
+    let status = Status::parse(&reimbursement.status).unwrap_or(Status::Submitted);
+
+    json!({
+        "id": reimbursement.id,
+        "name": format!("{} {}", reimbursement.first_name, reimbursement.last_name),
+        "account_holder": reimbursement.account_holder,
+        "iban": reimbursement.iban,
+        "bic": reimbursement.bic,
+        "amount": format_amount(reimbursement.amount),
+        "description": reimbursement.description,
+        "status": status.label(),
+        "can_approve": status.can_change_to(Status::Approved),
+        "can_reject": status.can_change_to(Status::Rejected),
+        "can_pay": status.can_change_to(Status::Paid),
+        "submitted_at": dates.timestamp(&reimbursement.submitted_at),
+        "updated_at": dates.timestamp(&reimbursement.updated_at)
+    })
+}
+
+// The token must belong to a registration whose email address is on the list in [Reimbursement]
+fn eligible_owner(req: &mut Request) -> Result<TokenOwner, HandleError> {
+    let token = router_param(req, "token");
+    let config = req.get::<Read<Configuration>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    match find_by_token(&db_connection, &token)? {
+        Some(owner) => {
+            if config.reimbursement_eligible.contains(&owner.email_normalized) {
+                Ok(owner)
+            } else {
+                Err(HandleError::NotFound)
+            }
+        }
+        None => Err(HandleError::NotFound)
+    }
+}
+
+fn render_participant_page(req: &mut Request, owner: &TokenOwner, message: &str) -> Result<Response, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let requests: Vec<JsonValue> = load_reimbursements(&db_connection, Some(owner.id))?.iter()
+        .map(|reimbursement| reimbursement2json(reimbursement, &config.date_format)).collect();
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("name".to_string(), json!(format!("{} {}", owner.first_name, owner.last_name)));
+    data.insert("token".to_string(), json!(router_param(req, "token")));
+    data.insert("requests".to_string(), json!(requests));
+    data.insert("message".to_string(), json!(message));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("reimbursement", data)).set_mut(status::Ok);
+    Ok(resp)
+}
+
+pub fn handle_reimbursement(req: &mut Request) -> IronResult<Response> {
+    let result = eligible_owner(req).and_then(|owner| render_participant_page(req, &owner, ""));
+
+    match result {
+        Ok(resp) => Ok(resp),
+        Err(HandleError::NotFound) => Ok(not_found()),
+        Err(e) => {
+            error!("Error while showing reimbursement page: {:?}", e);
+            Ok(Response::with((status::InternalServerError, "Internal server error")))
+        }
+    }
+}
+
+pub fn handle_reimbursement_submit(req: &mut Request) -> IronResult<Response> {
+    let owner = match eligible_owner(req) {
+        Ok(owner) => owner,
+        Err(HandleError::NotFound) => return Ok(not_found()),
+        Err(e) => {
+            error!("Error while looking up reimbursement token: {:?}", e);
+            return Ok(Response::with((status::InternalServerError, "Internal server error")));
+        }
+    };
+
+    let message = match submit_reimbursement(req, &owner) {
+        Ok(_) => {
+            info!("Reimbursement request submitted for registration {}", owner.id);
+            "Ihr Antrag wurde eingereicht.".to_string()
+        }
+        Err(HandleError::Validation(field)) => {
+            info!("Invalid value in reimbursement field '{}'", field);
+            format!("Ungültige Eingabe im Feld '{}'. Bitte korrigieren Sie die Angabe.", field)
+        }
+        Err(e) => {
+            error!("Error while processing reimbursement request: {:?}", e);
+            "Ein Fehler ist aufgetreten. Bitte versuchen Sie es später noch einmal.".to_string()
+        }
+    };
+
+    match render_participant_page(req, &owner, &message) {
+        Ok(resp) => Ok(resp),
+        Err(e) => {
+            error!("Error while showing reimbursement page: {:?}", e);
+            Ok(Response::with((status::InternalServerError, "Internal server error")))
+        }
+    }
+}
+
+fn required_string(map: &Map, key: &str) -> Result<String, HandleError> {
+    match extract_string(map, key) {
+        Ok(ref value) if !value.is_empty() => Ok(value.clone()),
+        _ => Err(HandleError::Validation(key.to_string()))
+    }
+}
+
+fn submit_reimbursement(req: &mut Request, owner: &TokenOwner) -> Result<(), HandleError> {
+    let map = req.get::<Params>()?;
+    let config = req.get::<Read<Configuration>>()?;
+
+    let account_holder = required_string(&map, "account_holder")?;
+    let iban = normalize_iban(&required_string(&map, "iban")?).ok_or_else(|| HandleError::Validation("iban".to_string()))?;
+    let amount = parse_amount(&required_string(&map, "amount")?).ok_or_else(|| HandleError::Validation("amount".to_string()))?;
+    let description = required_string(&map, "description")?;
+
+    let (receipt_file, receipt_name) = store_upload(&map, "receipt", "receipts", owner.id, &DOCUMENT_EXTENSIONS, &config)?;
+
+    let now = timestamp();
+    let reimbursement = Reimbursement {
+        id: 0,
+        registration_id: owner.id,
+        first_name: owner.first_name.clone(),
+        last_name: owner.last_name.clone(),
+        account_holder,
+        iban,
+        bic: extract_string(&map, "bic").unwrap_or_default().to_uppercase(),
+        amount,
+        description,
+        receipt_file,
+        receipt_name,
+        status: Status::Submitted.as_str().to_string(),
+        submitted_at: now.clone(),
+        updated_at: now
+    };
+
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    insert_reimbursement(&db_connection, &reimbursement)?;
+
+    Ok(())
+}
+
+pub fn handle_admin_reimbursements(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "reimbursements", admin_reimbursements_page)
+}
+
+fn admin_reimbursements_page(req: &mut Request) -> Result<Response, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let requests: Vec<JsonValue> = load_reimbursements(&db_connection, None)?.iter()
+        .map(|reimbursement| reimbursement2json(reimbursement, &config.date_format)).collect();
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("requests".to_string(), json!(requests));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("admin_reimbursements", data)).set_mut(status::Ok);
+    Ok(resp)
+}
+
+fn find_reimbursement(req: &mut Request) -> Result<Reimbursement, HandleError> {
+    let id = router_param(req, "id").parse::<i64>().map_err(|_| HandleError::NotFound)?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    load_reimbursements(&db_connection, None)?.into_iter().find(|reimbursement| reimbursement.id == id).ok_or(HandleError::NotFound)
+}
+
+pub fn handle_admin_reimbursement_status(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "reimbursement_status", change_status)
+}
+
+fn change_status(req: &mut Request) -> Result<Response, HandleError> {
+    let reimbursement = match find_reimbursement(req) {
+        Ok(reimbursement) => reimbursement,
+        Err(HandleError::NotFound) => return Ok(not_found()),
+        Err(e) => return Err(e)
+    };
+
+    let map = req.get::<Params>()?;
+    let current = Status::parse(&reimbursement.status).unwrap_or(Status::Submitted);
+    let next = Status::parse(&extract_string(&map, "status")?).ok_or(HandleError::FormValue)?;
+
+    if !current.can_change_to(next) {
+        info!("Reimbursement {}: status change from '{}' to '{}' not allowed", reimbursement.id, current.as_str(), next.as_str());
+        return Ok(Response::with((status::Conflict, "Status change not allowed")));
+    }
+
+    {
+        let mutex = req.get::<Write<DBConnection>>()?;
+        let db_connection = lock_connection(&mutex)?;
+        update_reimbursement_status(&db_connection, reimbursement.id, next.as_str(), &timestamp())?;
+    }
+
+    info!("Reimbursement {}: status changed from '{}' to '{}'", reimbursement.id, current.as_str(), next.as_str());
+
+    Ok(Response::with((status::Found, RedirectRaw("/admin/reimbursements".to_string()))))
+}
+
+pub fn handle_admin_reimbursement_receipt(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "reimbursement_receipt", receipt_download)
+}
+
+fn receipt_download(req: &mut Request) -> Result<Response, HandleError> {
+    let reimbursement = match find_reimbursement(req) {
+        Ok(reimbursement) => reimbursement,
+        Err(HandleError::NotFound) => return Ok(not_found()),
+        Err(e) => return Err(e)
+    };
+
+    let config = req.get::<Read<Configuration>>()?;
+
+    Ok(download_response("receipts", &reimbursement.receipt_file, &reimbursement.receipt_name, &config).unwrap_or_else(not_found))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Status;
+
+    #[test]
+    fn test_status_transitions() {
+        assert!(Status::Submitted.can_change_to(Status::Approved));
+        assert!(Status::Submitted.can_change_to(Status::Rejected));
+        assert!(!Status::Submitted.can_change_to(Status::Paid));
+        assert!(Status::Approved.can_change_to(Status::Paid));
+        assert!(!Status::Paid.can_change_to(Status::Rejected));
+        assert!(!Status::Rejected.can_change_to(Status::Approved));
+
+        for status in &[Status::Submitted, Status::Approved, Status::Rejected, Status::Paid] {
+            assert_eq!(Status::parse(status.as_str()), Some(*status));
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::BTreeMap;
+
+use iron::prelude::{Request, IronResult, Response, Set};
+use iron::headers::Cookie;
+use iron::modifiers::RedirectRaw;
+use iron::url::form_urlencoded::byte_serialize;
+use iron::status;
+
+use handlebars_iron::{Template};
+use serde_json::Value as JsonValue;
+use params::Params;
+use plugin::Pluggable;
+use persistent::{Read, Write};
+use chrono::Local;
+use openssl::sha::sha256;
+
+use ::DBConnection;
+use admin::{with_admin, secure_eq, unauthorized};
+use config::Configuration;
+use database::{lock_connection, insert_admin_token, load_admin_token, mark_admin_token_replaced, delete_admin_token, delete_stale_admin_tokens, AdminToken};
+use handler::{HandleError, extract_string};
+use throttle::throttle_submission;
+use token::generate_token;
+
+
+const COOKIE_NAME: &str = "admin_token";
+pub const REMEMBER_SECONDS: i64 = 30 * 24 * 60 * 60;
+// A remembered token is replaced by a new one once a day
+const ROTATE_AFTER: i64 = 24 * 60 * 60;
+// The requests a page started with the old token still get through
+const GRACE_SECONDS: i64 = 60;
+// A login at /admin/login without "stay logged in" lasts one working day
+const LOGIN_SECONDS: i64 = 12 * 60 * 60;
+
+/// Whether the request carries a token of a device the admin chose to stay logged in on
+#[derive(Debug, PartialEq)]
+pub enum Remembered {
+    No,
+    Yes,
+    // The new token that has to go back to the browser
+    Rotated(String)
+}
+
+// The tables only hold this hex digest, see login.rs for the other user
+pub fn token_hash(token: &str) -> String {
+    sha256(token.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn cookie_token(req: &Request) -> Option<String> {
+    let prefix = format!("{}=", COOKIE_NAME);
+    req.headers.get::<Cookie>().and_then(|cookies| cookies.iter()
+        .find(|cookie| cookie.starts_with(&prefix)).map(|cookie| cookie[prefix.len()..].to_string()))
+}
+
+pub fn set_token_cookie(resp: &mut Response, token: &str, max_age: i64, config: &Configuration) {
+    let secure = if config.base_url.starts_with("https://") { "; Secure" } else { "" };
+    let cookie = format!("{}={}; Path=/admin; Max-Age={}; HttpOnly; SameSite=Strict{}", COOKIE_NAME, token, max_age, secure);
+    resp.headers.set_raw("Set-Cookie", vec![cookie.into_bytes()]);
+}
+
+// Whether the token lets the admin in, and whether it is due to be replaced
+fn check_token(token: &AdminToken, now: i64) -> (bool, bool) {
+    let valid = now < token.expires_at && (token.replaced_at == 0 || now - token.replaced_at < GRACE_SECONDS);
+    (valid, valid && token.replaced_at == 0 && now - token.created_at >= ROTATE_AFTER)
+}
+
+pub fn remembered_admin(req: &mut Request) -> Result<Remembered, HandleError> {
+    let hash = match cookie_token(req) {
+        Some(token) => token_hash(&token),
+        None => return Ok(Remembered::No)
+    };
+
+    let now = Local::now().timestamp();
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let (valid, rotate) = match load_admin_token(&db_connection, &hash)? {
+        Some(token) => check_token(&token, now),
+        None => (false, false)
+    };
+
+    if !valid {
+        return Ok(Remembered::No);
+    }
+
+    if rotate && mark_admin_token_replaced(&db_connection, &hash, now)? {
+        delete_stale_admin_tokens(&db_connection, now, now - GRACE_SECONDS)?;
+        let token = generate_token()?;
+        insert_admin_token(&db_connection, &token_hash(&token), now, now + REMEMBER_SECONDS)?;
+        info!("Remembered admin token rotated");
+        return Ok(Remembered::Rotated(token));
+    }
+
+    Ok(Remembered::Yes)
+}
+
+pub fn handle_remember(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "remember", remember_page)
+}
+
+fn remember_page(req: &mut Request) -> Result<Response, HandleError> {
+    let map = req.get::<Params>()?;
+    let token = cookie_token(req);
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let remembered = match token {
+        Some(token) => load_admin_token(&db_connection, &token_hash(&token))?.is_some_and(|token| check_token(&token, Local::now().timestamp()).0),
+        None => false
+    };
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("remembered".to_string(), json!(remembered));
+    data.insert("days".to_string(), json!(REMEMBER_SECONDS / (24 * 60 * 60)));
+    data.insert("changed".to_string(), json!(extract_string(&map, "changed").is_ok()));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("remember", data)).set_mut(status::Ok);
+    Ok(resp)
+}
+
+pub fn handle_remember_submit(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "remember_submit", change_remember)
+}
+
+fn change_remember(req: &mut Request) -> Result<Response, HandleError> {
+    let action = extract_string(req.get_ref::<Params>()?, "action")?;
+    let config = req.get::<Read<Configuration>>()?;
+    let old_token = cookie_token(req);
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    if let Some(token) = old_token {
+        delete_admin_token(&db_connection, &token_hash(&token))?;
+    }
+
+    let mut resp = Response::with((status::Found, RedirectRaw("/admin/remember?changed=1".to_string())));
+
+    match action.as_str() {
+        "remember" => {
+            let now = Local::now().timestamp();
+            delete_stale_admin_tokens(&db_connection, now, now - GRACE_SECONDS)?;
+
+            let token = generate_token()?;
+            insert_admin_token(&db_connection, &token_hash(&token), now, now + REMEMBER_SECONDS)?;
+            set_token_cookie(&mut resp, &token, REMEMBER_SECONDS, &config);
+            info!("Admin stays logged in on a new device");
+        }
+        "forget" => {
+            set_token_cookie(&mut resp, "", 0, &config);
+            info!("Admin device forgotten");
+        }
+        _ => return Err(HandleError::FormValue)
+    }
+
+    Ok(resp)
+}
+
+// Only a path of this site, so that the login can't be used to send the admin elsewhere
+fn local_path(next: &str) -> &str {
+    let local = next.starts_with('/') && !next.starts_with("//") && !next.contains('\\') && !next.chars().any(|c| c.is_control());
+    if local && !next.starts_with("/admin/login") { next } else { "/admin" }
+}
+
+/// Where the browser goes for an admin page without credentials: the login form, which leads back to the page
+pub fn login_redirect(req: &Request) -> Response {
+    let mut path = format!("/{}", req.url.path().join("/"));
+    if let Some(query) = req.url.query() {
+        path.push('?');
+        path.push_str(query);
+    }
+
+    let next: String = byte_serialize(path.as_bytes()).collect();
+    Response::with((status::SeeOther, RedirectRaw(format!("/admin/login?next={}", next))))
+}
+
+fn render_login(next: &str, failed: bool, response_status: status::Status) -> Response {
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("next".to_string(), json!(local_path(next)));
+    data.insert("failed".to_string(), json!(failed));
+    data.insert("days".to_string(), json!(REMEMBER_SECONDS / (24 * 60 * 60)));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("admin_login", data)).set_mut(response_status);
+    resp
+}
+
+pub fn handle_admin_login(req: &mut Request) -> IronResult<Response> {
+    let enabled = req.get::<Read<Configuration>>().ok().is_some_and(|config| !config.admin_password.is_empty());
+    if !enabled {
+        return Ok(unauthorized());
+    }
+
+    let next = req.get_ref::<Params>().ok().and_then(|map| extract_string(map, "next").ok()).unwrap_or_default();
+    Ok(render_login(&next, false, status::Ok))
+}
+
+// The same credentials as HTTP basic auth, the browser then gets a token like /admin/remember sets
+pub fn handle_admin_login_submit(req: &mut Request) -> IronResult<Response> {
+    match admin_login(req) {
+        Ok(resp) => Ok(resp),
+        Err(HandleError::Throttled(ip)) => {
+            info!("Too many admin logins from '{}'", ip);
+            Ok(Response::with((status::TooManyRequests, "Zu viele Anmeldeversuche, bitte später noch einmal versuchen")))
+        }
+        Err(e) => {
+            error!("Error in the admin login: {:?}", e);
+            Ok(Response::with((status::InternalServerError, "Internal server error")))
+        }
+    }
+}
+
+fn admin_login(req: &mut Request) -> Result<Response, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    if config.admin_password.is_empty() {
+        return Ok(unauthorized());
+    }
+
+    throttle_submission(req)?;
+
+    let map = req.get::<Params>()?;
+    let next = extract_string(&map, "next").unwrap_or_default();
+    let username = extract_string(&map, "username").unwrap_or_default();
+    let password = extract_string(&map, "password").unwrap_or_default();
+
+    if !(secure_eq(&username, &config.admin_username) & secure_eq(&password, &config.admin_password)) {
+        info!("Failed admin login");
+        return Ok(render_login(&next, true, status::Unauthorized));
+    }
+
+    let seconds = if extract_string(&map, "remember").is_ok() { REMEMBER_SECONDS } else { LOGIN_SECONDS };
+    let now = Local::now().timestamp();
+    let token = generate_token()?;
+
+    {
+        let mutex = req.get::<Write<DBConnection>>()?;
+        let db_connection = lock_connection(&mutex)?;
+        delete_stale_admin_tokens(&db_connection, now, now - GRACE_SECONDS)?;
+        insert_admin_token(&db_connection, &token_hash(&token), now, now + seconds)?;
+    }
+
+    info!("Admin logged in");
+    let mut resp = Response::with((status::SeeOther, RedirectRaw(local_path(&next).to_string())));
+    set_token_cookie(&mut resp, &token, seconds, &config);
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_token, token_hash, local_path, GRACE_SECONDS, ROTATE_AFTER, REMEMBER_SECONDS};
+    use database::AdminToken;
+
+    #[test]
+    fn test_check_token() {
+        let token = AdminToken { created_at: 1000, expires_at: 1000 + REMEMBER_SECONDS, replaced_at: 0 };
+        assert_eq!(check_token(&token, 2000), (true, false));
+        assert_eq!(check_token(&token, 1000 + ROTATE_AFTER), (true, true));
+        assert_eq!(check_token(&token, 1000 + REMEMBER_SECONDS), (false, false));
+
+        let replaced = AdminToken { created_at: 1000, expires_at: 1000 + REMEMBER_SECONDS, replaced_at: 1000 + ROTATE_AFTER };
+        assert_eq!(check_token(&replaced, 1000 + ROTATE_AFTER + 10), (true, false));
+        assert_eq!(check_token(&replaced, 1000 + ROTATE_AFTER + GRACE_SECONDS), (false, false));
+    }
+
+    #[test]
+    fn test_token_hash() {
+        assert_eq!(token_hash("abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_string());
+    }
+
+    #[test]
+    fn test_local_path() {
+        assert_eq!(local_path("/admin/participants?status=cancelled"), "/admin/participants?status=cancelled");
+        assert_eq!(local_path("/admin/edit/3"), "/admin/edit/3");
+        assert_eq!(local_path(""), "/admin");
+        assert_eq!(local_path("https://example.org/admin"), "/admin");
+        assert_eq!(local_path("//example.org/admin"), "/admin");
+        assert_eq!(local_path("/\\example.org"), "/admin");
+        assert_eq!(local_path("/admin\r\nSet-Cookie: x"), "/admin");
+        assert_eq!(local_path("/admin/login?next=/admin"), "/admin");
+    }
+}
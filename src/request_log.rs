@@ -0,0 +1,82 @@
+use params::{Map, Value};
+use openssl::sha::sha256;
+
+use alert::current_request;
+
+
+// Form fields whose values never get into the log, matched as parts of the key (admin_password, form_token, ...)
+const SECRET_KEYS: [&str; 4] = ["password", "token", "draft", "preview"];
+
+fn is_secret(key: &str) -> bool {
+    let key = key.to_lowercase();
+    SECRET_KEYS.iter().any(|secret| key.contains(secret))
+}
+
+/// Identifies the submissions of one address in the log without writing the address itself.
+/// The first 12 hex digits of the SHA-256 of the trimmed lower case address.
+pub fn email_hash(email: &str) -> String {
+    sha256(email.trim().to_lowercase().as_bytes()).iter().take(6).map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// The parameters of a request for the log, with passwords and tokens replaced
+pub fn redact(map: &Map) -> String {
+    map.iter().map(|(key, value)| {
+        if is_secret(key) {
+            format!("{}=[redacted]", key)
+        } else {
+            match *value {
+                Value::String(ref text) => format!("{}={:?}", key, text),
+                ref other => format!("{}={:?}", key, other)
+            }
+        }
+    }).collect::<Vec<String>>().join(" ")
+}
+
+/// One line per submission of the form: the request (see RequestIds), the hashed address and what became of it.
+/// The form data itself stays out of the log, it is in the database or was rejected.
+pub fn submit_entry(email: Option<&str>, outcome: &str, detail: Option<&str>) -> String {
+    let mut entry = format!("request=\"{}\" email={} outcome={}", current_request().unwrap_or_else(|| "-".to_string()),
+        email.map_or("-".to_string(), email_hash), outcome);
+
+    if let Some(detail) = detail {
+        entry.push_str(&format!(" detail={:?}", detail));
+    }
+
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{email_hash, redact, submit_entry};
+    use params::{Map, Value};
+
+    #[test]
+    fn test_email_hash() {
+        assert_eq!(email_hash("bob@smith.com").len(), 12);
+        assert_eq!(email_hash(" Bob@Smith.com"), email_hash("bob@smith.com"));
+        assert!(email_hash("alice@smith.com") != email_hash("bob@smith.com"));
+    }
+
+    #[test]
+    fn test_redact() {
+        let mut map = Map::new();
+        map.assign("admin_password", Value::String("secret".into())).unwrap();
+        map.assign("form_token", Value::String("abc".into())).unwrap();
+        map.assign("draft", Value::String("def".into())).unwrap();
+        map.assign("lang", Value::String("de".into())).unwrap();
+
+        let text = redact(&map);
+        assert!(!text.contains("secret") && !text.contains("abc") && !text.contains("def"));
+        assert!(text.contains("admin_password=[redacted]"));
+        assert!(text.contains("lang=\"de\""));
+    }
+
+    #[test]
+    fn test_submit_entry() {
+        assert_eq!(submit_entry(None, "throttled", None), "request=\"-\" email=- outcome=throttled".to_string());
+
+        let entry = submit_entry(Some("bob@smith.com"), "invalid", Some("zip"));
+        assert!(!entry.contains("bob"));
+        assert!(entry.ends_with("outcome=invalid detail=\"zip\""));
+    }
+}
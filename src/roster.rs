@@ -0,0 +1,251 @@
+use std::collections::BTreeMap;
+
+use iron::prelude::{Request, IronResult, Response, Set};
+use iron::status;
+
+use handlebars_iron::{Template};
+use serde_json::Value as JsonValue;
+use params::{Params, Map};
+use plugin::Pluggable;
+use persistent::{Read, Write};
+use chrono::{Duration, Local, NaiveDate};
+
+use ::DBConnection;
+use admin::with_admin;
+use config::Configuration;
+use database::{lock_connection, load_roster, load_roster_changes, RosterEntry, RosterChange};
+use export::{export_response, Table};
+use handler::{HandleError, extract_string};
+use status::{RegistrationStatus, status_label};
+
+
+// What happened to a registration between two days, from the view of the venue and the caterer:
+// only who takes part (pending or confirmed) counts
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RosterDiff {
+    New,
+    Changed,
+    Cancelled
+}
+
+const ALL_DIFFS: [RosterDiff; 3] = [RosterDiff::New, RosterDiff::Changed, RosterDiff::Cancelled];
+
+impl RosterDiff {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            RosterDiff::New => "new",
+            RosterDiff::Changed => "changed",
+            RosterDiff::Cancelled => "cancelled"
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match *self {
+            RosterDiff::New => "Neu",
+            RosterDiff::Changed => "Geändert",
+            RosterDiff::Cancelled => "Abgemeldet"
+        }
+    }
+}
+
+// The last status change before time, otherwise the status the first later change started from.
+// A registration without any change still has the status it was registered with.
+fn status_at(entry: &RosterEntry, changes: &[&RosterChange], time: &str) -> String {
+    let status_changes: Vec<&&RosterChange> = changes.iter().filter(|change| change.kind == "status").collect();
+
+    match status_changes.iter().rev().find(|change| change.changed_at.as_str() < time) {
+        Some(change) => change.new_status.clone(),
+        None => status_changes.first().map_or(entry.status.clone(), |change| change.old_status.clone())
+    }
+}
+
+fn takes_part(entry: &RosterEntry, changes: &[&RosterChange], time: &str) -> bool {
+    entry.registered_at.as_str() < time &&
+        RegistrationStatus::from_name(&status_at(entry, changes, time)).is_some_and(|status| status.takes_place())
+}
+
+/// Compares who takes part at from and at to (both "YYYY-MM-DD HH:MM:SS"): who joined, who dropped out and
+/// whose details changed in between. Somebody who joined and left in the same week isn't listed.
+pub fn roster_diff<'a>(entries: &'a [RosterEntry], changes: &[RosterChange], from: &str, to: &str) -> Vec<(RosterDiff, &'a RosterEntry)> {
+    let mut result: Vec<(RosterDiff, &RosterEntry)> = entries.iter().filter_map(|entry| {
+        let own: Vec<&RosterChange> = changes.iter().filter(|change| change.registration_id == entry.id).collect();
+        let edited = own.iter().any(|change| change.kind == "details" && change.changed_at.as_str() >= from && change.changed_at.as_str() < to);
+
+        let diff = match (takes_part(entry, &own, from), takes_part(entry, &own, to)) {
+            (false, true) => RosterDiff::New,
+            (true, false) => RosterDiff::Cancelled,
+            (true, true) if edited => RosterDiff::Changed,
+            _ => return None
+        };
+
+        Some((diff, entry))
+    }).collect();
+
+    // Stable, the entries keep the order by name within each group
+    result.sort_by_key(|&(diff, _)| ALL_DIFFS.iter().position(|&other| other == diff));
+    result
+}
+
+// The days of ?from= and ?until=, both included. Without them the last seven days up to today.
+fn window(map: &Map) -> (NaiveDate, NaiveDate) {
+    let day = |key: &str| extract_string(map, key).ok().and_then(|day| NaiveDate::parse_from_str(&day, "%Y-%m-%d").ok());
+    let until = day("until").unwrap_or_else(|| Local::now().naive_local().date());
+    let from = day("from").unwrap_or_else(|| until - Duration::days(6));
+
+    (from, until)
+}
+
+// The days of the window with the registrations that changed in it
+type WindowDiff = (NaiveDate, NaiveDate, Vec<(RosterDiff, RosterEntry)>);
+
+fn load_diff(req: &mut Request) -> Result<WindowDiff, HandleError> {
+    let (from, until) = window(req.get_ref::<Params>()?);
+    let start = format!("{} 00:00:00", from.format("%Y-%m-%d"));
+    let end = format!("{} 00:00:00", until.succ().format("%Y-%m-%d"));
+
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let entries = load_roster(&db_connection, &end)?;
+    let changes = load_roster_changes(&db_connection)?;
+    let diff = roster_diff(&entries, &changes, &start, &end).into_iter().map(|(diff, entry)| (diff, entry.clone())).collect();
+
+    Ok((from, until, diff))
+}
+
+fn course_name<'a>(course_type: &str, config: &'a Configuration) -> &'a str {
+    if course_type == "course1" { &config.course1 } else { &config.course2 }
+}
+
+fn roster_table(diff: &[(RosterDiff, RosterEntry)], from: NaiveDate, until: NaiveDate, config: &Configuration) -> Table {
+    let mut table = Table::new(&format!("changes_{}_{}", from.format("%Y-%m-%d"), until.format("%Y-%m-%d")),
+        &["change", "first_name", "last_name", "institution", "email", "course", "more_info", "status"]);
+
+    for &(diff, ref entry) in diff {
+        table.push(&[diff.name(), &entry.first_name, &entry.last_name, &entry.institution, &entry.email_to,
+            course_name(&entry.course_type, config), &entry.more_info, &entry.status]);
+    }
+
+    table
+}
+
+pub fn handle_roster_changes(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "roster_changes", roster_changes_page)
+}
+
+fn roster_changes_page(req: &mut Request) -> Result<Response, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    let (from, until, diff) = load_diff(req)?;
+
+    let groups: Vec<JsonValue> = ALL_DIFFS.iter().map(|&kind| {
+        let people: Vec<JsonValue> = diff.iter().filter(|&&(other, _)| other == kind).map(|(_, entry)| json!({
+            "id": entry.id,
+            "name": format!("{} {}", entry.first_name, entry.last_name),
+            "institution": entry.institution,
+            "email": entry.email_to,
+            "course": course_name(&entry.course_type, &config),
+            "more_info": entry.more_info,
+            "status": status_label(&entry.status)
+        })).collect();
+
+        json!({ "label": kind.label(), "count": people.len(), "people": people })
+    }).collect();
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("from".to_string(), json!(from.format("%Y-%m-%d").to_string()));
+    data.insert("until".to_string(), json!(until.format("%Y-%m-%d").to_string()));
+    data.insert("groups".to_string(), json!(groups));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("roster_changes", data)).set_mut(status::Ok);
+    Ok(resp)
+}
+
+pub fn handle_export_roster_changes(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "export_roster_changes", export_roster_changes)
+}
+
+fn export_roster_changes(req: &mut Request) -> Result<Response, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    let (from, until, diff) = load_diff(req)?;
+
+    info!("Changes from {} to {} exported", from, until);
+
+    export_response(req, &roster_table(&diff, from, until, &config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{roster_diff, roster_table, RosterDiff};
+    use config::example_configuration;
+    use database::{RosterEntry, RosterChange};
+    use chrono::NaiveDate;
+
+    fn entry(id: i64, last_name: &str, status: &str, registered_at: &str) -> RosterEntry {
+        RosterEntry {
+            id,
+            first_name: "Test".to_string(),
+            last_name: last_name.to_string(),
+            institution: "Uni".to_string(),
+            email_to: format!("{}@example.org", last_name.to_lowercase()),
+            course_type: "course1".to_string(),
+            more_info: String::new(),
+            status: status.to_string(),
+            registered_at: registered_at.to_string()
+        }
+    }
+
+    fn change(id: i64, kind: &str, old_status: &str, new_status: &str, changed_at: &str) -> RosterChange {
+        RosterChange {
+            registration_id: id,
+            kind: kind.to_string(),
+            old_status: old_status.to_string(),
+            new_status: new_status.to_string(),
+            changed_at: changed_at.to_string()
+        }
+    }
+
+    #[test]
+    fn test_roster_diff() {
+        let entries = vec![
+            entry(1, "Adams", "confirmed", "2017-01-02 10:00:00"),
+            entry(2, "Brown", "cancelled", "2017-01-02 10:00:00"),
+            entry(3, "Clark", "confirmed", "2017-01-10 10:00:00"),
+            entry(4, "Davis", "confirmed", "2017-01-02 10:00:00"),
+            entry(5, "Evans", "cancelled", "2017-01-09 10:00:00"),
+            entry(6, "Foster", "confirmed", "2017-01-02 10:00:00"),
+            entry(7, "Green", "cancelled", "2017-01-02 10:00:00")
+        ];
+
+        let changes = vec![
+            change(2, "status", "confirmed", "cancelled", "2017-01-10 12:00:00"),
+            change(4, "details", "", "", "2017-01-11 12:00:00"),
+            change(5, "status", "confirmed", "cancelled", "2017-01-12 12:00:00"),
+            // Moved up from the waitlist
+            change(6, "status", "waitlisted", "confirmed", "2017-01-13 12:00:00"),
+            // Cancelled after the week, still there at its end
+            change(7, "status", "confirmed", "cancelled", "2017-01-20 12:00:00")
+        ];
+
+        let diff: Vec<(RosterDiff, i64)> = roster_diff(&entries, &changes, "2017-01-09 00:00:00", "2017-01-16 00:00:00").into_iter()
+            .map(|(diff, entry)| (diff, entry.id)).collect();
+        assert_eq!(diff, vec![(RosterDiff::New, 3), (RosterDiff::New, 6), (RosterDiff::Changed, 4), (RosterDiff::Cancelled, 2)]);
+
+        // The week after
+        let diff: Vec<(RosterDiff, i64)> = roster_diff(&entries, &changes, "2017-01-16 00:00:00", "2017-01-23 00:00:00").into_iter()
+            .map(|(diff, entry)| (diff, entry.id)).collect();
+        assert_eq!(diff, vec![(RosterDiff::Cancelled, 7)]);
+    }
+
+    #[test]
+    fn test_roster_csv() {
+        let mut person = entry(1, "Smith", "confirmed", "2017-01-02 10:00:00");
+        person.more_info = "Vegetarisch, keine Nüsse".to_string();
+
+        let table = roster_table(&[(RosterDiff::New, person)], NaiveDate::from_ymd(2017, 1, 9), NaiveDate::from_ymd(2017, 1, 15),
+            &example_configuration());
+        assert_eq!(table.file_stem, "changes_2017-01-09_2017-01-15".to_string());
+        assert_eq!(table.to_csv(), "change,first_name,last_name,institution,email,course,more_info,status\r\n\
+            new,Test,Smith,Uni,smith@example.org,1. Jan 2000,\"Vegetarisch, keine Nüsse\",confirmed\r\n".to_string());
+    }
+}
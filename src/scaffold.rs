@@ -0,0 +1,184 @@
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+
+use iron::prelude::{Request, IronResult, Response};
+use iron::middleware::Handler;
+use iron::mime::Mime;
+use iron::status;
+
+use database::Repository;
+use handler::HandleError;
+
+
+macro_rules! site_file {
+    ($path:expr) => (site_file!($path, $path));
+    ($source:expr, $target:expr) => (($target, include_str!(concat!("../", $source))));
+}
+
+// Everything a new site needs besides the database, as (path, content)
+const SITE_FILES: [(&str, &str); 50] = [
+    site_file!("registration_config.example.ini", "registration_config.ini"),
+    site_file!("css/style.css"),
+    site_file!("js/banner.js"),
+    site_file!("js/draft.js"),
+    site_file!("js/field_trip.js"),
+    site_file!("js/institutions.js"),
+    site_file!("js/jquery-3.1.1.min.js"),
+    site_file!("js/live.js"),
+    site_file!("js/presentation.js"),
+    site_file!("js/script.js"),
+    site_file!("templates/accessibility.hbs"),
+    site_file!("templates/admin.hbs"),
+    site_file!("templates/admin_abstracts.hbs"),
+    site_file!("templates/admin_reimbursements.hbs"),
+    site_file!("templates/admin_travel_grants.hbs"),
+    site_file!("templates/announcement.hbs"),
+    site_file!("templates/approvals.hbs"),
+    site_file!("templates/arrivals.hbs"),
+    site_file!("templates/child_care.hbs"),
+    site_file!("templates/closed.hbs"),
+    site_file!("templates/delete_registration.hbs"),
+    site_file!("templates/duplicate_merge.hbs"),
+    site_file!("templates/duplicates.hbs"),
+    site_file!("templates/edit_registration.hbs"),
+    site_file!("templates/email_preview.hbs"),
+    site_file!("templates/error.hbs"),
+    site_file!("templates/fee_waivers.hbs"),
+    site_file!("templates/index.hbs"),
+    site_file!("templates/institutions.hbs"),
+    site_file!("templates/login.hbs"),
+    site_file!("templates/mail_templates.hbs"),
+    site_file!("templates/outbox.hbs"),
+    site_file!("templates/overdue_payments.hbs"),
+    site_file!("templates/participant_edit.hbs"),
+    site_file!("templates/participants.hbs"),
+    site_file!("templates/posters.hbs"),
+    site_file!("templates/presentation_upload.hbs"),
+    site_file!("templates/program.hbs"),
+    site_file!("templates/reimbursement.hbs"),
+    site_file!("templates/remember.hbs"),
+    site_file!("templates/admin_login.hbs"),
+    site_file!("templates/rooms.hbs"),
+    site_file!("templates/roster_changes.hbs"),
+    site_file!("templates/session_suggestions.hbs"),
+    site_file!("templates/settings.hbs"),
+    site_file!("templates/smtp_check.hbs"),
+    site_file!("templates/stats.hbs"),
+    site_file!("templates/submit.hbs"),
+    site_file!("templates/travel_grant.hbs"),
+    site_file!("templates/unsubscribe.hbs")
+];
+
+/// The templates of SITE_FILES by name, e.g. "index", for bundled_files in [Basic]
+pub fn bundled_templates() -> BTreeMap<String, String> {
+    SITE_FILES.iter().filter(|&&(path, _)| path.starts_with("templates/") && path.ends_with(".hbs"))
+        .map(|&(path, content)| (path["templates/".len()..path.len() - ".hbs".len()].to_string(), content.to_string())).collect()
+}
+
+fn bundled_file(path: &str) -> Option<&'static str> {
+    SITE_FILES.iter().find(|&&(name, _)| name == path).map(|&(_, content)| content)
+}
+
+/// Serves css/ or js/ from SITE_FILES instead of the site's folder, for bundled_files in [Basic]
+pub struct BundledFiles {
+    folder: &'static str
+}
+
+impl BundledFiles {
+    pub fn new(folder: &'static str) -> BundledFiles {
+        BundledFiles { folder }
+    }
+}
+
+impl Handler for BundledFiles {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let path = format!("{}/{}", self.folder, req.url.path().join("/"));
+
+        match bundled_file(&path) {
+            Some(content) => {
+                let content_type = if path.ends_with(".css") { "text/css; charset=utf-8" } else { "application/javascript; charset=utf-8" };
+                Ok(Response::with((status::Ok, content_type.parse::<Mime>().unwrap(), content)))
+            }
+            None => Ok(Response::with(status::NotFound))
+        }
+    }
+}
+
+// Matches db_filename of the example configuration
+const DATABASE_FILE: &str = "registration_database.sqlite3";
+
+/// Sets up a new site in `folder`: a commented `registration_config.ini`, the templates, scripts and styles,
+/// the upload folder and the database. Existing files are left alone, so customized templates survive.
+/// Returns the files that were written.
+pub fn init_site(folder: &Path) -> Result<Vec<String>, HandleError> {
+    let mut written = Vec::new();
+
+    for &(path, content) in SITE_FILES.iter() {
+        let target = folder.join(path);
+
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        match OpenOptions::new().write(true).create_new(true).open(&target) {
+            Ok(mut file) => {
+                file.write_all(content.as_bytes())?;
+                written.push(path.to_string());
+            }
+            Err(ref e) if e.kind() == ErrorKind::AlreadyExists => (),
+            Err(e) => return Err(e.into())
+        }
+    }
+
+    fs::create_dir_all(folder.join("uploads"))?;
+
+    let database = folder.join(DATABASE_FILE);
+    if !database.exists() {
+        Repository::open(database.to_str().ok_or(HandleError::IO)?)?;
+        written.push(DATABASE_FILE.to_string());
+    }
+
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{init_site, bundled_templates, bundled_file, SITE_FILES};
+    use std::env;
+    use std::fs;
+    use config::load_configuration;
+
+    #[test]
+    fn test_init_site() {
+        let folder = env::temp_dir().join("conference_registration_init_test");
+        let _ = fs::remove_dir_all(&folder);
+
+        let written = init_site(&folder).unwrap();
+        assert_eq!(written.len(), SITE_FILES.len() + 1);
+        assert!(folder.join("templates/index.hbs").exists());
+        assert!(folder.join("uploads").is_dir());
+
+        // The example only sets the required keys
+        let config = load_configuration(folder.join("registration_config.ini").to_str().unwrap()).unwrap();
+        assert_eq!(config.db_filename, "registration_database.sqlite3".to_string());
+        assert!(config.admin_username.is_empty());
+
+        fs::write(folder.join("templates/index.hbs"), "customized").unwrap();
+        assert_eq!(init_site(&folder).unwrap(), Vec::<String>::new());
+        assert_eq!(fs::read_to_string(folder.join("templates/index.hbs")).unwrap(), "customized".to_string());
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+    #[test]
+    fn test_bundled_files() {
+        let templates = bundled_templates();
+        assert!(templates.contains_key("index"));
+        assert!(templates.contains_key("admin"));
+        assert!(!templates.contains_key("registration_config"));
+
+        assert!(bundled_file("css/style.css").is_some());
+        assert!(bundled_file("js/../registration_config.ini").is_none());
+    }
+}
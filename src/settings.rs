@@ -0,0 +1,296 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+use iron::prelude::{Request, IronResult, Response, Set};
+use iron::middleware::BeforeMiddleware;
+use iron::modifiers::RedirectRaw;
+use iron::status;
+
+use handlebars_iron::{Template};
+use serde_json::Value as JsonValue;
+use params::Params;
+use plugin::Pluggable;
+use persistent::{Read, Write};
+use rusqlite::Connection;
+use chrono::{NaiveDate, NaiveDateTime};
+
+use ::{DBConnection, RuntimeSettings};
+use admin::with_admin;
+use banner::valid_banner_until;
+use config::{Configuration, valid_window};
+use dates::{CONFIGURED_TIME, timestamp};
+use database::{lock_connection, load_settings, save_setting, delete_setting};
+use handler::{HandleError, extract_string};
+use validation::parse_amount;
+
+
+// What can be changed at /admin/settings without editing the configuration file and restarting: key and label
+pub const SETTINGS: [(&str, &str); 8] = [
+    ("registration_open", "Anmeldung öffnet (JJJJ-MM-TT HH:MM, leer für sofort)"),
+    ("registration_close", "Anmeldung schließt (JJJJ-MM-TT HH:MM, leer für nie)"),
+    ("capacity", "Anzahl der Plätze (leer für unbegrenzt)"),
+    ("payment_deadline", "Zahlungsfrist (JJJJ-MM-TT)"),
+    ("fee_student", "Gebühr Studierende"),
+    ("fee_regular", "Gebühr regulär"),
+    ("banner", "Hinweis oben auf allen öffentlichen Seiten"),
+    ("banner_until", "Hinweis anzeigen bis (JJJJ-MM-TT HH:MM, leer für unbegrenzt)")
+];
+
+fn apply_setting(config: &mut Configuration, key: &str, value: &str) -> Result<(), HandleError> {
+    let invalid = || HandleError::Validation(key.to_string());
+
+    match key {
+        // Together with the other end of the window checked by valid_settings
+        "registration_open" => {
+            if !value.is_empty() && NaiveDateTime::parse_from_str(value, CONFIGURED_TIME).is_err() {
+                return Err(invalid());
+            }
+            config.registration_open = value.to_string();
+        }
+        "registration_close" => {
+            if !value.is_empty() && NaiveDateTime::parse_from_str(value, CONFIGURED_TIME).is_err() {
+                return Err(invalid());
+            }
+            config.registration_close = value.to_string();
+        }
+        "capacity" => config.capacity = if value.is_empty() { 0 } else {
+            value.parse::<i64>().ok().and_then(|capacity| if capacity >= 0 { Some(capacity) } else { None }).ok_or_else(invalid)?
+        },
+        "payment_deadline" => {
+            if !value.is_empty() && NaiveDate::parse_from_str(value, "%Y-%m-%d").is_err() {
+                return Err(invalid());
+            }
+            config.payment_deadline = value.to_string();
+        }
+        "fee_student" => config.fee_student = parse_amount(value).ok_or_else(invalid)?,
+        "fee_regular" => config.fee_regular = parse_amount(value).ok_or_else(invalid)?,
+        "banner" => config.banner = value.to_string(),
+        "banner_until" => {
+            if !valid_banner_until(value) {
+                return Err(invalid());
+            }
+            config.banner_until = value.to_string();
+        }
+        _ => return Err(invalid())
+    }
+
+    Ok(())
+}
+
+// As it is entered in the form
+fn setting_value(config: &Configuration, key: &str) -> String {
+    let amount = |cents: i64| format!("{}.{:02}", cents / 100, cents % 100);
+
+    match key {
+        "registration_open" => config.registration_open.clone(),
+        "registration_close" => config.registration_close.clone(),
+        "capacity" if config.capacity > 0 => config.capacity.to_string(),
+        "payment_deadline" => config.payment_deadline.clone(),
+        "fee_student" => amount(config.fee_student),
+        "fee_regular" => amount(config.fee_regular),
+        "banner" => config.banner.clone(),
+        "banner_until" => config.banner_until.clone(),
+        _ => String::new()
+    }
+}
+
+// The settings that only make sense together, the key to blame if they don't
+fn invalid_combination(config: &Configuration) -> Option<&'static str> {
+    if valid_window(&config.registration_open, &config.registration_close) { None } else { Some("registration_close") }
+}
+
+// The configuration file with the settings saved in the database
+pub fn effective_configuration(db_connection: &Connection, base: &Configuration) -> Result<Configuration, HandleError> {
+    let mut config = base.clone();
+
+    for (key, value) in load_settings(db_connection)? {
+        if let Err(e) = apply_setting(&mut config, &key, &value) {
+            error!("Ignoring saved setting '{}' = '{}': {:?}", key, value, e);
+        }
+    }
+
+    if invalid_combination(&config).is_some() {
+        error!("Ignoring the saved registration window '{}' - '{}'", config.registration_open, config.registration_close);
+        config.registration_open = base.registration_open.clone();
+        config.registration_close = base.registration_close.clone();
+    }
+
+    Ok(config)
+}
+
+// Takes the place of Read<Configuration>: every request gets the configuration with the current settings
+#[derive(Clone)]
+pub struct RuntimeConfiguration {
+    base: Arc<Configuration>,
+    current: Arc<RwLock<Arc<Configuration>>>
+}
+
+impl RuntimeConfiguration {
+    pub fn new(base: Configuration, db_connection: &Connection) -> Result<RuntimeConfiguration, HandleError> {
+        let current = effective_configuration(db_connection, &base)?;
+        Ok(RuntimeConfiguration { base: Arc::new(base), current: Arc::new(RwLock::new(Arc::new(current))) })
+    }
+
+    pub fn current(&self) -> Arc<Configuration> {
+        self.current.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn reload(&self, db_connection: &Connection) -> Result<(), HandleError> {
+        let current = effective_configuration(db_connection, &self.base)?;
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(current);
+        Ok(())
+    }
+}
+
+impl BeforeMiddleware for RuntimeConfiguration {
+    fn before(&self, req: &mut Request) -> IronResult<()> {
+        req.extensions.insert::<Read<Configuration>>(self.current());
+        Ok(())
+    }
+}
+
+pub fn handle_settings(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "settings", |req| settings_page(req, None))
+}
+
+fn settings_page(req: &mut Request, error: Option<&str>) -> Result<Response, HandleError> {
+    let runtime = req.get::<Read<RuntimeSettings>>()?;
+    let map = req.get::<Params>()?;
+    let config = runtime.current();
+
+    let settings: Vec<JsonValue> = SETTINGS.iter().map(|&(key, label)| {
+        let value = setting_value(&config, key);
+        let default = setting_value(&runtime.base, key);
+
+        json!({
+            "key": key,
+            "label": label,
+            "value": value,
+            "default": default,
+            "changed": value != default,
+            "invalid": error == Some(key)
+        })
+    }).collect();
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("settings".to_string(), json!(settings));
+    data.insert("saved".to_string(), json!(extract_string(&map, "saved").is_ok()));
+    data.insert("error".to_string(), json!(error.is_some()));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("settings", data)).set_mut(if error.is_some() { status::BadRequest } else { status::Ok });
+    Ok(resp)
+}
+
+pub fn handle_settings_submit(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "settings_submit", save_settings)
+}
+
+// A value equal to the configuration file removes the saved setting, so later changes of the file take effect again
+fn save_settings(req: &mut Request) -> Result<Response, HandleError> {
+    let runtime = req.get::<Read<RuntimeSettings>>()?;
+    let map = req.get::<Params>()?;
+
+    let mut changes = Vec::new();
+    let mut check = (*runtime.base).clone();
+
+    for &(key, _) in SETTINGS.iter() {
+        let value = extract_string(&map, key).unwrap_or_default();
+
+        // Nothing is saved if one of the values is invalid
+        if apply_setting(&mut check, key, &value).is_err() {
+            info!("Invalid value for setting '{}': '{}'", key, value);
+            return settings_page(req, Some(key));
+        }
+
+        // Compared in the form's notation, "120" and "120.00" are the same fee
+        let changed = setting_value(&check, key) != setting_value(&runtime.base, key);
+        changes.push((key, value, changed));
+    }
+
+    if let Some(key) = invalid_combination(&check) {
+        info!("Invalid combination of settings at '{}'", key);
+        return settings_page(req, Some(key));
+    }
+
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+    let now = timestamp();
+
+    for (key, value, changed) in changes {
+        if changed {
+            save_setting(&db_connection, key, &value, &now)?;
+        } else {
+            delete_setting(&db_connection, key)?;
+        }
+    }
+
+    runtime.reload(&db_connection)?;
+    info!("Settings changed");
+
+    Ok(Response::with((status::Found, RedirectRaw("/admin/settings?saved=1".to_string()))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_setting, setting_value, effective_configuration, invalid_combination, SETTINGS};
+    use config::example_configuration;
+    use database::{init_database, save_setting};
+    use rusqlite::Connection;
+
+    #[test]
+    fn test_apply_setting() {
+        let mut config = example_configuration();
+
+        apply_setting(&mut config, "capacity", "120").unwrap();
+        apply_setting(&mut config, "fee_student", "45,50").unwrap();
+        apply_setting(&mut config, "payment_deadline", "2017-03-01").unwrap();
+        assert_eq!(config.capacity, 120);
+        assert_eq!(config.fee_student, 4550);
+        assert_eq!(setting_value(&config, "fee_student"), "45.50".to_string());
+
+        apply_setting(&mut config, "capacity", "").unwrap();
+        assert_eq!(setting_value(&config, "capacity"), "".to_string());
+
+        assert!(apply_setting(&mut config, "capacity", "-1").is_err());
+        assert!(apply_setting(&mut config, "payment_deadline", "1. März").is_err());
+        assert!(apply_setting(&mut config, "banner_until", "Freitag").is_err());
+        assert!(apply_setting(&mut config, "fee_regular", "viel").is_err());
+        assert!(apply_setting(&mut config, "db_filename", "other.sqlite3").is_err());
+        assert!(apply_setting(&mut config, "registration_close", "1. März").is_err());
+
+        apply_setting(&mut config, "registration_open", "2017-01-15 09:00").unwrap();
+        apply_setting(&mut config, "registration_close", "2017-03-01 23:59").unwrap();
+        assert_eq!(config.registration_close, "2017-03-01 23:59".to_string());
+        assert_eq!(invalid_combination(&config), None);
+        apply_setting(&mut config, "registration_close", "2017-01-01 00:00").unwrap();
+        assert_eq!(invalid_combination(&config), Some("registration_close"));
+        apply_setting(&mut config, "registration_close", "").unwrap();
+
+        // Every setting can be shown and saved again
+        for &(key, _) in SETTINGS.iter() {
+            let value = setting_value(&config, key);
+            apply_setting(&mut config, key, &value).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_effective_configuration() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+        let base = example_configuration();
+
+        save_setting(&conn, "banner", "Nur noch wenige Plätze", "2017-01-10 12:00:00").unwrap();
+        save_setting(&conn, "capacity", "kaputt", "2017-01-10 12:00:00").unwrap();
+
+        let config = effective_configuration(&conn, &base).unwrap();
+        assert_eq!(config.banner, "Nur noch wenige Plätze".to_string());
+        assert_eq!(config.capacity, base.capacity);
+
+        // The window of the form is closed early without a restart, but never closes before it opens
+        save_setting(&conn, "registration_close", "2017-02-01 12:00", "2017-01-10 12:00:00").unwrap();
+        assert_eq!(effective_configuration(&conn, &base).unwrap().registration_close, "2017-02-01 12:00".to_string());
+        save_setting(&conn, "registration_open", "2017-02-02 12:00", "2017-01-10 12:00:00").unwrap();
+        assert_eq!(effective_configuration(&conn, &base).unwrap().registration_close, base.registration_close);
+    }
+}
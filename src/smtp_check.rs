@@ -0,0 +1,252 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read};
+use std::net::{Ipv4Addr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::time::Duration;
+
+use iron::prelude::{Request, IronResult, Response, Set};
+use iron::status;
+
+use handlebars_iron::{Template};
+use serde_json::Value as JsonValue;
+use plugin::Pluggable;
+use persistent::Read as PersistentRead;
+use lettre::transport::smtp::SUBMISSION_PORT;
+use lettre::transport::smtp::authentication::Mechanism;
+use lettre::transport::smtp::client::Client;
+use lettre::transport::smtp::client::net::NetworkStream;
+use lettre::transport::smtp::error::Error as SmtpError;
+use lettre::transport::smtp::extension::{Extension, ServerInfo};
+use lettre::transport::smtp::response::{Response as SmtpResponse, ResponseParser};
+use openssl::ssl::{SslContext, SslMethod};
+
+use admin::with_admin;
+use config::Configuration;
+use handler::HandleError;
+
+
+// For connecting and for every answer, a real mail waits just as long
+const TIMEOUT_SECONDS: u64 = 10;
+
+// One stage of the connection, the check stops at the first one that fails
+#[derive(Debug, PartialEq)]
+pub struct Step {
+    pub stage: &'static str,
+    pub success: bool,
+    pub detail: String
+}
+
+fn step(stage: &'static str, result: Result<String, String>) -> Step {
+    match result {
+        Ok(detail) => Step { stage, success: true, detail },
+        Err(detail) => Step { stage, success: false, detail }
+    }
+}
+
+fn response_text(response: &SmtpResponse) -> String {
+    format!("{}{}{} {}", response.severity(), response.category(), response.detail(), response.message().join(" "))
+}
+
+// The reply code and text of the server instead of lettre's "a permanent error occured"
+fn smtp_error(error: SmtpError) -> String {
+    match error {
+        SmtpError::Transient(response) | SmtpError::Permanent(response) => format!("Der Server antwortet: {}", response_text(&response)),
+        SmtpError::Io(e) => e.to_string(),
+        other => other.to_string()
+    }
+}
+
+// The greeting comes before the first command, so the client of lettre can't read it
+fn read_greeting(stream: &mut dyn Read) -> Result<SmtpResponse, SmtpError> {
+    let mut parser = ResponseParser::default();
+
+    loop {
+        let mut line = Vec::new();
+        let mut byte = [0; 1];
+        while line.last() != Some(&b'\n') {
+            if stream.read(&mut byte)? == 0 {
+                return Err(SmtpError::Io(io::Error::new(io::ErrorKind::UnexpectedEof, "Verbindung vom Server geschlossen")));
+            }
+            line.push(byte[0]);
+        }
+
+        let line = String::from_utf8_lossy(&line);
+        if !parser.read_line(line.trim_end())? {
+            break;
+        }
+    }
+
+    let response = parser.response()?;
+    if response.is_positive() { Ok(response) } else { Err(SmtpError::from(response)) }
+}
+
+// The same address as deliver in handler.rs, which only takes an IPv4 address
+fn server_address(server: &str, port: u16) -> Result<SocketAddr, String> {
+    if let Ok(ip) = Ipv4Addr::from_str(server) {
+        return Ok(SocketAddr::from((ip, port)));
+    }
+
+    match (server, port).to_socket_addrs() {
+        Ok(addresses) => {
+            let addresses: Vec<String> = addresses.map(|address| address.ip().to_string()).collect();
+            Err(format!("'{}' ist keine IPv4-Adresse, der Name löst zu {} auf. Bitte unter [EMail] server die Adresse eintragen.",
+                server, addresses.join(", ")))
+        }
+        Err(e) => Err(format!("'{}' ist keine IPv4-Adresse und kann auch nicht aufgelöst werden: {}", server, e))
+    }
+}
+
+fn connect(address: &SocketAddr) -> Result<TcpStream, String> {
+    let timeout = Duration::from_secs(TIMEOUT_SECONDS);
+    let stream = TcpStream::connect_timeout(address, timeout).map_err(|e| format!("Keine Verbindung zu {}: {}", address, e))?;
+    stream.set_read_timeout(Some(timeout)).and_then(|()| stream.set_write_timeout(Some(timeout))).map_err(|e| e.to_string())?;
+    Ok(stream)
+}
+
+fn ehlo(client: &mut Client<NetworkStream>, hello_name: &str) -> Result<ServerInfo, String> {
+    client.ehlo(hello_name).and_then(|response| ServerInfo::from_response(&response)).map_err(smtp_error)
+}
+
+// Goes through the stages of sending a mail (address, TCP, greeting, EHLO, STARTTLS, CRAM-MD5) with the
+// settings of [EMail], without sending anything
+pub fn check_smtp(config: &Configuration, port: u16) -> Vec<Step> {
+    let mut steps = Vec::new();
+
+    macro_rules! stage {
+        ($stage: expr, $result: expr) => (
+            match $result {
+                Ok((value, detail)) => {
+                    steps.push(step($stage, Ok(detail)));
+                    value
+                }
+                Err(detail) => {
+                    steps.push(step($stage, Err(detail)));
+                    return steps;
+                }
+            }
+        )
+    }
+
+    let address = stage!("Adresse", server_address(&config.email_server, port).map(|address| (address, address.to_string())));
+    let mut stream = stage!("Verbindung", connect(&address).map(|stream| (stream, format!("TCP-Verbindung zu {} hergestellt", address))));
+    stage!("Begrüßung", read_greeting(&mut stream).map(|response| ((), response_text(&response))).map_err(smtp_error));
+
+    let mut client: Client<NetworkStream> = Client::new();
+    client.set_stream(NetworkStream::Plain(stream));
+
+    let server_info = stage!("EHLO", ehlo(&mut client, &config.email_hello).map(|info| {
+        let detail = format!("Server {}", info);
+        (info, detail)
+    }));
+
+    stage!("TLS", if server_info.supports_feature(&Extension::StartTls) {
+        SslContext::builder(SslMethod::tls()).map_err(|e| e.to_string())
+            .and_then(|builder| client.starttls().map_err(smtp_error).map(|_| builder.build()))
+            .and_then(|context| client.upgrade_tls_stream(&context).map_err(|e| format!("TLS-Handshake fehlgeschlagen: {}", e)))
+            .map(|()| ((), "Verbindung mit STARTTLS verschlüsselt".to_string()))
+    } else {
+        Err("Der Server bietet kein STARTTLS an, ohne Verschlüsselung werden keine Mails verschickt".to_string())
+    });
+
+    // Some servers only offer authentication after STARTTLS
+    let server_info = stage!("EHLO nach TLS", ehlo(&mut client, &config.email_hello).map(|info| {
+        let detail = format!("Server {}", info);
+        (info, detail)
+    }));
+
+    stage!("Anmeldung", if server_info.supports_auth_mechanism(Mechanism::CramMd5) {
+        client.auth(Mechanism::CramMd5, &config.email_username, &config.email_password)
+            .map(|response| ((), format!("Als '{}' angemeldet: {}", config.email_username, response_text(&response))))
+            .map_err(|e| format!("Anmeldung als '{}' abgelehnt. {}", config.email_username, smtp_error(e)))
+    } else {
+        Err("Der Server bietet CRAM-MD5 nicht an, das für die Anmeldung verwendet wird".to_string())
+    });
+
+    client.close();
+    steps
+}
+
+pub fn handle_smtp_check(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "smtp_check", |req| smtp_check_page(req, None))
+}
+
+pub fn handle_smtp_check_submit(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "smtp_check_submit", |req| {
+        let config = req.get::<PersistentRead<Configuration>>()?;
+        let steps = check_smtp(&config, SUBMISSION_PORT);
+
+        match steps.iter().find(|step| !step.success) {
+            Some(failed) => error!("SMTP check failed at '{}': {}", failed.stage, failed.detail),
+            None => info!("SMTP check successful")
+        }
+
+        smtp_check_page(req, Some(steps))
+    })
+}
+
+fn smtp_check_page(req: &mut Request, steps: Option<Vec<Step>>) -> Result<Response, HandleError> {
+    let config = req.get::<PersistentRead<Configuration>>()?;
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("server".to_string(), json!(format!("{}:{}", config.email_server, SUBMISSION_PORT)));
+    data.insert("username".to_string(), json!(config.email_username));
+    data.insert("checked".to_string(), json!(steps.is_some()));
+
+    if let Some(steps) = steps {
+        data.insert("success".to_string(), json!(steps.iter().all(|step| step.success)));
+        data.insert("steps".to_string(), json!(steps.iter().map(|step| json!({
+            "stage": step.stage,
+            "success": step.success,
+            "detail": step.detail
+        })).collect::<Vec<JsonValue>>()));
+    }
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("smtp_check", data)).set_mut(status::Ok);
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_smtp, read_greeting};
+    use config::example_configuration;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_read_greeting() {
+        let mut greeting: &[u8] = b"220-mail.example.org ESMTP\r\n220 ready\r\nEHLO";
+        assert_eq!(read_greeting(&mut greeting).unwrap().message(), vec!["mail.example.org ESMTP".to_string(), "ready".to_string()]);
+
+        let mut busy: &[u8] = b"421 too many connections\r\n";
+        assert!(read_greeting(&mut busy).is_err());
+    }
+
+    #[test]
+    fn test_check_smtp() {
+        let mut config = example_configuration();
+        config.email_server = "not a host name".to_string();
+        let steps = check_smtp(&config, 587);
+        assert_eq!(steps.len(), 1);
+        assert!(!steps[0].success);
+
+        // A server without STARTTLS
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"220 test ESMTP\r\n").unwrap();
+            let mut line = String::new();
+            BufReader::new(stream.try_clone().unwrap()).read_line(&mut line).unwrap();
+            stream.write_all(b"250-test\r\n250 AUTH CRAM-MD5\r\n").unwrap();
+            line
+        });
+
+        config.email_server = "127.0.0.1".to_string();
+        let steps = check_smtp(&config, port);
+        assert_eq!(server.join().unwrap(), "EHLO my.server.org\r\n".to_string());
+        assert_eq!(steps.iter().map(|step| (step.stage, step.success)).collect::<Vec<_>>(),
+            vec![("Adresse", true), ("Verbindung", true), ("Begrüßung", true), ("EHLO", true), ("TLS", false)]);
+    }
+}
@@ -0,0 +1,211 @@
+use iron::prelude::{Request, IronResult, Response};
+use iron::modifiers::RedirectRaw;
+use iron::status;
+
+use params::Params;
+use rusqlite::Connection;
+use plugin::Pluggable;
+use persistent::{Read, Write};
+
+use ::DBConnection;
+use admin::with_admin;
+use config::Configuration;
+use database::{lock_connection, load_registration_status, update_registration_status, OutboxMail};
+use handler::{HandleError, extract_string, load_registration, router_param, not_found};
+use mail_template::{mail_template, fill_template, status_values};
+use outbox::send_recorded;
+use dates::timestamp;
+
+
+/// Where a registration stands. Pending and confirmed registrations take a place at the conference,
+/// cancelled, waitlisted and rejected ones are left out of the counts and lists for the organization.
+/// With approval in [Basic] every registration starts as pending until the committee decides.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RegistrationStatus {
+    Pending,
+    Confirmed,
+    Cancelled,
+    Waitlisted,
+    Rejected
+}
+
+pub const ALL_STATUSES: [RegistrationStatus; 5] = [
+    RegistrationStatus::Pending,
+    RegistrationStatus::Confirmed,
+    RegistrationStatus::Waitlisted,
+    RegistrationStatus::Cancelled,
+    RegistrationStatus::Rejected
+];
+
+impl RegistrationStatus {
+    /// As stored in the status column
+    pub fn name(&self) -> &'static str {
+        match *self {
+            RegistrationStatus::Pending => "pending",
+            RegistrationStatus::Confirmed => "confirmed",
+            RegistrationStatus::Cancelled => "cancelled",
+            RegistrationStatus::Waitlisted => "waitlisted",
+            RegistrationStatus::Rejected => "rejected"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<RegistrationStatus> {
+        ALL_STATUSES.iter().find(|status| status.name() == name).cloned()
+    }
+
+    pub fn label(&self) -> &'static str {
+        match *self {
+            RegistrationStatus::Pending => "Offen",
+            RegistrationStatus::Confirmed => "Bestätigt",
+            RegistrationStatus::Cancelled => "Storniert",
+            RegistrationStatus::Waitlisted => "Warteliste",
+            RegistrationStatus::Rejected => "Abgelehnt"
+        }
+    }
+
+    pub fn takes_place(&self) -> bool {
+        *self == RegistrationStatus::Pending || *self == RegistrationStatus::Confirmed
+    }
+
+    /// A cancellation or rejection is final, the participant has to register again
+    pub fn can_change_to(&self, next: RegistrationStatus) -> bool {
+        matches!((*self, next),
+            (RegistrationStatus::Pending, RegistrationStatus::Confirmed) |
+            (RegistrationStatus::Pending, RegistrationStatus::Waitlisted) |
+            (RegistrationStatus::Pending, RegistrationStatus::Cancelled) |
+            (RegistrationStatus::Pending, RegistrationStatus::Rejected) |
+            (RegistrationStatus::Waitlisted, RegistrationStatus::Pending) |
+            (RegistrationStatus::Waitlisted, RegistrationStatus::Confirmed) |
+            (RegistrationStatus::Waitlisted, RegistrationStatus::Cancelled) |
+            (RegistrationStatus::Confirmed, RegistrationStatus::Cancelled))
+    }
+
+    pub fn transitions(&self) -> Vec<RegistrationStatus> {
+        ALL_STATUSES.iter().cloned().filter(|next| self.can_change_to(*next)).collect()
+    }
+}
+
+// The mail template sent for a change, waitlisted to pending needs no mail
+fn status_mail(current: RegistrationStatus, next: RegistrationStatus) -> Option<&'static str> {
+    match (current, next) {
+        (RegistrationStatus::Pending, RegistrationStatus::Confirmed) => Some("status_confirmed"),
+        (RegistrationStatus::Waitlisted, RegistrationStatus::Confirmed) => Some("status_promoted"),
+        (RegistrationStatus::Pending, RegistrationStatus::Waitlisted) => Some("status_waitlisted"),
+        (_, RegistrationStatus::Rejected) => Some("status_rejected"),
+        (_, RegistrationStatus::Cancelled) => Some("status_cancelled"),
+        _ => None
+    }
+}
+
+// The mail telling the participant about the change, to be sent with send_recorded once the lock is released
+pub fn status_change_mail(db_connection: &Connection, id: i64, current: RegistrationStatus, next: RegistrationStatus, config: &Configuration)
+    -> Result<Option<OutboxMail>, HandleError> {
+
+    Ok(match (status_mail(current, next), load_registration(db_connection, id)?) {
+        (Some(name), Some((registration, _))) => {
+            let template = mail_template(db_connection, name, config)?;
+            let values = status_values(&registration, config);
+
+            Some(OutboxMail {
+                id: 0,
+                registration_id: id,
+                template: name.to_string(),
+                email_to: registration.email_to,
+                subject: fill_template(&template.subject, &values),
+                body: fill_template(&template.body, &values),
+                created_at: timestamp(),
+                sent_at: String::new(),
+                error: String::new()
+            })
+        }
+        _ => None
+    })
+}
+
+// Label for a value of the status column, unknown values are shown as they are
+pub fn status_label(name: &str) -> String {
+    RegistrationStatus::from_name(name).map_or(name.to_string(), |status| status.label().to_string())
+}
+
+pub fn handle_registration_status(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "registration_status", change_status)
+}
+
+fn change_status(req: &mut Request) -> Result<Response, HandleError> {
+    let id = match router_param(req, "id").parse::<i64>() {
+        Ok(id) => id,
+        Err(_) => return Ok(not_found())
+    };
+
+    let map = req.get::<Params>()?;
+    let next = RegistrationStatus::from_name(&extract_string(&map, "status")?).ok_or(HandleError::FormValue)?;
+
+    let config = req.get::<Read<Configuration>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+
+    let mail = {
+        let db_connection = lock_connection(&mutex)?;
+
+        let current = match load_registration_status(&db_connection, id)? {
+            Some(current) => current,
+            None => return Ok(not_found())
+        };
+
+        // Compared again in the update, so that two admins can't both change the same status
+        let changed = match RegistrationStatus::from_name(&current) {
+            Some(status) if status.can_change_to(next) && update_registration_status(&db_connection, id, &current, next.name())? => status,
+            _ => {
+                info!("Registration {}: status can't change from {} to {}", id, current, next.name());
+                return Ok(Response::with((status::Conflict, "Status kann nicht geändert werden")));
+            }
+        };
+
+        info!("Registration {}: status changed from {} to {}", id, current, next.name());
+        status_change_mail(&db_connection, id, changed, next, &config)?
+    };
+
+    if let Some(mail) = mail {
+        send_recorded(&mutex, &mail, &config)?;
+    }
+
+    // The buttons of /admin/approvals lead back to the queue
+    let location = if extract_string(&map, "back").ok().is_some_and(|back| back == "approvals") { "/admin/approvals" } else { "/admin/participants" };
+    Ok(Response::with((status::Found, RedirectRaw(location.to_string()))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RegistrationStatus, status_label, status_mail};
+
+    #[test]
+    fn test_transitions() {
+        assert_eq!(RegistrationStatus::Pending.transitions(),
+            vec![RegistrationStatus::Confirmed, RegistrationStatus::Waitlisted, RegistrationStatus::Cancelled, RegistrationStatus::Rejected]);
+        assert!(!RegistrationStatus::Confirmed.can_change_to(RegistrationStatus::Rejected));
+        assert!(RegistrationStatus::Rejected.transitions().is_empty());
+        assert_eq!(RegistrationStatus::Confirmed.transitions(), vec![RegistrationStatus::Cancelled]);
+        assert!(RegistrationStatus::Cancelled.transitions().is_empty());
+        assert!(!RegistrationStatus::Confirmed.can_change_to(RegistrationStatus::Confirmed));
+        assert!(RegistrationStatus::Waitlisted.can_change_to(RegistrationStatus::Pending));
+    }
+
+    #[test]
+    fn test_status_mail() {
+        assert_eq!(status_mail(RegistrationStatus::Pending, RegistrationStatus::Confirmed), Some("status_confirmed"));
+        assert_eq!(status_mail(RegistrationStatus::Waitlisted, RegistrationStatus::Confirmed), Some("status_promoted"));
+        assert_eq!(status_mail(RegistrationStatus::Confirmed, RegistrationStatus::Cancelled), Some("status_cancelled"));
+        assert_eq!(status_mail(RegistrationStatus::Waitlisted, RegistrationStatus::Pending), None);
+        assert_eq!(status_mail(RegistrationStatus::Pending, RegistrationStatus::Rejected), Some("status_rejected"));
+    }
+
+    #[test]
+    fn test_status_names() {
+        assert_eq!(RegistrationStatus::from_name("waitlisted"), Some(RegistrationStatus::Waitlisted));
+        assert_eq!(RegistrationStatus::from_name("registered"), None);
+        assert_eq!(status_label("cancelled"), "Storniert".to_string());
+        assert_eq!(status_label("something"), "something".to_string());
+        assert!(RegistrationStatus::Pending.takes_place());
+        assert!(!RegistrationStatus::Waitlisted.takes_place());
+        assert!(!RegistrationStatus::Rejected.takes_place());
+    }
+}
@@ -0,0 +1,393 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use std::mem;
+use std::net::{SocketAddr, SocketAddrV4, Ipv4Addr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use iron::prelude::Iron;
+use iron::{Listening, Protocol};
+use hyper::net::HttpListener;
+
+use config::Configuration;
+use database::Repository;
+use handler::HandleError;
+use hooks::Hooks;
+use mailer::mail_file_number;
+use ::{registration_handler, ServerError};
+
+
+static SERVER_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// application/x-www-form-urlencoded like a browser: space as +, everything but letters, digits and -_.* escaped
+fn form_encode(fields: &[(&str, &str)]) -> String {
+    let encode = |text: &str| text.bytes().map(|byte| match byte {
+        b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'*' => (byte as char).to_string(),
+        b' ' => "+".to_string(),
+        _ => format!("%{:02X}", byte)
+    }).collect::<String>();
+
+    fields.iter().map(|&(name, value)| format!("{}={}", encode(name), encode(value))).collect::<Vec<String>>().join("&")
+}
+
+/// A response of the test server. The body is read as UTF-8, the exports in Latin-1 don't belong here.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String
+}
+
+impl TestResponse {
+    fn parse(raw: &str) -> io::Result<TestResponse> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "not an HTTP response");
+        let split = raw.find("\r\n\r\n").ok_or_else(invalid)?;
+        let mut lines = raw[..split].split("\r\n");
+
+        let status = lines.next().and_then(|line| line.split(' ').nth(1)).and_then(|code| code.parse::<u16>().ok())
+            .ok_or_else(invalid)?;
+        let headers = lines.filter_map(|line| line.find(':').map(|colon| (line[..colon].trim().to_string(), line[colon + 1..].trim().to_string())))
+            .collect();
+
+        Ok(TestResponse { status, headers, body: raw[split + 4..].to_string() })
+    }
+
+    /// The first header with this name, ignoring case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|&(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+}
+
+/// The whole registration site on a free port of 127.0.0.1, for end-to-end tests of a site's templates
+/// and hooks. Every test server has its own database in memory and writes its mails into its own folder
+/// (transport = file) instead of sending them.
+pub struct TestServer {
+    listening: Option<Listening>,
+    database: String,
+    mail_folder: PathBuf,
+    pub address: SocketAddr,
+    pub config: Configuration
+}
+
+/// Starts the registration site of `config` with `hooks` for a test. Only the database, the mails and the
+/// address are replaced, the templates come from `template_folder` unless `bundled_files` is set.
+pub fn test_server(config: Configuration, hooks: Hooks) -> Result<TestServer, ServerError> {
+    let number = SERVER_COUNTER.fetch_add(1, Ordering::SeqCst);
+    let database = format!("file:test_server_{}_{}?mode=memory&cache=shared", process::id(), number);
+    let mail_folder = env::temp_dir().join(format!("conference_registration_mails_{}_{}", process::id(), number));
+
+    // Bound before building the handler, base_url with the port goes into the mails. The same listener serves
+    // the requests, no other process can take the port in between.
+    let listener = TcpListener::bind("127.0.0.1:0").map_err(|e| ServerError::Listen(e.into()))?;
+    let port = listener.local_addr().map_err(|e| ServerError::Listen(e.into()))?.port();
+
+    let config = Configuration {
+        host: "127.0.0.1".to_string(),
+        port,
+        socket_addr: SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), port),
+        base_url: format!("http://127.0.0.1:{}", port),
+        db_filename: database.clone(),
+        email_transport: "file".to_string(),
+        email_folder: mail_folder.to_string_lossy().into_owned(),
+        bounce_server: String::new(),
+        ..config
+    };
+
+    let handler = registration_handler(&config, hooks)?;
+    let listening = Iron::new(handler).listen(HttpListener::from(listener), Protocol::http()).map_err(ServerError::Listen)?;
+
+    Ok(TestServer { address: listening.socket, listening: Some(listening), database, mail_folder, config })
+}
+
+impl TestServer {
+    /// Like "http://127.0.0.1:41234/admin"
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.address, path)
+    }
+
+    fn request(&self, method: &str, path: &str, headers: &[(&str, &str)], body: &str) -> io::Result<TestResponse> {
+        let mut stream = TcpStream::connect(self.address)?;
+        let mut request = format!("{} {} HTTP/1.0\r\nHost: {}\r\nContent-Length: {}\r\n", method, path, self.address, body.len());
+
+        for &(name, value) in headers {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+
+        request.push_str("\r\n");
+        request.push_str(body);
+        stream.write_all(request.as_bytes())?;
+
+        // HTTP/1.0, the server closes the connection after the response
+        let mut raw = Vec::new();
+        stream.read_to_end(&mut raw)?;
+        TestResponse::parse(&String::from_utf8_lossy(&raw))
+    }
+
+    /// A GET request, `headers` like `&[("Authorization", "Basic ...")]` for the admin pages
+    pub fn get(&self, path: &str, headers: &[(&str, &str)]) -> io::Result<TestResponse> {
+        self.request("GET", path, headers, "")
+    }
+
+    /// A POST request of a form like the browser sends it
+    pub fn post_form(&self, path: &str, headers: &[(&str, &str)], fields: &[(&str, &str)]) -> io::Result<TestResponse> {
+        let body = form_encode(fields);
+        let mut headers = headers.to_vec();
+        headers.push(("Content-Type", "application/x-www-form-urlencoded"));
+
+        self.request("POST", path, &headers, &body)
+    }
+
+    /// Every mail the server has "sent" so far, as the complete message with headers, the oldest first
+    pub fn mails(&self) -> Vec<String> {
+        let mut files: Vec<(usize, PathBuf)> = fs::read_dir(&self.mail_folder).into_iter().flatten()
+            .filter_map(|entry| entry.ok()).filter_map(|entry| {
+                let number = mail_file_number(&entry.file_name().to_string_lossy());
+                number.map(|number| (number, entry.path()))
+            }).collect();
+        files.sort();
+
+        files.into_iter().filter_map(|(_, path)| fs::read_to_string(path).ok()).collect()
+    }
+
+    /// Another connection to the database of the server, to prepare data or to check what was stored
+    pub fn repository(&self) -> Result<Repository, HandleError> {
+        Repository::open(&self.database)
+    }
+}
+
+impl Drop for TestServer {
+    // hyper 0.10 can't stop a server, dropping Listening would wait for it forever. The worker threads
+    // and the database stay until the test process ends.
+    fn drop(&mut self) {
+        if let Some(listening) = self.listening.take() {
+            mem::forget(listening);
+        }
+
+        let _ = fs::remove_dir_all(&self.mail_folder);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{test_server, form_encode, TestResponse};
+    use config::{bundled_configuration, in_memory_configuration, Session};
+    use hooks::Hooks;
+
+    #[test]
+    fn test_form_encode() {
+        assert_eq!(form_encode(&[("name", "Anna Müller"), ("email_to", "a+b@example.org")]),
+            "name=Anna+M%C3%BCller&email_to=a%2Bb%40example.org".to_string());
+
+        let response = TestResponse::parse("HTTP/1.1 409 Conflict\r\nContent-Type: text/html\r\n\r\n<p>").unwrap();
+        assert_eq!(response.status, 409);
+        assert_eq!(response.header("content-type"), Some("text/html"));
+        assert_eq!(response.body, "<p>".to_string());
+    }
+
+    #[test]
+    fn test_submit_over_http() {
+        let config = in_memory_configuration(bundled_configuration().unwrap());
+        let server = test_server(config.clone(), Hooks::new(&config)).unwrap();
+
+        let form = server.get("/", &[]).unwrap();
+        assert_eq!(form.status, 200);
+        let start = form.body.find("name=\"form_token\" value=\"").unwrap() + 26;
+        let form_token = &form.body[start..start + form.body[start..].find('"').unwrap()];
+
+        let submitted = server.post_form("/submit", &[], &[("form_token", form_token), ("title", "sir"), ("first_name", "Bob"), ("last_name", "Smith"), ("institution", "Uni"),
+            ("street", "Hauptstraße"), ("street_no", "1"), ("zip_code", "12345"), ("city", "Berlin"), ("phone", "0301234"),
+            ("email_to", "bob@example.org"), ("country", "DE"), ("more_info", ""), ("price_category", "regular"),
+            ("course_type", "course1"), ("presentation", "none")]).unwrap();
+        assert_eq!(submitted.status, 303, "{}", submitted.body);
+        assert_eq!(submitted.header("Location"), Some(format!("/submit?form_token={}", form_token).as_str()));
+
+        assert_eq!(server.repository().unwrap().count_registrations().unwrap(), 1);
+        assert!(server.mails().iter().any(|mail| mail.contains("bob@example.org")));
+
+        // The personal link of the confirmation mail
+        let mail = server.mails().into_iter().find(|mail| mail.contains("/edit/")).unwrap();
+        let start = mail.find(&server.url("/edit/")).unwrap() + server.url("").len();
+        let edit_path = mail[start..].split_whitespace().next().unwrap().to_string();
+        assert_eq!(server.get(&edit_path, &[]).unwrap().status, 200);
+
+        let saved = server.post_form(&edit_path, &[], &[("more_info", "Vegetarisch")]).unwrap();
+        assert_eq!(saved.header("Location"), Some(format!("{}?saved=1", edit_path).as_str()));
+        assert_eq!(server.get("/edit/unknown", &[]).unwrap().status, 404);
+
+        // Every test server starts empty
+        let other = test_server(config.clone(), Hooks::new(&config)).unwrap();
+        assert_eq!(other.repository().unwrap().count_registrations().unwrap(), 0);
+        assert!(other.mails().is_empty());
+    }
+
+    #[test]
+    fn test_registration_window_over_http() {
+        let mut config = in_memory_configuration(bundled_configuration().unwrap());
+        config.registration_open = "2000-01-01 09:00".to_string();
+        config.registration_close = "2000-03-01 23:59".to_string();
+        let closed = test_server(config.clone(), Hooks::new(&config)).unwrap();
+
+        let form = closed.get("/", &[]).unwrap();
+        assert_eq!(form.status, 200);
+        assert!(form.body.contains("Anmeldung geschlossen"));
+        assert!(!form.body.contains("form_token"));
+        assert_eq!(closed.post_form("/submit", &[], &[("first_name", "Bob")]).unwrap().status, 403);
+        assert_eq!(closed.repository().unwrap().count_registrations().unwrap(), 0);
+
+        config.registration_open = "2999-01-01 09:00".to_string();
+        config.registration_close = String::new();
+        let unopened = test_server(config.clone(), Hooks::new(&config)).unwrap();
+
+        let form = unopened.get("/", &[]).unwrap();
+        assert!(form.body.contains("Anmeldung noch nicht geöffnet"));
+        assert_eq!(unopened.post_form("/submit", &[], &[("first_name", "Bob")]).unwrap().status, 403);
+    }
+
+    #[test]
+    fn test_cancelled_presenter_over_http() {
+        let mut config = in_memory_configuration(bundled_configuration().unwrap());
+        config.public_poster_gallery = true;
+        config.sessions = vec![Session { key: "posters".to_string(), name: "Poster".to_string(), day: "2000-01-01".to_string(),
+            start: "14:00".to_string(), end: "16:00".to_string(), room: "Foyer".to_string() }];
+        let server = test_server(config.clone(), Hooks::new(&config)).unwrap();
+
+        for &(last_name, status) in &[("Smith", "confirmed"), ("Miller", "cancelled")] {
+            server.repository().unwrap().connection().execute("INSERT INTO registration (title, last_name, first_name, institution,
+                street, street_no, zip_code, city, phone, email_to, more_info, price_category, course_type, presentation,
+                presentation_title, presentation_authors, abstract_text, session, status)
+                VALUES ('sir', $1, 'Test', 'Uni', 'Street', '1', '12345', 'City', '123', 'bob@smith.com', '', 'student', 'course1',
+                'poster', $2, $1, 'Text', 'posters', $3)", &[&last_name, &format!("Poster of {}", last_name), &status]).unwrap();
+        }
+
+        let gallery = server.get("/posters", &[]).unwrap();
+        assert_eq!(gallery.status, 200);
+        assert!(gallery.body.contains("Poster of Smith"));
+        assert!(!gallery.body.contains("Poster of Miller"));
+
+        let program = server.get("/api/v1/program", &[]).unwrap();
+        assert_eq!(program.status, 200);
+        assert!(program.body.contains("Poster of Smith"));
+        assert!(!program.body.contains("Poster of Miller"));
+    }
+
+    #[test]
+    fn test_delete_over_http() {
+        let mut config = in_memory_configuration(bundled_configuration().unwrap());
+        config.admin_username = "admin".to_string();
+        config.admin_password = "secret".to_string();
+        let server = test_server(config.clone(), Hooks::new(&config)).unwrap();
+        let admin = [("Authorization", "Basic YWRtaW46c2VjcmV0")];
+
+        let repository = server.repository().unwrap();
+        let conn = repository.connection();
+        conn.execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no, zip_code,
+            city, phone, email_to, more_info, price_category, course_type)
+            VALUES ('sir', 'Smith', 'Bob', 'Uni', 'Street', '1', '12345', 'City', '123', 'bob@smith.com', '', 'student', 'course1')", &[]).unwrap();
+        let status = || conn.query_row("SELECT status FROM registration WHERE id = 1", &[], |row| row.get::<_, String>(0));
+
+        // Without the confirmation nothing happens
+        assert_eq!(server.post_form("/admin/delete/1", &admin, &[]).unwrap().header("Location"), Some("/admin/delete/1"));
+        assert_eq!(status().unwrap(), "confirmed".to_string());
+
+        // Cancelled and kept for the list of changes
+        assert_eq!(server.post_form("/admin/delete/1", &admin, &[("confirm", "1")]).unwrap().status, 302);
+        assert_eq!(status().unwrap(), "cancelled".to_string());
+        let changes: i64 = conn.query_row("SELECT COUNT(*) FROM roster_change WHERE registration_id = 1", &[], |row| row.get(0)).unwrap();
+        assert_eq!(changes, 1);
+
+        // An erasure request removes everything
+        assert_eq!(server.post_form("/admin/delete/1", &admin, &[("confirm", "1"), ("erase", "1")]).unwrap().status, 302);
+        assert!(status().is_err());
+        assert_eq!(server.post_form("/admin/delete/1", &admin, &[("confirm", "1"), ("erase", "1")]).unwrap().status, 404);
+    }
+
+    #[test]
+    fn test_filter_over_http() {
+        let mut config = in_memory_configuration(bundled_configuration().unwrap());
+        config.admin_username = "admin".to_string();
+        config.admin_password = "secret".to_string();
+        let server = test_server(config.clone(), Hooks::new(&config)).unwrap();
+        let admin = [("Authorization", "Basic YWRtaW46c2VjcmV0")];
+
+        let repository = server.repository().unwrap();
+        for &(last_name, status) in &[("Smith", "confirmed"), ("Brown", "cancelled")] {
+            repository.connection().execute("INSERT INTO registration (title, last_name, first_name, institution, street, street_no,
+                zip_code, city, phone, email_to, more_info, price_category, course_type, status)
+                VALUES ('sir', $1, 'Test', 'Uni', 'Street', '1', '12345', 'City', '123', 'test@example.org', '', 'student', 'course1', $2)",
+                &[&last_name, &status]).unwrap();
+        }
+
+        // The same selection on the front desk list and in the API
+        let page = server.get("/admin?status=cancelled", &admin).unwrap();
+        assert!(page.body.contains("Test Brown"));
+        assert!(!page.body.contains("Test Smith"));
+        assert!(page.body.contains("/admin?status=cancelled&amp;sort=id"));
+
+        let participants = server.get("/api/v1/participants?status=cancelled", &admin).unwrap();
+        assert_eq!(participants.status, 200);
+        let participants: ::serde_json::Value = ::serde_json::from_str(&participants.body).unwrap();
+        assert_eq!(participants.as_array().unwrap().len(), 1);
+        assert_eq!(participants[0]["name"], json!("Test Brown"));
+
+        assert_eq!(server.get("/api/v1/participants", &[("Authorization", "Basic YWRtaW46d3Jvbmc=")]).unwrap().status, 401);
+    }
+
+    #[test]
+    fn test_admin_login_over_http() {
+        let mut config = in_memory_configuration(bundled_configuration().unwrap());
+        config.admin_username = "admin".to_string();
+        config.admin_password = "secret".to_string();
+        let server = test_server(config.clone(), Hooks::new(&config)).unwrap();
+
+        // A browser is sent to the login form, remembering the page
+        let page = server.get("/admin/participants?status=cancelled", &[]).unwrap();
+        assert_eq!(page.status, 303);
+        assert_eq!(page.header("Location"), Some("/admin/login?next=%2Fadmin%2Fparticipants%3Fstatus%3Dcancelled"));
+        let form = server.get(page.header("Location").unwrap(), &[]).unwrap();
+        assert_eq!(form.status, 200);
+        assert!(form.body.contains("value=\"/admin/participants?status=cancelled\""));
+
+        let failed = server.post_form("/admin/login", &[], &[("next", "/admin/participants"), ("username", "admin"), ("password", "wrong")]).unwrap();
+        assert_eq!(failed.status, 401);
+        assert!(failed.header("Set-Cookie").is_none());
+
+        let login = server.post_form("/admin/login", &[], &[("next", "/admin/participants?status=cancelled"), ("username", "admin"), ("password", "secret")]).unwrap();
+        assert_eq!(login.status, 303);
+        assert_eq!(login.header("Location"), Some("/admin/participants?status=cancelled"));
+        let cookie = login.header("Set-Cookie").unwrap().split(';').next().unwrap().to_string();
+        assert_eq!(server.get("/admin/participants?status=cancelled", &[("Cookie", &cookie)]).unwrap().status, 200);
+
+        // Never to another site
+        let login = server.post_form("/admin/login", &[], &[("next", "//example.org/"), ("username", "admin"), ("password", "secret")]).unwrap();
+        assert_eq!(login.header("Location"), Some("/admin"));
+
+        // Scripts still get the basic auth challenge
+        assert_eq!(server.get("/admin/participants", &[("Authorization", "Basic d3Jvbmc6d3Jvbmc=")]).unwrap().status, 401);
+        assert_eq!(server.get("/admin/participants", &[("Authorization", "Basic YWRtaW46c2VjcmV0")]).unwrap().status, 200);
+    }
+
+    #[test]
+    fn test_login_link_over_http() {
+        let config = in_memory_configuration(bundled_configuration().unwrap());
+        let server = test_server(config.clone(), Hooks::new(&config)).unwrap();
+
+        server.repository().unwrap().connection().execute("INSERT INTO registration (title, last_name, first_name, institution,
+            street, street_no, zip_code, city, phone, email_to, email_normalized, more_info, price_category, course_type, token)
+            VALUES ('sir', 'Smith', 'Bob', 'Uni', 'Street', '1', '12345', 'City', '123', 'bob@smith.com', 'bob@smith.com', '',
+            'student', 'course1', 'personal_token')", &[]).unwrap();
+
+        assert_eq!(server.post_form("/login", &[], &[("email", "Bob@Smith.com")]).unwrap().status, 200);
+        let mail = server.mails().into_iter().find(|mail| mail.contains("/login/")).unwrap();
+        let start = mail.find(&server.url("/login/")).unwrap() + server.url("").len();
+        let link = mail[start..].split_whitespace().next().unwrap().to_string();
+
+        // The page of the link leads on to the personal edit page
+        let page = server.get(&link, &[]).unwrap();
+        assert_eq!(page.status, 200);
+        assert!(page.body.contains("href=\"/edit/personal_token\""));
+        assert_eq!(server.get("/edit/personal_token", &[]).unwrap().status, 200);
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use iron::prelude::Request;
+
+use plugin::Pluggable;
+use persistent::{Read, Write};
+
+use ::SubmissionThrottle;
+use config::Configuration;
+use handler::HandleError;
+
+
+const WINDOW: u64 = 3600;
+
+// When the registration form was submitted lately, per IP address
+pub struct Throttle {
+    submissions: HashMap<IpAddr, Vec<Instant>>
+}
+
+impl Throttle {
+    pub fn new() -> Throttle {
+        Throttle { submissions: HashMap::new() }
+    }
+
+    // Counts the submission if the address is still below the limit
+    fn allow(&mut self, ip: IpAddr, limit: u32, now: Instant) -> bool {
+        let window = Duration::from_secs(WINDOW);
+
+        // Forget everything older than an hour, so that the map doesn't grow over the registration period
+        for times in self.submissions.values_mut() {
+            times.retain(|time| now.duration_since(*time) < window);
+        }
+        self.submissions.retain(|_, times| !times.is_empty());
+
+        let times = self.submissions.entry(ip).or_default();
+
+        if times.len() >= limit as usize {
+            return false;
+        }
+
+        times.push(now);
+        true
+    }
+}
+
+// Keeps a single scripted client from filling all places with junk registrations
+pub fn throttle_submission(req: &mut Request) -> Result<(), HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+
+    if config.registrations_per_hour == 0 {
+        return Ok(());
+    }
+
+    let ip = req.remote_addr.ip();
+    let mutex = req.get::<Write<SubmissionThrottle>>()?;
+    let mut throttle = mutex.lock()?;
+
+    if throttle.allow(ip, config.registrations_per_hour, Instant::now()) {
+        Ok(())
+    } else {
+        Err(HandleError::Throttled(ip.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Throttle;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_throttle() {
+        let mut throttle = Throttle::new();
+        let alice = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10));
+        let bob = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 11));
+        let start = Instant::now();
+
+        assert!(throttle.allow(alice, 2, start));
+        assert!(throttle.allow(alice, 2, start + Duration::from_secs(60)));
+        assert!(!throttle.allow(alice, 2, start + Duration::from_secs(120)));
+        assert!(throttle.allow(bob, 2, start + Duration::from_secs(120)));
+
+        // The first submission has left the window
+        assert!(throttle.allow(alice, 2, start + Duration::from_secs(3600)));
+        assert!(!throttle.allow(alice, 2, start + Duration::from_secs(3601)));
+
+        assert!(throttle.allow(bob, 2, start + Duration::from_secs(7300)));
+        assert_eq!(throttle.submissions.len(), 1);
+    }
+}
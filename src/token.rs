@@ -0,0 +1,29 @@
+use std::io;
+
+use rand::{OsRng, Rng};
+
+
+// Random hex string used in links that give access to a single registration
+pub fn generate_token() -> Result<String, io::Error> {
+    let mut rng = OsRng::new()?;
+    let mut bytes = [0u8; 16];
+
+    rng.fill_bytes(&mut bytes);
+
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_token};
+
+    #[test]
+    fn test_generate_token() {
+        let token1 = generate_token().unwrap();
+        let token2 = generate_token().unwrap();
+
+        assert_eq!(token1.len(), 32);
+        assert!(token1.chars().all(|c| c.is_ascii_hexdigit()));
+        assert!(token1 != token2);
+    }
+}
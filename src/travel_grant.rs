@@ -0,0 +1,334 @@
+use std::collections::BTreeMap;
+
+use iron::prelude::{Request, IronResult, Response, Set};
+use iron::modifiers::RedirectRaw;
+use iron::status;
+
+use handlebars_iron::{Template};
+use serde_json::Value as JsonValue;
+use params::Params;
+use plugin::Pluggable;
+use persistent::{Read, Write};
+
+use ::DBConnection;
+use admin::with_admin;
+use config::Configuration;
+use dates::{DateFormat, timestamp};
+use database::{lock_connection, find_by_token, insert_travel_grant, load_travel_grants, review_travel_grant, mark_travel_grant_notified,
+    TravelGrant, TokenOwner};
+use fee::format_amount;
+use handler::{HandleError, extract_string, send_email, router_param, not_found};
+use upload::{store_upload, download_response, DOCUMENT_EXTENSIONS};
+use validation::parse_amount;
+
+
+const MAX_SCORE: i64 = 10;
+
+fn grant2json(grant: &TravelGrant, dates: &DateFormat) -> JsonValue {
+    json!({
+        "id": grant.id,
+        "name": format!("{} {}", grant.first_name, grant.last_name),
+        "amount": format_amount(grant.amount),
+        "motivation": grant.motivation,
+        "score": grant.score,
+        "award": format_amount(grant.award),
+        "award_input": format!("{}.{:02}", grant.award / 100, grant.award % 100),
+        "submitted_at": dates.timestamp(&grant.submitted_at),
+        "notified_at": dates.timestamp(&grant.notified_at)
+    })
+}
+
+// Only while applications are accepted and only with a valid personal link
+fn applicant(req: &mut Request) -> Result<TokenOwner, HandleError> {
+    let token = router_param(req, "token");
+    let config = req.get::<Read<Configuration>>()?;
+
+    if config.travel_grant_max == 0 {
+        return Err(HandleError::NotFound);
+    }
+
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    find_by_token(&db_connection, &token)?.ok_or(HandleError::NotFound)
+}
+
+fn render_applicant_page(req: &mut Request, owner: &TokenOwner, message: &str) -> Result<Response, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("name".to_string(), json!(format!("{} {}", owner.first_name, owner.last_name)));
+    data.insert("token".to_string(), json!(router_param(req, "token")));
+    data.insert("max_amount".to_string(), json!(format_amount(config.travel_grant_max)));
+    data.insert("message".to_string(), json!(message));
+
+    // The score is internal, the applicant only learns the decision from the notification
+    if let Some(grant) = load_travel_grants(&db_connection, Some(owner.id))?.first() {
+        data.insert("application".to_string(), json!({
+            "amount": format_amount(grant.amount),
+            "submitted_at": config.date_format.timestamp(&grant.submitted_at),
+            "decided": !grant.notified_at.is_empty(),
+            "award": format_amount(grant.award)
+        }));
+    }
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("travel_grant", data)).set_mut(status::Ok);
+    Ok(resp)
+}
+
+fn error_response(e: HandleError) -> Response {
+    match e {
+        HandleError::NotFound => not_found(),
+        e => {
+            error!("Error in travel grant page: {:?}", e);
+            Response::with((status::InternalServerError, "Internal server error"))
+        }
+    }
+}
+
+pub fn handle_travel_grant(req: &mut Request) -> IronResult<Response> {
+    let result = applicant(req).and_then(|owner| render_applicant_page(req, &owner, ""));
+
+    Ok(result.unwrap_or_else(error_response))
+}
+
+pub fn handle_travel_grant_submit(req: &mut Request) -> IronResult<Response> {
+    let owner = match applicant(req) {
+        Ok(owner) => owner,
+        Err(e) => return Ok(error_response(e))
+    };
+
+    let message = match submit_application(req, &owner) {
+        Ok(_) => {
+            info!("Travel grant application submitted for registration {}", owner.id);
+            "Ihr Antrag wurde eingereicht.".to_string()
+        }
+        Err(HandleError::Validation(field)) => {
+            info!("Invalid value in travel grant field '{}'", field);
+            format!("Ungültige Eingabe im Feld '{}'. Bitte korrigieren Sie die Angabe.", field)
+        }
+        Err(e) => {
+            error!("Error while processing travel grant application: {:?}", e);
+            "Ein Fehler ist aufgetreten. Bitte versuchen Sie es später noch einmal.".to_string()
+        }
+    };
+
+    Ok(render_applicant_page(req, &owner, &message).unwrap_or_else(error_response))
+}
+
+fn submit_application(req: &mut Request, owner: &TokenOwner) -> Result<(), HandleError> {
+    let map = req.get::<Params>()?;
+    let config = req.get::<Read<Configuration>>()?;
+
+    {
+        let mutex = req.get::<Write<DBConnection>>()?;
+        let db_connection = lock_connection(&mutex)?;
+
+        if !load_travel_grants(&db_connection, Some(owner.id))?.is_empty() {
+            return Err(HandleError::Validation("application".to_string()));
+        }
+    }
+
+    let amount = match parse_amount(&extract_string(&map, "amount").unwrap_or_default()) {
+        Some(amount) if amount > 0 && amount <= config.travel_grant_max => amount,
+        _ => return Err(HandleError::Validation("amount".to_string()))
+    };
+    let motivation = extract_string(&map, "motivation").unwrap_or_default();
+
+    if motivation.is_empty() {
+        return Err(HandleError::Validation("motivation".to_string()));
+    }
+
+    let (cv_file, cv_name) = store_upload(&map, "cv", "cvs", owner.id, &DOCUMENT_EXTENSIONS, &config)?;
+
+    let grant = TravelGrant {
+        id: 0,
+        registration_id: owner.id,
+        title: String::new(),
+        first_name: owner.first_name.clone(),
+        last_name: owner.last_name.clone(),
+        email_to: String::new(),
+        amount,
+        motivation,
+        cv_file,
+        cv_name,
+        score: None,
+        award: 0,
+        submitted_at: timestamp(),
+        notified_at: String::new()
+    };
+
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    insert_travel_grant(&db_connection, &grant)?;
+
+    Ok(())
+}
+
+pub fn handle_admin_travel_grants(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "travel_grants", admin_travel_grants_page)
+}
+
+fn admin_travel_grants_page(req: &mut Request) -> Result<Response, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    let grants = load_travel_grants(&db_connection, None)?;
+    let awarded: i64 = grants.iter().map(|grant| grant.award).sum();
+
+    let mut data: BTreeMap<String, JsonValue> = BTreeMap::new();
+    data.insert("grants".to_string(), json!(grants.iter().map(|grant| grant2json(grant, &config.date_format)).collect::<Vec<JsonValue>>()));
+    data.insert("awarded".to_string(), json!(format_amount(awarded)));
+    data.insert("pending".to_string(), json!(grants.iter().filter(|grant| ready_to_notify(grant)).count()));
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("admin_travel_grants", data)).set_mut(status::Ok);
+    Ok(resp)
+}
+
+fn grant_id(req: &Request) -> Option<i64> {
+    router_param(req, "id").parse::<i64>().ok()
+}
+
+pub fn handle_admin_travel_grant_review(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "travel_grant_review", review)
+}
+
+fn review(req: &mut Request) -> Result<Response, HandleError> {
+    let id = match grant_id(req) {
+        Some(id) => id,
+        None => return Ok(not_found())
+    };
+
+    let map = req.get::<Params>()?;
+    let score = match extract_string(&map, "score")?.parse::<i64>() {
+        Ok(score) if (0..=MAX_SCORE).contains(&score) => score,
+        _ => return Ok(Response::with((status::BadRequest, "Invalid score")))
+    };
+    let award = match parse_amount(&extract_string(&map, "award")?) {
+        Some(award) => award,
+        None => return Ok(Response::with((status::BadRequest, "Invalid award")))
+    };
+
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    if !review_travel_grant(&db_connection, id, score, award)? {
+        return Ok(Response::with((status::Conflict, "Applicant already notified")));
+    }
+
+    info!("Travel grant {}: score {}, award {}", id, score, award);
+
+    Ok(Response::with((status::Found, RedirectRaw("/admin/travel_grants".to_string()))))
+}
+
+pub fn handle_admin_travel_grant_cv(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "travel_grant_cv", cv_download)
+}
+
+fn cv_download(req: &mut Request) -> Result<Response, HandleError> {
+    let id = match grant_id(req) {
+        Some(id) => id,
+        None => return Ok(not_found())
+    };
+
+    let config = req.get::<Read<Configuration>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    match load_travel_grants(&db_connection, None)?.into_iter().find(|grant| grant.id == id) {
+        Some(grant) => Ok(download_response("cvs", &grant.cv_file, &grant.cv_name, &config).unwrap_or_else(not_found)),
+        None => Ok(not_found())
+    }
+}
+
+// Reviewed, but the applicant doesn't know yet
+fn ready_to_notify(grant: &TravelGrant) -> bool {
+    grant.score.is_some() && grant.notified_at.is_empty()
+}
+
+fn notification_mail(grant: &TravelGrant, organizer: &str) -> (String, String) {
+    let greeting = if grant.title == "sir" { format!("Sehr geehrter Herr {},", grant.last_name) } else { format!("Sehr geehrte Frau {},", grant.last_name) };
+    let decision = if grant.award > 0 {
+        format!("wir freuen uns, Ihnen einen Reisekostenzuschuss in Hoehe von {} gewaehren zu koennen.", format_amount(grant.award))
+    } else {
+        "leider koennen wir Ihnen keinen Reisekostenzuschuss gewaehren.".to_string()
+    };
+
+    ("Ihr Antrag auf Reisekostenzuschuss".to_string(),
+     format!("{}\n\n{}\n\nMit freundlichen Gruessen,\n{}", greeting, decision, organizer))
+}
+
+// Sends the decision to everybody who has been reviewed but not notified yet
+pub fn handle_admin_travel_grant_notify(req: &mut Request) -> IronResult<Response> {
+    with_admin(req, "travel_grant_notify", notify)
+}
+
+fn notify(req: &mut Request) -> Result<Response, HandleError> {
+    let config = req.get::<Read<Configuration>>()?;
+    let mutex = req.get::<Write<DBConnection>>()?;
+    let db_connection = lock_connection(&mutex)?;
+
+    for grant in load_travel_grants(&db_connection, None)?.iter().filter(|grant| ready_to_notify(grant)) {
+        let (subject, body) = notification_mail(grant, &config.conference.organizer);
+
+        // A failed mail stays pending and is sent with the next batch
+        match send_email(&grant.email_to, &subject, &body, &config) {
+            Ok(_) => {
+                mark_travel_grant_notified(&db_connection, grant.id, &timestamp())?;
+                info!("Travel grant {}: applicant notified", grant.id);
+            }
+            Err(e) => error!("Travel grant {}: could not send notification: {:?}", grant.id, e)
+        }
+    }
+
+    Ok(Response::with((status::Found, RedirectRaw("/admin/travel_grants".to_string()))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ready_to_notify, notification_mail};
+    use database::TravelGrant;
+
+    fn grant(score: Option<i64>, award: i64, notified_at: &str) -> TravelGrant {
+        TravelGrant {
+            id: 1,
+            registration_id: 1,
+            title: "sir".to_string(),
+            first_name: "Bob".to_string(),
+            last_name: "Smith".to_string(),
+            email_to: "bob@smith.com".to_string(),
+            amount: 50000,
+            motivation: "".to_string(),
+            cv_file: "".to_string(),
+            cv_name: "".to_string(),
+            score,
+            award,
+            submitted_at: "".to_string(),
+            notified_at: notified_at.to_string()
+        }
+    }
+
+    #[test]
+    fn test_ready_to_notify() {
+        assert!(!ready_to_notify(&grant(None, 0, "")));
+        assert!(ready_to_notify(&grant(Some(3), 0, "")));
+        assert!(!ready_to_notify(&grant(Some(3), 0, "2017-02-01 12:00:00")));
+    }
+
+    #[test]
+    fn test_notification_mail() {
+        let (_, body) = notification_mail(&grant(Some(9), 25000, ""), "Das Organisationskomitee");
+        assert!(body.starts_with("Sehr geehrter Herr Smith,"));
+        assert!(body.contains("250,00 €"));
+
+        let (_, body) = notification_mail(&grant(Some(2), 0, ""), "Das Organisationskomitee");
+        assert!(body.ends_with("\nDas Organisationskomitee"));
+        assert!(body.contains("leider"));
+    }
+}
@@ -0,0 +1,83 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use iron::prelude::Response;
+use iron::status;
+
+use params::{Value, Map};
+
+use config::Configuration;
+use handler::HandleError;
+use token::generate_token;
+
+
+const MAX_UPLOAD_SIZE: u64 = 10 * 1024 * 1024;
+// Receipts, CVs and other scanned documents
+pub const DOCUMENT_EXTENSIONS: [&str; 4] = ["pdf", "jpg", "jpeg", "png"];
+pub const SLIDE_EXTENSIONS: [&str; 2] = ["pdf", "pptx"];
+
+fn upload_extension(file_name: &str, extensions: &[&str]) -> Option<String> {
+    let extension = Path::new(file_name).extension()?.to_str()?.to_lowercase();
+
+    if extensions.contains(&extension.as_str()) { Some(extension) } else { None }
+}
+
+// Copies the uploaded file into upload_folder/<folder> if its extension is one of the given ones,
+// returns the file name on disk and the file name given by the client
+pub fn store_upload(map: &Map, key: &str, folder: &str, owner_id: i64, extensions: &[&str], config: &Configuration) -> Result<(String, String), HandleError> {
+    let file = match map.find(&[key]) {
+        Some(Value::File(file)) => file,
+        _ => return Err(HandleError::Validation(key.to_string()))
+    };
+
+    let original_name = file.filename.clone().unwrap_or_default();
+    let extension = upload_extension(&original_name, extensions).ok_or_else(|| HandleError::Validation(key.to_string()))?;
+
+    if file.size == 0 || file.size > MAX_UPLOAD_SIZE {
+        return Err(HandleError::Validation(key.to_string()));
+    }
+
+    // Never use the client's file name on disk
+    let folder = Path::new(&config.upload_folder).join(folder);
+    let stored_name = format!("{}_{}.{}", owner_id, generate_token()?, extension);
+
+    fs::create_dir_all(&folder)?;
+    fs::copy(&file.path, folder.join(&stored_name))?;
+
+    Ok((stored_name, original_name))
+}
+
+pub fn upload_path(folder: &str, stored_name: &str, config: &Configuration) -> PathBuf {
+    Path::new(&config.upload_folder).join(folder).join(stored_name)
+}
+
+// None if the file is gone
+pub fn download_response(folder: &str, stored_name: &str, original_name: &str, config: &Configuration) -> Option<Response> {
+    let path = upload_path(folder, stored_name, config);
+
+    if !path.is_file() {
+        return None;
+    }
+
+    let file_name: String = original_name.chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '.' || *c == '-' || *c == '_').collect();
+    let mut resp = Response::with((status::Ok, path.as_path()));
+    resp.headers.set_raw("Content-Disposition", vec![format!("attachment; filename=\"{}\"", file_name).into_bytes()]);
+
+    Some(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{upload_extension, DOCUMENT_EXTENSIONS, SLIDE_EXTENSIONS};
+
+    #[test]
+    fn test_upload_extension() {
+        assert_eq!(upload_extension("Ticket.PDF", &DOCUMENT_EXTENSIONS), Some("pdf".to_string()));
+        assert_eq!(upload_extension("scan.jpeg", &DOCUMENT_EXTENSIONS), Some("jpeg".to_string()));
+        assert_eq!(upload_extension("evil.exe", &DOCUMENT_EXTENSIONS), None);
+        assert_eq!(upload_extension("no_extension", &DOCUMENT_EXTENSIONS), None);
+        assert_eq!(upload_extension("talk.pptx", &SLIDE_EXTENSIONS), Some("pptx".to_string()));
+        assert_eq!(upload_extension("scan.jpeg", &SLIDE_EXTENSIONS), None);
+    }
+}
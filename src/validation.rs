@@ -0,0 +1,201 @@
+// Checks and normalizations for individual form fields
+
+
+// Accepts the bare iD with or without dashes as well as the https://orcid.org/ form
+// and returns the canonical 0000-0000-0000-0000 notation if the checksum matches
+pub fn normalize_orcid(orcid: &str) -> Option<String> {
+    let orcid = orcid.trim();
+    let orcid = orcid.trim_start_matches("https://").trim_start_matches("http://").trim_start_matches("orcid.org/");
+
+    let chars: Vec<char> = orcid.chars().filter(|c| *c != '-').map(|c| c.to_ascii_uppercase()).collect();
+
+    if chars.len() != 16 {
+        return None;
+    }
+
+    let mut digits = Vec::with_capacity(15);
+
+    for c in &chars[..15] {
+        digits.push(c.to_digit(10)?);
+    }
+
+    if orcid_check_digit(&digits) != chars[15] {
+        return None;
+    }
+
+    let plain: String = chars.iter().collect();
+
+    Some(format!("{}-{}-{}-{}", &plain[0..4], &plain[4..8], &plain[8..12], &plain[12..16]))
+}
+
+// ISO 7064 MOD 11-2
+fn orcid_check_digit(digits: &[u32]) -> char {
+    let total = digits.iter().fold(0, |total, digit| (total + digit) * 2);
+    let result = (12 - total % 11) % 11;
+
+    if result == 10 { 'X' } else { ::std::char::from_digit(result, 10).unwrap() }
+}
+
+// Converts a phone number to E.164 (+4970711234567), national numbers get the given calling code.
+// Returns None if the input doesn't look like a phone number, the caller keeps the original then.
+pub fn normalize_phone_number(number: &str, calling_code: &str) -> Option<String> {
+    // The trunk prefix in "+49 (0)7071 ..." must not end up in the international number
+    let number = number.trim().replace("(0)", "");
+    let mut digits: String = number.chars().filter(|c| !" -/().".contains(*c)).collect();
+
+    if digits.starts_with("00") {
+        digits = format!("+{}", &digits[2..]);
+    } else if digits.starts_with('0') {
+        digits = format!("+{}{}", calling_code, &digits[1..]);
+    }
+
+    if !digits.starts_with('+') || !digits[1..].chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    // E.164 allows at most 15 digits, very short numbers are most likely typos
+    let length = digits.len() - 1;
+
+    if !(7..=15).contains(&length) || digits[1..].starts_with('0') {
+        return None;
+    }
+
+    Some(digits)
+}
+
+// Removes spaces and checks the ISO 13616 check digits, returns the IBAN in upper case without spaces
+pub fn normalize_iban(iban: &str) -> Option<String> {
+    let iban: String = iban.chars().filter(|c| !c.is_whitespace()).map(|c| c.to_ascii_uppercase()).collect();
+
+    if iban.len() < 15 || iban.len() > 34 || !iban.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+
+    if !iban[0..2].chars().all(|c| c.is_ascii_alphabetic()) || !iban[2..4].chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let rearranged = format!("{}{}", &iban[4..], &iban[0..4]);
+    let remainder = rearranged.chars().fold(0, |remainder, c| {
+        let value = c.to_digit(36).unwrap();
+        let factor = if value < 10 { 10 } else { 100 };
+        (remainder * factor + value) % 97
+    });
+
+    if remainder == 1 { Some(iban) } else { None }
+}
+
+// Accepts "123", "123.45" and "123,45", returns the amount in cents
+pub fn parse_amount(amount: &str) -> Option<i64> {
+    let amount = amount.trim().replace(',', ".");
+    let mut parts = amount.splitn(2, '.');
+    let whole = parts.next()?;
+    let fraction = parts.next().unwrap_or("0");
+
+    if whole.is_empty() || fraction.is_empty() || fraction.len() > 2 ||
+       !whole.chars().all(|c| c.is_ascii_digit()) || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let cents = if fraction.len() == 1 { fraction.parse::<i64>().ok()? * 10 } else { fraction.parse::<i64>().ok()? };
+
+    Some(whole.parse::<i64>().ok()? * 100 + cents)
+}
+
+// How an institution is looked up in its aliases: case and spacing don't matter, "Uni  Tübingen" is "uni tübingen"
+pub fn normalize_institution(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<&str>>().join(" ").to_lowercase()
+}
+
+// Only a plausibility check for addresses in the configuration and the form: one @, a domain with a dot and
+// without empty labels, no spaces or list separators. Whether the address exists only the mail server knows.
+pub fn valid_email(address: &str) -> bool {
+    let mut parts = address.split('@');
+
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(local), Some(domain), None) => !local.is_empty() && domain.contains('.') && domain.split('.').all(|label| !label.is_empty()) &&
+            !address.chars().any(|c| c.is_whitespace() || c == ',' || c == ';' || c == '<' || c == '>'),
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize_orcid, normalize_phone_number, normalize_iban, parse_amount, valid_email, normalize_institution};
+
+    #[test]
+    fn test_normalize_institution() {
+        assert_eq!(normalize_institution(" Universität  TÜBINGEN "), "universität tübingen".to_string());
+        assert_eq!(normalize_institution(""), "".to_string());
+    }
+
+    #[test]
+    fn test_valid_email() {
+        assert!(valid_email("registration@example.org"));
+        assert!(valid_email("first.last+tag@mail.example.org"));
+        assert!(!valid_email("registration"));
+        assert!(!valid_email("@example.org"));
+        assert!(!valid_email("a@b@example.org"));
+        assert!(!valid_email("registration@localhost"));
+        assert!(!valid_email("alice@example.org bob@example.org"));
+        assert!(!valid_email("registration@example..org"));
+        assert!(!valid_email("registration@.example.org"));
+    }
+
+    #[test]
+    fn test_normalize_orcid1() {
+        assert_eq!(normalize_orcid("0000-0002-1825-0097"), Some("0000-0002-1825-0097".to_string()));
+        assert_eq!(normalize_orcid(" https://orcid.org/0000-0002-1825-0097 "), Some("0000-0002-1825-0097".to_string()));
+        assert_eq!(normalize_orcid("0000000218250097"), Some("0000-0002-1825-0097".to_string()));
+        assert_eq!(normalize_orcid("0000-0002-1694-233x"), Some("0000-0002-1694-233X".to_string()));
+    }
+
+    #[test]
+    fn test_normalize_orcid2() {
+        assert_eq!(normalize_orcid("0000-0002-1825-0098"), None);
+        assert_eq!(normalize_orcid("0000-0002-1825"), None);
+        assert_eq!(normalize_orcid("000A-0002-1825-0097"), None);
+        assert_eq!(normalize_orcid("n/a"), None);
+        assert_eq!(normalize_orcid(""), None);
+    }
+
+    #[test]
+    fn test_normalize_phone_number1() {
+        assert_eq!(normalize_phone_number("07071 / 12 34 56", "49"), Some("+497071123456".to_string()));
+        assert_eq!(normalize_phone_number("+49 (0)7071-123456", "49"), Some("+497071123456".to_string()));
+        assert_eq!(normalize_phone_number("0041 44 123 45 67", "49"), Some("+41441234567".to_string()));
+        assert_eq!(normalize_phone_number("0171 1234567", "43"), Some("+431711234567".to_string()));
+        assert_eq!(normalize_phone_number("+1 (555) 123.4567", "49"), Some("+15551234567".to_string()));
+    }
+
+    #[test]
+    fn test_normalize_phone_number2() {
+        assert_eq!(normalize_phone_number("", "49"), None);
+        assert_eq!(normalize_phone_number("ask my secretary", "49"), None);
+        assert_eq!(normalize_phone_number("123456", "49"), None);
+        assert_eq!(normalize_phone_number("+49 123", "49"), None);
+        assert_eq!(normalize_phone_number("+49 1234 5678 9012 3456", "49"), None);
+        assert_eq!(normalize_phone_number("+0 1234 56789", "49"), None);
+    }
+
+    #[test]
+    fn test_normalize_iban() {
+        assert_eq!(normalize_iban("DE89 3704 0044 0532 0130 00"), Some("DE89370400440532013000".to_string()));
+        assert_eq!(normalize_iban("gb82west12345698765432"), Some("GB82WEST12345698765432".to_string()));
+        assert_eq!(normalize_iban("DE89 3704 0044 0532 0130 01"), None);
+        assert_eq!(normalize_iban("DE89"), None);
+        assert_eq!(normalize_iban("1289 3704 0044 0532 0130 00"), None);
+    }
+
+    #[test]
+    fn test_parse_amount() {
+        assert_eq!(parse_amount("123"), Some(12300));
+        assert_eq!(parse_amount(" 123,45 "), Some(12345));
+        assert_eq!(parse_amount("0.5"), Some(50));
+        assert_eq!(parse_amount("12.345"), None);
+        assert_eq!(parse_amount("-5"), None);
+        assert_eq!(parse_amount("1.000,00"), None);
+        assert_eq!(parse_amount(""), None);
+        assert_eq!(parse_amount("12."), None);
+    }
+}